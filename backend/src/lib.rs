@@ -7,4 +7,4 @@ pub struct ErrorResponse {
     pub error: String,
 }
 
-
+pub mod rollup;
@@ -4,20 +4,25 @@ use axum::{
     extract::{ConnectInfo, Extension, Path, Query},
     http::{header::AUTHORIZATION, HeaderValue, StatusCode, HeaderMap, request::Parts},
     response::Json,
-    routing::{get, post, put, delete}, // delete is used in route definitions (line 5269)
+    routing::{get, post, put, patch, delete}, // delete is used in route definitions (line 5269)
     Router,
 };
 use axum::async_trait;
-use chrono::{DateTime, Datelike, TimeZone, Utc};
+use chrono::{DateTime, Datelike, FixedOffset, TimeZone, Utc};
+use chrono_tz::Tz;
 use hmac::{Hmac, Mac};
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use sqlx::sqlite::SqlitePoolOptions;
-use sqlx::{Connection, Pool, Sqlite};
+use sqlx::{Connection, Pool, QueryBuilder, Sqlite};
 use std::net::SocketAddr;
 use tower_http::cors::{Any, AllowOrigin, CorsLayer};
+use live_schedule_api::rollup::{
+    calculate_rollup, fetch_cost_split_members, fetch_exchange_rates, split_cost, CostSplitRow, ExchangeRates, SplitRule,
+    SplitShares, COST_SPLIT_SELECT, DEFAULT_CURRENCY,
+};
 use resend_rs::types::CreateEmailBaseOptions;
 use resend_rs::{Resend, Result};
 
@@ -86,6 +91,9 @@ struct ProfileAvatarRequest {
 #[derive(Debug, Deserialize)]
 struct UpdateProfileRequest {
     display_name: Option<String>,
+    timezone: Option<String>, // IANAタイムゾーン名（省略時は変更しない）
+    home_currency: Option<String>, // 基準通貨（ISO 4217、省略時は変更しない）
+    auto_done_enabled: Option<bool>, // 終演後にKeepのスケジュールを自動でDoneにするか（省略時は変更しない）
 }
 
 #[derive(Debug, Deserialize)]
@@ -208,6 +216,48 @@ fn get_stripe_webhook_secret() -> String {
     std::env::var("STRIPE_WEBHOOK_SECRET").unwrap_or_default()
 }
 
+// ゴミ箱の保持期間（日数、未設定の場合は30日）。経過したものはバックグラウンドで完全削除する
+fn get_trash_retention_days() -> i64 {
+    std::env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|days| *days > 0)
+        .unwrap_or(30)
+}
+
+// 添付ファイルの保存先の種類（未設定の場合はlocal）
+fn get_attachment_storage_kind() -> String {
+    std::env::var("ATTACHMENT_STORAGE").unwrap_or_else(|_| "local".to_string())
+}
+
+// localストレージの保存ディレクトリ（未設定の場合は./data/attachments）
+fn get_attachment_dir() -> String {
+    std::env::var("ATTACHMENT_DIR").unwrap_or_else(|_| "./data/attachments".to_string())
+}
+
+// 1ファイルあたりのサイズ上限（バイト、未設定の場合は10MB）
+fn get_attachment_max_file_bytes() -> usize {
+    std::env::var("ATTACHMENT_MAX_FILE_BYTES")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|bytes| *bytes > 0)
+        .unwrap_or(10 * 1024 * 1024)
+}
+
+// ユーザーごとの添付ファイル合計容量の上限（バイト）。未設定の場合は無料プラン50MB、プレミアム・トライアル中は1GB
+fn get_attachment_quota_bytes(paid: bool) -> i64 {
+    let (name, default) = if paid {
+        ("ATTACHMENT_QUOTA_PAID_BYTES", 1024 * 1024 * 1024)
+    } else {
+        ("ATTACHMENT_QUOTA_FREE_BYTES", 50 * 1024 * 1024)
+    };
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|bytes| *bytes >= 0)
+        .unwrap_or(default)
+}
+
 // ====== 認証ヘルパー関数 ======
 
 // ランダムなトークンを生成
@@ -290,7 +340,8 @@ async fn fetch_user_plan(pool: &Pool<Sqlite>, user_id: i32) -> Result<(String, O
 
 // 無料プランで閲覧できる過去アーカイブの下限年（暦年ベース、今年と去年の2年分）を返す
 // 未来の予定はこの制限の対象外（呼び出し側で年の上限は設けない）
-fn free_plan_earliest_archive_year(now: DateTime<Utc>) -> i32 {
+// 「今年」はユーザーのタイムゾーンでの現在日時で判定する
+fn free_plan_earliest_archive_year<Z: TimeZone>(now: DateTime<Z>) -> i32 {
     now.year() - 1
 }
 
@@ -299,6 +350,105 @@ fn parse_year_prefix(date: &str) -> Option<i32> {
     date.get(0..4)?.parse().ok()
 }
 
+// ====== タイムゾーン ======
+// 日付・時刻の入力値（schedules.date/start、stays.deadline 等）は開催地の現地時刻として保存し、
+// 日時の比較や年の判定はスケジュール個別の設定 > ユーザー設定 > Asia/Tokyo の順で決まるタイムゾーンで行う
+
+// ユーザー設定の既定値（タイムゾーン設定の導入前に登録されたデータはすべて日本時間で入力されている）
+const DEFAULT_TIMEZONE: &str = "Asia/Tokyo";
+
+// IANAタイムゾーン名をパースする（空文字・不明な名前はNone）
+fn parse_timezone(name: &str) -> Option<Tz> {
+    name.trim().parse::<Tz>().ok()
+}
+
+fn resolve_timezone(schedule_timezone: Option<&str>, user_timezone: Option<&str>) -> Tz {
+    schedule_timezone
+        .and_then(parse_timezone)
+        .or_else(|| user_timezone.and_then(parse_timezone))
+        .unwrap_or(chrono_tz::Asia::Tokyo)
+}
+
+// 現地時刻をタイムゾーン付きの日時に変換する
+// サマータイムの切替で重複する時刻は早い方、存在しない時刻は1時間後の時刻として扱う
+fn localize_datetime(naive: chrono::NaiveDateTime, tz: Tz) -> DateTime<FixedOffset> {
+    tz.from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(naive + chrono::Duration::hours(1))).earliest())
+        .unwrap_or_else(|| tz.from_utc_datetime(&naive))
+        .fixed_offset()
+}
+
+// date + start から datetime を生成する（startがない場合は00:00、dateもない場合は現在時刻）
+fn schedule_datetime(date: Option<&str>, start: Option<&str>, tz: Tz) -> DateTime<FixedOffset> {
+    let Some(date) = date.and_then(|d| chrono::NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d").ok()) else {
+        return Utc::now().with_timezone(&tz).fixed_offset();
+    };
+    let time = start
+        .and_then(|s| chrono::NaiveTime::parse_from_str(s.trim(), "%H:%M").ok())
+        .unwrap_or(chrono::NaiveTime::MIN);
+    localize_datetime(date.and_time(time), tz)
+}
+
+// リクエストで指定されたタイムゾーン名を検証する（空文字はNone = 既定に従う）
+fn validate_timezone_name(value: &str) -> Result<Option<String>, (StatusCode, Json<ErrorResponse>)> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    parse_timezone(value)
+        .map(|tz| Some(tz.name().to_string()))
+        .ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: "タイムゾーンが正しくありません（例: Asia/Tokyo）".to_string(),
+                }),
+            )
+        })
+}
+
+async fn fetch_user_timezone(pool: &Pool<Sqlite>, user_id: i32) -> Result<Tz, sqlx::Error> {
+    let timezone: Option<String> = sqlx::query_scalar("SELECT timezone FROM users WHERE id = ?")
+        .bind(user_id as i64)
+        .fetch_optional(pool)
+        .await?;
+    Ok(resolve_timezone(None, timezone.as_deref()))
+}
+
+#[cfg(test)]
+mod timezone_tests {
+    use super::{localize_datetime, resolve_timezone, schedule_datetime};
+
+    #[test]
+    fn schedule_datetime_is_local_wall_clock_time() {
+        let tokyo = resolve_timezone(None, None);
+        let dt = schedule_datetime(Some("2025-01-01"), Some("05:00"), tokyo);
+        assert_eq!(dt.to_rfc3339(), "2025-01-01T05:00:00+09:00");
+        // UTCでは前年になるが、年の判定は現地時刻で行う
+        assert_eq!(chrono::Datelike::year(&dt.naive_utc()), 2024);
+        assert_eq!(chrono::Datelike::year(&dt), 2025);
+    }
+
+    #[test]
+    fn schedule_timezone_overrides_user_timezone() {
+        let tz = resolve_timezone(Some("America/New_York"), Some("Asia/Tokyo"));
+        let dt = schedule_datetime(Some("2025-07-04"), Some("19:30"), tz);
+        assert_eq!(dt.to_rfc3339(), "2025-07-04T19:30:00-04:00");
+        assert_eq!(resolve_timezone(Some("Invalid/Zone"), Some("Europe/London")).name(), "Europe/London");
+    }
+
+    #[test]
+    fn nonexistent_local_time_is_shifted_forward() {
+        let tz = resolve_timezone(Some("America/New_York"), None);
+        let naive = chrono::NaiveDate::from_ymd_opt(2025, 3, 9)
+            .unwrap()
+            .and_hms_opt(2, 30, 0)
+            .unwrap();
+        assert_eq!(localize_datetime(naive, tz).to_rfc3339(), "2025-03-09T03:30:00-04:00");
+    }
+}
+
 // share_idの所有者を解決する。sharing_enabledがOFF、または所有者が無料プラン相当
 // （premiumでもトライアル中でもない）の場合は、共有されていないものとして扱う
 // （premium失効時にsharing_enabledのDB値を書き換えるのではなく、都度動的に判定する）
@@ -341,6 +491,51 @@ async fn resolve_active_share_owner(
     Ok(user_id)
 }
 
+// DBエラー時の共通レスポンス（詳細は呼び出し側でeprintln!してから返す）
+fn database_error() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ErrorResponse {
+            error: "データベースエラーが発生しました".to_string(),
+        }),
+    )
+}
+
+// スケジュールがログインユーザーの所有であることを確認する
+// （スケジュール配下のサブエンティティを操作するハンドラで使う）
+async fn ensure_schedule_owner(
+    pool: &Pool<Sqlite>,
+    schedule_id: i64,
+    user_id: i32,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let schedule_user_id: Option<Option<i64>> = sqlx::query_scalar(
+        "SELECT user_id FROM schedules WHERE id = ? AND deleted_at IS NULL"
+    )
+    .bind(schedule_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[EnsureScheduleOwner] Database error: {}", e);
+        database_error()
+    })?;
+
+    match schedule_user_id {
+        None => Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "スケジュールが見つかりませんでした".to_string(),
+            }),
+        )),
+        Some(Some(owner_id)) if owner_id == user_id as i64 => Ok(()),
+        Some(_) => Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "このスケジュールを編集する権限がありません".to_string(),
+            }),
+        )),
+    }
+}
+
 // public_idがNULLの既存行に、衝突しないランダムIDを1件ずつ発行してバックフィルする
 async fn backfill_public_ids(pool: &Pool<Sqlite>, table: &str) -> Result<(), sqlx::Error> {
     let select_sql = format!("SELECT id FROM {} WHERE public_id IS NULL", table);
@@ -541,6 +736,106 @@ async fn send_deadline_notification_email(
     }
 }
 
+// チケット申込（当選分）の支払期限通知メールを送信する
+async fn send_payment_deadline_notification_email(
+    email: &str,
+    application_label: &str,
+    deadline: &str,
+    schedule_title: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // 環境変数からResend APIキーを取得
+    let api_key = match std::env::var("RESEND_API_KEY") {
+        Ok(key) => key,
+        Err(_) => {
+            // 開発環境: コンソールに出力
+            println!("[PAYMENT_DEADLINE_NOTIFICATION] RESEND_API_KEY not found, using development mode (console output)");
+            println!("=== 支払期限通知（開発環境） ===");
+            println!("宛先: {}", email);
+            println!("件名: チケットの支払期限が近づいています");
+            println!("本文:");
+            println!("当選したチケット「{}」の支払期限が24時間以内に迫っています。", application_label);
+            println!("期限日時: {}", deadline);
+            println!("関連イベント: {}", schedule_title);
+            println!("===========================");
+            return Ok(());
+        }
+    };
+
+    // 本番環境: Resend APIを使用
+    println!("[PAYMENT_DEADLINE_NOTIFICATION] RESEND_API_KEY found, using Resend API");
+    let email_body = format!(
+        r#"<p>当選したチケット「{}」の支払期限が24時間以内に迫っています。</p><p><strong>期限日時:</strong> {}</p><p><strong>関連イベント:</strong> {}</p><p>期限を過ぎると当選が無効になる場合があります。お早めにお支払いください。</p>"#,
+        html_escape(application_label), deadline, html_escape(schedule_title)
+    );
+
+    let resend = Resend::new(&api_key);
+    let from = get_email_from();
+    let to = [email];
+    let subject = "チケットの支払期限が近づいています";
+
+    let email_options = CreateEmailBaseOptions::new(&from, to, subject)
+        .with_html(&email_body);
+
+    match resend.emails.send(email_options).await {
+        Ok(result) => {
+            println!("[PAYMENT_DEADLINE_NOTIFICATION] Payment deadline email sent successfully to {}: {:?}", email, result);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("[PAYMENT_DEADLINE_NOTIFICATION] Failed to send payment deadline email to {}: {:?}", email, e);
+            Err(e.into())
+        }
+    }
+}
+
+// 予算のしきい値・超過の通知メールを送信する
+async fn send_budget_alert_email(
+    email: &str,
+    title: &str,
+    message: &str,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // 環境変数からResend APIキーを取得
+    let api_key = match std::env::var("RESEND_API_KEY") {
+        Ok(key) => key,
+        Err(_) => {
+            // 開発環境: コンソールに出力
+            println!("[BUDGET_NOTIFICATION] RESEND_API_KEY not found, using development mode (console output)");
+            println!("=== 予算通知（開発環境） ===");
+            println!("宛先: {}", email);
+            println!("件名: {}", title);
+            println!("本文:");
+            println!("{}", message);
+            println!("===========================");
+            return Ok(());
+        }
+    };
+
+    // 本番環境: Resend APIを使用
+    println!("[BUDGET_NOTIFICATION] RESEND_API_KEY found, using Resend API");
+    let email_body = format!(
+        r#"<p>{}</p><p>{}</p><p>予算の設定はアプリの「予算」画面から変更できます。</p>"#,
+        html_escape(title), html_escape(message)
+    );
+
+    let resend = Resend::new(&api_key);
+    let from = get_email_from();
+    let to = [email];
+
+    let email_options = CreateEmailBaseOptions::new(&from, to, title)
+        .with_html(&email_body);
+
+    match resend.emails.send(email_options).await {
+        Ok(result) => {
+            println!("[BUDGET_NOTIFICATION] Budget alert email sent successfully to {}: {:?}", email, result);
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("[BUDGET_NOTIFICATION] Failed to send budget alert email to {}: {:?}", email, e);
+            Err(e.into())
+        }
+    }
+}
+
 // Expo Push APIを使ってアプリへプッシュ通知を送信する。
 // 無効化されたトークン（DeviceNotRegistered）はpush_tokensテーブルから削除する。
 // Expo APIへの通信・レスポンス解析に失敗した場合はErrを返す（一時的な障害で
@@ -552,7 +847,7 @@ async fn send_expo_push_notifications(
     tokens: &[String],
     title: &str,
     body: &str,
-    schedule_id: i64,
+    data: serde_json::Value, // アプリで開く画面の指定（例: {"schedule_id": 1}）
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     if tokens.is_empty() {
         return Ok(());
@@ -566,7 +861,7 @@ async fn send_expo_push_notifications(
                 "to": token,
                 "title": title,
                 "body": body,
-                "data": { "schedule_id": schedule_id },
+                "data": data,
                 "sound": "default",
             })
         })
@@ -897,10 +1192,13 @@ struct Schedule {
     id: i32,
     title: String,
     group: Option<String>, // NULL許可（空欄の場合はNULLのまま）
-    datetime: DateTime<Utc>, // date + start から生成（計算フィールド）
+    datetime: DateTime<FixedOffset>, // date + start（現地時刻）から生成（計算フィールド、現地のUTCオフセット付き）
 
     // Event Info
     date: Option<String>,
+    end_date: Option<String>, // 複数日開催（フェス等）の最終日。単日の場合はNULL
+    timezone: Option<String>, // 海外公演など、ユーザー設定と異なるタイムゾーンで開催する場合のみ（IANA名）
+    tour_id: Option<i32>,     // 所属するツアー（tours.id）
     open: Option<String>,
     start: Option<String>,
     end: Option<String>,
@@ -910,15 +1208,27 @@ struct Schedule {
     venue: String,
     target: Option<String>,
     lineup: Option<String>,
+    days: Vec<ScheduleDay>, // 複数日開催の各日程（単日の場合は空）
+    tickets: Vec<ScheduleTicket>, // チケット・座席情報（整理番号順）
+    seat_public: bool,            // 公開・共有ページで座席・整理番号を表示するか
 
     // Cost
     seller: Option<String>,
-    ticket_fee: Option<i32>,  // チケット代
-    drink_fee: Option<i32>,   // ドリンク代
+    ticket_fee: Option<i32>,  // チケット代（手入力値）
+    drink_fee: Option<i32>,   // ドリンク代（手入力値）
+    ticket_fee_currency: String, // チケット代の通貨（ISO 4217、日別チケット代も同じ通貨）
+    drink_fee_currency: String,  // ドリンク代の通貨
+    ticket_fee_effective: Option<i32>, // 総費用に使うチケット代（優先順位はrollup::effective_feeを参照）
+    drink_fee_effective: Option<i32>,  // 総費用に使うドリンク代（同上）
     total_fare: Option<i32>,  // Traffic の合計
     stay_fee: Option<i32>,    // Stay の合計
     travel_cost: Option<i32>, // = Total fare + Stay fee
-    total_cost: Option<i32>,  // = Ticket fee + Drink fee + Travel cost
+    other_cost: Option<i32>,  // 諸経費（食事・コインロッカーなどの明細）の合計
+    total_cost: Option<i32>,  // = Ticket fee effective + Drink fee effective + Travel cost + Other cost
+    my_share: Option<i32>,    // total_costのうち自分の負担分（割り勘の設定を反映、設定のない費用は全額）
+    net_cost: Option<i32>,    // = My share - 譲渡・リセールの受取額（手数料を除く）
+    goods_cost: Option<i32>,  // グッズ代の合計（total_costには含めない）
+    cost_currency: String,    // ticket_fee_effective〜goods_costの通貨（所有ユーザーの基準通貨に換算済み）
 
     status: String, // "Canceled" / "Pending" / "Keep" / "Done"
 
@@ -932,6 +1242,9 @@ struct Schedule {
     // 認証・公開関連
     user_id: Option<i32>,
     is_public: bool,
+
+    // 旅程チェックの警告。作成・更新時のレスポンスのみ（取得時はNULL、常に確認する場合は /schedules/:id/checks）
+    warnings: Option<Vec<ItineraryWarning>>,
 }
 
 // ====== Schedule 行定義（DB 用） ======
@@ -948,6 +1261,10 @@ struct ScheduleRow {
     start: Option<String>,
     #[sqlx(rename = "end")]
     end_time: Option<String>,
+    end_date: Option<String>,
+    timezone: Option<String>,
+    tour_id: Option<i64>,
+    user_timezone: Option<String>, // 所有ユーザーのタイムゾーン設定（usersテーブルから取得）
     notes: Option<String>,
     category: Option<String>,
     area: String,
@@ -957,10 +1274,19 @@ struct ScheduleRow {
     seller: Option<String>,
     ticket_fee: Option<i32>,
     drink_fee: Option<i32>,
+    ticket_fee_currency: String,
+    drink_fee_currency: String,
+    ticket_fee_effective: Option<i32>,
+    drink_fee_effective: Option<i32>,
     total_fare: Option<i32>,
     stay_fee: Option<i32>,
     travel_cost: Option<i32>,
+    other_cost: Option<i32>,
     total_cost: Option<i32>,
+    my_share: Option<i32>,
+    net_cost: Option<i32>,
+    goods_cost: Option<i32>,
+    cost_currency: Option<String>, // 所有ユーザーの基準通貨（usersテーブルから取得）
     status: String,
     related_schedule_ids: Option<String>, // JSON形式で保存
     user_id: Option<i64>,
@@ -973,6 +1299,20 @@ struct ScheduleRow {
 struct ScheduleQuery {
     year: Option<i32>,
     include_canceled: Option<bool>,
+    from: Option<String>,   // YYYY-MM-DD。開催期間がこの日以降にかかるもの
+    to: Option<String>,     // YYYY-MM-DD。この日以前に始まるもの
+    status: Option<String>, // カンマ区切り（例: Keep,Done）。指定時はinclude_canceledより優先
+    category: Option<String>,
+    area: Option<String>,
+    target: Option<String>,
+    seller: Option<String>,
+    venue: Option<String>, // 部分一致
+    min_cost: Option<i64>, // total_costの範囲
+    max_cost: Option<i64>,
+    sort: Option<String>,  // date（既定）/ total_cost / title / created_at / updated_at
+    order: Option<String>, // asc（既定）/ desc
+    limit: Option<i64>,
+    cursor: Option<String>, // 前ページのX-Next-Cursor
 }
 
 // POST /schedules 用リクエストボディ
@@ -981,6 +1321,9 @@ struct NewSchedule {
     title: String,
     group: Option<String>, // NULL許可（空文字列もNULLに変換）
     date: Option<String>,
+    end_date: Option<String>, // 複数日開催の最終日（単日の場合は省略）
+    timezone: Option<String>, // 開催地のタイムゾーン（空文字でユーザー設定に戻す、省略時は変更しない）
+    tour_id: Option<i32>,     // 所属するツアー（省略時は変更しない。ツアーから外す場合は DELETE /tours/:id/schedules/:schedule_id）
     open: Option<String>,
     start: Option<String>,
     end: Option<String>,
@@ -993,9 +1336,92 @@ struct NewSchedule {
     seller: Option<String>,
     ticket_fee: Option<i32>,
     drink_fee: Option<i32>,
+    ticket_fee_currency: Option<String>, // 省略時は新規作成ではユーザーの基準通貨、更新では変更しない
+    drink_fee_currency: Option<String>,
     status: Option<String>,
     related_schedule_ids: Option<Vec<i32>>, // 関連スケジュールIDの配列
     is_public: Option<bool>, // 公開フラグ
+    days: Option<Vec<NewScheduleDay>>, // 複数日開催の各日程（省略時は開催期間から自動生成・維持）
+    tickets: Option<Vec<NewScheduleTicket>>, // チケット・座席情報（指定時は置き換え、省略時は変更しない）
+    seat_public: Option<bool>, // 公開・共有ページで座席・整理番号を表示するか（省略時は変更しない）
+}
+
+// ====== ScheduleDay 型定義（複数日開催） ======
+
+#[derive(Serialize, Clone)]
+struct ScheduleDay {
+    id: i32,
+    date: String,
+    open: Option<String>,
+    start: Option<String>,
+    end: Option<String>,
+    lineup: Option<String>,
+    attended: bool,           // その日に参加したか（参加予定か）
+    ticket_fee: Option<i32>,  // 1日券などの日別チケット代
+    drink_fee: Option<i32>,   // 日別ドリンク代
+}
+
+#[derive(sqlx::FromRow)]
+struct ScheduleDayRow {
+    id: i64,
+    schedule_id: i64,
+    date: String,
+    open: Option<String>,
+    start: Option<String>,
+    #[sqlx(rename = "end")]
+    end_time: Option<String>,
+    lineup: Option<String>,
+    attended: i32,
+    ticket_fee: Option<i32>,
+    drink_fee: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct NewScheduleDay {
+    date: String,
+    open: Option<String>,
+    start: Option<String>,
+    end: Option<String>,
+    lineup: Option<String>,
+    attended: Option<bool>,
+    ticket_fee: Option<i32>,
+    drink_fee: Option<i32>,
+}
+
+// ====== ScheduleTicket 型定義（チケット・座席情報） ======
+
+#[derive(Serialize, Clone)]
+struct ScheduleTicket {
+    id: i32,
+    ticket_type: Option<String>,  // "reserved"（指定席）/ "standing"（スタンディング）/ "unreserved"（自由席）/ "other"
+    seat_block: Option<String>,   // ブロック・エリア（例: A、アリーナB）
+    seat_row: Option<String>,     // 列
+    seat_number: Option<String>,  // 番号
+    entry_number: Option<String>, // 整理番号（英字の接頭辞+数字、例: A123）
+    gate: Option<String>,         // 入場ゲート
+}
+
+#[derive(sqlx::FromRow)]
+struct ScheduleTicketRow {
+    id: i64,
+    schedule_id: i64,
+    position: i64,
+    ticket_type: Option<String>,
+    seat_block: Option<String>,
+    seat_row: Option<String>,
+    seat_number: Option<String>,
+    entry_number: Option<String>,
+    gate: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct NewScheduleTicket {
+    ticket_type: Option<String>,
+    seat_block: Option<String>,
+    seat_row: Option<String>,
+    seat_number: Option<String>,
+    entry_number: Option<String>,
+    gate: Option<String>,
 }
 
 // ====== Traffic 型定義 ======
@@ -1011,10 +1437,12 @@ struct Traffic {
     to: String,
     notes: Option<String>,
     fare: i32,
+    fare_currency: String,
     miles: Option<i32>,
     return_flag: bool,
     total_fare: Option<i32>,
     total_miles: Option<i32>,
+    warnings: Option<Vec<ItineraryWarning>>, // 旅程チェックの警告（作成・更新時のみ）
 }
 
 #[derive(sqlx::FromRow)]
@@ -1029,6 +1457,7 @@ struct TrafficRow {
     to_place: String,
     notes: Option<String>,
     fare: i32,
+    fare_currency: String,
     miles: Option<i32>,
     return_flag: i32, // 0/1
     total_fare: Option<i32>,
@@ -1051,6 +1480,7 @@ struct NewTraffic {
     to: String,
     notes: Option<String>,
     fare: i32,
+    fare_currency: Option<String>, // 省略時は新規作成ではユーザーの基準通貨、更新では変更しない
     miles: Option<i32>,
     return_flag: bool,
 }
@@ -1096,10 +1526,12 @@ struct Stay {
     hotel_name: String,
     website: Option<String>,
     fee: i32,
+    fee_currency: String,
     breakfast_flag: bool,
     deadline: Option<String>,
     penalty: Option<i32>,
     status: String,
+    warnings: Option<Vec<ItineraryWarning>>, // 旅程チェックの警告（作成・更新時のみ）
 }
 
 #[derive(sqlx::FromRow)]
@@ -1111,6 +1543,7 @@ struct StayRow {
     hotel_name: String,
     website: Option<String>,
     fee: i32,
+    fee_currency: String,
     breakfast_flag: i32, // 0/1
     deadline: Option<String>,
     penalty: Option<i32>,
@@ -1131,6 +1564,7 @@ struct NewStay {
     hotel_name: String,
     website: Option<String>,
     fee: i32,
+    fee_currency: Option<String>, // 省略時は新規作成ではユーザーの基準通貨、更新では変更しない
     breakfast_flag: bool,
     deadline: Option<String>,
     penalty: Option<i32>,
@@ -1145,8 +1579,9 @@ struct PublicSchedule {
     id: String, // = public_id
     title: String,
     group: Option<String>,
-    datetime: DateTime<Utc>,
+    datetime: DateTime<FixedOffset>,
     date: Option<String>,
+    end_date: Option<String>,
     open: Option<String>,
     start: Option<String>,
     end: Option<String>,
@@ -1156,16 +1591,47 @@ struct PublicSchedule {
     venue: String,
     target: Option<String>,
     lineup: Option<String>,
+    days: Vec<PublicScheduleDay>,
+    tickets: Vec<PublicScheduleTicket>, // 所有者がseat_publicを有効にしていない場合は種別以外を伏せ字（***）にする
     seller: Option<String>,
     ticket_fee: Option<i32>,
     drink_fee: Option<i32>,
+    ticket_fee_currency: String,
+    drink_fee_currency: String,
+    ticket_fee_effective: Option<i32>,
+    drink_fee_effective: Option<i32>,
     total_fare: Option<i32>,
     stay_fee: Option<i32>,
     travel_cost: Option<i32>,
+    other_cost: Option<i32>,
     total_cost: Option<i32>,
+    cost_currency: String, // ticket_fee_effective〜total_costの通貨（所有ユーザーの基準通貨）
     status: String,
     related_schedule_ids: Vec<String>, // 関連スケジュールのpublic_id
     is_public: bool,
+    setlist: Option<Vec<PublicSetlistEntry>>, // 共有ページでセットリストを公開している場合のみ（詳細取得時）
+}
+
+#[derive(Serialize, Clone)]
+struct PublicScheduleTicket {
+    ticket_type: Option<String>,
+    seat_block: Option<String>,
+    seat_row: Option<String>,
+    seat_number: Option<String>,
+    entry_number: Option<String>,
+    gate: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+struct PublicScheduleDay {
+    date: String,
+    open: Option<String>,
+    start: Option<String>,
+    end: Option<String>,
+    lineup: Option<String>,
+    attended: bool,
+    ticket_fee: Option<i32>,
+    drink_fee: Option<i32>,
 }
 
 #[derive(sqlx::FromRow)]
@@ -1180,6 +1646,9 @@ struct PublicScheduleRow {
     start: Option<String>,
     #[sqlx(rename = "end")]
     end_time: Option<String>,
+    end_date: Option<String>,
+    timezone: Option<String>,
+    user_timezone: Option<String>,
     notes: Option<String>,
     category: Option<String>,
     area: String,
@@ -1189,10 +1658,16 @@ struct PublicScheduleRow {
     seller: Option<String>,
     ticket_fee: Option<i32>,
     drink_fee: Option<i32>,
+    ticket_fee_currency: String,
+    drink_fee_currency: String,
+    ticket_fee_effective: Option<i32>,
+    drink_fee_effective: Option<i32>,
     total_fare: Option<i32>,
     stay_fee: Option<i32>,
     travel_cost: Option<i32>,
+    other_cost: Option<i32>,
     total_cost: Option<i32>,
+    cost_currency: Option<String>,
     status: String,
     related_schedule_ids: Option<String>, // JSON形式（内部id）で保存
     is_public: i32,
@@ -1209,6 +1684,7 @@ struct PublicTraffic {
     to: String,
     notes: Option<String>,
     fare: i32,
+    fare_currency: String,
     miles: Option<i32>,
     return_flag: bool,
     total_fare: Option<i32>,
@@ -1226,6 +1702,7 @@ struct PublicTrafficRow {
     to_place: String,
     notes: Option<String>,
     fare: i32,
+    fare_currency: String,
     miles: Option<i32>,
     return_flag: i32,
     total_fare: Option<i32>,
@@ -1249,6 +1726,7 @@ struct SharedTrafficRow {
     to_place: String,
     notes: Option<String>,
     fare: i32,
+    fare_currency: String,
     miles: Option<i32>,
     return_flag: i32,
     total_fare: Option<i32>,
@@ -1265,6 +1743,7 @@ struct PublicStay {
     hotel_name: String,
     website: Option<String>,
     fee: i32,
+    fee_currency: String,
     breakfast_flag: bool,
     deadline: Option<String>,
     penalty: Option<i32>,
@@ -1279,6 +1758,7 @@ struct PublicStayRow {
     hotel_name: String,
     website: Option<String>,
     fee: i32,
+    fee_currency: String,
     breakfast_flag: i32,
     deadline: Option<String>,
     penalty: Option<i32>,
@@ -1299,6 +1779,7 @@ struct SharedStayRow {
     hotel_name: String,
     website: Option<String>,
     fee: i32,
+    fee_currency: String,
     breakfast_flag: i32,
     deadline: Option<String>,
     penalty: Option<i32>,
@@ -1309,23 +1790,9 @@ struct SharedStayRow {
 // ====== Row → API 用への変換 ======
 
 fn row_to_schedule(row: ScheduleRow) -> Schedule {
-    // date + start から datetime を生成
-    let datetime = if let (Some(date), Some(start)) = (&row.date, &row.start) {
-        // date: "YYYY-MM-DD", start: "HH:MM"
-        let datetime_str = format!("{}T{}:00Z", date, start);
-        datetime_str
-            .parse::<DateTime<Utc>>()
-            .unwrap_or_else(|_| Utc::now())
-    } else if let Some(date) = &row.date {
-        // date のみがある場合、00:00:00 として扱う
-        let datetime_str = format!("{}T00:00:00Z", date);
-        datetime_str
-            .parse::<DateTime<Utc>>()
-            .unwrap_or_else(|_| Utc::now())
-    } else {
-        // date も start もない場合、現在時刻を使用
-        Utc::now()
-    };
+    // date + start（開催地の現地時刻）から datetime を生成
+    let tz = resolve_timezone(row.timezone.as_deref(), row.user_timezone.as_deref());
+    let datetime = schedule_datetime(row.date.as_deref(), row.start.as_deref(), tz);
 
     // groupがNULLの場合はそのままNULLを返す（データとして保持）
     let group = row.group_name
@@ -1338,6 +1805,9 @@ fn row_to_schedule(row: ScheduleRow) -> Schedule {
         group,
         datetime,
         date: row.date,
+        end_date: row.end_date,
+        timezone: row.timezone,
+        tour_id: row.tour_id.map(|id| id as i32),
         open: row.open,
         start: row.start,
         end: row.end_time,
@@ -1347,13 +1817,25 @@ fn row_to_schedule(row: ScheduleRow) -> Schedule {
         venue: row.venue,
         target: row.target,
         lineup: row.lineup,
+        days: vec![],
+        tickets: vec![],
+        seat_public: false,
         seller: row.seller,
         ticket_fee: row.ticket_fee,
         drink_fee: row.drink_fee,
+        ticket_fee_currency: row.ticket_fee_currency,
+        drink_fee_currency: row.drink_fee_currency,
+        ticket_fee_effective: row.ticket_fee_effective,
+        drink_fee_effective: row.drink_fee_effective,
         total_fare: row.total_fare,
         stay_fee: row.stay_fee,
         travel_cost: row.travel_cost,
+        other_cost: row.other_cost,
         total_cost: row.total_cost,
+        my_share: row.my_share,
+        net_cost: row.net_cost,
+        goods_cost: row.goods_cost,
+        cost_currency: row.cost_currency.unwrap_or_else(|| DEFAULT_CURRENCY.to_string()),
         status: row.status,
         // related_schedule_idsをJSONからパース
         related_schedule_ids: row.related_schedule_ids
@@ -1364,6 +1846,7 @@ fn row_to_schedule(row: ScheduleRow) -> Schedule {
         stay_ids: vec![],
         user_id: row.user_id.map(|id| id as i32),
         is_public: row.is_public != 0,
+        warnings: None,
     }
 }
 
@@ -1378,10 +1861,12 @@ fn row_to_traffic(row: TrafficRow) -> Traffic {
         to: row.to_place,
         notes: row.notes,
         fare: row.fare,
+        fare_currency: row.fare_currency,
         miles: row.miles,
         return_flag: row.return_flag != 0,
         total_fare: row.total_fare,
         total_miles: row.total_miles,
+        warnings: None,
     }
 }
 
@@ -1404,10 +1889,26 @@ fn row_to_stay(row: StayRow) -> Stay {
         hotel_name: row.hotel_name,
         website: row.website,
         fee: row.fee,
+        fee_currency: row.fee_currency,
         breakfast_flag: row.breakfast_flag != 0,
         deadline: row.deadline,
         penalty: row.penalty,
         status: row.status,
+        warnings: None,
+    }
+}
+
+fn row_to_schedule_day(row: ScheduleDayRow) -> ScheduleDay {
+    ScheduleDay {
+        id: row.id as i32,
+        date: row.date,
+        open: row.open,
+        start: row.start,
+        end: row.end_time,
+        lineup: row.lineup,
+        attended: row.attended != 0,
+        ticket_fee: row.ticket_fee,
+        drink_fee: row.drink_fee,
     }
 }
 
@@ -1432,17 +1933,8 @@ async fn resolve_schedule_public_ids(pool: &Pool<Sqlite>, ids: &[i32]) -> Vec<St
 }
 
 async fn row_to_public_schedule(pool: &Pool<Sqlite>, row: PublicScheduleRow) -> PublicSchedule {
-    let datetime = if let (Some(date), Some(start)) = (&row.date, &row.start) {
-        format!("{}T{}:00Z", date, start)
-            .parse::<DateTime<Utc>>()
-            .unwrap_or_else(|_| Utc::now())
-    } else if let Some(date) = &row.date {
-        format!("{}T00:00:00Z", date)
-            .parse::<DateTime<Utc>>()
-            .unwrap_or_else(|_| Utc::now())
-    } else {
-        Utc::now()
-    };
+    let tz = resolve_timezone(row.timezone.as_deref(), row.user_timezone.as_deref());
+    let datetime = schedule_datetime(row.date.as_deref(), row.start.as_deref(), tz);
 
     let group = row
         .group_name
@@ -1456,12 +1948,29 @@ async fn row_to_public_schedule(pool: &Pool<Sqlite>, row: PublicScheduleRow) ->
         .unwrap_or_default();
     let related_schedule_ids = resolve_schedule_public_ids(pool, &related_internal_ids).await;
 
+    // 複数日開催の各日程（取得に失敗した場合は日程なしとして返す）
+    let days = match fetch_schedule_day_rows(pool, &[row.id]).await {
+        Ok(rows) => rows.into_iter().map(row_to_public_schedule_day).collect(),
+        Err(e) => {
+            eprintln!("[RowToPublicSchedule] Failed to fetch schedule days: {}", e);
+            vec![]
+        }
+    };
+    let tickets = match fetch_public_schedule_tickets(pool, row.id).await {
+        Ok(tickets) => tickets,
+        Err(e) => {
+            eprintln!("[RowToPublicSchedule] Failed to fetch schedule tickets: {}", e);
+            vec![]
+        }
+    };
+
     PublicSchedule {
         id: row.public_id,
         title: row.title,
         group,
         datetime,
         date: row.date,
+        end_date: row.end_date,
         open: row.open,
         start: row.start,
         end: row.end_time,
@@ -1471,16 +1980,38 @@ async fn row_to_public_schedule(pool: &Pool<Sqlite>, row: PublicScheduleRow) ->
         venue: row.venue,
         target: row.target,
         lineup: row.lineup,
+        days,
+        tickets,
         seller: row.seller,
         ticket_fee: row.ticket_fee,
         drink_fee: row.drink_fee,
+        ticket_fee_currency: row.ticket_fee_currency,
+        drink_fee_currency: row.drink_fee_currency,
+        ticket_fee_effective: row.ticket_fee_effective,
+        drink_fee_effective: row.drink_fee_effective,
         total_fare: row.total_fare,
         stay_fee: row.stay_fee,
         travel_cost: row.travel_cost,
+        other_cost: row.other_cost,
         total_cost: row.total_cost,
+        cost_currency: row.cost_currency.unwrap_or_else(|| DEFAULT_CURRENCY.to_string()),
         status: row.status,
         related_schedule_ids,
         is_public: row.is_public != 0,
+        setlist: None,
+    }
+}
+
+fn row_to_public_schedule_day(row: ScheduleDayRow) -> PublicScheduleDay {
+    PublicScheduleDay {
+        date: row.date,
+        open: row.open,
+        start: row.start,
+        end: row.end_time,
+        lineup: row.lineup,
+        attended: row.attended != 0,
+        ticket_fee: row.ticket_fee,
+        drink_fee: row.drink_fee,
     }
 }
 
@@ -1495,6 +2026,7 @@ fn row_to_public_traffic(row: PublicTrafficRow, schedule_public_id: &str) -> Pub
         to: row.to_place,
         notes: row.notes,
         fare: row.fare,
+        fare_currency: row.fare_currency,
         miles: row.miles,
         return_flag: row.return_flag != 0,
         total_fare: row.total_fare,
@@ -1513,6 +2045,7 @@ fn shared_row_to_public_traffic(row: SharedTrafficRow) -> PublicTraffic {
         to: row.to_place,
         notes: row.notes,
         fare: row.fare,
+        fare_currency: row.fare_currency,
         miles: row.miles,
         return_flag: row.return_flag != 0,
         total_fare: row.total_fare,
@@ -1529,6 +2062,7 @@ fn row_to_public_stay(row: PublicStayRow, schedule_public_id: &str) -> PublicSta
         hotel_name: row.hotel_name,
         website: row.website,
         fee: row.fee,
+        fee_currency: row.fee_currency,
         breakfast_flag: row.breakfast_flag != 0,
         deadline: row.deadline,
         penalty: row.penalty,
@@ -1545,6 +2079,7 @@ fn shared_row_to_public_stay(row: SharedStayRow) -> PublicStay {
         hotel_name: row.hotel_name,
         website: row.website,
         fee: row.fee,
+        fee_currency: row.fee_currency,
         breakfast_flag: row.breakfast_flag != 0,
         deadline: row.deadline,
         penalty: row.penalty,
@@ -3337,8 +3872,8 @@ async fn get_profile(
     user: AuthenticatedUser,
     Extension(pool): Extension<Pool<Sqlite>>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
-    let row: Option<(String, Option<String>, Option<String>, Option<String>)> = sqlx::query_as(
-        "SELECT email, share_id, avatar_data_url, display_name FROM users WHERE id = ?"
+    let row: Option<(String, Option<String>, Option<String>, Option<String>, String, String, i32)> = sqlx::query_as(
+        "SELECT email, share_id, avatar_data_url, display_name, timezone, home_currency, auto_done_enabled FROM users WHERE id = ?"
     )
     .bind(user.user_id as i64)
     .fetch_optional(&pool)
@@ -3346,17 +3881,23 @@ async fn get_profile(
     .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "プロフィールの取得に失敗しました".to_string() })))?;
 
     match row {
-        Some((email, share_id, avatar_data_url, display_name)) => Ok(Json(serde_json::json!({
+        Some((email, share_id, avatar_data_url, display_name, timezone, home_currency, auto_done_enabled)) => Ok(Json(serde_json::json!({
             "email": email,
             "share_id": share_id,
             "avatar_data_url": avatar_data_url,
             "display_name": display_name,
+            "timezone": timezone,
+            "home_currency": home_currency,
+            "auto_done_enabled": auto_done_enabled != 0,
         }))),
         None => Err((StatusCode::NOT_FOUND, Json(ErrorResponse { error: "ユーザーが見つかりません".to_string() }))),
     }
 }
 
 // PUT /auth/profile - プロフィール名更新（空文字で未設定に戻す）
+// timezoneを指定した場合はタイムゾーン設定も更新する（空文字でAsia/Tokyoに戻す）
+// home_currencyを指定した場合は基準通貨を変更し、すべてのスケジュールのロールアップを換算し直す
+// auto_done_enabledを指定した場合は終演後のステータス自動更新の設定も更新する
 async fn update_profile(
     user: AuthenticatedUser,
     Extension(pool): Extension<Pool<Sqlite>>,
@@ -3373,9 +3914,42 @@ async fn update_profile(
         })));
     }
 
-    let now = Utc::now().to_rfc3339();
-    sqlx::query("UPDATE users SET display_name = ?, updated_at = ? WHERE id = ?")
+    let timezone = payload
+        .timezone
+        .as_deref()
+        .map(validate_timezone_name)
+        .transpose()?
+        .map(|tz| tz.unwrap_or_else(|| DEFAULT_TIMEZONE.to_string()));
+
+    // 基準通貨を変更する場合は、使用中のすべての通貨が新しい基準通貨に換算できることを確認する
+    let home_currency = match payload.home_currency.as_deref() {
+        Some(value) => {
+            let currency = normalize_currency_code(value)
+                .ok_or_else(|| currency_bad_request("通貨はISO 4217の通貨コード（例: JPY, KRW）で指定してください"))?;
+            let check = async {
+                let mut rates = fetch_exchange_rates(&pool, user.user_id as i64).await?;
+                rates.home_currency = currency.clone();
+                let in_use = currencies_in_use(&pool, user.user_id as i64).await?;
+                Ok::<Vec<String>, sqlx::Error>(rates.unconvertible(&in_use))
+            };
+            let unconvertible = check.await.map_err(|e| {
+                eprintln!("[UpdateProfile] Failed to check currencies: {}", e);
+                database_error()
+            })?;
+            if !unconvertible.is_empty() {
+                return Err(unconvertible_currencies_conflict(&unconvertible, &currency));
+            }
+            Some(currency)
+        }
+        None => None,
+    };
+
+    let now = Utc::now().to_rfc3339();
+    sqlx::query("UPDATE users SET display_name = ?, timezone = COALESCE(?, timezone), home_currency = COALESCE(?, home_currency), auto_done_enabled = COALESCE(?, auto_done_enabled), updated_at = ? WHERE id = ?")
         .bind(&display_name)
+        .bind(&timezone)
+        .bind(&home_currency)
+        .bind(payload.auto_done_enabled.map(|enabled| enabled as i32))
         .bind(&now)
         .bind(user.user_id as i64)
         .execute(&pool)
@@ -3384,7 +3958,28 @@ async fn update_profile(
             error: "名前の更新に失敗しました".to_string(),
         })))?;
 
-    Ok(Json(serde_json::json!({ "success": true, "display_name": display_name })))
+    if home_currency.is_some() {
+        recalculate_user_rollups(&pool, user.user_id as i64).await.map_err(|e| {
+            eprintln!("[UpdateProfile] Failed to recalculate rollups: {}", e);
+            database_error()
+        })?;
+    }
+
+    let (timezone, home_currency, auto_done_enabled): (String, String, i32) = sqlx::query_as("SELECT timezone, home_currency, auto_done_enabled FROM users WHERE id = ?")
+        .bind(user.user_id as i64)
+        .fetch_one(&pool)
+        .await
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse {
+            error: "プロフィールの取得に失敗しました".to_string(),
+        })))?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "display_name": display_name,
+        "timezone": timezone,
+        "home_currency": home_currency,
+        "auto_done_enabled": auto_done_enabled != 0,
+    })))
 }
 
 // PUT /auth/profile-avatar - プロフィール画像更新（nullで削除）
@@ -3474,6 +4069,43 @@ async fn delete_account(
         .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
     sqlx::query("DELETE FROM notifications WHERE user_id = ?")
         .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM ticket_applications WHERE schedule_id IN (SELECT id FROM schedules WHERE user_id = ?)")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM ticket_transfers WHERE schedule_id IN (SELECT id FROM schedules WHERE user_id = ?)")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM goods WHERE schedule_id IN (SELECT id FROM schedules WHERE user_id = ?)")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM expenses WHERE schedule_id IN (SELECT id FROM schedules WHERE user_id = ?)")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM schedule_days WHERE schedule_id IN (SELECT id FROM schedules WHERE user_id = ?)")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM schedule_tickets WHERE schedule_id IN (SELECT id FROM schedules WHERE user_id = ?)")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM setlist_entries WHERE schedule_id IN (SELECT id FROM schedules WHERE user_id = ?)")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM search_index WHERE user_id = ?")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM revisions WHERE schedule_id IN (SELECT id FROM schedules WHERE user_id = ?)")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM schedule_status_history WHERE user_id = ?")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM cost_split_members WHERE split_id IN (SELECT id FROM cost_splits WHERE user_id = ?)")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM cost_splits WHERE user_id = ?")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM companions WHERE user_id = ?")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM exchange_rates WHERE user_id = ?")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM budgets WHERE user_id = ?")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM year_reviews WHERE user_id = ?")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    // 他のユーザーの同行者として紐づけられている場合は、紐づけだけを外す（同行者の名前は残す）
+    sqlx::query("UPDATE companions SET linked_user_id = NULL WHERE linked_user_id = ?")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    let attachment_keys: Vec<String> = sqlx::query_scalar("DELETE FROM attachments WHERE user_id = ? RETURNING storage_key")
+        .bind(user.user_id as i64).fetch_all(&mut *tx).await.map_err(|_| delete_failed())?;
     sqlx::query("DELETE FROM masked_locations WHERE user_id = ?")
         .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
     sqlx::query("DELETE FROM select_options WHERE user_id = ?")
@@ -3484,11 +4116,16 @@ async fn delete_account(
         .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
     sqlx::query("DELETE FROM schedules WHERE user_id = ?")
         .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
+    sqlx::query("DELETE FROM tours WHERE user_id = ?")
+        .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
     sqlx::query("DELETE FROM users WHERE id = ?")
         .bind(user.user_id as i64).execute(&mut *tx).await.map_err(|_| delete_failed())?;
 
     tx.commit().await.map_err(|_| delete_failed())?;
 
+    // ファイル本体は行の削除が確定してから消す
+    remove_attachment_files(&attachment_keys).await;
+
     Ok(Json(serde_json::json!({ "success": true })))
 }
 
@@ -3861,11 +4498,18 @@ async fn delete_masked_location(
     }
 }
 
-// GET /share/:share_id - 共有ページ用のスケジュール一覧取得
+// GET /share/:share_id - 共有ページ用のスケジュール一覧取得（/schedulesと同じ絞り込み・並び替え・ページングに対応）
 async fn get_shared_schedules(
     Path(share_id): Path<String>,
+    Query(mut params): Query<ScheduleQuery>,
     Extension(pool): Extension<Pool<Sqlite>>,
-) -> Result<Json<Vec<PublicSchedule>>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<(HeaderMap, Json<Vec<PublicSchedule>>), (StatusCode, Json<ErrorResponse>)> {
+    // 共有ページは従来キャンセル分も含めて返していたため、指定がなければ含める
+    if params.include_canceled.is_none() {
+        params.include_canceled = Some(true);
+    }
+    let spec = validate_schedule_query(&params, false)?;
+
     // share_idからユーザーIDを取得
     let user_row: Option<(i64, i32, String, Option<String>)> = sqlx::query_as(
         "SELECT id, sharing_enabled, plan, trial_started_at FROM users WHERE share_id = ?"
@@ -3895,7 +4539,7 @@ async fn get_shared_schedules(
 
         // 共有されているスケジュールを取得（is_public = 1 のみ）
         eprintln!("[GetSharedSchedules] Fetching schedules for user_id: {}, checking is_public = 1", user_id);
-        let rows: Vec<PublicScheduleRow> = sqlx::query_as::<_, PublicScheduleRow>(
+        let mut qb = QueryBuilder::<Sqlite>::new(
             r#"
             SELECT
               id,
@@ -3906,6 +4550,10 @@ async fn get_shared_schedules(
               open,
               start,
               "end",
+              end_date,
+              timezone,
+              (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+              (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
               notes,
               category,
               area,
@@ -3915,37 +4563,47 @@ async fn get_shared_schedules(
               seller,
               ticket_fee,
               drink_fee,
+              ticket_fee_currency,
+              drink_fee_currency,
+              ticket_fee_effective,
+              drink_fee_effective,
               total_fare,
               stay_fee,
               travel_cost,
+              other_cost,
               total_cost,
               status,
               related_schedule_ids,
               CAST(is_public AS INTEGER) as is_public
             FROM schedules
-            WHERE user_id = ? AND CAST(is_public AS INTEGER) = 1
-            ORDER BY date ASC, start ASC
-            "#
-        )
-        .bind(user_id)
-        .fetch_all(&pool)
-        .await
-        .map_err(|e| {
-            eprintln!("[GetSharedSchedules] Failed to fetch schedules: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Database error".to_string(),
-                }),
-            )
-        })?;
+            WHERE CAST(is_public AS INTEGER) = 1 AND user_id = "#,
+        );
+        qb.push_bind(user_id);
+        // 共有は有料プランのみのため、無料プランの過去アーカイブ制限はかからない
+        push_schedule_list_filters(&mut qb, &params, &spec, None);
+        push_schedule_list_order(&mut qb, &spec, "public_id");
+
+        let mut rows: Vec<PublicScheduleRow> = qb
+            .build_query_as::<PublicScheduleRow>()
+            .fetch_all(&pool)
+            .await
+            .map_err(|e| {
+                eprintln!("[GetSharedSchedules] Failed to fetch schedules: {}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse {
+                        error: "Database error".to_string(),
+                    }),
+                )
+            })?;
 
         eprintln!("[GetSharedSchedules] Found {} schedules with is_public = 1", rows.len());
+        let headers = paginate_schedule_rows(&mut rows, &spec, |row| public_schedule_cursor(&spec, row));
         let mut schedules = Vec::with_capacity(rows.len());
         for row in rows {
             schedules.push(row_to_public_schedule(&pool, row).await);
         }
-        Ok(Json(schedules))
+        Ok((headers, Json(schedules)))
     } else {
         Err((
             StatusCode::NOT_FOUND,
@@ -4000,6 +4658,10 @@ async fn get_shared_schedule(
               open,
               start,
               "end",
+              end_date,
+              timezone,
+              (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+              (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
               notes,
               category,
               area,
@@ -4009,15 +4671,20 @@ async fn get_shared_schedule(
               seller,
               ticket_fee,
               drink_fee,
+              ticket_fee_currency,
+              drink_fee_currency,
+              ticket_fee_effective,
+              drink_fee_effective,
               total_fare,
               stay_fee,
               travel_cost,
+              other_cost,
               total_cost,
               status,
               related_schedule_ids,
               CAST(is_public AS INTEGER) as is_public
             FROM schedules
-            WHERE public_id = ? AND user_id = ? AND CAST(is_public AS INTEGER) = 1
+            WHERE public_id = ? AND user_id = ? AND CAST(is_public AS INTEGER) = 1 AND deleted_at IS NULL
             "#
         )
         .bind(public_id)
@@ -4035,7 +4702,14 @@ async fn get_shared_schedule(
         })?;
 
         if let Some(schedule_row) = row {
-            Ok(Json(row_to_public_schedule(&pool, schedule_row).await))
+            let schedule_id = schedule_row.id;
+            let mut schedule = row_to_public_schedule(&pool, schedule_row).await;
+            // セットリストは所有者が公開を選んだ場合のみ含める
+            schedule.setlist = fetch_public_setlist(&pool, schedule_id).await.map_err(|e| {
+                eprintln!("[GetSharedSchedule] Failed to fetch setlist: {}", e);
+                database_error()
+            })?;
+            Ok(Json(schedule))
         } else {
             Err((
                 StatusCode::NOT_FOUND,
@@ -4054,412 +4728,1201 @@ async fn get_shared_schedule(
     }
 }
 
-// GET /schedules?year=2025 など
-async fn list_schedules(
-    Query(params): Query<ScheduleQuery>,
-    Extension(pool): Extension<Pool<Sqlite>>,
-    user: AuthenticatedUser,
-) -> Result<Json<Vec<Schedule>>, (StatusCode, Json<ErrorResponse>)> {
-    eprintln!("[ListSchedules] User ID: {}", user.user_id);
-    let rows: Vec<ScheduleRow> = sqlx::query_as::<_, ScheduleRow>(
-        r#"
-        SELECT
-          id,
-          title,
-          "group",
-          date,
-          open,
-          start,
-          "end",
-          notes,
-          category,
-          area,
-          venue,
-          target,
-          lineup,
-          seller,
-          ticket_fee,
-          drink_fee,
-          total_fare,
-          stay_fee,
-          travel_cost,
-          total_cost,
-          status,
-          related_schedule_ids,
-          user_id,
-          CAST(is_public AS INTEGER) as is_public,
-          created_at,
-          updated_at
-        FROM schedules
-        WHERE user_id = ?
-        "#,
-    )
-    .bind(user.user_id)
-    .fetch_all(&pool)
-    .await
-    .map_err(|e| {
-        eprintln!("[ListSchedules] Database error: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: format!("データベースエラーが発生しました: {}", e),
-            }),
-        )
-    })?;
+// ====== 複数日開催（フェス等）ヘルパー ======
 
-    // 各スケジュールに対してロールアップ計算を実行
-    for row in &rows {
-        calculate_rollup(&pool, row.id).await.ok();
+// 複数日開催の最大日数（入力ミスで大量の日程行が作られるのを防ぐ）
+const SCHEDULE_MAX_DAYS: i64 = 31;
+
+// end_dateを正規化する（空文字列・初日と同じ日付の場合は単日開催としてNULLにする）
+fn normalize_end_date(date: Option<&str>, end_date: Option<&str>) -> Option<String> {
+    let end_date = end_date.map(str::trim).filter(|d| !d.is_empty())?;
+    if Some(end_date) == date.map(str::trim) {
+        return None;
     }
-    
-    // 計算後のスケジュールを再取得
-    let rows: Vec<ScheduleRow> = sqlx::query_as::<_, ScheduleRow>(
+    Some(end_date.to_string())
+}
+
+// 開催期間（初日〜最終日）の日付一覧を返す。単日開催の場合は空
+fn schedule_date_range(
+    date: Option<&str>,
+    end_date: Option<&str>,
+) -> Result<Vec<chrono::NaiveDate>, &'static str> {
+    let Some(end_date) = end_date else {
+        return Ok(vec![]);
+    };
+    let first = date
+        .and_then(|d| chrono::NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d").ok())
+        .ok_or("複数日開催の場合は開始日（YYYY-MM-DD）を指定してください")?;
+    let last = chrono::NaiveDate::parse_from_str(end_date.trim(), "%Y-%m-%d")
+        .map_err(|_| "終了日はYYYY-MM-DD形式で指定してください")?;
+    if last < first {
+        return Err("終了日は開始日以降の日付を指定してください");
+    }
+    if (last - first).num_days() + 1 > SCHEDULE_MAX_DAYS {
+        return Err("複数日開催の期間は31日以内で指定してください");
+    }
+    Ok(first.iter_days().take_while(|d| *d <= last).collect())
+}
+
+// 開催期間の最終年を返す（年フィルタ・無料プランの閲覧制限で期間全体を考慮するため）
+fn schedule_end_year(start_year: i32, end_date: Option<&str>) -> i32 {
+    end_date
+        .and_then(parse_year_prefix)
+        .unwrap_or(start_year)
+        .max(start_year)
+}
+
+// 日程の入力が開催期間内に収まっているか、同じ日付が重複していないかを検証する
+fn validate_schedule_days(
+    range: &[chrono::NaiveDate],
+    days: &[NewScheduleDay],
+) -> Result<(), &'static str> {
+    if range.is_empty() && !days.is_empty() {
+        return Err("日程は複数日開催（終了日を指定）の場合のみ登録できます");
+    }
+    let mut seen = std::collections::HashSet::new();
+    for day in days {
+        let date = chrono::NaiveDate::parse_from_str(day.date.trim(), "%Y-%m-%d")
+            .map_err(|_| "日程の日付はYYYY-MM-DD形式で指定してください")?;
+        if !range.contains(&date) {
+            return Err("日程の日付は開催期間内で指定してください");
+        }
+        if !seen.insert(date) {
+            return Err("同じ日付の日程が重複しています");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod schedule_range_tests {
+    use super::{normalize_end_date, schedule_date_range, schedule_end_year};
+
+    #[test]
+    fn single_day_has_no_range() {
+        assert_eq!(normalize_end_date(Some("2025-08-01"), Some("2025-08-01")), None);
+        assert_eq!(normalize_end_date(Some("2025-08-01"), Some(" ")), None);
+        assert_eq!(schedule_date_range(Some("2025-08-01"), None), Ok(vec![]));
+    }
+
+    #[test]
+    fn expands_range_across_year_boundary() {
+        let range = schedule_date_range(Some("2025-12-30"), Some("2026-01-01")).unwrap();
+        let dates: Vec<String> = range.iter().map(|d| d.to_string()).collect();
+        assert_eq!(dates, vec!["2025-12-30", "2025-12-31", "2026-01-01"]);
+        assert_eq!(schedule_end_year(2025, Some("2026-01-01")), 2026);
+    }
+
+    #[test]
+    fn rejects_reversed_or_too_long_range() {
+        assert!(schedule_date_range(Some("2025-08-03"), Some("2025-08-01")).is_err());
+        assert!(schedule_date_range(Some("2025-08-01"), Some("2025-09-15")).is_err());
+        assert!(schedule_date_range(None, Some("2025-08-01")).is_err());
+    }
+}
+
+// 指定したスケジュールの日程を日付順に取得する
+async fn fetch_schedule_day_rows(
+    pool: &Pool<Sqlite>,
+    schedule_ids: &[i64],
+) -> Result<Vec<ScheduleDayRow>, sqlx::Error> {
+    if schedule_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let placeholders = vec!["?"; schedule_ids.len()].join(", ");
+    let sql = format!(
         r#"
-        SELECT
-          id,
-          title,
-          "group",
-          date,
-          open,
-          start,
-          "end",
-          notes,
-          category,
-          area,
-          venue,
-          target,
-          lineup,
-          seller,
-          ticket_fee,
-          drink_fee,
-          total_fare,
-          stay_fee,
-          travel_cost,
-          total_cost,
-          status,
-          related_schedule_ids,
-          user_id,
-          CAST(is_public AS INTEGER) as is_public,
-          created_at,
-          updated_at
-        FROM schedules
-        WHERE user_id = ?
+        SELECT id, schedule_id, date, open, start, "end", lineup, attended, ticket_fee, drink_fee
+        FROM schedule_days
+        WHERE schedule_id IN ({})
+        ORDER BY schedule_id ASC, date ASC
         "#,
-    )
-    .bind(user.user_id)
-    .fetch_all(&pool)
-    .await
-    .map_err(|e| {
-        eprintln!("[ListSchedules] Database error on second fetch: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: format!("データベースエラーが発生しました: {}", e),
-            }),
-        )
-    })?;
-
-    let mut schedules: Vec<Schedule> = rows.into_iter().map(row_to_schedule).collect();
+        placeholders
+    );
+    let mut query = sqlx::query_as::<_, ScheduleDayRow>(&sql);
+    for id in schedule_ids {
+        query = query.bind(id);
+    }
+    query.fetch_all(pool).await
+}
 
-    if let Some(year) = params.year {
-        schedules = schedules
-            .into_iter()
-            .filter(|s| s.datetime.year() == year)
-            .collect();
+// スケジュール一覧に日程（schedule_days）を埋め込む
+async fn attach_schedule_days(pool: &Pool<Sqlite>, schedules: &mut [Schedule]) -> Result<(), sqlx::Error> {
+    let ids: Vec<i64> = schedules
+        .iter()
+        .filter(|s| s.end_date.is_some())
+        .map(|s| s.id as i64)
+        .collect();
+    let mut days_by_schedule: std::collections::HashMap<i64, Vec<ScheduleDay>> = std::collections::HashMap::new();
+    for row in fetch_schedule_day_rows(pool, &ids).await? {
+        days_by_schedule
+            .entry(row.schedule_id)
+            .or_default()
+            .push(row_to_schedule_day(row));
     }
+    for schedule in schedules.iter_mut() {
+        schedule.days = days_by_schedule.remove(&(schedule.id as i64)).unwrap_or_default();
+    }
+    Ok(())
+}
 
-    if params.include_canceled != Some(true) {
-        schedules = schedules
-            .into_iter()
-            .filter(|s| s.status != "Canceled")
-            .collect();
+// 開催期間に合わせて日程を保存する
+// daysが指定された場合はその内容で置き換え、省略された場合は期間外の日程を削除し、
+// 不足している日付をスケジュール本体の開場・開演・終演時刻（open・start・end）で補う
+async fn sync_schedule_days(
+    pool: &Pool<Sqlite>,
+    schedule_id: i64,
+    range: &[chrono::NaiveDate],
+    days: Option<&[NewScheduleDay]>,
+    open: Option<&str>,
+    start: Option<&str>,
+    end: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+
+    if let Some(days) = days {
+        sqlx::query("DELETE FROM schedule_days WHERE schedule_id = ?")
+            .bind(schedule_id)
+            .execute(&mut *tx)
+            .await?;
+        for day in days {
+            sqlx::query(
+                r#"
+                INSERT INTO schedule_days (schedule_id, date, open, start, "end", lineup, attended, ticket_fee, drink_fee, created_at, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(schedule_id)
+            .bind(day.date.trim())
+            .bind(&day.open)
+            .bind(&day.start)
+            .bind(&day.end)
+            .bind(&day.lineup)
+            .bind(day.attended.unwrap_or(true) as i32)
+            .bind(day.ticket_fee)
+            .bind(day.drink_fee)
+            .bind(&now)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+    } else {
+        let range_strs: Vec<String> = range.iter().map(|d| d.format("%Y-%m-%d").to_string()).collect();
+        let existing: Vec<String> = sqlx::query_scalar("SELECT date FROM schedule_days WHERE schedule_id = ?")
+            .bind(schedule_id)
+            .fetch_all(&mut *tx)
+            .await?;
+        for date in existing.iter().filter(|d| !range_strs.contains(d)) {
+            sqlx::query("DELETE FROM schedule_days WHERE schedule_id = ? AND date = ?")
+                .bind(schedule_id)
+                .bind(date)
+                .execute(&mut *tx)
+                .await?;
+        }
+        for date in range_strs.iter().filter(|d| !existing.contains(d)) {
+            sqlx::query(
+                r#"
+                INSERT INTO schedule_days (schedule_id, date, open, start, "end", lineup, attended, ticket_fee, drink_fee, created_at, updated_at)
+                VALUES (?, ?, ?, ?, ?, NULL, 1, NULL, NULL, ?, ?)
+                "#,
+            )
+            .bind(schedule_id)
+            .bind(date)
+            .bind(open)
+            .bind(start)
+            .bind(end)
+            .bind(&now)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
     }
 
-    let (plan, trial_started_at) = fetch_user_plan(&pool, user.user_id).await.map_err(|e| {
-        eprintln!("[ListSchedules] Failed to fetch plan: {}", e);
+    tx.commit().await
+}
+
+// 複数日開催の場合、最終日の終演（未入力の場合はその日の終わり）の日時を返す
+// 単日開催の場合はNone（従来どおりdatetimeのみで判定する）
+fn schedule_range_end(schedule: &Schedule, user_timezone: Tz) -> Option<DateTime<FixedOffset>> {
+    let end_date = schedule.end_date.as_deref()?;
+    let last_day_end = schedule
+        .days
+        .iter()
+        .find(|d| d.date == end_date)
+        .and_then(|d| d.end.as_deref())
+        .unwrap_or("23:59");
+    let tz = resolve_timezone(schedule.timezone.as_deref(), Some(user_timezone.name()));
+    let date = chrono::NaiveDate::parse_from_str(end_date, "%Y-%m-%d").ok()?;
+    let time = chrono::NaiveTime::parse_from_str(last_day_end, "%H:%M").ok()?;
+    Some(localize_datetime(date.and_time(time), tz))
+}
+
+// スケジュールの入力から開催期間を検証し、日付一覧を返す（create/update共通）
+fn validate_schedule_range(
+    payload: &NewSchedule,
+    end_date: Option<&str>,
+) -> Result<Vec<chrono::NaiveDate>, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |message: &str| {
         (
-            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::BAD_REQUEST,
             Json(ErrorResponse {
-                error: "Database error".to_string(),
+                error: message.to_string(),
             }),
         )
-    })?;
-    if !has_paid_access(&plan, trial_started_at.as_deref()) {
-        // 無料プランは過去アーカイブを直近2年分（今年+去年）のみ閲覧可能。未来の予定は制限しない
-        let earliest_visible_year = free_plan_earliest_archive_year(Utc::now());
-        schedules = schedules
-            .into_iter()
-            .filter(|s| s.datetime.year() >= earliest_visible_year)
-            .collect();
+    };
+    let range = schedule_date_range(payload.date.as_deref(), end_date).map_err(bad_request)?;
+    if let Some(days) = &payload.days {
+        validate_schedule_days(&range, days).map_err(bad_request)?;
     }
+    Ok(range)
+}
 
-    eprintln!("[ListSchedules] Returning {} schedules for user_id: {}", schedules.len(), user.user_id);
-    Ok(Json(schedules))
+// ====== チケット・座席情報 ======
+// 座席・整理番号・ゲートなど、これまでnotesに書かれていた情報をチケット単位で持つ。
+// 複数枚まとめて購入した場合に備えてスケジュールの子テーブル（schedule_tickets）に保存し、
+// 公開・共有ページでは所有者がseat_publicを有効にしない限り伏せ字にする
+
+// 1スケジュールあたりのチケットの最大枚数
+const SCHEDULE_MAX_TICKETS: usize = 20;
+// 各項目の最大文字数
+const TICKET_FIELD_MAX_CHARS: usize = 30;
+const TICKET_TYPES: &[&str] = &["reserved", "standing", "unreserved", "other"];
+
+// 保存用に正規化したチケット
+#[derive(Debug, PartialEq)]
+struct ScheduleTicketInput {
+    ticket_type: Option<String>,
+    seat_block: Option<String>,
+    seat_row: Option<String>,
+    seat_number: Option<String>,
+    entry_number: Option<String>,
+    gate: Option<String>,
 }
 
-// GET /schedules/upcoming
-async fn list_upcoming(
-    Extension(pool): Extension<Pool<Sqlite>>,
-    user: AuthenticatedUser,
-) -> Json<Vec<Schedule>> {
-    let rows: Vec<ScheduleRow> = sqlx::query_as::<_, ScheduleRow>(
-        r#"
-        SELECT
-          id,
-          title,
-          "group",
-          date,
-          open,
-          start,
-          "end",
-          notes,
-          category,
-          area,
-          venue,
-          target,
-          lineup,
-          seller,
-          ticket_fee,
-          drink_fee,
-          total_fare,
-          stay_fee,
-          travel_cost,
-          total_cost,
-          status,
-          related_schedule_ids,
-          user_id,
-          CAST(is_public AS INTEGER) as is_public,
-          created_at,
-          updated_at
-        FROM schedules
-        WHERE status != 'Canceled' AND user_id = ?
-        "#,
-    )
-    .bind(user.user_id)
-    .fetch_all(&pool)
-    .await
-    .expect("failed to fetch schedules");
+// 全角英数字を半角にそろえ、前後の空白を除く（空の場合はNone）
+fn normalize_ticket_text(value: Option<&str>) -> Result<Option<String>, &'static str> {
+    use unicode_normalization::UnicodeNormalization;
+    let Some(value) = value else { return Ok(None) };
+    let normalized: String = value.nfkc().collect();
+    let normalized = normalized.trim();
+    if normalized.is_empty() {
+        return Ok(None);
+    }
+    if normalized.chars().count() > TICKET_FIELD_MAX_CHARS {
+        return Err("座席・整理番号・ゲートは30文字以内で入力してください");
+    }
+    Ok(Some(normalized.to_string()))
+}
 
-    // 各スケジュールに対してロールアップ計算を実行
-    for row in &rows {
-        calculate_rollup(&pool, row.id).await.ok();
+// 整理番号を「英字3文字までの接頭辞+数字6桁まで」に正規化する（例: ａ１２３ → A123、A-012 → A012）
+fn normalize_entry_number(value: Option<&str>) -> Result<Option<String>, &'static str> {
+    let Some(value) = normalize_ticket_text(value)? else { return Ok(None) };
+    let compact: String = value
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_uppercase();
+    let digits_at = compact.find(|c: char| c.is_ascii_digit()).unwrap_or(compact.len());
+    let (prefix, digits) = compact.split_at(digits_at);
+    if prefix.len() > 3
+        || !prefix.chars().all(|c| c.is_ascii_uppercase())
+        || digits.is_empty()
+        || digits.len() > 6
+        || !digits.chars().all(|c| c.is_ascii_digit())
+    {
+        return Err("整理番号は英字（3文字まで）と数字（6桁まで）で入力してください（例: A123）");
     }
-    
-    // 計算後のスケジュールを再取得
-    let rows: Vec<ScheduleRow> = sqlx::query_as::<_, ScheduleRow>(
+    Ok(Some(compact))
+}
+
+fn normalize_schedule_ticket(ticket: &NewScheduleTicket) -> Result<ScheduleTicketInput, &'static str> {
+    let ticket_type = match ticket.ticket_type.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        Some(t) if TICKET_TYPES.contains(&t) => Some(t.to_string()),
+        Some(_) => return Err("チケット種別はreserved・standing・unreserved・otherのいずれかを指定してください"),
+        None => None,
+    };
+    let input = ScheduleTicketInput {
+        ticket_type,
+        seat_block: normalize_ticket_text(ticket.seat_block.as_deref())?,
+        seat_row: normalize_ticket_text(ticket.seat_row.as_deref())?,
+        seat_number: normalize_ticket_text(ticket.seat_number.as_deref())?,
+        entry_number: normalize_entry_number(ticket.entry_number.as_deref())?,
+        gate: normalize_ticket_text(ticket.gate.as_deref())?,
+    };
+    if input == (ScheduleTicketInput {
+        ticket_type: None,
+        seat_block: None,
+        seat_row: None,
+        seat_number: None,
+        entry_number: None,
+        gate: None,
+    }) {
+        return Err("チケットには種別・座席・整理番号・ゲートのいずれかを入力してください");
+    }
+    Ok(input)
+}
+
+// スケジュールの入力からチケットを検証・正規化する（create/update共通、省略時はNone）
+fn validate_schedule_tickets(
+    tickets: Option<&[NewScheduleTicket]>,
+) -> Result<Option<Vec<ScheduleTicketInput>>, (StatusCode, Json<ErrorResponse>)> {
+    let Some(tickets) = tickets else { return Ok(None) };
+    let bad_request = |message: &str| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: message.to_string(),
+            }),
+        )
+    };
+    if tickets.len() > SCHEDULE_MAX_TICKETS {
+        return Err(bad_request("チケットは1つのスケジュールにつき20枚まで登録できます"));
+    }
+    let tickets = tickets
+        .iter()
+        .map(normalize_schedule_ticket)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(bad_request)?;
+    Ok(Some(tickets))
+}
+
+// 整理番号順の並び替えキー（接頭辞のアルファベット順→番号順、整理番号のないチケットは最後）
+fn entry_number_sort_key(entry_number: Option<&str>) -> (bool, String, u32) {
+    let Some(entry_number) = entry_number else {
+        return (true, String::new(), 0);
+    };
+    let digits_at = entry_number.find(|c: char| c.is_ascii_digit()).unwrap_or(entry_number.len());
+    let (prefix, digits) = entry_number.split_at(digits_at);
+    (false, prefix.to_string(), digits.parse().unwrap_or(u32::MAX))
+}
+
+#[cfg(test)]
+mod schedule_ticket_tests {
+    use super::*;
+
+    fn ticket(ticket_type: Option<&str>, entry_number: Option<&str>) -> NewScheduleTicket {
+        NewScheduleTicket {
+            ticket_type: ticket_type.map(str::to_string),
+            seat_block: None,
+            seat_row: None,
+            seat_number: None,
+            entry_number: entry_number.map(str::to_string),
+            gate: None,
+        }
+    }
+
+    #[test]
+    fn entry_number_is_normalized() {
+        assert_eq!(normalize_entry_number(Some("ａ１２３")), Ok(Some("A123".to_string())));
+        assert_eq!(normalize_entry_number(Some(" b-012 ")), Ok(Some("B012".to_string())));
+        assert_eq!(normalize_entry_number(Some("456")), Ok(Some("456".to_string())));
+        assert_eq!(normalize_entry_number(Some("  ")), Ok(None));
+        assert!(normalize_entry_number(Some("ABCD1")).is_err());
+        assert!(normalize_entry_number(Some("A")).is_err());
+        assert!(normalize_entry_number(Some("A12B")).is_err());
+        assert!(normalize_entry_number(Some("1234567")).is_err());
+    }
+
+    #[test]
+    fn ticket_requires_known_type_and_some_value() {
+        assert!(normalize_schedule_ticket(&ticket(Some("reserved"), None)).is_ok());
+        assert!(normalize_schedule_ticket(&ticket(Some("vip"), None)).is_err());
+        assert!(normalize_schedule_ticket(&ticket(None, None)).is_err());
+        assert!(normalize_schedule_ticket(&ticket(Some(" "), Some(" "))).is_err());
+    }
+
+    #[test]
+    fn sorts_by_prefix_then_number_with_missing_last() {
+        let mut numbers = vec![None, Some("B2"), Some("A100"), Some("A20"), Some("15")];
+        numbers.sort_by_key(|n| entry_number_sort_key(*n));
+        assert_eq!(numbers, vec![Some("15"), Some("A20"), Some("A100"), Some("B2"), None]);
+    }
+}
+
+fn row_to_schedule_ticket(row: ScheduleTicketRow) -> ScheduleTicket {
+    ScheduleTicket {
+        id: row.id as i32,
+        ticket_type: row.ticket_type,
+        seat_block: row.seat_block,
+        seat_row: row.seat_row,
+        seat_number: row.seat_number,
+        entry_number: row.entry_number,
+        gate: row.gate,
+    }
+}
+
+// 公開・共有用のチケット。所有者が座席の公開を選んでいない場合は種別以外を伏せ字にする
+fn row_to_public_schedule_ticket(row: ScheduleTicketRow, seat_public: bool) -> PublicScheduleTicket {
+    let mask = |value: Option<String>| if seat_public { value } else { value.map(|_| "***".to_string()) };
+    PublicScheduleTicket {
+        ticket_type: row.ticket_type,
+        seat_block: mask(row.seat_block),
+        seat_row: mask(row.seat_row),
+        seat_number: mask(row.seat_number),
+        entry_number: mask(row.entry_number),
+        gate: mask(row.gate),
+    }
+}
+
+// 指定したスケジュールのチケットを、スケジュールごとに整理番号順で取得する
+async fn fetch_schedule_ticket_rows(
+    pool: &Pool<Sqlite>,
+    schedule_ids: &[i64],
+) -> Result<Vec<ScheduleTicketRow>, sqlx::Error> {
+    if schedule_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let placeholders = vec!["?"; schedule_ids.len()].join(", ");
+    let sql = format!(
         r#"
-        SELECT
-          id,
-          title,
-          "group",
-          date,
-          open,
-          start,
-          "end",
-          notes,
-          category,
-          area,
-          venue,
-          target,
-          lineup,
-          seller,
-          ticket_fee,
-          drink_fee,
-          total_fare,
-          stay_fee,
-          travel_cost,
-          total_cost,
-          status,
-          related_schedule_ids,
-          user_id,
-          CAST(is_public AS INTEGER) as is_public,
-          created_at,
-          updated_at
-        FROM schedules
-        WHERE status != 'Canceled' AND user_id = ?
+        SELECT id, schedule_id, position, ticket_type, seat_block, seat_row, seat_number, entry_number, gate
+        FROM schedule_tickets
+        WHERE schedule_id IN ({})
+        ORDER BY schedule_id ASC, position ASC
         "#,
-    )
-    .bind(user.user_id)
-    .fetch_all(&pool)
-    .await
-    .expect("failed to fetch schedules");
+        placeholders
+    );
+    let mut query = sqlx::query_as::<_, ScheduleTicketRow>(&sql);
+    for id in schedule_ids {
+        query = query.bind(id);
+    }
+    let mut rows = query.fetch_all(pool).await?;
+    rows.sort_by(|a, b| {
+        a.schedule_id
+            .cmp(&b.schedule_id)
+            .then_with(|| {
+                entry_number_sort_key(a.entry_number.as_deref()).cmp(&entry_number_sort_key(b.entry_number.as_deref()))
+            })
+            .then_with(|| a.position.cmp(&b.position))
+    });
+    Ok(rows)
+}
 
-    let mut schedules: Vec<Schedule> = rows.into_iter().map(row_to_schedule).collect();
+// スケジュール一覧にチケットと座席の公開設定を埋め込む
+async fn attach_schedule_tickets(pool: &Pool<Sqlite>, schedules: &mut [Schedule]) -> Result<(), sqlx::Error> {
+    let ids: Vec<i64> = schedules.iter().map(|s| s.id as i64).collect();
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let mut tickets_by_schedule: std::collections::HashMap<i64, Vec<ScheduleTicket>> = std::collections::HashMap::new();
+    for row in fetch_schedule_ticket_rows(pool, &ids).await? {
+        tickets_by_schedule
+            .entry(row.schedule_id)
+            .or_default()
+            .push(row_to_schedule_ticket(row));
+    }
 
-    let now = Utc::now();
-    schedules = schedules
+    let placeholders = vec!["?"; ids.len()].join(", ");
+    let sql = format!("SELECT id, seat_public FROM schedules WHERE id IN ({})", placeholders);
+    let mut query = sqlx::query_as::<_, (i64, i32)>(&sql);
+    for id in &ids {
+        query = query.bind(id);
+    }
+    let seat_public: std::collections::HashMap<i64, bool> = query
+        .fetch_all(pool)
+        .await?
         .into_iter()
-        .filter(|s| s.datetime > now)
+        .map(|(id, value)| (id, value != 0))
         .collect();
 
-    // 日付順にソートして直近3件に制限
-    schedules.sort_by(|a, b| a.datetime.cmp(&b.datetime));
-    schedules.truncate(3);
+    for schedule in schedules.iter_mut() {
+        let id = schedule.id as i64;
+        schedule.tickets = tickets_by_schedule.remove(&id).unwrap_or_default();
+        schedule.seat_public = seat_public.get(&id).copied().unwrap_or(false);
+    }
+    Ok(())
+}
 
-    Json(schedules)
+// 公開・共有用にチケットを取得する（座席の公開設定に応じて伏せ字にする）
+async fn fetch_public_schedule_tickets(
+    pool: &Pool<Sqlite>,
+    schedule_id: i64,
+) -> Result<Vec<PublicScheduleTicket>, sqlx::Error> {
+    let seat_public: Option<i32> = sqlx::query_scalar("SELECT seat_public FROM schedules WHERE id = ?")
+        .bind(schedule_id)
+        .fetch_optional(pool)
+        .await?;
+    let seat_public = seat_public.unwrap_or(0) != 0;
+    Ok(fetch_schedule_ticket_rows(pool, &[schedule_id])
+        .await?
+        .into_iter()
+        .map(|row| row_to_public_schedule_ticket(row, seat_public))
+        .collect())
 }
 
-// ロールアップ計算関数
-async fn calculate_rollup(
+// チケットと座席の公開設定を保存する（ticketsが指定された場合はその内容で置き換え、省略時は変更しない）
+async fn save_schedule_tickets(
     pool: &Pool<Sqlite>,
     schedule_id: i64,
+    tickets: Option<&[ScheduleTicketInput]>,
+    seat_public: Option<bool>,
 ) -> Result<(), sqlx::Error> {
-    // total_fare: 関連するtrafficsのfareの合計
-    let total_fare: Option<i32> = sqlx::query_scalar(
-        "SELECT COALESCE(SUM(fare), 0) FROM traffics WHERE schedule_id = ?"
-    )
-    .bind(schedule_id)
-    .fetch_optional(pool)
-    .await?
-    .map(|sum: i64| sum as i32);
-    
-    // stay_fee: 関連するstaysのfeeの合計
-    let stay_fee: Option<i32> = sqlx::query_scalar(
-        "SELECT COALESCE(SUM(fee), 0) FROM stays WHERE schedule_id = ?"
-    )
-    .bind(schedule_id)
-    .fetch_optional(pool)
-    .await?
-    .map(|sum: i64| sum as i32);
-    
-    // スケジュールのticket_feeとdrink_feeを取得
-    let schedule_row: Option<(Option<i32>, Option<i32>)> = sqlx::query_as(
-        "SELECT ticket_fee, drink_fee FROM schedules WHERE id = ?"
-    )
-    .bind(schedule_id)
-    .fetch_optional(pool)
-    .await?;
-    
-    let (ticket_fee, drink_fee) = schedule_row.unwrap_or((None, None));
-    
-    // travel_cost: total_fare + stay_fee
-    let travel_cost = total_fare
-        .unwrap_or(0)
-        .saturating_add(stay_fee.unwrap_or(0));
-    
-    // total_cost: ticket_fee + drink_fee + travel_cost
-    let total_cost = ticket_fee.unwrap_or(0)
-        .saturating_add(drink_fee.unwrap_or(0))
-        .saturating_add(travel_cost);
-    
-    // スケジュールを更新
     let now = Utc::now().to_rfc3339();
-    sqlx::query(
-        r#"
-        UPDATE schedules SET
-          total_fare = ?,
-          stay_fee = ?,
-          travel_cost = ?,
-          total_cost = ?,
-          updated_at = ?
-        WHERE id = ?
-        "#
-    )
-    .bind(total_fare)
-    .bind(stay_fee)
-    .bind(Some(travel_cost))
-    .bind(Some(total_cost))
-    .bind(&now)
-    .bind(schedule_id)
-    .execute(pool)
-    .await?;
-    
-    Ok(())
+    let mut tx = pool.begin().await?;
+
+    if let Some(seat_public) = seat_public {
+        sqlx::query("UPDATE schedules SET seat_public = ? WHERE id = ?")
+            .bind(seat_public as i32)
+            .bind(schedule_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    if let Some(tickets) = tickets {
+        sqlx::query("DELETE FROM schedule_tickets WHERE schedule_id = ?")
+            .bind(schedule_id)
+            .execute(&mut *tx)
+            .await?;
+        for (position, ticket) in tickets.iter().enumerate() {
+            sqlx::query(
+                r#"
+                INSERT INTO schedule_tickets (schedule_id, position, ticket_type, seat_block, seat_row, seat_number, entry_number, gate, created_at, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(schedule_id)
+            .bind(position as i64 + 1)
+            .bind(&ticket.ticket_type)
+            .bind(&ticket.seat_block)
+            .bind(&ticket.seat_row)
+            .bind(&ticket.seat_number)
+            .bind(&ticket.entry_number)
+            .bind(&ticket.gate)
+            .bind(&now)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
+
+    tx.commit().await
 }
 
-// POST /schedules
-async fn create_schedule(
+#[derive(Debug, Deserialize)]
+struct DayTicketsQuery {
+    date: Option<String>, // YYYY-MM-DD（省略時はユーザーのタイムゾーンでの今日）
+}
+
+// 当日ビュー用のチケット（スケジュールの開場・開演時刻つき）
+#[derive(Serialize)]
+struct DayTicket {
+    schedule_id: i32,
+    title: String,
+    venue: String,
+    open: Option<String>,
+    start: Option<String>,
+    #[serde(flatten)]
+    ticket: ScheduleTicket,
+}
+
+#[derive(sqlx::FromRow)]
+struct DayTicketScheduleRow {
+    id: i64,
+    title: String,
+    venue: String,
+    open: Option<String>,
+    start: Option<String>,
+}
+
+// GET /tickets?date=YYYY-MM-DD - 当日ビュー。その日に開催されるスケジュールのチケットを
+// 開場・開演の早い順、同じスケジュール内は整理番号順に返す
+async fn list_day_tickets(
+    Query(params): Query<DayTicketsQuery>,
     user: AuthenticatedUser,
     Extension(pool): Extension<Pool<Sqlite>>,
-    Json(payload): Json<NewSchedule>,
-) -> Result<(StatusCode, Json<Schedule>), (StatusCode, Json<ErrorResponse>)> {
-    // 必須項目のバリデーション（targetはNULL許可）
-    let now = Utc::now().to_rfc3339();
-    let is_public = payload.is_public.unwrap_or(true) as i32;
-    eprintln!("[CreateSchedule] is_public value: {} (from payload: {:?})", is_public, payload.is_public);
-    let result = sqlx::query(
+) -> Result<Json<Vec<DayTicket>>, (StatusCode, Json<ErrorResponse>)> {
+    let date = match params.date.as_deref().map(str::trim).filter(|d| !d.is_empty()) {
+        Some(date) => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: "dateはYYYY-MM-DD形式で指定してください".to_string(),
+                }),
+            )
+        })?,
+        None => {
+            let tz = fetch_user_timezone(&pool, user.user_id).await.map_err(|e| {
+                eprintln!("[ListDayTickets] Failed to fetch user timezone: {}", e);
+                database_error()
+            })?;
+            Utc::now().with_timezone(&tz).date_naive()
+        }
+    };
+    let date = date.format("%Y-%m-%d").to_string();
+
+    // 複数日開催の場合は、参加しない日（attended = 0）を除いて開催期間に含まれる日を対象にする
+    let schedules: Vec<DayTicketScheduleRow> = sqlx::query_as::<_, DayTicketScheduleRow>(
         r#"
-        INSERT INTO schedules (
-          user_id,
-          title,
-          "group",
-          date,
-          open,
-          start,
-          "end",
-          notes,
-          category,
-          area,
-          venue,
-          target,
-          lineup,
-          seller,
-          ticket_fee,
-          drink_fee,
-          total_fare,
-          stay_fee,
-          travel_cost,
-          total_cost,
-          status,
-          related_schedule_ids,
-          is_public,
-          public_id,
-          created_at,
-          updated_at
-        ) VALUES (
-          ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, NULL, NULL, NULL, NULL, ?, ?, ?, ?, ?, ?
-        )
+        SELECT s.id, s.title, s.venue,
+               COALESCE(d.open, s.open) AS open,
+               COALESCE(d.start, s.start) AS start
+        FROM schedules s
+        LEFT JOIN schedule_days d ON d.schedule_id = s.id AND d.date = ?
+        WHERE s.user_id = ? AND s.deleted_at IS NULL AND s.status != 'Canceled'
+          AND (s.date = ? OR (s.end_date IS NOT NULL AND s.date <= ? AND s.end_date >= ? AND COALESCE(d.attended, 1) = 1))
+        ORDER BY COALESCE(d.open, s.open, d.start, s.start, '') ASC, s.id ASC
         "#,
     )
-    .bind(user.user_id)
-    .bind(&payload.title)
-    .bind(&payload.group.as_ref().and_then(|g| {
-        let trimmed = g.trim();
-        if trimmed.is_empty() { None } else { Some(trimmed.to_string()) }
-    }))
-    .bind(&payload.date)
-    .bind(&payload.open)
-    .bind(&payload.start)
-    .bind(&payload.end)
-    .bind(&payload.notes)
-    .bind(&payload.category)
-    .bind(&payload.area)
-    .bind(&payload.venue)
-    .bind(&payload.target)
-    .bind(&payload.lineup)
-    .bind(&payload.seller)
-    .bind(payload.ticket_fee)
-    .bind(payload.drink_fee)
-    .bind(payload.status.as_deref().unwrap_or("Pending"))
-    .bind(&payload.related_schedule_ids.as_ref().and_then(|ids| {
-        if ids.is_empty() {
-            None
-        } else {
-            serde_json::to_string(ids).ok()
-        }
-    }))
-    .bind(is_public)
-    .bind(generate_public_id())
-    .bind(&now)
-    .bind(&now)
-    .execute(&pool)
+    .bind(&date)
+    .bind(user.user_id as i64)
+    .bind(&date)
+    .bind(&date)
+    .bind(&date)
+    .fetch_all(&pool)
     .await
-    .map_err(|_| {
+    .map_err(|e| {
+        eprintln!("[ListDayTickets] Failed to fetch schedules: {}", e);
+        database_error()
+    })?;
+
+    let ids: Vec<i64> = schedules.iter().map(|s| s.id).collect();
+    let mut tickets_by_schedule: std::collections::HashMap<i64, Vec<ScheduleTicketRow>> = std::collections::HashMap::new();
+    for row in fetch_schedule_ticket_rows(&pool, &ids).await.map_err(|e| {
+        eprintln!("[ListDayTickets] Failed to fetch tickets: {}", e);
+        database_error()
+    })? {
+        tickets_by_schedule.entry(row.schedule_id).or_default().push(row);
+    }
+
+    let mut result = Vec::new();
+    for schedule in schedules {
+        for row in tickets_by_schedule.remove(&schedule.id).unwrap_or_default() {
+            result.push(DayTicket {
+                schedule_id: schedule.id as i32,
+                title: schedule.title.clone(),
+                venue: schedule.venue.clone(),
+                open: schedule.open.clone(),
+                start: schedule.start.clone(),
+                ticket: row_to_schedule_ticket(row),
+            });
+        }
+    }
+    Ok(Json(result))
+}
+
+// ====== スケジュール一覧の絞り込み・並び替え・ページング ======
+// /schedules・/public/schedules・/share/:share_id で共通。条件はすべてSQLに組み込み、
+// limit指定時は次ページがあればレスポンスヘッダー X-Next-Cursor にカーソルを返す
+
+const SCHEDULE_LIST_MAX_LIMIT: i64 = 500;
+const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScheduleSortKey {
+    Date,
+    TotalCost,
+    Title,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl ScheduleSortKey {
+    // created_at / updated_at は公開・共有一覧では使えない（PublicScheduleRowに含まれないため）
+    fn parse(value: &str, allow_timestamps: bool) -> Option<Self> {
+        match value {
+            "date" => Some(Self::Date),
+            "total_cost" => Some(Self::TotalCost),
+            "title" => Some(Self::Title),
+            "created_at" if allow_timestamps => Some(Self::CreatedAt),
+            "updated_at" if allow_timestamps => Some(Self::UpdatedAt),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Date => "date",
+            Self::TotalCost => "total_cost",
+            Self::Title => "title",
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+        }
+    }
+
+    // 並び替えに使うSQL式。NULLは比較できないためCOALESCEで埋める
+    fn sql_expr(self) -> &'static str {
+        match self {
+            Self::Date => "(COALESCE(date, '') || ' ' || COALESCE(start, ''))",
+            Self::TotalCost => "COALESCE(total_cost, 0)",
+            Self::Title => "title",
+            Self::CreatedAt => "COALESCE(created_at, '')",
+            Self::UpdatedAt => "COALESCE(updated_at, '')",
+        }
+    }
+}
+
+// 次ページの開始位置。最後の行の並び替え値と、同値の場合の順序を決めるキー（所有者一覧はid、公開一覧はpublic_id）
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct ScheduleCursor {
+    sort: String,
+    value: serde_json::Value,
+    key: serde_json::Value,
+}
+
+fn encode_schedule_cursor(cursor: &ScheduleCursor) -> String {
+    hex::encode(serde_json::to_vec(cursor).unwrap_or_default())
+}
+
+fn decode_schedule_cursor(value: &str) -> Option<ScheduleCursor> {
+    let bytes = hex::decode(value).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+// 検証済みの一覧条件
+struct ScheduleListSpec {
+    sort: ScheduleSortKey,
+    descending: bool,
+    limit: Option<i64>,
+    cursor: Option<ScheduleCursor>,
+    statuses: Option<Vec<String>>,
+    from: Option<String>,
+    to: Option<String>,
+}
+
+fn validate_schedule_query(
+    params: &ScheduleQuery,
+    allow_timestamps: bool,
+) -> Result<ScheduleListSpec, (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |message: &str| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: message.to_string(),
+            }),
+        )
+    };
+
+    let sort = match params.sort.as_deref() {
+        None | Some("") => ScheduleSortKey::Date,
+        Some(value) => ScheduleSortKey::parse(value, allow_timestamps)
+            .ok_or_else(|| bad_request("sortの指定が正しくありません"))?,
+    };
+    let descending = match params.order.as_deref() {
+        None | Some("") | Some("asc") => false,
+        Some("desc") => true,
+        Some(_) => return Err(bad_request("orderはascまたはdescで指定してください")),
+    };
+
+    let limit = match params.limit {
+        None => None,
+        Some(limit) if (1..=SCHEDULE_LIST_MAX_LIMIT).contains(&limit) => Some(limit),
+        Some(_) => {
+            return Err(bad_request(&format!(
+                "limitは1〜{}の範囲で指定してください",
+                SCHEDULE_LIST_MAX_LIMIT
+            )))
+        }
+    };
+
+    let cursor = match params.cursor.as_deref() {
+        None | Some("") => None,
+        Some(value) => {
+            let cursor = decode_schedule_cursor(value)
+                .filter(|c| c.sort == sort.name())
+                .ok_or_else(|| bad_request("cursorが正しくありません"))?;
+            Some(cursor)
+        }
+    };
+
+    let parse_date = |value: &Option<String>, label: &str| -> Result<Option<String>, (StatusCode, Json<ErrorResponse>)> {
+        match value.as_deref().map(str::trim) {
+            None | Some("") => Ok(None),
+            Some(value) => chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map(|d| Some(d.format("%Y-%m-%d").to_string()))
+                .map_err(|_| bad_request(&format!("{}はYYYY-MM-DD形式で指定してください", label))),
+        }
+    };
+    let from = parse_date(&params.from, "from")?;
+    let to = parse_date(&params.to, "to")?;
+    if let (Some(from), Some(to)) = (&from, &to) {
+        if from > to {
+            return Err(bad_request("fromはto以前の日付を指定してください"));
+        }
+    }
+    if let (Some(min), Some(max)) = (params.min_cost, params.max_cost) {
+        if min > max {
+            return Err(bad_request("min_costはmax_cost以下で指定してください"));
+        }
+    }
+
+    // status指定がある場合はその集合で絞り込み、ない場合は従来どおりinclude_canceledでキャンセル分を除く
+    let statuses = params.status.as_deref().map(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    });
+    let statuses = statuses.filter(|s| !s.is_empty());
+
+    Ok(ScheduleListSpec {
+        sort,
+        descending,
+        limit,
+        cursor,
+        statuses,
+        from,
+        to,
+    })
+}
+
+// WHERE句の後ろに一覧の絞り込み条件を追加する
+// earliest_visible_year は無料プランの過去アーカイブ制限（開催期間の最終年がこれより前のものを除く）
+fn push_schedule_list_filters(
+    qb: &mut QueryBuilder<'_, Sqlite>,
+    params: &ScheduleQuery,
+    spec: &ScheduleListSpec,
+    earliest_visible_year: Option<i32>,
+) {
+    // 複数日開催は開催期間のいずれかの日が指定年・期間にかかっていれば対象とする
+    qb.push(" AND deleted_at IS NULL");
+
+    let start_year = "CAST(substr(date, 1, 4) AS INTEGER)";
+    let end_year = "MAX(CAST(substr(COALESCE(end_date, date), 1, 4) AS INTEGER), CAST(substr(date, 1, 4) AS INTEGER))";
+    if let Some(year) = params.year {
+        qb.push(format!(" AND {} <= ", start_year)).push_bind(year);
+        qb.push(format!(" AND {} >= ", end_year)).push_bind(year);
+    }
+    if let Some(earliest) = earliest_visible_year {
+        qb.push(format!(" AND {} >= ", end_year)).push_bind(earliest);
+    }
+    if let Some(from) = &spec.from {
+        qb.push(" AND MAX(COALESCE(end_date, date), date) >= ").push_bind(from.clone());
+    }
+    if let Some(to) = &spec.to {
+        qb.push(" AND date <= ").push_bind(to.clone());
+    }
+
+    match &spec.statuses {
+        Some(statuses) => {
+            qb.push(" AND status IN (");
+            let mut separated = qb.separated(", ");
+            for status in statuses {
+                separated.push_bind(status.clone());
+            }
+            qb.push(")");
+        }
+        None if params.include_canceled != Some(true) => {
+            qb.push(" AND status <> 'Canceled'");
+        }
+        None => {}
+    }
+
+    for (column, value) in [
+        ("category", &params.category),
+        ("area", &params.area),
+        ("target", &params.target),
+        ("seller", &params.seller),
+    ] {
+        if let Some(value) = value.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+            qb.push(format!(" AND {} = ", column)).push_bind(value.to_string());
+        }
+    }
+    if let Some(venue) = params.venue.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+        let escaped = venue.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+        qb.push(" AND venue LIKE ").push_bind(format!("%{}%", escaped)).push(" ESCAPE '\\'");
+    }
+    if let Some(min_cost) = params.min_cost {
+        qb.push(" AND COALESCE(total_cost, 0) >= ").push_bind(min_cost);
+    }
+    if let Some(max_cost) = params.max_cost {
+        qb.push(" AND COALESCE(total_cost, 0) <= ").push_bind(max_cost);
+    }
+}
+
+// カーソル条件・ORDER BY・LIMITを追加する。key_column は同値の並び順を決める一意な列
+// 次ページの有無を判定するため、limitより1件多く取得する
+fn push_schedule_list_order(qb: &mut QueryBuilder<'_, Sqlite>, spec: &ScheduleListSpec, key_column: &str) {
+    let expr = spec.sort.sql_expr();
+    let op = if spec.descending { "<" } else { ">" };
+    if let Some(cursor) = &spec.cursor {
+        qb.push(format!(" AND ({} {} ", expr, op));
+        push_json_bind(qb, &cursor.value);
+        qb.push(format!(" OR ({} = ", expr));
+        push_json_bind(qb, &cursor.value);
+        qb.push(format!(" AND {} {} ", key_column, op));
+        push_json_bind(qb, &cursor.key);
+        qb.push("))");
+    }
+    let direction = if spec.descending { "DESC" } else { "ASC" };
+    qb.push(format!(" ORDER BY {} {}, {} {}", expr, direction, key_column, direction));
+    if let Some(limit) = spec.limit {
+        qb.push(" LIMIT ").push_bind(limit + 1);
+    }
+}
+
+fn push_json_bind(qb: &mut QueryBuilder<'_, Sqlite>, value: &serde_json::Value) {
+    match value {
+        serde_json::Value::Number(n) => {
+            qb.push_bind(n.as_i64().unwrap_or_default());
+        }
+        serde_json::Value::String(s) => {
+            qb.push_bind(s.clone());
+        }
+        other => {
+            qb.push_bind(other.to_string());
+        }
+    }
+}
+
+// 並び替え値（sql_exprと同じ値）を行の内容から作る
+fn schedule_sort_value(
+    sort: ScheduleSortKey,
+    date: Option<&str>,
+    start: Option<&str>,
+    total_cost: Option<i32>,
+    title: &str,
+    created_at: Option<&str>,
+    updated_at: Option<&str>,
+) -> serde_json::Value {
+    match sort {
+        ScheduleSortKey::Date => serde_json::Value::from(format!(
+            "{} {}",
+            date.unwrap_or_default(),
+            start.unwrap_or_default()
+        )),
+        ScheduleSortKey::TotalCost => serde_json::Value::from(total_cost.unwrap_or(0)),
+        ScheduleSortKey::Title => serde_json::Value::from(title),
+        ScheduleSortKey::CreatedAt => serde_json::Value::from(created_at.unwrap_or_default()),
+        ScheduleSortKey::UpdatedAt => serde_json::Value::from(updated_at.unwrap_or_default()),
+    }
+}
+
+// limit+1件取得した結果を切り詰め、次ページがあればカーソルをヘッダーに設定する
+fn paginate_schedule_rows<T>(
+    rows: &mut Vec<T>,
+    spec: &ScheduleListSpec,
+    cursor_of: impl Fn(&T) -> ScheduleCursor,
+) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(limit) = spec.limit {
+        if rows.len() as i64 > limit {
+            rows.truncate(limit as usize);
+            if let Some(last) = rows.last() {
+                if let Ok(value) = HeaderValue::from_str(&encode_schedule_cursor(&cursor_of(last))) {
+                    headers.insert(NEXT_CURSOR_HEADER, value);
+                }
+            }
+        }
+    }
+    headers
+}
+
+fn public_schedule_cursor(spec: &ScheduleListSpec, row: &PublicScheduleRow) -> ScheduleCursor {
+    ScheduleCursor {
+        sort: spec.sort.name().to_string(),
+        value: schedule_sort_value(
+            spec.sort,
+            row.date.as_deref(),
+            row.start.as_deref(),
+            row.total_cost,
+            &row.title,
+            None,
+            None,
+        ),
+        key: serde_json::Value::from(row.public_id.clone()),
+    }
+}
+
+#[cfg(test)]
+mod schedule_list_tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = ScheduleCursor {
+            sort: "total_cost".to_string(),
+            value: serde_json::json!(12000),
+            key: serde_json::json!(42),
+        };
+        assert_eq!(decode_schedule_cursor(&encode_schedule_cursor(&cursor)), Some(cursor));
+        assert_eq!(decode_schedule_cursor("zz"), None);
+    }
+
+    #[test]
+    fn timestamp_sort_is_owner_only() {
+        assert_eq!(ScheduleSortKey::parse("updated_at", true), Some(ScheduleSortKey::UpdatedAt));
+        assert_eq!(ScheduleSortKey::parse("updated_at", false), None);
+        assert_eq!(ScheduleSortKey::parse("venue", true), None);
+    }
+
+    // 日付がNULLの行も、日付順のカーソルをまたいで欠けずに返る（sql_exprとschedule_sort_valueで空文字として揃える）
+    #[tokio::test]
+    async fn date_pages_include_rows_without_date() {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        sqlx::query("CREATE TABLE schedules (id INTEGER PRIMARY KEY, date TEXT, start TEXT)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO schedules (id, date, start) VALUES (1, NULL, NULL), (2, '2026-11-01', '18:00'), (3, '2026-12-01', NULL)")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        for (descending, expected) in [(false, vec![1, 2, 3]), (true, vec![3, 2, 1])] {
+            let mut ids = Vec::new();
+            let mut cursor = None;
+            loop {
+                let spec = ScheduleListSpec {
+                    sort: ScheduleSortKey::Date,
+                    descending,
+                    limit: Some(1),
+                    cursor: cursor.take(),
+                    statuses: None,
+                    from: None,
+                    to: None,
+                };
+                let mut qb = QueryBuilder::<Sqlite>::new("SELECT id, date, start FROM schedules WHERE 1 = 1");
+                push_schedule_list_order(&mut qb, &spec, "id");
+                let mut rows: Vec<(i64, Option<String>, Option<String>)> =
+                    qb.build_query_as().fetch_all(&pool).await.unwrap();
+                let headers = paginate_schedule_rows(&mut rows, &spec, |(id, date, start)| ScheduleCursor {
+                    sort: spec.sort.name().to_string(),
+                    value: schedule_sort_value(spec.sort, date.as_deref(), start.as_deref(), None, "", None, None),
+                    key: serde_json::Value::from(*id),
+                });
+                ids.extend(rows.iter().map(|(id, _, _)| *id));
+                cursor = headers
+                    .get(NEXT_CURSOR_HEADER)
+                    .and_then(|value| decode_schedule_cursor(value.to_str().unwrap()));
+                if cursor.is_none() || ids.len() > expected.len() {
+                    break;
+                }
+            }
+            assert_eq!(ids, expected, "descending={}", descending);
+        }
+    }
+}
+
+// GET /schedules?year=2025 など
+// ロールアップ（費用の集計値）は交通・宿泊・チケット申込などの書き込み時に更新済みのため、一覧取得時には再計算しない
+async fn list_schedules(
+    Query(params): Query<ScheduleQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    user: AuthenticatedUser,
+) -> Result<(HeaderMap, Json<Vec<Schedule>>), (StatusCode, Json<ErrorResponse>)> {
+    eprintln!("[ListSchedules] User ID: {}", user.user_id);
+    let spec = validate_schedule_query(&params, true)?;
+
+    let (plan, trial_started_at) = fetch_user_plan(&pool, user.user_id).await.map_err(|e| {
+        eprintln!("[ListSchedules] Failed to fetch plan: {}", e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(ErrorResponse {
-                error: "データベースエラーが発生しました".to_string(),
+                error: "Database error".to_string(),
             }),
         )
     })?;
+    // 無料プランは過去アーカイブを直近2年分（今年+去年）のみ閲覧可能。未来の予定は制限しない
+    let earliest_visible_year = if has_paid_access(&plan, trial_started_at.as_deref()) {
+        None
+    } else {
+        let user_timezone = fetch_user_timezone(&pool, user.user_id).await.map_err(|e| {
+            eprintln!("[ListSchedules] Failed to fetch timezone: {}", e);
+            database_error()
+        })?;
+        Some(free_plan_earliest_archive_year(Utc::now().with_timezone(&user_timezone)))
+    };
 
-    let last_id = result.last_insert_rowid();
+    let mut qb = QueryBuilder::<Sqlite>::new(
+        r#"
+        SELECT
+          id,
+          title,
+          "group",
+          date,
+          open,
+          start,
+          "end",
+          end_date,
+          timezone,
+          tour_id,
+          (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+          (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
+          notes,
+          category,
+          area,
+          venue,
+          target,
+          lineup,
+          seller,
+          ticket_fee,
+          drink_fee,
+          ticket_fee_currency,
+          drink_fee_currency,
+          ticket_fee_effective,
+          drink_fee_effective,
+          total_fare,
+          stay_fee,
+          travel_cost,
+          other_cost,
+          total_cost,
+          my_share,
+          net_cost,
+          goods_cost,
+          status,
+          related_schedule_ids,
+          user_id,
+          CAST(is_public AS INTEGER) as is_public,
+          created_at,
+          updated_at
+        FROM schedules
+        WHERE user_id = "#,
+    );
+    qb.push_bind(user.user_id);
+    push_schedule_list_filters(&mut qb, &params, &spec, earliest_visible_year);
+    push_schedule_list_order(&mut qb, &spec, "id");
 
-    // ロールアップ計算を実行
-    calculate_rollup(&pool, last_id).await.ok();
-    
-    // 計算後のスケジュールを再取得
-    let row: ScheduleRow = sqlx::query_as::<_, ScheduleRow>(
+    let mut rows: Vec<ScheduleRow> = qb
+        .build_query_as::<ScheduleRow>()
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[ListSchedules] Database error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: format!("データベースエラーが発生しました: {}", e),
+                }),
+            )
+        })?;
+
+    let headers = paginate_schedule_rows(&mut rows, &spec, |row| ScheduleCursor {
+        sort: spec.sort.name().to_string(),
+        value: schedule_sort_value(
+            spec.sort,
+            row.date.as_deref(),
+            row.start.as_deref(),
+            row.total_cost,
+            &row.title,
+            row.created_at.as_deref(),
+            row.updated_at.as_deref(),
+        ),
+        key: serde_json::Value::from(row.id),
+    });
+
+    let mut schedules: Vec<Schedule> = rows.into_iter().map(row_to_schedule).collect();
+
+    attach_schedule_days(&pool, &mut schedules).await.map_err(|e| {
+        eprintln!("[ListSchedules] Failed to fetch schedule days: {}", e);
+        database_error()
+    })?;
+    attach_schedule_tickets(&pool, &mut schedules).await.map_err(|e| {
+        eprintln!("[ListSchedules] Failed to fetch schedule tickets: {}", e);
+        database_error()
+    })?;
+
+    eprintln!("[ListSchedules] Returning {} schedules for user_id: {}", schedules.len(), user.user_id);
+    Ok((headers, Json(schedules)))
+}
+
+// GET /schedules/upcoming
+async fn list_upcoming(
+    Extension(pool): Extension<Pool<Sqlite>>,
+    user: AuthenticatedUser,
+) -> Json<Vec<Schedule>> {
+    let rows: Vec<ScheduleRow> = sqlx::query_as::<_, ScheduleRow>(
         r#"
         SELECT
           id,
@@ -4469,6 +5932,11 @@ async fn create_schedule(
           open,
           start,
           "end",
+          end_date,
+          timezone,
+          tour_id,
+          (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+          (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
           notes,
           category,
           area,
@@ -4478,10 +5946,18 @@ async fn create_schedule(
           seller,
           ticket_fee,
           drink_fee,
+          ticket_fee_currency,
+          drink_fee_currency,
+          ticket_fee_effective,
+          drink_fee_effective,
           total_fare,
           stay_fee,
           travel_cost,
+          other_cost,
           total_cost,
+          my_share,
+          net_cost,
+          goods_cost,
           status,
           related_schedule_ids,
           user_id,
@@ -4489,22 +5965,311 @@ async fn create_schedule(
           created_at,
           updated_at
         FROM schedules
-        WHERE id = ?
+        WHERE status != 'Canceled' AND user_id = ? AND deleted_at IS NULL
         "#,
     )
-    .bind(last_id)
-    .fetch_one(&pool)
+    .bind(user.user_id)
+    .fetch_all(&pool)
     .await
-    .map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "データベースエラーが発生しました".to_string(),
-            }),
-        )
+    .expect("failed to fetch schedules");
+
+    // 各スケジュールに対してロールアップ計算を実行
+    for row in &rows {
+        calculate_rollup(&pool, row.id).await.ok();
+    }
+    
+    // 計算後のスケジュールを再取得
+    let rows: Vec<ScheduleRow> = sqlx::query_as::<_, ScheduleRow>(
+        r#"
+        SELECT
+          id,
+          title,
+          "group",
+          date,
+          open,
+          start,
+          "end",
+          end_date,
+          timezone,
+          tour_id,
+          (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+          (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
+          notes,
+          category,
+          area,
+          venue,
+          target,
+          lineup,
+          seller,
+          ticket_fee,
+          drink_fee,
+          ticket_fee_currency,
+          drink_fee_currency,
+          ticket_fee_effective,
+          drink_fee_effective,
+          total_fare,
+          stay_fee,
+          travel_cost,
+          other_cost,
+          total_cost,
+          my_share,
+          net_cost,
+          goods_cost,
+          status,
+          related_schedule_ids,
+          user_id,
+          CAST(is_public AS INTEGER) as is_public,
+          created_at,
+          updated_at
+        FROM schedules
+        WHERE status != 'Canceled' AND user_id = ? AND deleted_at IS NULL
+        "#,
+    )
+    .bind(user.user_id)
+    .fetch_all(&pool)
+    .await
+    .expect("failed to fetch schedules");
+
+    let mut schedules: Vec<Schedule> = rows.into_iter().map(row_to_schedule).collect();
+    attach_schedule_days(&pool, &mut schedules)
+        .await
+        .expect("failed to fetch schedule days");
+    attach_schedule_tickets(&pool, &mut schedules)
+        .await
+        .expect("failed to fetch schedule tickets");
+    let user_timezone = fetch_user_timezone(&pool, user.user_id)
+        .await
+        .expect("failed to fetch user timezone");
+
+    // 複数日開催は初日の開演を過ぎても、最終日が終わるまでは直近の予定として扱う
+    let now = Utc::now();
+    schedules = schedules
+        .into_iter()
+        .filter(|s| s.datetime > now || schedule_range_end(s, user_timezone).is_some_and(|end| end > now))
+        .collect();
+
+    // 日付順にソートして直近3件に制限
+    schedules.sort_by(|a, b| a.datetime.cmp(&b.datetime));
+    schedules.truncate(3);
+
+    Json(schedules)
+}
+
+// POST /schedules
+async fn create_schedule(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewSchedule>,
+) -> Result<(StatusCode, Json<Schedule>), (StatusCode, Json<ErrorResponse>)> {
+    // 必須項目のバリデーション（targetはNULL許可）
+    let end_date = normalize_end_date(payload.date.as_deref(), payload.end_date.as_deref());
+    let range = validate_schedule_range(&payload, end_date.as_deref())?;
+    let tickets = validate_schedule_tickets(payload.tickets.as_deref())?;
+    let timezone = payload.timezone.as_deref().map(validate_timezone_name).transpose()?.flatten();
+    let status = validate_schedule_status(payload.status.as_deref().unwrap_or("Pending"))?;
+    let home_currency = fetch_user_home_currency(&pool, user.user_id).await.map_err(|e| {
+        eprintln!("[CreateSchedule] Failed to fetch home currency: {}", e);
+        database_error()
+    })?;
+    let ticket_fee_currency = resolve_currency(&pool, user.user_id, payload.ticket_fee_currency.as_deref())
+        .await?
+        .unwrap_or_else(|| home_currency.clone());
+    let drink_fee_currency = resolve_currency(&pool, user.user_id, payload.drink_fee_currency.as_deref())
+        .await?
+        .unwrap_or(home_currency);
+    if let Some(tour_id) = payload.tour_id {
+        ensure_tour_owner(&pool, tour_id as i64, user.user_id).await?;
+    }
+    let now = Utc::now().to_rfc3339();
+    let is_public = payload.is_public.unwrap_or(true) as i32;
+    eprintln!("[CreateSchedule] is_public value: {} (from payload: {:?})", is_public, payload.is_public);
+    let result = sqlx::query(
+        r#"
+        INSERT INTO schedules (
+          user_id,
+          title,
+          "group",
+          date,
+          end_date,
+          timezone,
+          tour_id,
+          open,
+          start,
+          "end",
+          notes,
+          category,
+          area,
+          venue,
+          target,
+          lineup,
+          seller,
+          ticket_fee,
+          drink_fee,
+          ticket_fee_currency,
+          drink_fee_currency,
+          total_fare,
+          stay_fee,
+          travel_cost,
+          total_cost,
+          status,
+          related_schedule_ids,
+          is_public,
+          public_id,
+          created_at,
+          updated_at
+        ) VALUES (
+          ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, NULL, NULL, NULL, NULL, ?, ?, ?, ?, ?, ?
+        )
+        "#,
+    )
+    .bind(user.user_id)
+    .bind(&payload.title)
+    .bind(&payload.group.as_ref().and_then(|g| {
+        let trimmed = g.trim();
+        if trimmed.is_empty() { None } else { Some(trimmed.to_string()) }
+    }))
+    .bind(&payload.date)
+    .bind(&end_date)
+    .bind(&timezone)
+    .bind(payload.tour_id)
+    .bind(&payload.open)
+    .bind(&payload.start)
+    .bind(&payload.end)
+    .bind(&payload.notes)
+    .bind(&payload.category)
+    .bind(&payload.area)
+    .bind(&payload.venue)
+    .bind(&payload.target)
+    .bind(&payload.lineup)
+    .bind(&payload.seller)
+    .bind(payload.ticket_fee)
+    .bind(payload.drink_fee)
+    .bind(&ticket_fee_currency)
+    .bind(&drink_fee_currency)
+    .bind(&status)
+    .bind(&payload.related_schedule_ids.as_ref().and_then(|ids| {
+        if ids.is_empty() {
+            None
+        } else {
+            serde_json::to_string(ids).ok()
+        }
+    }))
+    .bind(is_public)
+    .bind(generate_public_id())
+    .bind(&now)
+    .bind(&now)
+    .execute(&pool)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "データベースエラーが発生しました".to_string(),
+            }),
+        )
+    })?;
+
+    let last_id = result.last_insert_rowid();
+
+    sync_schedule_days(
+        &pool,
+        last_id,
+        &range,
+        payload.days.as_deref(),
+        payload.open.as_deref(),
+        payload.start.as_deref(),
+        payload.end.as_deref(),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("[CreateSchedule] Failed to save schedule days: {}", e);
+        database_error()
+    })?;
+    save_schedule_tickets(&pool, last_id, tickets.as_deref(), payload.seat_public)
+        .await
+        .map_err(|e| {
+            eprintln!("[CreateSchedule] Failed to save schedule tickets: {}", e);
+            database_error()
+        })?;
+
+    // ロールアップ計算を実行
+    calculate_rollup(&pool, last_id).await.ok();
+    refresh_search_index(&pool, last_id).await;
+    track_revision(&pool, user.user_id, RevisionEntity::Schedule, last_id, last_id, RevisionAction::Create, None).await;
+    record_status_change(&pool, last_id, user.user_id as i64, None, &status, StatusChangeSource::Manual).await;
+    
+    // 計算後のスケジュールを再取得
+    let row: ScheduleRow = sqlx::query_as::<_, ScheduleRow>(
+        r#"
+        SELECT
+          id,
+          title,
+          "group",
+          date,
+          open,
+          start,
+          "end",
+          end_date,
+          timezone,
+          tour_id,
+          (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+          (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
+          notes,
+          category,
+          area,
+          venue,
+          target,
+          lineup,
+          seller,
+          ticket_fee,
+          drink_fee,
+          ticket_fee_currency,
+          drink_fee_currency,
+          ticket_fee_effective,
+          drink_fee_effective,
+          total_fare,
+          stay_fee,
+          travel_cost,
+          other_cost,
+          total_cost,
+          my_share,
+          net_cost,
+          goods_cost,
+          status,
+          related_schedule_ids,
+          user_id,
+          CAST(is_public AS INTEGER) as is_public,
+          created_at,
+          updated_at
+        FROM schedules
+        WHERE id = ?
+        "#,
+    )
+    .bind(last_id)
+    .fetch_one(&pool)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "データベースエラーが発生しました".to_string(),
+            }),
+        )
     })?;
 
-    let schedule = row_to_schedule(row);
+    let mut schedule = row_to_schedule(row);
+    attach_schedule_days(&pool, std::slice::from_mut(&mut schedule))
+        .await
+        .map_err(|e| {
+            eprintln!("[CreateSchedule] Failed to fetch schedule days: {}", e);
+            database_error()
+        })?;
+    attach_schedule_tickets(&pool, std::slice::from_mut(&mut schedule))
+        .await
+        .map_err(|e| {
+            eprintln!("[CreateSchedule] Failed to fetch schedule tickets: {}", e);
+            database_error()
+        })?;
     
     // 双方向リレーションを更新
     // 新規作成時は、related_schedule_idsに含まれるスケジュールに対して、このスケジュールを追加
@@ -4521,6 +6286,11 @@ async fn create_schedule(
                   open,
                   start,
                   "end",
+                  end_date,
+                  timezone,
+                  tour_id,
+                  (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+                  (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
                   notes,
                   category,
                   area,
@@ -4530,10 +6300,18 @@ async fn create_schedule(
                   seller,
                   ticket_fee,
                   drink_fee,
+                  ticket_fee_currency,
+                  drink_fee_currency,
+                  ticket_fee_effective,
+                  drink_fee_effective,
                   total_fare,
                   stay_fee,
                   travel_cost,
+                  other_cost,
                   total_cost,
+                  my_share,
+                  net_cost,
+                  goods_cost,
                   status,
                   related_schedule_ids,
                   user_id,
@@ -4541,7 +6319,7 @@ async fn create_schedule(
                   created_at,
                   updated_at
                 FROM schedules
-                WHERE id = ?
+                WHERE id = ? AND deleted_at IS NULL
                 "#,
             )
             .bind(related_id)
@@ -4580,7 +6358,9 @@ async fn create_schedule(
             }
         }
     }
-    
+
+    schedule.warnings = itinerary_warnings_for_entity(&pool, user.user_id, last_id, |_| true).await;
+
     Ok((StatusCode::CREATED, Json(schedule)))
 }
 
@@ -4588,10 +6368,14 @@ async fn create_schedule(
 async fn update_schedule(
     Path(id): Path<i32>,
     user: AuthenticatedUser,
+    headers: HeaderMap,
     Extension(pool): Extension<Pool<Sqlite>>,
     Json(payload): Json<NewSchedule>,
-) -> Result<Json<Schedule>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<(HeaderMap, Json<Schedule>), WriteError<(StatusCode, Json<ErrorResponse>)>> {
     // 必須項目のバリデーション（targetはNULL許可）
+    let end_date = normalize_end_date(payload.date.as_deref(), payload.end_date.as_deref());
+    let range = validate_schedule_range(&payload, end_date.as_deref())?;
+    let tickets = validate_schedule_tickets(payload.tickets.as_deref())?;
     
     // スケジュールが存在し、ユーザーが所有しているかチェック
     let existing: Option<ScheduleRow> = sqlx::query_as::<_, ScheduleRow>(
@@ -4604,6 +6388,11 @@ async fn update_schedule(
           open,
           start,
           "end",
+          end_date,
+          timezone,
+          tour_id,
+          (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+          (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
           notes,
           category,
           area,
@@ -4613,10 +6402,18 @@ async fn update_schedule(
           seller,
           ticket_fee,
           drink_fee,
+          ticket_fee_currency,
+          drink_fee_currency,
+          ticket_fee_effective,
+          drink_fee_effective,
           total_fare,
           stay_fee,
           travel_cost,
+          other_cost,
           total_cost,
+          my_share,
+          net_cost,
+          goods_cost,
           status,
           related_schedule_ids,
           user_id,
@@ -4624,7 +6421,7 @@ async fn update_schedule(
           created_at,
           updated_at
         FROM schedules
-        WHERE id = ?
+        WHERE id = ? AND deleted_at IS NULL
         "#,
     )
     .bind(id)
@@ -4652,12 +6449,38 @@ async fn update_schedule(
             Json(ErrorResponse {
                 error: "このスケジュールを編集する権限がありません".to_string(),
             }),
-        ));
+        ).into());
+    }
+
+    // If-Matchが指定されている場合は、取得時点から変更されていないことを確認する
+    if !if_match_satisfied(&headers, &etag_for(existing.updated_at.as_deref())) {
+        return Err(schedule_precondition_failed(&pool, id as i64).await);
     }
+    let guard = if_match_guard(&headers, existing.updated_at.as_deref());
+
+    // ステータスの省略時は変更しない
+    let status = match payload.status.as_deref() {
+        Some(value) => validate_schedule_status(value)?,
+        None => existing.status.clone(),
+    };
+    ensure_schedule_status_transition(&existing.status, &status)?;
 
     let now = Utc::now().to_rfc3339();
     let existing_is_public = existing.is_public != 0;
     let is_public = payload.is_public.unwrap_or(existing_is_public) as i32;
+    let timezone = match payload.timezone.as_deref() {
+        Some(value) => validate_timezone_name(value)?,
+        None => existing.timezone.clone(),
+    };
+    let ticket_fee_currency = resolve_currency(&pool, user.user_id, payload.ticket_fee_currency.as_deref()).await?;
+    let drink_fee_currency = resolve_currency(&pool, user.user_id, payload.drink_fee_currency.as_deref()).await?;
+    let tour_id = match payload.tour_id {
+        Some(tour_id) => {
+            ensure_tour_owner(&pool, tour_id as i64, user.user_id).await?;
+            Some(tour_id as i64)
+        }
+        None => existing.tour_id,
+    };
     
     // 既存のrelated_schedule_idsを取得
     let existing_related_ids: Vec<i32> = existing.related_schedule_ids
@@ -4680,12 +6503,16 @@ async fn update_schedule(
         .cloned()
         .collect();
     
+    let before = revision_snapshot(&pool, RevisionEntity::Schedule, id as i64).await;
     let result = sqlx::query(
         r#"
         UPDATE schedules SET
           title = ?,
           "group" = ?,
           date = ?,
+          end_date = ?,
+          timezone = ?,
+          tour_id = ?,
           open = ?,
           start = ?,
           "end" = ?,
@@ -4698,11 +6525,13 @@ async fn update_schedule(
           seller = ?,
           ticket_fee = ?,
           drink_fee = ?,
+          ticket_fee_currency = COALESCE(?, ticket_fee_currency),
+          drink_fee_currency = COALESCE(?, drink_fee_currency),
           status = ?,
           related_schedule_ids = ?,
           is_public = ?,
           updated_at = ?
-        WHERE id = ? AND user_id = ?
+        WHERE id = ? AND user_id = ? AND updated_at IS COALESCE(?, updated_at)
         "#,
     )
     .bind(&payload.title)
@@ -4711,6 +6540,9 @@ async fn update_schedule(
         if trimmed.is_empty() { None } else { Some(trimmed.to_string()) }
     }))
     .bind(&payload.date)
+    .bind(&end_date)
+    .bind(&timezone)
+    .bind(tour_id)
     .bind(&payload.open)
     .bind(&payload.start)
     .bind(&payload.end)
@@ -4723,7 +6555,9 @@ async fn update_schedule(
     .bind(&payload.seller)
     .bind(payload.ticket_fee)
     .bind(payload.drink_fee)
-    .bind(payload.status.as_deref().unwrap_or("Pending"))
+    .bind(&ticket_fee_currency)
+    .bind(&drink_fee_currency)
+    .bind(&status)
     .bind(&payload.related_schedule_ids.as_ref().and_then(|ids| {
         if ids.is_empty() {
             None
@@ -4735,6 +6569,7 @@ async fn update_schedule(
     .bind(&now)
     .bind(id)
     .bind(user.user_id)
+    .bind(&guard)
     .execute(&pool)
     .await
     .map_err(|e| {
@@ -4748,16 +6583,45 @@ async fn update_schedule(
     })?;
 
     if result.rows_affected() == 0 {
+        if guard.is_some() {
+            return Err(schedule_precondition_failed(&pool, id as i64).await);
+        }
         return Err((
             StatusCode::NOT_FOUND,
             Json(ErrorResponse {
                 error: "スケジュールが見つかりませんでした".to_string(),
             }),
-        ));
+        ).into());
     }
 
+    sync_schedule_days(
+        &pool,
+        id as i64,
+        &range,
+        payload.days.as_deref(),
+        payload.open.as_deref(),
+        payload.start.as_deref(),
+        payload.end.as_deref(),
+    )
+    .await
+    .map_err(|e| {
+        eprintln!("[UpdateSchedule] Failed to save schedule days: {}", e);
+        database_error()
+    })?;
+    save_schedule_tickets(&pool, id as i64, tickets.as_deref(), payload.seat_public)
+        .await
+        .map_err(|e| {
+            eprintln!("[UpdateSchedule] Failed to save schedule tickets: {}", e);
+            database_error()
+        })?;
+
     // ロールアップ計算を実行
     calculate_rollup(&pool, id as i64).await.ok();
+    refresh_search_index(&pool, id as i64).await;
+    track_revision(&pool, user.user_id, RevisionEntity::Schedule, id as i64, id as i64, RevisionAction::Update, before).await;
+    if status != existing.status {
+        record_status_change(&pool, id as i64, user.user_id as i64, Some(&existing.status), &status, StatusChangeSource::Manual).await;
+    }
     
     // 計算後のスケジュールを再取得
     let row: ScheduleRow = sqlx::query_as::<_, ScheduleRow>(
@@ -4770,6 +6634,11 @@ async fn update_schedule(
           open,
           start,
           "end",
+          end_date,
+          timezone,
+          tour_id,
+          (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+          (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
           notes,
           category,
           area,
@@ -4779,10 +6648,18 @@ async fn update_schedule(
           seller,
           ticket_fee,
           drink_fee,
+          ticket_fee_currency,
+          drink_fee_currency,
+          ticket_fee_effective,
+          drink_fee_effective,
           total_fare,
           stay_fee,
           travel_cost,
+          other_cost,
           total_cost,
+          my_share,
+          net_cost,
+          goods_cost,
           status,
           related_schedule_ids,
           user_id,
@@ -4790,7 +6667,7 @@ async fn update_schedule(
           created_at,
           updated_at
         FROM schedules
-        WHERE id = ?
+        WHERE id = ? AND deleted_at IS NULL
         "#,
     )
     .bind(id)
@@ -4805,7 +6682,20 @@ async fn update_schedule(
         )
     })?;
 
-    let schedule = row_to_schedule(row);
+    let etag = etag_for(row.updated_at.as_deref());
+    let mut schedule = row_to_schedule(row);
+    attach_schedule_days(&pool, std::slice::from_mut(&mut schedule))
+        .await
+        .map_err(|e| {
+            eprintln!("[UpdateSchedule] Failed to fetch schedule days: {}", e);
+            database_error()
+        })?;
+    attach_schedule_tickets(&pool, std::slice::from_mut(&mut schedule))
+        .await
+        .map_err(|e| {
+            eprintln!("[UpdateSchedule] Failed to fetch schedule tickets: {}", e);
+            database_error()
+        })?;
     
     // 双方向リレーションを更新
     // 追加されたIDに対して、このスケジュールを追加
@@ -4820,6 +6710,11 @@ async fn update_schedule(
               open,
               start,
               "end",
+              end_date,
+              timezone,
+              tour_id,
+              (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+              (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
               notes,
               category,
               area,
@@ -4829,10 +6724,18 @@ async fn update_schedule(
               seller,
               ticket_fee,
               drink_fee,
+              ticket_fee_currency,
+              drink_fee_currency,
+              ticket_fee_effective,
+              drink_fee_effective,
               total_fare,
               stay_fee,
               travel_cost,
+              other_cost,
               total_cost,
+              my_share,
+              net_cost,
+              goods_cost,
               status,
               related_schedule_ids,
               user_id,
@@ -4840,7 +6743,7 @@ async fn update_schedule(
               created_at,
               updated_at
             FROM schedules
-            WHERE id = ?
+            WHERE id = ? AND deleted_at IS NULL
             "#,
         )
         .bind(related_id)
@@ -4888,6 +6791,11 @@ async fn update_schedule(
               open,
               start,
               "end",
+              end_date,
+              timezone,
+              tour_id,
+              (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+              (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
               notes,
               category,
               area,
@@ -4897,10 +6805,18 @@ async fn update_schedule(
               seller,
               ticket_fee,
               drink_fee,
+              ticket_fee_currency,
+              drink_fee_currency,
+              ticket_fee_effective,
+              drink_fee_effective,
               total_fare,
               stay_fee,
               travel_cost,
+              other_cost,
               total_cost,
+              my_share,
+              net_cost,
+              goods_cost,
               status,
               related_schedule_ids,
               user_id,
@@ -4945,1551 +6861,10753 @@ async fn update_schedule(
             .await;
         }
     }
-    
-    Ok(Json(schedule))
+
+    schedule.warnings = itinerary_warnings_for_entity(&pool, user.user_id, id as i64, |_| true).await;
+
+    Ok((etag_header(&etag), Json(schedule)))
+}
+
+// DELETE /schedules/:id - ゴミ箱に移動（論理削除）。保持期間の経過後にバックグラウンドで完全削除する
+async fn delete_schedule(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    // スケジュールが存在し、ユーザーが所有しているかチェック
+    let existing: Option<ScheduleRow> = sqlx::query_as::<_, ScheduleRow>(
+        r#"
+        SELECT
+          id,
+          title,
+          "group",
+          date,
+          open,
+          start,
+          "end",
+          end_date,
+          timezone,
+          tour_id,
+          (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+          (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
+          notes,
+          category,
+          area,
+          venue,
+          target,
+          lineup,
+          seller,
+          ticket_fee,
+          drink_fee,
+          ticket_fee_currency,
+          drink_fee_currency,
+          ticket_fee_effective,
+          drink_fee_effective,
+          total_fare,
+          stay_fee,
+          travel_cost,
+          other_cost,
+          total_cost,
+          my_share,
+          net_cost,
+          goods_cost,
+          status,
+          related_schedule_ids,
+          user_id,
+          is_public,
+          created_at,
+          updated_at
+        FROM schedules
+        WHERE id = ? AND deleted_at IS NULL
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "データベースエラーが発生しました".to_string(),
+            }),
+        )
+    })?;
+
+    let existing = existing.ok_or((
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "スケジュールが見つかりませんでした".to_string(),
+        }),
+    ))?;
+    
+    if existing.user_id.map(|uid| uid as i32) != Some(user.user_id) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "このスケジュールを削除する権限がありません".to_string(),
+            }),
+        ));
+    }
+
+    let before = revision_snapshot(&pool, RevisionEntity::Schedule, id as i64).await;
+    let mut transaction = pool.begin().await.map_err(|_| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "データベースエラーが発生しました".to_string(),
+            }),
+        )
+    })?;
+
+    // 関連スケジュールからこのスケジュールへの参照を削除
+    // このスケジュール側のrelated_schedule_idsは、ゴミ箱から復元したときに双方向リンクを張り直すため残しておく
+    let related_ids: Vec<i32> = existing.related_schedule_ids
+        .as_ref()
+        .and_then(|json| serde_json::from_str::<Vec<i32>>(json).ok())
+        .unwrap_or_default();
+    
+    let now = Utc::now().to_rfc3339();
+    for related_id in related_ids {
+        let related_row: Option<ScheduleRow> = sqlx::query_as::<_, ScheduleRow>(
+            r#"
+            SELECT
+              id,
+              title,
+              "group",
+              date,
+              open,
+              start,
+              "end",
+              end_date,
+              timezone,
+              tour_id,
+              (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+              (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
+              notes,
+              category,
+              area,
+              venue,
+              target,
+              lineup,
+              seller,
+              ticket_fee,
+              drink_fee,
+              ticket_fee_currency,
+              drink_fee_currency,
+              ticket_fee_effective,
+              drink_fee_effective,
+              total_fare,
+              stay_fee,
+              travel_cost,
+              other_cost,
+              total_cost,
+              my_share,
+              net_cost,
+              goods_cost,
+              status,
+              related_schedule_ids,
+              user_id,
+              is_public,
+              created_at,
+              updated_at
+            FROM schedules
+            WHERE id = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(related_id)
+        .fetch_optional(&pool)
+        .await
+        .ok()
+        .flatten();
+        
+        if let Some(related) = related_row {
+            let mut related_ids_vec: Vec<i32> = related.related_schedule_ids
+                .as_ref()
+                .and_then(|json| serde_json::from_str::<Vec<i32>>(json).ok())
+                .unwrap_or_default();
+            
+            related_ids_vec.retain(|&x| x != id);
+            let updated_json = if related_ids_vec.is_empty() {
+                None
+            } else {
+                serde_json::to_string(&related_ids_vec).ok()
+            };
+            
+            let _ = sqlx::query(
+                r#"
+                UPDATE schedules SET
+                  related_schedule_ids = ?,
+                  updated_at = ?
+                WHERE id = ?
+                "#,
+            )
+            .bind(&updated_json)
+            .bind(&now)
+            .bind(related_id)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse { error: "関連スケジュールの更新に失敗しました".to_string() }),
+            ))?;
+        }
+    }
+
+    // 関連する交通・宿泊情報も同じ日時でゴミ箱に移動する（復元時はこの日時で一致するものを戻す）
+    // チケット申込・日程・セットリスト・通知は完全削除（purge_schedule）まで残す
+    sqlx::query("UPDATE traffics SET deleted_at = ? WHERE schedule_id = ? AND deleted_at IS NULL")
+        .bind(&now)
+        .bind(id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: "関連する交通情報の削除に失敗しました".to_string() }),
+        ))?;
+
+    sqlx::query("UPDATE stays SET deleted_at = ? WHERE schedule_id = ? AND deleted_at IS NULL")
+        .bind(&now)
+        .bind(id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: "関連する宿泊情報の削除に失敗しました".to_string() }),
+        ))?;
+
+    sqlx::query("DELETE FROM search_index WHERE schedule_id = ?")
+        .bind(id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse { error: "検索インデックスの削除に失敗しました".to_string() }),
+        ))?;
+
+    // スケジュールをゴミ箱に移動
+    let result = sqlx::query("UPDATE schedules SET deleted_at = ? WHERE id = ? AND user_id = ? AND deleted_at IS NULL")
+        .bind(&now)
+        .bind(id)
+        .bind(user.user_id)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "データベースエラーが発生しました".to_string(),
+                }),
+            )
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "スケジュールが見つかりませんでした".to_string(),
+            }),
+        ));
+    }
+
+    transaction.commit().await.map_err(|_| (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(ErrorResponse { error: "削除処理の確定に失敗しました".to_string() }),
+    ))?;
+
+    track_revision(&pool, user.user_id, RevisionEntity::Schedule, id as i64, id as i64, RevisionAction::Delete, before).await;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "スケジュールをゴミ箱に移動しました"
+    })))
+}
+
+// GET /public/schedules - 公開されているスケジュール一覧
+// DISABLE_AUTHが設定されている場合、DEFAULT_USER_IDのスケジュールも返す
+async fn list_public_schedules(
+    Query(params): Query<ScheduleQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<(HeaderMap, Json<Vec<PublicSchedule>>), (StatusCode, Json<ErrorResponse>)> {
+    let spec = validate_schedule_query(&params, false)?;
+
+    let mut qb = QueryBuilder::<Sqlite>::new(
+        r#"
+        SELECT
+          id,
+          public_id,
+          title,
+          "group",
+          date,
+          open,
+          start,
+          "end",
+          end_date,
+          timezone,
+          (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+          (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
+          notes,
+          category,
+          area,
+          venue,
+          target,
+          lineup,
+          seller,
+          ticket_fee,
+          drink_fee,
+          ticket_fee_currency,
+          drink_fee_currency,
+          ticket_fee_effective,
+          drink_fee_effective,
+          total_fare,
+          stay_fee,
+          travel_cost,
+          other_cost,
+          total_cost,
+          status,
+          related_schedule_ids,
+          CAST(is_public AS INTEGER) as is_public
+        FROM schedules
+        "#,
+    );
+    // DISABLE_AUTHが設定されている場合、全てのスケジュールを取得（ユーザーIDによるフィルタリングなし）
+    if std::env::var("DISABLE_AUTH").is_ok() {
+        // 認証が無効化されている場合、全てのスケジュールを返す
+        println!("[PUBLIC SCHEDULES] DISABLE_AUTH is set, returning ALL schedules (ignoring user_id and is_public)");
+        qb.push(" WHERE 1 = 1");
+    } else {
+        // 通常の動作：公開スケジュールのみ
+        qb.push(" WHERE CAST(is_public AS INTEGER) = 1");
+    }
+    push_schedule_list_filters(&mut qb, &params, &spec, None);
+    push_schedule_list_order(&mut qb, &spec, "public_id");
+
+    let mut rows: Vec<PublicScheduleRow> = qb
+        .build_query_as::<PublicScheduleRow>()
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[PUBLIC SCHEDULES] Database error: {}", e);
+            database_error()
+        })?;
+    println!("[PUBLIC SCHEDULES] Found {} schedules", rows.len());
+
+    let headers = paginate_schedule_rows(&mut rows, &spec, |row| public_schedule_cursor(&spec, row));
+    let mut schedules = Vec::with_capacity(rows.len());
+    for row in rows {
+        schedules.push(row_to_public_schedule(&pool, row).await);
+    }
+
+    Ok((headers, Json(schedules)))
+}
+
+// GET /public/schedules/:id - 公開されているスケジュール詳細
+// DISABLE_AUTHが設定されている場合、全てのスケジュールを返す
+async fn get_public_schedule(
+    Path(public_id): Path<String>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<PublicSchedule>, StatusCode> {
+    // DISABLE_AUTHが設定されている場合、is_publicの条件を無視
+    let row: PublicScheduleRow = if std::env::var("DISABLE_AUTH").is_ok() {
+        sqlx::query_as::<_, PublicScheduleRow>(
+            r#"
+            SELECT
+              id,
+              public_id,
+              title,
+              "group",
+              date,
+              open,
+              start,
+              "end",
+              end_date,
+              timezone,
+              (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+              (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
+              notes,
+              category,
+              area,
+              venue,
+              target,
+              lineup,
+              seller,
+              ticket_fee,
+              drink_fee,
+              ticket_fee_currency,
+              drink_fee_currency,
+              ticket_fee_effective,
+              drink_fee_effective,
+              total_fare,
+              stay_fee,
+              travel_cost,
+              other_cost,
+              total_cost,
+              status,
+              related_schedule_ids,
+              is_public
+            FROM schedules
+            WHERE public_id = ? AND deleted_at IS NULL
+            "#,
+        )
+        .bind(&public_id)
+        .fetch_one(&pool)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?
+    } else {
+        sqlx::query_as::<_, PublicScheduleRow>(
+            r#"
+            SELECT
+              id,
+              public_id,
+              title,
+              "group",
+              date,
+              open,
+              start,
+              "end",
+              end_date,
+              timezone,
+              (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+              (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
+              notes,
+              category,
+              area,
+              venue,
+              target,
+              lineup,
+              seller,
+              ticket_fee,
+              drink_fee,
+              ticket_fee_currency,
+              drink_fee_currency,
+              ticket_fee_effective,
+              drink_fee_effective,
+              total_fare,
+              stay_fee,
+              travel_cost,
+              other_cost,
+              total_cost,
+              status,
+              related_schedule_ids,
+              CAST(is_public AS INTEGER) as is_public
+            FROM schedules
+            WHERE public_id = ? AND CAST(is_public AS INTEGER) = 1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(&public_id)
+        .fetch_one(&pool)
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?
+    };
+
+    let schedule = row_to_public_schedule(&pool, row).await;
+    Ok(Json(schedule))
+}
+
+// GET /public/traffic?schedule_id=... - 公開スケジュールの交通情報
+// DISABLE_AUTHが設定されている場合、全てのスケジュールの交通情報を返す
+async fn list_public_traffics(
+    Query(params): Query<PublicTrafficQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Json<Vec<PublicTraffic>> {
+    // DISABLE_AUTHが設定されている場合、is_publicの条件を無視
+    let schedule_exists: Option<i64> = if std::env::var("DISABLE_AUTH").is_ok() {
+        sqlx::query_scalar("SELECT id FROM schedules WHERE public_id = ? AND deleted_at IS NULL")
+            .bind(&params.schedule_id)
+            .fetch_optional(&pool)
+            .await
+            .expect("failed to check schedule")
+    } else {
+        sqlx::query_scalar("SELECT id FROM schedules WHERE public_id = ? AND CAST(is_public AS INTEGER) = 1 AND deleted_at IS NULL")
+            .bind(&params.schedule_id)
+            .fetch_optional(&pool)
+            .await
+            .expect("failed to check schedule")
+    };
+
+    let Some(schedule_internal_id) = schedule_exists else {
+        return Json(vec![]);
+    };
+
+    let rows: Vec<PublicTrafficRow> = sqlx::query_as::<_, PublicTrafficRow>(
+        r#"
+        SELECT
+          public_id,
+          date,
+          "order",
+          transportation,
+          from_place,
+          to_place,
+          notes,
+          fare,
+          fare_currency,
+          miles,
+          return_flag,
+          total_fare,
+          total_miles
+        FROM traffics
+        WHERE schedule_id = ? AND deleted_at IS NULL
+        ORDER BY date ASC, "order" ASC
+        "#,
+    )
+    .bind(schedule_internal_id)
+    .fetch_all(&pool)
+    .await
+    .expect("failed to fetch traffics");
+
+    let mut traffics: Vec<PublicTraffic> = rows
+        .into_iter()
+        .map(|row| row_to_public_traffic(row, &params.schedule_id))
+        .collect();
+
+    // スケジュールのユーザーIDを取得してマスク処理を適用
+    let user_id: Option<i64> = sqlx::query_scalar("SELECT user_id FROM schedules WHERE id = ?")
+        .bind(schedule_internal_id)
+        .fetch_optional(&pool)
+        .await
+        .ok()
+        .flatten();
+
+    if let Some(uid) = user_id {
+        if let Ok(masked_locations) = get_masked_locations_for_user(&pool, uid).await {
+            traffics = traffics.into_iter()
+                .map(|t| apply_mask_to_public_traffic(t, &masked_locations))
+                .collect();
+        }
+    }
+
+    Json(traffics)
+}
+
+// GET /public/stay?schedule_id=... - 公開スケジュールの宿泊情報
+// DISABLE_AUTHが設定されている場合、全てのスケジュールの宿泊情報を返す
+async fn list_public_stays(
+    Query(params): Query<PublicStayQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Json<Vec<PublicStay>> {
+    // DISABLE_AUTHが設定されている場合、is_publicの条件を無視
+    let schedule_exists: Option<i64> = if std::env::var("DISABLE_AUTH").is_ok() {
+        sqlx::query_scalar("SELECT id FROM schedules WHERE public_id = ? AND deleted_at IS NULL")
+            .bind(&params.schedule_id)
+            .fetch_optional(&pool)
+            .await
+            .expect("failed to check schedule")
+    } else {
+        sqlx::query_scalar("SELECT id FROM schedules WHERE public_id = ? AND CAST(is_public AS INTEGER) = 1 AND deleted_at IS NULL")
+            .bind(&params.schedule_id)
+            .fetch_optional(&pool)
+            .await
+            .expect("failed to check schedule")
+    };
+
+    let Some(schedule_internal_id) = schedule_exists else {
+        return Json(vec![]);
+    };
+
+    let rows: Vec<PublicStayRow> = sqlx::query_as::<_, PublicStayRow>(
+        r#"
+        SELECT
+          public_id,
+          check_in,
+          check_out,
+          hotel_name,
+          website,
+          fee,
+          fee_currency,
+          breakfast_flag,
+          deadline,
+          penalty,
+          status
+        FROM stays
+        WHERE schedule_id = ? AND deleted_at IS NULL
+        "#,
+    )
+    .bind(schedule_internal_id)
+    .fetch_all(&pool)
+    .await
+    .expect("failed to fetch stays");
+
+    let stays = rows
+        .into_iter()
+        .map(|row| row_to_public_stay(row, &params.schedule_id))
+        .collect();
+    Json(stays)
+}
+
+// GET /public/traffic/:id - 公開スケジュールの交通情報（個別）
+async fn get_public_traffic(
+    Path(public_id): Path<String>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<PublicTraffic>, StatusCode> {
+    let schedule_internal_id: Option<i64> = sqlx::query_scalar(
+        "SELECT schedule_id FROM traffics WHERE public_id = ? AND deleted_at IS NULL",
+    )
+    .bind(&public_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let schedule_internal_id = schedule_internal_id.ok_or(StatusCode::NOT_FOUND)?;
+
+    let row: Option<PublicTrafficRow> = sqlx::query_as::<_, PublicTrafficRow>(
+        r#"
+        SELECT
+          public_id,
+          date,
+          "order",
+          transportation,
+          from_place,
+          to_place,
+          notes,
+          fare,
+          fare_currency,
+          miles,
+          return_flag,
+          total_fare,
+          total_miles
+        FROM traffics
+        WHERE public_id = ?
+        "#,
+    )
+    .bind(&public_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let row = row.ok_or(StatusCode::NOT_FOUND)?;
+
+    // 関連するスケジュールが公開されているか確認
+    let schedule: Option<(i64, Option<String>)> = if std::env::var("DISABLE_AUTH").is_ok() {
+        sqlx::query_as("SELECT id, public_id FROM schedules WHERE id = ? AND deleted_at IS NULL")
+            .bind(schedule_internal_id)
+            .fetch_optional(&pool)
+            .await
+            .expect("failed to check schedule")
+    } else {
+        sqlx::query_as("SELECT id, public_id FROM schedules WHERE id = ? AND CAST(is_public AS INTEGER) = 1 AND deleted_at IS NULL")
+            .bind(schedule_internal_id)
+            .fetch_optional(&pool)
+            .await
+            .expect("failed to check schedule")
+    };
+
+    let Some((_, Some(schedule_public_id))) = schedule else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let mut traffic = row_to_public_traffic(row, &schedule_public_id);
+
+    // スケジュールのユーザーIDを取得してマスク処理を適用
+    let user_id: Option<i64> = sqlx::query_scalar("SELECT user_id FROM schedules WHERE id = ?")
+        .bind(schedule_internal_id)
+        .fetch_optional(&pool)
+        .await
+        .ok()
+        .flatten();
+
+    if let Some(uid) = user_id {
+        if let Ok(masked_locations) = get_masked_locations_for_user(&pool, uid).await {
+            traffic = apply_mask_to_public_traffic(traffic, &masked_locations);
+        }
+    }
+
+    Ok(Json(traffic))
+}
+
+// GET /public/stay/:id - 公開スケジュールの宿泊情報（個別）
+async fn get_public_stay(
+    Path(public_id): Path<String>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<PublicStay>, StatusCode> {
+    let schedule_internal_id: Option<i64> = sqlx::query_scalar(
+        "SELECT schedule_id FROM stays WHERE public_id = ? AND deleted_at IS NULL",
+    )
+    .bind(&public_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let schedule_internal_id = schedule_internal_id.ok_or(StatusCode::NOT_FOUND)?;
+
+    let row: Option<PublicStayRow> = sqlx::query_as::<_, PublicStayRow>(
+        r#"
+        SELECT
+          public_id,
+          check_in,
+          check_out,
+          hotel_name,
+          website,
+          fee,
+          fee_currency,
+          breakfast_flag,
+          deadline,
+          penalty,
+          status
+        FROM stays
+        WHERE public_id = ?
+        "#,
+    )
+    .bind(&public_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let row = row.ok_or(StatusCode::NOT_FOUND)?;
+
+    // 関連するスケジュールが公開されているか確認
+    let schedule: Option<(i64, Option<String>)> = if std::env::var("DISABLE_AUTH").is_ok() {
+        sqlx::query_as("SELECT id, public_id FROM schedules WHERE id = ? AND deleted_at IS NULL")
+            .bind(schedule_internal_id)
+            .fetch_optional(&pool)
+            .await
+            .expect("failed to check schedule")
+    } else {
+        sqlx::query_as("SELECT id, public_id FROM schedules WHERE id = ? AND CAST(is_public AS INTEGER) = 1 AND deleted_at IS NULL")
+            .bind(schedule_internal_id)
+            .fetch_optional(&pool)
+            .await
+            .expect("failed to check schedule")
+    };
+
+    let Some((_, Some(schedule_public_id))) = schedule else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    Ok(Json(row_to_public_stay(row, &schedule_public_id)))
+}
+
+// GET /share/:share_id/traffic/:id - 共有ページ用の交通情報（個別）
+async fn list_shared_traffics(
+    Path(share_id): Path<String>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<PublicTraffic>>, (StatusCode, Json<ErrorResponse>)> {
+    let user_id = resolve_active_share_owner(&pool, &share_id).await?;
+
+    let rows: Vec<SharedTrafficRow> = sqlx::query_as::<_, SharedTrafficRow>(
+        r#"
+        SELECT t.public_id, t.date, t."order", t.transportation,
+               t.from_place, t.to_place, t.notes, t.fare, t.fare_currency, t.miles,
+               t.return_flag, t.total_fare, t.total_miles,
+               s.public_id AS schedule_public_id
+        FROM traffics t
+        INNER JOIN schedules s ON s.id = t.schedule_id
+        WHERE s.user_id = ? AND CAST(s.is_public AS INTEGER) = 1 AND s.deleted_at IS NULL AND t.deleted_at IS NULL
+        ORDER BY t.date ASC, t."order" ASC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "Database error".to_string() })))?;
+
+    let masked_locations = get_masked_locations_for_user(&pool, user_id).await.unwrap_or_default();
+    let traffics = rows.into_iter()
+        .map(shared_row_to_public_traffic)
+        .map(|traffic| apply_mask_to_public_traffic(traffic, &masked_locations))
+        .collect();
+    Ok(Json(traffics))
+}
+
+async fn get_shared_traffic(
+    Path((share_id, public_id)): Path<(String, String)>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<PublicTraffic>, (StatusCode, Json<ErrorResponse>)> {
+    // share_idからユーザーIDを取得
+    let user_row: Option<(i64, i32, String, Option<String>)> = sqlx::query_as(
+        "SELECT id, sharing_enabled, plan, trial_started_at FROM users WHERE share_id = ?"
+    )
+    .bind(&share_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[GetSharedTraffic] Database error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Database error".to_string(),
+            }),
+        )
+    })?;
+
+    if let Some((user_id, sharing_enabled, plan, trial_started_at)) = user_row {
+        if sharing_enabled == 0 || !has_paid_access(&plan, trial_started_at.as_deref()) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(ErrorResponse {
+                    error: "このユーザーのスケジュールは共有されていません".to_string(),
+                }),
+            ));
+        }
+
+        // 交通情報を取得（このユーザーの公開スケジュールに属するもののみ）
+        let row: Option<SharedTrafficRow> = sqlx::query_as::<_, SharedTrafficRow>(
+            r#"
+            SELECT t.public_id, t.date, t."order", t.transportation,
+                   t.from_place, t.to_place, t.notes, t.fare, t.fare_currency, t.miles,
+                   t.return_flag, t.total_fare, t.total_miles,
+                   s.public_id AS schedule_public_id
+            FROM traffics t
+            INNER JOIN schedules s ON s.id = t.schedule_id
+            WHERE t.public_id = ? AND s.user_id = ? AND CAST(s.is_public AS INTEGER) = 1 AND s.deleted_at IS NULL AND t.deleted_at IS NULL
+            "#,
+        )
+        .bind(&public_id)
+        .bind(user_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[GetSharedTraffic] Database error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "Database error".to_string(),
+                }),
+            )
+        })?;
+
+        let row = row.ok_or((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Traffic not found".to_string(),
+            }),
+        ))?;
+
+        let mut traffic = shared_row_to_public_traffic(row);
+
+        // マスク処理を適用
+        if let Ok(masked_locations) = get_masked_locations_for_user(&pool, user_id).await {
+            traffic = apply_mask_to_public_traffic(traffic, &masked_locations);
+        }
+
+        Ok(Json(traffic))
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "User not found".to_string(),
+            }),
+        ))
+    }
+}
+
+// GET /share/:share_id/stay/:id - 共有ページ用の宿泊情報（個別）
+async fn get_shared_stay(
+    Path((share_id, public_id)): Path<(String, String)>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<PublicStay>, (StatusCode, Json<ErrorResponse>)> {
+    // share_idからユーザーIDを取得
+    let user_row: Option<(i64, i32, String, Option<String>)> = sqlx::query_as(
+        "SELECT id, sharing_enabled, plan, trial_started_at FROM users WHERE share_id = ?"
+    )
+    .bind(&share_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[GetSharedStay] Database error: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Database error".to_string(),
+            }),
+        )
+    })?;
+
+    if let Some((user_id, sharing_enabled, plan, trial_started_at)) = user_row {
+        if sharing_enabled == 0 || !has_paid_access(&plan, trial_started_at.as_deref()) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                Json(ErrorResponse {
+                    error: "このユーザーのスケジュールは共有されていません".to_string(),
+                }),
+            ));
+        }
+
+        // 宿泊情報を取得（このユーザーの公開スケジュールに属するもののみ）
+        let row: Option<SharedStayRow> = sqlx::query_as::<_, SharedStayRow>(
+            r#"
+            SELECT st.public_id, st.check_in, st.check_out, st.hotel_name,
+                   st.website, st.fee, st.fee_currency, st.breakfast_flag, st.deadline,
+                   st.penalty, st.status,
+                   s.public_id AS schedule_public_id
+            FROM stays st
+            INNER JOIN schedules s ON s.id = st.schedule_id
+            WHERE st.public_id = ? AND s.user_id = ? AND CAST(s.is_public AS INTEGER) = 1 AND s.deleted_at IS NULL AND st.deleted_at IS NULL
+            "#,
+        )
+        .bind(&public_id)
+        .bind(user_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[GetSharedStay] Database error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "Database error".to_string(),
+                }),
+            )
+        })?;
+
+        let row = row.ok_or((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "Stay not found".to_string(),
+            }),
+        ))?;
+
+        Ok(Json(shared_row_to_public_stay(row)))
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "User not found".to_string(),
+            }),
+        ))
+    }
+}
+
+// GET /traffic?schedule_id=...
+async fn list_traffics(
+    user: AuthenticatedUser,
+    Query(params): Query<TrafficQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Json<Vec<Traffic>> {
+    let rows: Vec<TrafficRow> = sqlx::query_as::<_, TrafficRow>(
+        r#"
+        SELECT
+          t.id,
+          t.schedule_id,
+          t.date,
+          t."order",
+          t.transportation,
+          t.from_place,
+          t.to_place,
+          t.notes,
+          t.fare,
+          t.fare_currency,
+          t.miles,
+          t.return_flag,
+          t.total_fare,
+          t.total_miles
+        FROM traffics t
+        INNER JOIN schedules s ON t.schedule_id = s.id
+        WHERE t.schedule_id = ? AND s.user_id = ? AND s.deleted_at IS NULL AND t.deleted_at IS NULL
+        ORDER BY t."order" ASC
+        "#,
+    )
+    .bind(params.schedule_id)
+    .bind(user.user_id as i64)
+    .fetch_all(&pool)
+    .await
+    .expect("failed to fetch traffics");
+
+    let traffics = rows.into_iter().map(row_to_traffic).collect();
+    Json(traffics)
+}
+
+// GET /traffic/:id
+async fn get_traffic(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<(HeaderMap, Json<Traffic>), StatusCode> {
+    let (traffic, etag) = fetch_traffic_with_etag(&pool, id as i64)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // スケジュールの所有者を確認（スケジュールが存在しない場合も含め、
+    // 所有者が確認できなければアクセスを拒否する）
+    let schedule_user_id: Option<i64> = sqlx::query_scalar(
+        "SELECT user_id FROM schedules WHERE id = ? AND deleted_at IS NULL",
+    )
+    .bind(traffic.schedule_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match schedule_user_id {
+        Some(schedule_user_id) if schedule_user_id == user.user_id as i64 => {}
+        _ => return Err(StatusCode::FORBIDDEN),
+    }
+
+    Ok((etag_header(&etag), Json(traffic)))
+}
+
+// POST /traffic
+async fn create_traffic(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewTraffic>,
+) -> Result<(StatusCode, Json<Traffic>), StatusCode> {
+    // スケジュールの所有者を確認
+    let schedule_user_id: Option<i64> = sqlx::query_scalar(
+        "SELECT user_id FROM schedules WHERE id = ? AND deleted_at IS NULL",
+    )
+    .bind(payload.schedule_id as i64)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(schedule_user_id) = schedule_user_id {
+        if schedule_user_id != user.user_id as i64 {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    } else {
+        // スケジュールが存在しない場合
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let fare_currency = match resolve_currency(&pool, user.user_id, payload.fare_currency.as_deref()).await {
+        Ok(Some(currency)) => currency,
+        Ok(None) => fetch_user_home_currency(&pool, user.user_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        Err((status, _)) => return Err(status),
+    };
+
+    let now = Utc::now().to_rfc3339();
+    let result = sqlx::query(
+        r#"
+        INSERT INTO traffics (
+          schedule_id,
+          date,
+          "order",
+          transportation,
+          from_place,
+          to_place,
+          notes,
+          fare,
+          fare_currency,
+          miles,
+          return_flag,
+          total_fare,
+          total_miles,
+          public_id,
+          created_at,
+          updated_at
+        ) VALUES (
+          ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, NULL, NULL, ?, ?, ?
+        )
+        "#,
+    )
+    .bind(payload.schedule_id)
+    .bind(&payload.date)
+    .bind(payload.order)
+    .bind(&payload.transportation)
+    .bind(&payload.from)
+    .bind(&payload.to)
+    .bind(&payload.notes)
+    .bind(payload.fare)
+    .bind(&fare_currency)
+    .bind(payload.miles)
+    .bind(if payload.return_flag { 1 } else { 0 })
+    .bind(generate_public_id())
+    .bind(&now)
+    .bind(&now)
+    .execute(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let last_id = result.last_insert_rowid();
+
+    // 関連するスケジュールのロールアップ計算を実行
+    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+    refresh_search_index(&pool, payload.schedule_id as i64).await;
+    track_revision(&pool, user.user_id, RevisionEntity::Traffic, last_id, payload.schedule_id as i64, RevisionAction::Create, None).await;
+
+    let row: TrafficRow = sqlx::query_as::<_, TrafficRow>(
+        r#"
+        SELECT
+          id,
+          schedule_id,
+          date,
+          "order",
+          transportation,
+          from_place,
+          to_place,
+          notes,
+          fare,
+          fare_currency,
+          miles,
+          return_flag,
+          total_fare,
+          total_miles
+        FROM traffics
+        WHERE id = ?
+        "#,
+    )
+    .bind(last_id)
+    .fetch_one(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut traffic = row_to_traffic(row);
+    traffic.warnings = itinerary_warnings_for_entity(&pool, user.user_id, payload.schedule_id as i64, |w| {
+        w.traffic_id == Some(last_id as i32)
+    })
+    .await;
+
+    Ok((StatusCode::CREATED, Json(traffic)))
+}
+
+// PUT /traffic/:id
+async fn update_traffic(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    headers: HeaderMap,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewTraffic>,
+) -> Result<(HeaderMap, Json<Traffic>), WriteError<StatusCode>> {
+    // 更新対象のtrafficが現在所属しているスケジュールの所有者を確認
+    let current_schedule_id: Option<i64> = sqlx::query_scalar(
+        "SELECT schedule_id FROM traffics WHERE id = ? AND deleted_at IS NULL",
+    )
+    .bind(id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let current_schedule_id = current_schedule_id.ok_or(StatusCode::NOT_FOUND)?;
+
+    // 付け替え先（またはそのまま）のスケジュールの所有者も併せて確認し、
+    // 他人のスケジュールへ再割り当てできないようにする
+    for schedule_id in [current_schedule_id, payload.schedule_id as i64] {
+        let schedule_user_id: Option<i64> = sqlx::query_scalar(
+            "SELECT user_id FROM schedules WHERE id = ? AND deleted_at IS NULL",
+        )
+        .bind(schedule_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        match schedule_user_id {
+            Some(schedule_user_id) if schedule_user_id == user.user_id as i64 => {}
+            _ => return Err(StatusCode::FORBIDDEN.into()),
+        }
+    }
+
+    // If-Matchが指定されている場合は、取得時点から変更されていないことを確認する
+    let current_updated_at = row_updated_at(&pool, "traffics", id as i64)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !if_match_satisfied(&headers, &etag_for(current_updated_at.as_deref())) {
+        return Err(precondition_failed(
+            fetch_traffic_with_etag(&pool, id as i64).await,
+            StatusCode::NOT_FOUND,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ));
+    }
+    let guard = if_match_guard(&headers, current_updated_at.as_deref());
+
+    let fare_currency = resolve_currency(&pool, user.user_id, payload.fare_currency.as_deref())
+        .await
+        .map_err(|(status, _)| status)?;
+
+    let now = Utc::now().to_rfc3339();
+    let before = revision_snapshot(&pool, RevisionEntity::Traffic, id as i64).await;
+    let result = sqlx::query(
+        r#"
+        UPDATE traffics SET
+          schedule_id = ?,
+          date = ?,
+          "order" = ?,
+          transportation = ?,
+          from_place = ?,
+          to_place = ?,
+          notes = ?,
+          fare = ?,
+          fare_currency = COALESCE(?, fare_currency),
+          miles = ?,
+          return_flag = ?,
+          updated_at = ?
+        WHERE id = ? AND updated_at IS COALESCE(?, updated_at)
+        "#,
+    )
+    .bind(payload.schedule_id)
+    .bind(&payload.date)
+    .bind(payload.order)
+    .bind(&payload.transportation)
+    .bind(&payload.from)
+    .bind(&payload.to)
+    .bind(&payload.notes)
+    .bind(payload.fare)
+    .bind(&fare_currency)
+    .bind(payload.miles)
+    .bind(if payload.return_flag { 1 } else { 0 })
+    .bind(&now)
+    .bind(id)
+    .bind(&guard)
+    .execute(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if result.rows_affected() == 0 {
+        if guard.is_some() {
+            return Err(precondition_failed(
+                fetch_traffic_with_etag(&pool, id as i64).await,
+                StatusCode::NOT_FOUND,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    // 関連するスケジュールのロールアップ計算を実行
+    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+    refresh_search_index_for_entity(&pool, "traffic", id as i64, payload.schedule_id as i64).await;
+    track_revision(&pool, user.user_id, RevisionEntity::Traffic, id as i64, payload.schedule_id as i64, RevisionAction::Update, before).await;
+
+    let (mut traffic, etag) = fetch_traffic_with_etag(&pool, id as i64)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    traffic.warnings = itinerary_warnings_for_entity(&pool, user.user_id, payload.schedule_id as i64, |w| {
+        w.traffic_id == Some(id)
+    })
+    .await;
+
+    Ok((etag_header(&etag), Json(traffic)))
+}
+
+// GET /traffic/all - ユーザーが所有するすべてのTrafficを取得
+async fn list_all_traffics(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Json<Vec<Traffic>> {
+    let rows: Vec<TrafficRow> = sqlx::query_as::<_, TrafficRow>(
+        r#"
+        SELECT
+          t.id,
+          t.schedule_id,
+          t.date,
+          t."order",
+          t.transportation,
+          t.from_place,
+          t.to_place,
+          t.notes,
+          t.fare,
+          t.fare_currency,
+          t.miles,
+          t.return_flag,
+          t.total_fare,
+          t.total_miles
+        FROM traffics t
+        INNER JOIN schedules s ON t.schedule_id = s.id
+        WHERE s.user_id = ? AND s.deleted_at IS NULL AND t.deleted_at IS NULL
+        ORDER BY t.date ASC, t."order" ASC
+        "#,
+    )
+    .bind(user.user_id as i64)
+    .fetch_all(&pool)
+    .await
+    .unwrap_or_else(|e| {
+        eprintln!("Error fetching traffics: {}", e);
+        vec![]
+    });
+
+    let traffics = rows.into_iter().map(row_to_traffic).collect();
+    Json(traffics)
+}
+
+// GET /stay/all - ユーザーが所有するすべてのStayを取得
+async fn list_all_stays(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<Stay>>, (StatusCode, Json<ErrorResponse>)> {
+    let rows: Vec<StayRow> = sqlx::query_as::<_, StayRow>(
+        r#"
+        SELECT
+          st.id,
+          st.schedule_id,
+          st.check_in,
+          st.check_out,
+          st.hotel_name,
+          st.website,
+          st.fee,
+          st.fee_currency,
+          st.breakfast_flag,
+          st.deadline,
+          st.penalty,
+          st.status
+        FROM stays st
+        INNER JOIN schedules s ON st.schedule_id = s.id
+        WHERE s.user_id = ? AND s.deleted_at IS NULL AND st.deleted_at IS NULL
+        ORDER BY st.check_in ASC
+        "#,
+    )
+    .bind(user.user_id as i64)
+    .fetch_all(&pool)
+    .await
+    .unwrap_or_else(|e| {
+        eprintln!("Error fetching stays: {}", e);
+        vec![]
+    });
+
+    let mut stays: Vec<Stay> = rows.into_iter().map(row_to_stay).collect();
+
+    let (plan, trial_started_at) = fetch_user_plan(&pool, user.user_id).await.map_err(|e| {
+        eprintln!("Error fetching plan for user {}: {}", user.user_id, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Database error".to_string(),
+            }),
+        )
+    })?;
+    if !has_paid_access(&plan, trial_started_at.as_deref()) {
+        // 無料プランは過去アーカイブを直近2年分（今年+去年）のみ閲覧可能。未来の予定は制限しない
+        let user_timezone = fetch_user_timezone(&pool, user.user_id).await.map_err(|e| {
+            eprintln!("Error fetching timezone for user {}: {}", user.user_id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "Database error".to_string(),
+                }),
+            )
+        })?;
+        let earliest_visible_year = free_plan_earliest_archive_year(Utc::now().with_timezone(&user_timezone));
+        stays = stays
+            .into_iter()
+            .filter(|s| parse_year_prefix(&s.check_in).map_or(true, |y| y >= earliest_visible_year))
+            .collect();
+    }
+
+    Ok(Json(stays))
+}
+
+// GET /stay?schedule_id=...
+async fn list_stays(
+    user: AuthenticatedUser,
+    Query(params): Query<StayQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Json<Vec<Stay>> {
+    let rows: Vec<StayRow> = sqlx::query_as::<_, StayRow>(
+        r#"
+        SELECT
+          st.id,
+          st.schedule_id,
+          st.check_in,
+          st.check_out,
+          st.hotel_name,
+          st.website,
+          st.fee,
+          st.fee_currency,
+          st.breakfast_flag,
+          st.deadline,
+          st.penalty,
+          st.status
+        FROM stays st
+        INNER JOIN schedules s ON st.schedule_id = s.id
+        WHERE st.schedule_id = ? AND s.user_id = ? AND s.deleted_at IS NULL AND st.deleted_at IS NULL
+        "#,
+    )
+    .bind(params.schedule_id)
+    .bind(user.user_id as i64)
+    .fetch_all(&pool)
+    .await
+    .expect("failed to fetch stays");
+
+    let stays = rows.into_iter().map(row_to_stay).collect();
+    Json(stays)
+}
+
+// GET /stay/:id
+async fn get_stay(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<(HeaderMap, Json<Stay>), StatusCode> {
+    let (stay, etag) = fetch_stay_with_etag(&pool, id as i64)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // スケジュールの所有者を確認（スケジュールが存在しない場合も含め、
+    // 所有者が確認できなければアクセスを拒否する）
+    let schedule_user_id: Option<i64> = sqlx::query_scalar(
+        "SELECT user_id FROM schedules WHERE id = ? AND deleted_at IS NULL",
+    )
+    .bind(stay.schedule_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match schedule_user_id {
+        Some(schedule_user_id) if schedule_user_id == user.user_id as i64 => {}
+        _ => return Err(StatusCode::FORBIDDEN),
+    }
+
+    Ok((etag_header(&etag), Json(stay)))
+}
+
+// POST /stay
+async fn create_stay(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewStay>,
+) -> Result<(StatusCode, Json<Stay>), StatusCode> {
+    // スケジュールの所有者を確認
+    let schedule_user_id: Option<i64> = sqlx::query_scalar(
+        "SELECT user_id FROM schedules WHERE id = ? AND deleted_at IS NULL",
+    )
+    .bind(payload.schedule_id as i64)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(schedule_user_id) = schedule_user_id {
+        if schedule_user_id != user.user_id as i64 {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    } else {
+        // スケジュールが存在しない場合
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let fee_currency = match resolve_currency(&pool, user.user_id, payload.fee_currency.as_deref()).await {
+        Ok(Some(currency)) => currency,
+        Ok(None) => fetch_user_home_currency(&pool, user.user_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        Err((status, _)) => return Err(status),
+    };
+
+    let now = Utc::now().to_rfc3339();
+    let result = sqlx::query(
+        r#"
+        INSERT INTO stays (
+          schedule_id,
+          check_in,
+          check_out,
+          hotel_name,
+          website,
+          fee,
+          fee_currency,
+          breakfast_flag,
+          deadline,
+          penalty,
+          status,
+          public_id,
+          created_at,
+          updated_at
+        ) VALUES (
+          ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
+        )
+        "#,
+    )
+    .bind(payload.schedule_id)
+    .bind(&payload.check_in)
+    .bind(&payload.check_out)
+    .bind(&payload.hotel_name)
+    .bind(&payload.website)
+    .bind(payload.fee)
+    .bind(&fee_currency)
+    .bind(if payload.breakfast_flag { 1 } else { 0 })
+    .bind(&payload.deadline)
+    .bind(payload.penalty)
+    .bind(payload.status.as_deref().unwrap_or("Keep"))
+    .bind(generate_public_id())
+    .bind(&now)
+    .bind(&now)
+    .execute(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let last_id = result.last_insert_rowid();
+
+    // 関連するスケジュールのロールアップ計算を実行
+    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+    refresh_search_index(&pool, payload.schedule_id as i64).await;
+    track_revision(&pool, user.user_id, RevisionEntity::Stay, last_id, payload.schedule_id as i64, RevisionAction::Create, None).await;
+
+    let row: StayRow = sqlx::query_as::<_, StayRow>(
+        r#"
+        SELECT
+          id,
+          schedule_id,
+          check_in,
+          check_out,
+          hotel_name,
+          website,
+          fee,
+          fee_currency,
+          breakfast_flag,
+          deadline,
+          penalty,
+          status
+        FROM stays
+        WHERE id = ?
+        "#,
+    )
+    .bind(last_id)
+    .fetch_one(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut stay = row_to_stay(row);
+    stay.warnings = itinerary_warnings_for_entity(&pool, user.user_id, payload.schedule_id as i64, |w| {
+        w.stay_id == Some(last_id as i32)
+    })
+    .await;
+
+    Ok((StatusCode::CREATED, Json(stay)))
+}
+
+// PUT /stay/:id
+async fn update_stay(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    headers: HeaderMap,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewStay>,
+) -> Result<(HeaderMap, Json<Stay>), WriteError<StatusCode>> {
+    // 更新対象のstayが現在所属しているスケジュールの所有者を確認
+    let current_schedule_id: Option<i64> = sqlx::query_scalar(
+        "SELECT schedule_id FROM stays WHERE id = ? AND deleted_at IS NULL",
+    )
+    .bind(id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let current_schedule_id = current_schedule_id.ok_or(StatusCode::NOT_FOUND)?;
+
+    // 付け替え先（またはそのまま）のスケジュールの所有者も併せて確認し、
+    // 他人のスケジュールへ再割り当てできないようにする
+    for schedule_id in [current_schedule_id, payload.schedule_id as i64] {
+        let schedule_user_id: Option<i64> = sqlx::query_scalar(
+            "SELECT user_id FROM schedules WHERE id = ? AND deleted_at IS NULL",
+        )
+        .bind(schedule_id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        match schedule_user_id {
+            Some(schedule_user_id) if schedule_user_id == user.user_id as i64 => {}
+            _ => return Err(StatusCode::FORBIDDEN.into()),
+        }
+    }
+
+    // If-Matchが指定されている場合は、取得時点から変更されていないことを確認する
+    let current_updated_at = row_updated_at(&pool, "stays", id as i64)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !if_match_satisfied(&headers, &etag_for(current_updated_at.as_deref())) {
+        return Err(precondition_failed(
+            fetch_stay_with_etag(&pool, id as i64).await,
+            StatusCode::NOT_FOUND,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ));
+    }
+    let guard = if_match_guard(&headers, current_updated_at.as_deref());
+
+    let fee_currency = resolve_currency(&pool, user.user_id, payload.fee_currency.as_deref())
+        .await
+        .map_err(|(status, _)| status)?;
+
+    let now = Utc::now().to_rfc3339();
+    let before = revision_snapshot(&pool, RevisionEntity::Stay, id as i64).await;
+    let result = sqlx::query(
+        r#"
+        UPDATE stays SET
+          schedule_id = ?,
+          check_in = ?,
+          check_out = ?,
+          hotel_name = ?,
+          website = ?,
+          fee = ?,
+          fee_currency = COALESCE(?, fee_currency),
+          breakfast_flag = ?,
+          deadline = ?,
+          penalty = ?,
+          status = ?,
+          updated_at = ?
+        WHERE id = ? AND updated_at IS COALESCE(?, updated_at)
+        "#,
+    )
+    .bind(payload.schedule_id)
+    .bind(&payload.check_in)
+    .bind(&payload.check_out)
+    .bind(&payload.hotel_name)
+    .bind(&payload.website)
+    .bind(payload.fee)
+    .bind(&fee_currency)
+    .bind(if payload.breakfast_flag { 1 } else { 0 })
+    .bind(&payload.deadline)
+    .bind(payload.penalty)
+    .bind(payload.status.as_deref().unwrap_or("Keep"))
+    .bind(&now)
+    .bind(id)
+    .bind(&guard)
+    .execute(&pool)
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if result.rows_affected() == 0 {
+        if guard.is_some() {
+            return Err(precondition_failed(
+                fetch_stay_with_etag(&pool, id as i64).await,
+                StatusCode::NOT_FOUND,
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    // 関連するスケジュールのロールアップ計算を実行
+    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+    refresh_search_index_for_entity(&pool, "stay", id as i64, payload.schedule_id as i64).await;
+    track_revision(&pool, user.user_id, RevisionEntity::Stay, id as i64, payload.schedule_id as i64, RevisionAction::Update, before).await;
+
+    let (mut stay, etag) = fetch_stay_with_etag(&pool, id as i64)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    stay.warnings = itinerary_warnings_for_entity(&pool, user.user_id, payload.schedule_id as i64, |w| {
+        w.stay_id == Some(id)
+    })
+    .await;
+
+    Ok((etag_header(&etag), Json(stay)))
+}
+
+// ====== チケット申込（抽選）管理 ======
+
+// 抽選結果: applied（申込済み）/ won（当選）/ lost（落選）/ paid（支払済み）/ expired（支払期限切れ）
+const TICKET_APPLICATION_RESULTS: [&str; 5] = ["applied", "won", "lost", "paid", "expired"];
+
+#[derive(Serialize, Clone)]
+struct TicketApplication {
+    id: i32,
+    schedule_id: i32,
+    seller: Option<String>,
+    seat_type: Option<String>,
+    quantity: i32,
+    price: Option<i32>, // 1枚あたりの価格
+    currency: String,   // 価格の通貨
+    application_start: Option<String>,
+    application_end: Option<String>,
+    result_announced_at: Option<String>,
+    payment_deadline: Option<String>,
+    result: String,
+    notes: Option<String>,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct TicketApplicationRow {
+    id: i64,
+    schedule_id: i64,
+    seller: Option<String>,
+    seat_type: Option<String>,
+    quantity: i64,
+    price: Option<i32>,
+    currency: String,
+    application_start: Option<String>,
+    application_end: Option<String>,
+    result_announced_at: Option<String>,
+    payment_deadline: Option<String>,
+    result: String,
+    notes: Option<String>,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TicketApplicationQuery {
+    schedule_id: i32,
+}
+
+// POST /ticket-applications, PUT /ticket-applications/:id 用リクエストボディ
+#[derive(Deserialize)]
+struct NewTicketApplication {
+    schedule_id: i32,
+    seller: Option<String>,
+    seat_type: Option<String>,
+    quantity: Option<i32>,
+    price: Option<i32>,
+    currency: Option<String>, // 省略時は新規作成ではスケジュールのチケット代の通貨、更新では変更しない
+    application_start: Option<String>,
+    application_end: Option<String>,
+    result_announced_at: Option<String>,
+    payment_deadline: Option<String>,
+    result: Option<String>,
+    notes: Option<String>,
+}
+
+fn row_to_ticket_application(row: TicketApplicationRow) -> TicketApplication {
+    TicketApplication {
+        id: row.id as i32,
+        schedule_id: row.schedule_id as i32,
+        seller: row.seller,
+        seat_type: row.seat_type,
+        quantity: row.quantity as i32,
+        price: row.price,
+        currency: row.currency,
+        application_start: row.application_start,
+        application_end: row.application_end,
+        result_announced_at: row.result_announced_at,
+        payment_deadline: row.payment_deadline,
+        result: row.result,
+        notes: row.notes,
+        created_at: row.created_at,
+        updated_at: row.updated_at,
+    }
+}
+
+// 抽選結果の値を検証する（未指定の場合は "applied"）
+fn normalize_ticket_result(result: Option<&str>) -> Option<&'static str> {
+    match result.map(str::trim) {
+        None | Some("") => Some("applied"),
+        Some(value) => TICKET_APPLICATION_RESULTS.iter().copied().find(|r| *r == value),
+    }
+}
+
+#[cfg(test)]
+mod ticket_result_tests {
+    use super::normalize_ticket_result;
+
+    #[test]
+    fn defaults_to_applied() {
+        assert_eq!(normalize_ticket_result(None), Some("applied"));
+        assert_eq!(normalize_ticket_result(Some(" ")), Some("applied"));
+    }
+
+    #[test]
+    fn accepts_known_results_only() {
+        assert_eq!(normalize_ticket_result(Some("won")), Some("won"));
+        assert_eq!(normalize_ticket_result(Some("paid")), Some("paid"));
+        assert_eq!(normalize_ticket_result(Some("Won")), None);
+        assert_eq!(normalize_ticket_result(Some("canceled")), None);
+    }
+}
+
+// リクエストボディを検証し、保存する抽選結果を返す
+fn validate_ticket_application(
+    payload: &NewTicketApplication,
+) -> Result<&'static str, (StatusCode, Json<ErrorResponse>)> {
+    let result = normalize_ticket_result(payload.result.as_deref()).ok_or((
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            error: "抽選結果は applied / won / lost / paid / expired のいずれかを指定してください".to_string(),
+        }),
+    ))?;
+
+    if payload.quantity.is_some_and(|q| q < 1) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "枚数は1以上を指定してください".to_string(),
+            }),
+        ));
+    }
+    if payload.price.is_some_and(|p| p < 0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "価格は0以上を指定してください".to_string(),
+            }),
+        ));
+    }
+
+    // 支払期限の通知は保存した文字列をそのままパースするため、読めない日時は保存しない
+    let application_start = parse_application_datetime(payload.application_start.as_deref(), "申込受付開始日時")?;
+    let application_end = parse_application_datetime(payload.application_end.as_deref(), "申込受付終了日時")?;
+    parse_application_datetime(payload.result_announced_at.as_deref(), "結果発表日時")?;
+    parse_application_datetime(payload.payment_deadline.as_deref(), "支払期限")?;
+    if let (Some(start), Some(end)) = (application_start, application_end) {
+        if start > end {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: "申込受付終了日時は申込受付開始日時以降を指定してください".to_string(),
+                }),
+            ));
+        }
+    }
+
+    Ok(result)
+}
+
+// 申込の日時を検証する（"YYYY-MM-DD HH:MM" 形式またはRFC3339形式、未指定・空文字はNone）
+// 申込の日時はすべて同じスケジュールのタイムゾーンでの現地時刻のため、形式と前後関係の確認はUTCとして読めば足りる
+fn parse_application_datetime(
+    value: Option<&str>,
+    label: &str,
+) -> Result<Option<DateTime<Utc>>, (StatusCode, Json<ErrorResponse>)> {
+    match value {
+        None | Some("") => Ok(None),
+        Some(value) => parse_deadline_datetime(value, Tz::UTC).map(Some).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: format!("{}はYYYY-MM-DD HH:MM形式で指定してください", label),
+                }),
+            )
+        }),
+    }
+}
+
+#[cfg(test)]
+mod ticket_application_datetime_tests {
+    use super::parse_application_datetime;
+
+    #[test]
+    fn accepts_empty_and_known_formats() {
+        assert_eq!(parse_application_datetime(None, "支払期限").unwrap(), None);
+        assert_eq!(parse_application_datetime(Some(""), "支払期限").unwrap(), None);
+        assert!(parse_application_datetime(Some("2025-05-10 23:59"), "支払期限").unwrap().is_some());
+        assert!(parse_application_datetime(Some("2025-05-10T23:59:00+09:00"), "支払期限").unwrap().is_some());
+    }
+
+    #[test]
+    fn rejects_unparseable_values() {
+        assert!(parse_application_datetime(Some("2025/05/10"), "支払期限").is_err());
+        assert!(parse_application_datetime(Some("5月10日 23:59"), "支払期限").is_err());
+        assert!(parse_application_datetime(Some(" "), "支払期限").is_err());
+    }
+}
+
+async fn fetch_ticket_application(
+    pool: &Pool<Sqlite>,
+    id: i64,
+) -> Result<Option<TicketApplicationRow>, sqlx::Error> {
+    sqlx::query_as::<_, TicketApplicationRow>(
+        r#"
+        SELECT
+          id,
+          schedule_id,
+          seller,
+          seat_type,
+          quantity,
+          price,
+          currency,
+          application_start,
+          application_end,
+          result_announced_at,
+          payment_deadline,
+          result,
+          notes,
+          created_at,
+          updated_at
+        FROM ticket_applications
+        WHERE id = ?
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+}
+
+fn ticket_application_not_found() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "チケット申込が見つかりませんでした".to_string(),
+        }),
+    )
+}
+
+// GET /ticket-applications?schedule_id=1
+async fn list_ticket_applications(
+    user: AuthenticatedUser,
+    Query(params): Query<TicketApplicationQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<TicketApplication>>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, params.schedule_id as i64, user.user_id).await?;
+
+    let rows: Vec<TicketApplicationRow> = sqlx::query_as::<_, TicketApplicationRow>(
+        r#"
+        SELECT
+          id,
+          schedule_id,
+          seller,
+          seat_type,
+          quantity,
+          price,
+          currency,
+          application_start,
+          application_end,
+          result_announced_at,
+          payment_deadline,
+          result,
+          notes,
+          created_at,
+          updated_at
+        FROM ticket_applications
+        WHERE schedule_id = ?
+        ORDER BY COALESCE(application_end, application_start, created_at) ASC, id ASC
+        "#,
+    )
+    .bind(params.schedule_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[ListTicketApplications] Database error: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(rows.into_iter().map(row_to_ticket_application).collect()))
+}
+
+// GET /ticket-applications/:id
+async fn get_ticket_application(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<TicketApplication>, (StatusCode, Json<ErrorResponse>)> {
+    let row = fetch_ticket_application(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[GetTicketApplication] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(ticket_application_not_found)?;
+
+    ensure_schedule_owner(&pool, row.schedule_id, user.user_id).await?;
+
+    Ok(Json(row_to_ticket_application(row)))
+}
+
+// POST /ticket-applications
+async fn create_ticket_application(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewTicketApplication>,
+) -> Result<(StatusCode, Json<TicketApplication>), (StatusCode, Json<ErrorResponse>)> {
+    let result = validate_ticket_application(&payload)?;
+    ensure_schedule_owner(&pool, payload.schedule_id as i64, user.user_id).await?;
+    let currency = match resolve_currency(&pool, user.user_id, payload.currency.as_deref()).await? {
+        Some(currency) => currency,
+        None => sqlx::query_scalar("SELECT ticket_fee_currency FROM schedules WHERE id = ?")
+            .bind(payload.schedule_id)
+            .fetch_one(&pool)
+            .await
+            .map_err(|e| {
+                eprintln!("[CreateTicketApplication] Database error: {}", e);
+                database_error()
+            })?,
+    };
+
+    let now = Utc::now().to_rfc3339();
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO ticket_applications (
+          schedule_id,
+          seller,
+          seat_type,
+          quantity,
+          price,
+          currency,
+          application_start,
+          application_end,
+          result_announced_at,
+          payment_deadline,
+          result,
+          notes,
+          created_at,
+          updated_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(payload.schedule_id)
+    .bind(&payload.seller)
+    .bind(&payload.seat_type)
+    .bind(payload.quantity.unwrap_or(1))
+    .bind(payload.price)
+    .bind(&currency)
+    .bind(&payload.application_start)
+    .bind(&payload.application_end)
+    .bind(&payload.result_announced_at)
+    .bind(&payload.payment_deadline)
+    .bind(result)
+    .bind(&payload.notes)
+    .bind(&now)
+    .bind(&now)
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[CreateTicketApplication] Database error: {}", e);
+        database_error()
+    })?;
+
+    // 当選・支払済みの申込はticket_fee_effectiveに反映されるため、ロールアップを再計算する
+    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+
+    let row = fetch_ticket_application(&pool, inserted.last_insert_rowid())
+        .await
+        .map_err(|e| {
+            eprintln!("[CreateTicketApplication] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(ticket_application_not_found)?;
+
+    Ok((StatusCode::CREATED, Json(row_to_ticket_application(row))))
+}
+
+// PUT /ticket-applications/:id
+async fn update_ticket_application(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewTicketApplication>,
+) -> Result<Json<TicketApplication>, (StatusCode, Json<ErrorResponse>)> {
+    let result = validate_ticket_application(&payload)?;
+
+    let current = fetch_ticket_application(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[UpdateTicketApplication] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(ticket_application_not_found)?;
+
+    // 付け替え先のスケジュールも含めて所有者を確認する
+    ensure_schedule_owner(&pool, current.schedule_id, user.user_id).await?;
+    if current.schedule_id != payload.schedule_id as i64 {
+        ensure_schedule_owner(&pool, payload.schedule_id as i64, user.user_id).await?;
+    }
+    let currency = resolve_currency(&pool, user.user_id, payload.currency.as_deref()).await?;
+
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        r#"
+        UPDATE ticket_applications SET
+          schedule_id = ?,
+          seller = ?,
+          seat_type = ?,
+          quantity = ?,
+          price = ?,
+          currency = COALESCE(?, currency),
+          application_start = ?,
+          application_end = ?,
+          result_announced_at = ?,
+          payment_deadline = ?,
+          result = ?,
+          notes = ?,
+          updated_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(payload.schedule_id)
+    .bind(&payload.seller)
+    .bind(&payload.seat_type)
+    .bind(payload.quantity.unwrap_or(1))
+    .bind(payload.price)
+    .bind(&currency)
+    .bind(&payload.application_start)
+    .bind(&payload.application_end)
+    .bind(&payload.result_announced_at)
+    .bind(&payload.payment_deadline)
+    .bind(result)
+    .bind(&payload.notes)
+    .bind(&now)
+    .bind(id)
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[UpdateTicketApplication] Database error: {}", e);
+        database_error()
+    })?;
+
+    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+    if current.schedule_id != payload.schedule_id as i64 {
+        calculate_rollup(&pool, current.schedule_id).await.ok();
+    }
+
+    let row = fetch_ticket_application(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[UpdateTicketApplication] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(ticket_application_not_found)?;
+
+    Ok(Json(row_to_ticket_application(row)))
+}
+
+// DELETE /ticket-applications/:id
+async fn delete_ticket_application(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let current = fetch_ticket_application(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[DeleteTicketApplication] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(ticket_application_not_found)?;
+
+    ensure_schedule_owner(&pool, current.schedule_id, user.user_id).await?;
+
+    let mut tx = pool.begin().await.map_err(|e| {
+        eprintln!("[DeleteTicketApplication] Database error: {}", e);
+        database_error()
+    })?;
+
+    // 支払期限の通知が申込を参照しているため先に削除する
+    sqlx::query("DELETE FROM notifications WHERE ticket_application_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("[DeleteTicketApplication] Database error: {}", e);
+            database_error()
+        })?;
+    sqlx::query("DELETE FROM ticket_applications WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("[DeleteTicketApplication] Database error: {}", e);
+            database_error()
+        })?;
+
+    tx.commit().await.map_err(|e| {
+        eprintln!("[DeleteTicketApplication] Database error: {}", e);
+        database_error()
+    })?;
+
+    calculate_rollup(&pool, current.schedule_id).await.ok();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "チケット申込を削除しました"
+    })))
+}
+
+// ====== チケットの譲渡・リセール ======
+// 公式リセールや友人への譲渡で手放したチケットの記録。受取額から手数料を引いた金額をnet_costから差し引く
+
+// 譲渡の方法: resale（公式リセール）/ transfer（個人間の譲渡）/ other
+const TICKET_TRANSFER_METHODS: [&str; 3] = ["resale", "transfer", "other"];
+
+#[derive(Serialize, Clone)]
+struct TicketTransfer {
+    id: i32,
+    schedule_id: i32,
+    method: String,
+    counterparty: Option<String>, // 譲渡先（リセールの場合は空でもよい）
+    transferred_on: String,       // YYYY-MM-DD
+    quantity: i32,
+    price_received: i32, // 受け取った金額（合計）
+    fees: i32,           // 手数料・送料など
+    currency: String,    // price_received・feesの通貨
+    net_proceeds: i32,   // = price_received - fees
+    notes: Option<String>,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct TicketTransferRow {
+    id: i64,
+    schedule_id: i64,
+    method: String,
+    counterparty: Option<String>,
+    transferred_on: String,
+    quantity: i64,
+    price_received: i32,
+    fees: i32,
+    currency: String,
+    notes: Option<String>,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TicketTransferQuery {
+    schedule_id: i32,
+}
+
+// POST /ticket-transfers, PUT /ticket-transfers/:id 用リクエストボディ
+#[derive(Deserialize)]
+struct NewTicketTransfer {
+    schedule_id: i32,
+    method: Option<String>,
+    counterparty: Option<String>,
+    transferred_on: String,
+    quantity: Option<i32>,
+    price_received: Option<i32>,
+    fees: Option<i32>,
+    currency: Option<String>, // 省略時は新規作成ではユーザーの基準通貨、更新では変更しない
+    notes: Option<String>,
+}
+
+fn row_to_ticket_transfer(row: TicketTransferRow) -> TicketTransfer {
+    TicketTransfer {
+        id: row.id as i32,
+        schedule_id: row.schedule_id as i32,
+        method: row.method,
+        counterparty: row.counterparty,
+        transferred_on: row.transferred_on,
+        quantity: row.quantity as i32,
+        price_received: row.price_received,
+        fees: row.fees,
+        currency: row.currency,
+        net_proceeds: row.price_received - row.fees,
+        notes: row.notes,
+        created_at: row.created_at,
+        updated_at: row.updated_at,
+    }
+}
+
+// 譲渡の方法を検証する（未指定の場合は "transfer"）
+fn normalize_transfer_method(method: Option<&str>) -> Option<&'static str> {
+    match method.map(str::trim) {
+        None | Some("") => Some("transfer"),
+        Some(value) => TICKET_TRANSFER_METHODS.iter().copied().find(|m| *m == value),
+    }
+}
+
+#[cfg(test)]
+mod ticket_transfer_tests {
+    use super::normalize_transfer_method;
+
+    #[test]
+    fn defaults_to_transfer() {
+        assert_eq!(normalize_transfer_method(None), Some("transfer"));
+        assert_eq!(normalize_transfer_method(Some(" ")), Some("transfer"));
+    }
+
+    #[test]
+    fn accepts_known_methods_only() {
+        assert_eq!(normalize_transfer_method(Some("resale")), Some("resale"));
+        assert_eq!(normalize_transfer_method(Some("other")), Some("other"));
+        assert_eq!(normalize_transfer_method(Some("Resale")), None);
+        assert_eq!(normalize_transfer_method(Some("sold")), None);
+    }
+}
+
+fn ticket_transfer_bad_request(message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            error: message.to_string(),
+        }),
+    )
+}
+
+// 譲渡先（前後の空白を除き、空の場合はNULL）
+fn transfer_counterparty(payload: &NewTicketTransfer) -> Option<String> {
+    payload
+        .counterparty
+        .as_deref()
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(str::to_string)
+}
+
+// リクエストボディを検証し、保存する譲渡の方法を返す
+fn validate_ticket_transfer(
+    payload: &NewTicketTransfer,
+) -> Result<&'static str, (StatusCode, Json<ErrorResponse>)> {
+    let method = normalize_transfer_method(payload.method.as_deref())
+        .ok_or_else(|| ticket_transfer_bad_request("譲渡の方法は resale / transfer / other のいずれかを指定してください"))?;
+    if chrono::NaiveDate::parse_from_str(payload.transferred_on.trim(), "%Y-%m-%d").is_err() {
+        return Err(ticket_transfer_bad_request("譲渡日はYYYY-MM-DD形式で指定してください"));
+    }
+    if payload.quantity.is_some_and(|q| q < 1) {
+        return Err(ticket_transfer_bad_request("枚数は1以上を指定してください"));
+    }
+    if payload.price_received.is_some_and(|p| p < 0) || payload.fees.is_some_and(|f| f < 0) {
+        return Err(ticket_transfer_bad_request("受取額・手数料は0以上を指定してください"));
+    }
+    if transfer_counterparty(payload).is_some_and(|c| c.chars().count() > 50) {
+        return Err(ticket_transfer_bad_request("譲渡先は50文字以内で入力してください"));
+    }
+    Ok(method)
+}
+
+async fn fetch_ticket_transfer(
+    pool: &Pool<Sqlite>,
+    id: i64,
+) -> Result<Option<TicketTransferRow>, sqlx::Error> {
+    sqlx::query_as::<_, TicketTransferRow>(
+        r#"
+        SELECT
+          id,
+          schedule_id,
+          method,
+          counterparty,
+          transferred_on,
+          quantity,
+          price_received,
+          fees,
+          currency,
+          notes,
+          created_at,
+          updated_at
+        FROM ticket_transfers
+        WHERE id = ?
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+}
+
+fn ticket_transfer_not_found() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "譲渡・リセールの記録が見つかりませんでした".to_string(),
+        }),
+    )
+}
+
+// GET /ticket-transfers?schedule_id=1
+async fn list_ticket_transfers(
+    user: AuthenticatedUser,
+    Query(params): Query<TicketTransferQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<TicketTransfer>>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, params.schedule_id as i64, user.user_id).await?;
+
+    let rows: Vec<TicketTransferRow> = sqlx::query_as::<_, TicketTransferRow>(
+        r#"
+        SELECT
+          id,
+          schedule_id,
+          method,
+          counterparty,
+          transferred_on,
+          quantity,
+          price_received,
+          fees,
+          currency,
+          notes,
+          created_at,
+          updated_at
+        FROM ticket_transfers
+        WHERE schedule_id = ?
+        ORDER BY transferred_on ASC, id ASC
+        "#,
+    )
+    .bind(params.schedule_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[ListTicketTransfers] Database error: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(rows.into_iter().map(row_to_ticket_transfer).collect()))
+}
+
+// GET /ticket-transfers/:id
+async fn get_ticket_transfer(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<TicketTransfer>, (StatusCode, Json<ErrorResponse>)> {
+    let row = fetch_ticket_transfer(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[GetTicketTransfer] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(ticket_transfer_not_found)?;
+
+    ensure_schedule_owner(&pool, row.schedule_id, user.user_id).await?;
+
+    Ok(Json(row_to_ticket_transfer(row)))
+}
+
+// POST /ticket-transfers
+async fn create_ticket_transfer(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewTicketTransfer>,
+) -> Result<(StatusCode, Json<TicketTransfer>), (StatusCode, Json<ErrorResponse>)> {
+    let method = validate_ticket_transfer(&payload)?;
+    ensure_schedule_owner(&pool, payload.schedule_id as i64, user.user_id).await?;
+    let currency = match resolve_currency(&pool, user.user_id, payload.currency.as_deref()).await? {
+        Some(currency) => currency,
+        None => fetch_user_home_currency(&pool, user.user_id).await.map_err(|e| {
+            eprintln!("[CreateTicketTransfer] Database error: {}", e);
+            database_error()
+        })?,
+    };
+
+    let now = Utc::now().to_rfc3339();
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO ticket_transfers (
+          schedule_id,
+          method,
+          counterparty,
+          transferred_on,
+          quantity,
+          price_received,
+          fees,
+          currency,
+          notes,
+          created_at,
+          updated_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(payload.schedule_id)
+    .bind(method)
+    .bind(transfer_counterparty(&payload))
+    .bind(payload.transferred_on.trim())
+    .bind(payload.quantity.unwrap_or(1))
+    .bind(payload.price_received.unwrap_or(0))
+    .bind(payload.fees.unwrap_or(0))
+    .bind(&currency)
+    .bind(&payload.notes)
+    .bind(&now)
+    .bind(&now)
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[CreateTicketTransfer] Database error: {}", e);
+        database_error()
+    })?;
+
+    // 受取額はnet_costに反映されるため、ロールアップを再計算する
+    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+
+    let row = fetch_ticket_transfer(&pool, inserted.last_insert_rowid())
+        .await
+        .map_err(|e| {
+            eprintln!("[CreateTicketTransfer] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(ticket_transfer_not_found)?;
+
+    Ok((StatusCode::CREATED, Json(row_to_ticket_transfer(row))))
+}
+
+// PUT /ticket-transfers/:id
+async fn update_ticket_transfer(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewTicketTransfer>,
+) -> Result<Json<TicketTransfer>, (StatusCode, Json<ErrorResponse>)> {
+    let method = validate_ticket_transfer(&payload)?;
+
+    let current = fetch_ticket_transfer(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[UpdateTicketTransfer] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(ticket_transfer_not_found)?;
+
+    // 付け替え先のスケジュールも含めて所有者を確認する
+    ensure_schedule_owner(&pool, current.schedule_id, user.user_id).await?;
+    if current.schedule_id != payload.schedule_id as i64 {
+        ensure_schedule_owner(&pool, payload.schedule_id as i64, user.user_id).await?;
+    }
+    let currency = resolve_currency(&pool, user.user_id, payload.currency.as_deref()).await?;
+
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        r#"
+        UPDATE ticket_transfers SET
+          schedule_id = ?,
+          method = ?,
+          counterparty = ?,
+          transferred_on = ?,
+          quantity = ?,
+          price_received = ?,
+          fees = ?,
+          currency = COALESCE(?, currency),
+          notes = ?,
+          updated_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(payload.schedule_id)
+    .bind(method)
+    .bind(transfer_counterparty(&payload))
+    .bind(payload.transferred_on.trim())
+    .bind(payload.quantity.unwrap_or(1))
+    .bind(payload.price_received.unwrap_or(0))
+    .bind(payload.fees.unwrap_or(0))
+    .bind(&currency)
+    .bind(&payload.notes)
+    .bind(&now)
+    .bind(id)
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[UpdateTicketTransfer] Database error: {}", e);
+        database_error()
+    })?;
+
+    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+    if current.schedule_id != payload.schedule_id as i64 {
+        calculate_rollup(&pool, current.schedule_id).await.ok();
+    }
+
+    let row = fetch_ticket_transfer(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[UpdateTicketTransfer] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(ticket_transfer_not_found)?;
+
+    Ok(Json(row_to_ticket_transfer(row)))
+}
+
+// DELETE /ticket-transfers/:id
+async fn delete_ticket_transfer(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let current = fetch_ticket_transfer(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[DeleteTicketTransfer] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(ticket_transfer_not_found)?;
+
+    ensure_schedule_owner(&pool, current.schedule_id, user.user_id).await?;
+
+    sqlx::query("DELETE FROM ticket_transfers WHERE id = ?")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[DeleteTicketTransfer] Database error: {}", e);
+            database_error()
+        })?;
+
+    calculate_rollup(&pool, current.schedule_id).await.ok();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "譲渡・リセールの記録を削除しました"
+    })))
+}
+
+// ====== グッズ ======
+// 公演で買ったグッズをコレクションとして記録する。金額はスケジュールごとにgoods_costへ集計するが、
+// total_cost（チケット代・遠征費）には含めない。画像は添付ファイル（entity_type = goods）として保存する
+
+// 購入方法: venue（会場）/ online（通販・事後通販）/ mail_order（受注生産・予約）/ other
+const GOODS_CHANNELS: [&str; 4] = ["venue", "online", "mail_order", "other"];
+
+#[derive(Serialize, Clone)]
+struct Goods {
+    id: i32,
+    schedule_id: i32,
+    schedule_title: String,
+    schedule_date: Option<String>,
+    tour_id: Option<i32>,
+    name: String,
+    target: Option<String>, // アーティスト（省略時はスケジュールのお目当て）
+    price: Option<i32>,     // 1個あたりの価格
+    quantity: i32,
+    currency: String,
+    subtotal: i32, // = price × quantity（通貨はcurrency）
+    channel: String,
+    image_attachment_id: Option<i64>, // 最後に添付された画像（GET /attachments/:id で取得）
+    notes: Option<String>,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct GoodsRow {
+    id: i64,
+    schedule_id: i64,
+    schedule_title: String,
+    schedule_date: Option<String>,
+    tour_id: Option<i64>,
+    name: String,
+    target: Option<String>,
+    price: Option<i32>,
+    quantity: i64,
+    currency: String,
+    channel: String,
+    image_attachment_id: Option<i64>,
+    notes: Option<String>,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+// グッズの一覧・取得で使うSELECT（goodsの別名はg、schedulesの別名はs）
+const GOODS_SELECT: &str = r#"
+    SELECT
+      g.id,
+      g.schedule_id,
+      s.title AS schedule_title,
+      s.date AS schedule_date,
+      s.tour_id,
+      g.name,
+      g.target,
+      g.price,
+      g.quantity,
+      g.currency,
+      g.channel,
+      (
+        SELECT a.id FROM attachments a
+        WHERE a.entity_type = 'goods' AND a.entity_id = g.id AND a.content_type LIKE 'image/%'
+        ORDER BY a.id DESC
+        LIMIT 1
+      ) AS image_attachment_id,
+      g.notes,
+      g.created_at,
+      g.updated_at
+    FROM goods g
+    INNER JOIN schedules s ON s.id = g.schedule_id
+"#;
+
+#[derive(Debug, Deserialize)]
+struct GoodsQuery {
+    schedule_id: Option<i32>,
+    target: Option<String>,
+    tour_id: Option<i32>,
+    channel: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GoodsCollectionQuery {
+    group_by: Option<String>, // target（既定）/ tour
+}
+
+// POST /goods, PUT /goods/:id 用リクエストボディ
+#[derive(Deserialize)]
+struct NewGoods {
+    schedule_id: i32,
+    name: String,
+    target: Option<String>,
+    price: Option<i32>,
+    quantity: Option<i32>,
+    currency: Option<String>, // 省略時は新規作成ではユーザーの基準通貨、更新では変更しない
+    channel: Option<String>,
+    notes: Option<String>,
+}
+
+// アーティスト別・ツアー別のコレクション集計
+#[derive(Serialize, Debug, PartialEq)]
+struct GoodsCollectionGroup {
+    target: Option<String>,    // group_by=target の場合
+    tour_id: Option<i32>,      // group_by=tour の場合（ツアーに属さない公演のグッズはNULL）
+    tour_name: Option<String>,
+    item_count: i32, // 品目数
+    quantity: i32,   // 個数の合計
+    total_spend: i32, // 金額の合計（基準通貨に換算）
+    currency: String,
+}
+
+// コレクション集計の元になる行（グループ・通貨ごと）
+#[derive(sqlx::FromRow)]
+struct GoodsSpendRow {
+    target: Option<String>,
+    tour_id: Option<i64>,
+    tour_name: Option<String>,
+    currency: String,
+    item_count: i64,
+    quantity: i64,
+    amount: i64,
+}
+
+fn row_to_goods(row: GoodsRow) -> Goods {
+    Goods {
+        id: row.id as i32,
+        schedule_id: row.schedule_id as i32,
+        schedule_title: row.schedule_title,
+        schedule_date: row.schedule_date,
+        tour_id: row.tour_id.map(|id| id as i32),
+        name: row.name,
+        target: row.target,
+        price: row.price,
+        quantity: row.quantity as i32,
+        currency: row.currency,
+        subtotal: row.price.unwrap_or(0).saturating_mul(row.quantity as i32),
+        channel: row.channel,
+        image_attachment_id: row.image_attachment_id,
+        notes: row.notes,
+        created_at: row.created_at,
+        updated_at: row.updated_at,
+    }
+}
+
+// 購入方法を検証する（未指定の場合は "venue"）
+fn normalize_goods_channel(channel: Option<&str>) -> Option<&'static str> {
+    match channel.map(str::trim) {
+        None | Some("") => Some("venue"),
+        Some(value) => GOODS_CHANNELS.iter().copied().find(|c| *c == value),
+    }
+}
+
+// グループ・通貨ごとの行を、グループごとに基準通貨で合計する（金額の多い順、同額は元の順）
+fn summarize_goods_spend(rows: Vec<GoodsSpendRow>, rates: &ExchangeRates) -> Vec<GoodsCollectionGroup> {
+    let mut groups: Vec<GoodsCollectionGroup> = Vec::new();
+    for row in rows {
+        let tour_id = row.tour_id.map(|id| id as i32);
+        let spend = rates.to_home(row.amount, &row.currency) as i32;
+        match groups.iter_mut().find(|g| g.target == row.target && g.tour_id == tour_id) {
+            Some(group) => {
+                group.item_count += row.item_count as i32;
+                group.quantity += row.quantity as i32;
+                group.total_spend = group.total_spend.saturating_add(spend);
+            }
+            None => groups.push(GoodsCollectionGroup {
+                target: row.target,
+                tour_id,
+                tour_name: row.tour_name,
+                item_count: row.item_count as i32,
+                quantity: row.quantity as i32,
+                total_spend: spend,
+                currency: rates.home_currency.clone(),
+            }),
+        }
+    }
+    groups.sort_by_key(|g| std::cmp::Reverse(g.total_spend));
+    groups
+}
+
+#[cfg(test)]
+mod goods_tests {
+    use super::*;
+
+    fn spend_row(target: Option<&str>, currency: &str, item_count: i64, quantity: i64, amount: i64) -> GoodsSpendRow {
+        GoodsSpendRow {
+            target: target.map(str::to_string),
+            tour_id: None,
+            tour_name: None,
+            currency: currency.to_string(),
+            item_count,
+            quantity,
+            amount,
+        }
+    }
+
+    #[test]
+    fn channel_defaults_to_venue() {
+        assert_eq!(normalize_goods_channel(None), Some("venue"));
+        assert_eq!(normalize_goods_channel(Some("online")), Some("online"));
+        assert_eq!(normalize_goods_channel(Some("shop")), None);
+    }
+
+    #[test]
+    fn spend_is_merged_per_group_in_home_currency() {
+        let rates = ExchangeRates {
+            home_currency: "JPY".to_string(),
+            rates: [(("KRW".to_string(), "JPY".to_string()), 0.1)].into_iter().collect(),
+        };
+        let groups = summarize_goods_spend(
+            vec![
+                spend_row(Some("A"), "JPY", 2, 3, 6000),
+                spend_row(Some("B"), "JPY", 1, 1, 9000),
+                spend_row(Some("A"), "KRW", 1, 2, 50000),
+                spend_row(None, "JPY", 1, 1, 500),
+            ],
+            &rates,
+        );
+        let summary: Vec<(Option<&str>, i32, i32, i32)> = groups
+            .iter()
+            .map(|g| (g.target.as_deref(), g.item_count, g.quantity, g.total_spend))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![(Some("A"), 3, 5, 11000), (Some("B"), 1, 1, 9000), (None, 1, 1, 500)]
+        );
+        assert!(groups.iter().all(|g| g.currency == "JPY"));
+    }
+}
+
+fn goods_bad_request(message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            error: message.to_string(),
+        }),
+    )
+}
+
+fn goods_not_found() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "グッズが見つかりませんでした".to_string(),
+        }),
+    )
+}
+
+// リクエストボディを検証し、保存する購入方法を返す
+fn validate_goods(payload: &NewGoods) -> Result<&'static str, (StatusCode, Json<ErrorResponse>)> {
+    let channel = normalize_goods_channel(payload.channel.as_deref())
+        .ok_or_else(|| goods_bad_request("購入方法は venue / online / mail_order / other のいずれかを指定してください"))?;
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(goods_bad_request("グッズ名を入力してください"));
+    }
+    if name.chars().count() > 100 {
+        return Err(goods_bad_request("グッズ名は100文字以内で入力してください"));
+    }
+    if trimmed_or_none(&payload.target).is_some_and(|t| t.chars().count() > 100) {
+        return Err(goods_bad_request("アーティストは100文字以内で入力してください"));
+    }
+    if payload.price.is_some_and(|p| p < 0) {
+        return Err(goods_bad_request("価格は0以上を指定してください"));
+    }
+    if payload.quantity.is_some_and(|q| q < 1) {
+        return Err(goods_bad_request("個数は1以上を指定してください"));
+    }
+    Ok(channel)
+}
+
+// アーティストの指定がなければ、スケジュールのお目当て（target）を使う
+async fn resolve_goods_target(pool: &Pool<Sqlite>, payload: &NewGoods) -> Result<Option<String>, sqlx::Error> {
+    if let Some(target) = trimmed_or_none(&payload.target) {
+        return Ok(Some(target));
+    }
+    let target: Option<Option<String>> = sqlx::query_scalar("SELECT target FROM schedules WHERE id = ?")
+        .bind(payload.schedule_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(trimmed_or_none(&target.flatten()))
+}
+
+async fn fetch_goods(pool: &Pool<Sqlite>, id: i64) -> Result<Option<GoodsRow>, sqlx::Error> {
+    sqlx::query_as::<_, GoodsRow>(&format!("{} WHERE g.id = ?", GOODS_SELECT))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+// GET /goods?schedule_id=&target=&tour_id=&channel= - グッズのコレクション（公演日順）
+async fn list_goods(
+    user: AuthenticatedUser,
+    Query(params): Query<GoodsQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<Goods>>, (StatusCode, Json<ErrorResponse>)> {
+    let mut sql = format!("{} WHERE s.user_id = ? AND s.deleted_at IS NULL", GOODS_SELECT);
+    if params.schedule_id.is_some() {
+        sql.push_str(" AND g.schedule_id = ?");
+    }
+    let target = trimmed_or_none(&params.target);
+    if target.is_some() {
+        sql.push_str(" AND g.target = ?");
+    }
+    if params.tour_id.is_some() {
+        sql.push_str(" AND s.tour_id = ?");
+    }
+    let channel = trimmed_or_none(&params.channel);
+    if channel.is_some() {
+        sql.push_str(" AND g.channel = ?");
+    }
+    sql.push_str(" ORDER BY s.date ASC, g.id ASC");
+
+    let mut query = sqlx::query_as::<_, GoodsRow>(&sql).bind(user.user_id as i64);
+    if let Some(schedule_id) = params.schedule_id {
+        query = query.bind(schedule_id);
+    }
+    if let Some(target) = &target {
+        query = query.bind(target);
+    }
+    if let Some(tour_id) = params.tour_id {
+        query = query.bind(tour_id);
+    }
+    if let Some(channel) = &channel {
+        query = query.bind(channel);
+    }
+    let rows = query.fetch_all(&pool).await.map_err(|e| {
+        eprintln!("[ListGoods] Database error: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(rows.into_iter().map(row_to_goods).collect()))
+}
+
+// GET /goods/collection?group_by=target|tour - アーティスト別・ツアー別の品目数・個数・金額
+async fn get_goods_collection(
+    user: AuthenticatedUser,
+    Query(params): Query<GoodsCollectionQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<GoodsCollectionGroup>>, (StatusCode, Json<ErrorResponse>)> {
+    let (columns, group_by) = match params.group_by.as_deref().map(str::trim) {
+        None | Some("") | Some("target") => ("g.target AS target, NULL AS tour_id, NULL AS tour_name", "g.target"),
+        Some("tour") => ("NULL AS target, s.tour_id, t.name AS tour_name", "s.tour_id"),
+        Some(_) => return Err(goods_bad_request("group_byはtargetまたはtourを指定してください")),
+    };
+    let rows = sqlx::query_as::<_, GoodsSpendRow>(&format!(
+        r#"
+        SELECT
+          {},
+          g.currency,
+          COUNT(*) AS item_count,
+          SUM(g.quantity) AS quantity,
+          SUM(COALESCE(g.price, 0) * g.quantity) AS amount
+        FROM goods g
+        INNER JOIN schedules s ON s.id = g.schedule_id
+        LEFT JOIN tours t ON t.id = s.tour_id
+        WHERE s.user_id = ? AND s.deleted_at IS NULL
+        GROUP BY {}, g.currency
+        ORDER BY MIN(s.date) ASC
+        "#,
+        columns, group_by
+    ))
+    .bind(user.user_id as i64)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[GoodsCollection] Database error: {}", e);
+        database_error()
+    })?;
+    let rates = fetch_exchange_rates(&pool, user.user_id as i64).await.map_err(|e| {
+        eprintln!("[GoodsCollection] Failed to fetch exchange rates: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(summarize_goods_spend(rows, &rates)))
+}
+
+// GET /goods/:id
+async fn get_goods(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Goods>, (StatusCode, Json<ErrorResponse>)> {
+    let row = fetch_goods(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[GetGoods] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(goods_not_found)?;
+
+    ensure_schedule_owner(&pool, row.schedule_id, user.user_id).await?;
+
+    Ok(Json(row_to_goods(row)))
+}
+
+// POST /goods
+async fn create_goods(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewGoods>,
+) -> Result<(StatusCode, Json<Goods>), (StatusCode, Json<ErrorResponse>)> {
+    let channel = validate_goods(&payload)?;
+    ensure_schedule_owner(&pool, payload.schedule_id as i64, user.user_id).await?;
+    let currency = match resolve_currency(&pool, user.user_id, payload.currency.as_deref()).await? {
+        Some(currency) => currency,
+        None => fetch_user_home_currency(&pool, user.user_id).await.map_err(|e| {
+            eprintln!("[CreateGoods] Database error: {}", e);
+            database_error()
+        })?,
+    };
+    let target = resolve_goods_target(&pool, &payload).await.map_err(|e| {
+        eprintln!("[CreateGoods] Database error: {}", e);
+        database_error()
+    })?;
+
+    let now = Utc::now().to_rfc3339();
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO goods (
+          schedule_id,
+          name,
+          target,
+          price,
+          quantity,
+          currency,
+          channel,
+          notes,
+          created_at,
+          updated_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(payload.schedule_id)
+    .bind(payload.name.trim())
+    .bind(&target)
+    .bind(payload.price)
+    .bind(payload.quantity.unwrap_or(1))
+    .bind(&currency)
+    .bind(channel)
+    .bind(&payload.notes)
+    .bind(&now)
+    .bind(&now)
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[CreateGoods] Database error: {}", e);
+        database_error()
+    })?;
+
+    // グッズ代はgoods_costに集計されるため、ロールアップを再計算する
+    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+
+    let row = fetch_goods(&pool, inserted.last_insert_rowid())
+        .await
+        .map_err(|e| {
+            eprintln!("[CreateGoods] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(goods_not_found)?;
+
+    Ok((StatusCode::CREATED, Json(row_to_goods(row))))
+}
+
+// PUT /goods/:id
+async fn update_goods(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewGoods>,
+) -> Result<Json<Goods>, (StatusCode, Json<ErrorResponse>)> {
+    let channel = validate_goods(&payload)?;
+
+    let current = fetch_goods(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[UpdateGoods] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(goods_not_found)?;
+
+    // 付け替え先のスケジュールも含めて所有者を確認する
+    ensure_schedule_owner(&pool, current.schedule_id, user.user_id).await?;
+    if current.schedule_id != payload.schedule_id as i64 {
+        ensure_schedule_owner(&pool, payload.schedule_id as i64, user.user_id).await?;
+    }
+    let currency = resolve_currency(&pool, user.user_id, payload.currency.as_deref()).await?;
+    let target = resolve_goods_target(&pool, &payload).await.map_err(|e| {
+        eprintln!("[UpdateGoods] Database error: {}", e);
+        database_error()
+    })?;
+
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await.map_err(|e| {
+        eprintln!("[UpdateGoods] Failed to start transaction: {}", e);
+        database_error()
+    })?;
+    sqlx::query(
+        r#"
+        UPDATE goods SET
+          schedule_id = ?,
+          name = ?,
+          target = ?,
+          price = ?,
+          quantity = ?,
+          currency = COALESCE(?, currency),
+          channel = ?,
+          notes = ?,
+          updated_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(payload.schedule_id)
+    .bind(payload.name.trim())
+    .bind(&target)
+    .bind(payload.price)
+    .bind(payload.quantity.unwrap_or(1))
+    .bind(&currency)
+    .bind(channel)
+    .bind(&payload.notes)
+    .bind(&now)
+    .bind(id)
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| {
+        eprintln!("[UpdateGoods] Database error: {}", e);
+        database_error()
+    })?;
+    // 画像の添付ファイルもスケジュールに合わせて付け替える（スケジュールの完全削除で一緒に消えるように）
+    sqlx::query("UPDATE attachments SET schedule_id = ? WHERE entity_type = 'goods' AND entity_id = ?")
+        .bind(payload.schedule_id)
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("[UpdateGoods] Failed to move attachments: {}", e);
+            database_error()
+        })?;
+    tx.commit().await.map_err(|e| {
+        eprintln!("[UpdateGoods] Failed to commit: {}", e);
+        database_error()
+    })?;
+
+    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+    if current.schedule_id != payload.schedule_id as i64 {
+        calculate_rollup(&pool, current.schedule_id).await.ok();
+    }
+
+    let row = fetch_goods(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[UpdateGoods] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(goods_not_found)?;
+
+    Ok(Json(row_to_goods(row)))
+}
+
+// DELETE /goods/:id - グッズと画像の添付ファイルを削除する
+async fn delete_goods(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let current = fetch_goods(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[DeleteGoods] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(goods_not_found)?;
+
+    ensure_schedule_owner(&pool, current.schedule_id, user.user_id).await?;
+
+    let mut tx = pool.begin().await.map_err(|e| {
+        eprintln!("[DeleteGoods] Failed to start transaction: {}", e);
+        database_error()
+    })?;
+    let attachment_keys: Vec<String> =
+        sqlx::query_scalar("DELETE FROM attachments WHERE entity_type = 'goods' AND entity_id = ? RETURNING storage_key")
+            .bind(id)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(|e| {
+                eprintln!("[DeleteGoods] Failed to delete attachments: {}", e);
+                database_error()
+            })?;
+    sqlx::query("DELETE FROM goods WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("[DeleteGoods] Database error: {}", e);
+            database_error()
+        })?;
+    tx.commit().await.map_err(|e| {
+        eprintln!("[DeleteGoods] Failed to commit: {}", e);
+        database_error()
+    })?;
+    remove_attachment_files(&attachment_keys).await;
+
+    calculate_rollup(&pool, current.schedule_id).await.ok();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "グッズを削除しました"
+    })))
+}
+
+// ====== 諸経費（食事・コインロッカーなど） ======
+// チケット代・ドリンク代・交通費・宿泊費以外の費用を、スケジュールごとの明細として記録する。
+// 合計はother_costに集計し、total_costに含める。カテゴリの選択肢はselect_options（EXPENSE_CATEGORIES）で管理する
+
+#[derive(Serialize, Clone)]
+struct Expense {
+    id: i32,
+    schedule_id: i32,
+    category: Option<String>, // 食事・コインロッカー・タクシーなど（選択肢はユーザーごとに定義）
+    description: Option<String>,
+    amount: i32,
+    currency: String,
+    spent_on: Option<String>, // YYYY-MM-DD
+    notes: Option<String>,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ExpenseRow {
+    id: i64,
+    schedule_id: i64,
+    category: Option<String>,
+    description: Option<String>,
+    amount: i32,
+    currency: String,
+    spent_on: Option<String>,
+    notes: Option<String>,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+const EXPENSE_SELECT: &str = r#"
+    SELECT
+      id,
+      schedule_id,
+      category,
+      description,
+      amount,
+      currency,
+      spent_on,
+      notes,
+      created_at,
+      updated_at
+    FROM expenses
+"#;
+
+#[derive(Debug, Deserialize)]
+struct ExpenseQuery {
+    schedule_id: i32,
+    category: Option<String>,
+}
+
+// POST /expenses, PUT /expenses/:id 用リクエストボディ
+#[derive(Deserialize)]
+struct NewExpense {
+    schedule_id: i32,
+    category: Option<String>,
+    description: Option<String>,
+    amount: i32,
+    currency: Option<String>, // 省略時は新規作成ではユーザーの基準通貨、更新では変更しない
+    spent_on: Option<String>,
+    notes: Option<String>,
+}
+
+fn row_to_expense(row: ExpenseRow) -> Expense {
+    Expense {
+        id: row.id as i32,
+        schedule_id: row.schedule_id as i32,
+        category: row.category,
+        description: row.description,
+        amount: row.amount,
+        currency: row.currency,
+        spent_on: row.spent_on,
+        notes: row.notes,
+        created_at: row.created_at,
+        updated_at: row.updated_at,
+    }
+}
+
+fn expense_bad_request(message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            error: message.to_string(),
+        }),
+    )
+}
+
+fn expense_not_found() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "費用の明細が見つかりませんでした".to_string(),
+        }),
+    )
+}
+
+// リクエストボディを検証する
+fn validate_expense(payload: &NewExpense) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if payload.amount < 0 {
+        return Err(expense_bad_request("金額は0以上を指定してください"));
+    }
+    if trimmed_or_none(&payload.category).is_some_and(|c| c.chars().count() > 50) {
+        return Err(expense_bad_request("カテゴリは50文字以内で入力してください"));
+    }
+    if trimmed_or_none(&payload.description).is_some_and(|d| d.chars().count() > 100) {
+        return Err(expense_bad_request("内容は100文字以内で入力してください"));
+    }
+    if let Some(spent_on) = trimmed_or_none(&payload.spent_on) {
+        if chrono::NaiveDate::parse_from_str(&spent_on, "%Y-%m-%d").is_err() {
+            return Err(expense_bad_request("支払日はYYYY-MM-DD形式で指定してください"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod expense_tests {
+    use super::*;
+
+    fn expense(amount: i32, spent_on: Option<&str>) -> NewExpense {
+        NewExpense {
+            schedule_id: 1,
+            category: Some(" 食事 ".to_string()),
+            description: None,
+            amount,
+            currency: None,
+            spent_on: spent_on.map(str::to_string),
+            notes: None,
+        }
+    }
+
+    #[test]
+    fn accepts_blank_or_iso_date() {
+        assert!(validate_expense(&expense(1200, None)).is_ok());
+        assert!(validate_expense(&expense(0, Some(" "))).is_ok());
+        assert!(validate_expense(&expense(500, Some("2025-05-11"))).is_ok());
+    }
+
+    #[test]
+    fn rejects_negative_amount_and_bad_date() {
+        assert!(validate_expense(&expense(-1, None)).is_err());
+        assert!(validate_expense(&expense(500, Some("2025/05/11"))).is_err());
+    }
+}
+
+async fn fetch_expense(pool: &Pool<Sqlite>, id: i64) -> Result<Option<ExpenseRow>, sqlx::Error> {
+    sqlx::query_as::<_, ExpenseRow>(&format!("{} WHERE id = ?", EXPENSE_SELECT))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+// GET /expenses?schedule_id=1&category=
+async fn list_expenses(
+    user: AuthenticatedUser,
+    Query(params): Query<ExpenseQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<Expense>>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, params.schedule_id as i64, user.user_id).await?;
+
+    let category = trimmed_or_none(&params.category);
+    let mut sql = format!("{} WHERE schedule_id = ?", EXPENSE_SELECT);
+    if category.is_some() {
+        sql.push_str(" AND category = ?");
+    }
+    sql.push_str(" ORDER BY spent_on IS NULL, spent_on ASC, id ASC");
+
+    let mut query = sqlx::query_as::<_, ExpenseRow>(&sql).bind(params.schedule_id);
+    if let Some(category) = &category {
+        query = query.bind(category);
+    }
+    let rows = query.fetch_all(&pool).await.map_err(|e| {
+        eprintln!("[ListExpenses] Database error: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(rows.into_iter().map(row_to_expense).collect()))
+}
+
+// GET /expenses/:id
+async fn get_expense(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Expense>, (StatusCode, Json<ErrorResponse>)> {
+    let row = fetch_expense(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[GetExpense] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(expense_not_found)?;
+
+    ensure_schedule_owner(&pool, row.schedule_id, user.user_id).await?;
+
+    Ok(Json(row_to_expense(row)))
+}
+
+// POST /expenses
+async fn create_expense(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewExpense>,
+) -> Result<(StatusCode, Json<Expense>), (StatusCode, Json<ErrorResponse>)> {
+    validate_expense(&payload)?;
+    ensure_schedule_owner(&pool, payload.schedule_id as i64, user.user_id).await?;
+    let currency = match resolve_currency(&pool, user.user_id, payload.currency.as_deref()).await? {
+        Some(currency) => currency,
+        None => fetch_user_home_currency(&pool, user.user_id).await.map_err(|e| {
+            eprintln!("[CreateExpense] Database error: {}", e);
+            database_error()
+        })?,
+    };
+
+    let now = Utc::now().to_rfc3339();
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO expenses (
+          schedule_id,
+          category,
+          description,
+          amount,
+          currency,
+          spent_on,
+          notes,
+          created_at,
+          updated_at
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(payload.schedule_id)
+    .bind(trimmed_or_none(&payload.category))
+    .bind(trimmed_or_none(&payload.description))
+    .bind(payload.amount)
+    .bind(&currency)
+    .bind(trimmed_or_none(&payload.spent_on))
+    .bind(&payload.notes)
+    .bind(&now)
+    .bind(&now)
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[CreateExpense] Database error: {}", e);
+        database_error()
+    })?;
+
+    // 明細の合計はother_cost・total_costに反映されるため、ロールアップを再計算する
+    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+
+    let row = fetch_expense(&pool, inserted.last_insert_rowid())
+        .await
+        .map_err(|e| {
+            eprintln!("[CreateExpense] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(expense_not_found)?;
+
+    Ok((StatusCode::CREATED, Json(row_to_expense(row))))
+}
+
+// PUT /expenses/:id
+async fn update_expense(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewExpense>,
+) -> Result<Json<Expense>, (StatusCode, Json<ErrorResponse>)> {
+    validate_expense(&payload)?;
+
+    let current = fetch_expense(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[UpdateExpense] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(expense_not_found)?;
+
+    // 付け替え先のスケジュールも含めて所有者を確認する
+    ensure_schedule_owner(&pool, current.schedule_id, user.user_id).await?;
+    if current.schedule_id != payload.schedule_id as i64 {
+        ensure_schedule_owner(&pool, payload.schedule_id as i64, user.user_id).await?;
+    }
+    let currency = resolve_currency(&pool, user.user_id, payload.currency.as_deref()).await?;
+
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        r#"
+        UPDATE expenses SET
+          schedule_id = ?,
+          category = ?,
+          description = ?,
+          amount = ?,
+          currency = COALESCE(?, currency),
+          spent_on = ?,
+          notes = ?,
+          updated_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(payload.schedule_id)
+    .bind(trimmed_or_none(&payload.category))
+    .bind(trimmed_or_none(&payload.description))
+    .bind(payload.amount)
+    .bind(&currency)
+    .bind(trimmed_or_none(&payload.spent_on))
+    .bind(&payload.notes)
+    .bind(&now)
+    .bind(id)
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[UpdateExpense] Database error: {}", e);
+        database_error()
+    })?;
+
+    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+    if current.schedule_id != payload.schedule_id as i64 {
+        calculate_rollup(&pool, current.schedule_id).await.ok();
+    }
+
+    let row = fetch_expense(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[UpdateExpense] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(expense_not_found)?;
+
+    Ok(Json(row_to_expense(row)))
+}
+
+// DELETE /expenses/:id
+async fn delete_expense(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let current = fetch_expense(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[DeleteExpense] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(expense_not_found)?;
+
+    ensure_schedule_owner(&pool, current.schedule_id, user.user_id).await?;
+
+    sqlx::query("DELETE FROM expenses WHERE id = ?")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[DeleteExpense] Database error: {}", e);
+            database_error()
+        })?;
+
+    calculate_rollup(&pool, current.schedule_id).await.ok();
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "費用の明細を削除しました"
+    })))
+}
+
+// ====== 予算 ======
+// 年間・月間の予算を、カテゴリ・お目当て（アーティスト）ごとに設定し、実績（Done・Keepの公演のtotal_cost）と
+// 見込み（Pendingの公演を加えた額）を比べる。実績がしきい値を超えたかはバックグラウンドで確認して通知する
+
+// 通知の段階（budgets.alert_levelに保存し、同じ段階の通知を繰り返さない）
+const BUDGET_ALERT_NONE: i32 = 0;
+const BUDGET_ALERT_WARNING: i32 = 1; // 予算のalert_percent%に達した
+const BUDGET_ALERT_OVER: i32 = 2; // 予算を超えた
+
+#[derive(Serialize)]
+struct Budget {
+    id: i32,
+    period: String,     // "year"（年間）/ "month"（月間）
+    year: i32,
+    month: Option<i32>, // 月間予算の場合のみ（1〜12）
+    category: Option<String>, // 指定した場合は、schedules.categoryが一致する公演だけを集計する
+    target: Option<String>,   // 指定した場合は、schedules.targetが一致する公演だけを集計する
+    amount: i32,        // 予算額（基準通貨）
+    alert_percent: i32, // 実績がこの割合（%）に達したら通知する
+    actual: i32,        // Done・Keepの公演のtotal_costの合計
+    forecast: i32,      // = actual + Pendingの公演のtotal_costの合計
+    remaining: i32,     // = amount - actual（超過した場合は負の値）
+    usage_percent: i32, // = actual / amount（%、切り捨て）
+    currency: String,   // 基準通貨
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct BudgetRow {
+    id: i64,
+    user_id: i64,
+    year: i64,
+    month: Option<i64>,
+    category: Option<String>,
+    target: Option<String>,
+    amount: i64,
+    alert_percent: i64,
+    alert_level: i64,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+const BUDGET_SELECT: &str = r#"
+    SELECT
+      id,
+      user_id,
+      year,
+      month,
+      category,
+      target,
+      amount,
+      alert_percent,
+      alert_level,
+      created_at,
+      updated_at
+    FROM budgets
+"#;
+
+#[derive(Debug, Deserialize)]
+struct BudgetQuery {
+    year: Option<i32>,
+}
+
+// POST /budgets, PUT /budgets/:id 用リクエストボディ
+#[derive(Deserialize)]
+struct NewBudget {
+    year: i32,
+    month: Option<i32>, // 省略時は年間予算
+    category: Option<String>,
+    target: Option<String>,
+    amount: i32,
+    alert_percent: Option<i32>, // 省略時は80
+}
+
+// 集計対象のスケジュールを絞り込むdateの前方一致パターン（年間は "2025-%"、月間は "2025-05-%"）
+fn budget_date_pattern(year: i64, month: Option<i64>) -> String {
+    match month {
+        Some(month) => format!("{:04}-{:02}-%", year, month),
+        None => format!("{:04}-%", year),
+    }
+}
+
+// 実績から通知の段階を求める（予算を超えたらOVER、alert_percent%に達したらWARNING）
+fn budget_alert_level(actual: i64, amount: i64, alert_percent: i64) -> i32 {
+    if actual > amount {
+        BUDGET_ALERT_OVER
+    } else if actual * 100 >= amount * alert_percent {
+        BUDGET_ALERT_WARNING
+    } else {
+        BUDGET_ALERT_NONE
+    }
+}
+
+// 通知に使う予算の名前（例: "2025年5月の予算（ライブ / A）"）
+fn budget_label(year: i64, month: Option<i64>, category: Option<&str>, target: Option<&str>) -> String {
+    let period = match month {
+        Some(month) => format!("{}年{}月", year, month),
+        None => format!("{}年", year),
+    };
+    let scope = [category, target].into_iter().flatten().collect::<Vec<_>>().join(" / ");
+    if scope.is_empty() {
+        format!("{}の予算", period)
+    } else {
+        format!("{}の予算（{}）", period, scope)
+    }
+}
+
+#[cfg(test)]
+mod budget_tests {
+    use super::*;
+
+    #[test]
+    fn date_pattern_matches_year_or_month() {
+        assert_eq!(budget_date_pattern(2025, None), "2025-%");
+        assert_eq!(budget_date_pattern(2025, Some(5)), "2025-05-%");
+    }
+
+    #[test]
+    fn alert_level_follows_thresholds() {
+        assert_eq!(budget_alert_level(79_999, 100_000, 80), BUDGET_ALERT_NONE);
+        assert_eq!(budget_alert_level(80_000, 100_000, 80), BUDGET_ALERT_WARNING);
+        assert_eq!(budget_alert_level(100_000, 100_000, 80), BUDGET_ALERT_WARNING);
+        assert_eq!(budget_alert_level(100_001, 100_000, 80), BUDGET_ALERT_OVER);
+        // alert_percentが100の場合はちょうど使い切ったときに一度だけ知らせる
+        assert_eq!(budget_alert_level(100_000, 100_000, 100), BUDGET_ALERT_WARNING);
+    }
+
+    #[test]
+    fn label_includes_scope() {
+        assert_eq!(budget_label(2025, None, None, None), "2025年の予算");
+        assert_eq!(budget_label(2025, Some(5), Some("ライブ"), Some("A")), "2025年5月の予算（ライブ / A）");
+    }
+}
+
+fn budget_bad_request(message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            error: message.to_string(),
+        }),
+    )
+}
+
+fn budget_not_found() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "予算が見つかりませんでした".to_string(),
+        }),
+    )
+}
+
+fn duplicate_budget() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::CONFLICT,
+        Json(ErrorResponse {
+            error: "同じ期間・条件の予算が既に存在します".to_string(),
+        }),
+    )
+}
+
+fn validate_budget(payload: &NewBudget) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if !(2000..=2100).contains(&payload.year) {
+        return Err(budget_bad_request("年は2000〜2100の範囲で指定してください"));
+    }
+    if payload.month.is_some_and(|m| !(1..=12).contains(&m)) {
+        return Err(budget_bad_request("月は1〜12の範囲で指定してください"));
+    }
+    if payload.amount < 1 {
+        return Err(budget_bad_request("予算額は1以上を指定してください"));
+    }
+    if payload.alert_percent.is_some_and(|p| !(1..=100).contains(&p)) {
+        return Err(budget_bad_request("通知のしきい値は1〜100（%）で指定してください"));
+    }
+    Ok(())
+}
+
+// 予算の期間・条件に当てはまる公演のtotal_costの合計（Done・Keepの実績, Pendingの見込み）
+async fn fetch_budget_spend(pool: &Pool<Sqlite>, budget: &BudgetRow) -> Result<(i64, i64), sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT
+          COALESCE(SUM(CASE WHEN status IN ('Done', 'Keep') THEN COALESCE(total_cost, 0) ELSE 0 END), 0),
+          COALESCE(SUM(CASE WHEN status = 'Pending' THEN COALESCE(total_cost, 0) ELSE 0 END), 0)
+        FROM schedules
+        WHERE user_id = ?
+          AND deleted_at IS NULL
+          AND date LIKE ?
+          AND (? IS NULL OR category = ?)
+          AND (? IS NULL OR target = ?)
+        "#,
+    )
+    .bind(budget.user_id)
+    .bind(budget_date_pattern(budget.year, budget.month))
+    .bind(&budget.category)
+    .bind(&budget.category)
+    .bind(&budget.target)
+    .bind(&budget.target)
+    .fetch_one(pool)
+    .await
+}
+
+async fn row_to_budget(pool: &Pool<Sqlite>, row: BudgetRow) -> Result<Budget, sqlx::Error> {
+    let (actual, pending) = fetch_budget_spend(pool, &row).await?;
+    let currency = fetch_user_home_currency(pool, row.user_id as i32).await?;
+    Ok(Budget {
+        id: row.id as i32,
+        period: if row.month.is_some() { "month" } else { "year" }.to_string(),
+        year: row.year as i32,
+        month: row.month.map(|m| m as i32),
+        category: row.category,
+        target: row.target,
+        amount: row.amount as i32,
+        alert_percent: row.alert_percent as i32,
+        actual: actual as i32,
+        forecast: (actual + pending) as i32,
+        remaining: (row.amount - actual) as i32,
+        usage_percent: (actual * 100 / row.amount.max(1)) as i32,
+        currency,
+        created_at: row.created_at,
+        updated_at: row.updated_at,
+    })
+}
+
+async fn fetch_owned_budget(
+    pool: &Pool<Sqlite>,
+    id: i64,
+    user_id: i32,
+) -> Result<BudgetRow, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as::<_, BudgetRow>(&format!("{} WHERE id = ? AND user_id = ?", BUDGET_SELECT))
+        .bind(id)
+        .bind(user_id as i64)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[Budgets] Failed to fetch budget {}: {}", id, e);
+            database_error()
+        })?
+        .ok_or_else(budget_not_found)
+}
+
+async fn budget_response(pool: &Pool<Sqlite>, row: BudgetRow) -> Result<Budget, (StatusCode, Json<ErrorResponse>)> {
+    row_to_budget(pool, row).await.map_err(|e| {
+        eprintln!("[Budgets] Failed to calculate spend: {}", e);
+        database_error()
+    })
+}
+
+// GET /budgets?year=2025 - 予算と実績・見込み（年間予算を先に、月の順）
+async fn list_budgets(
+    user: AuthenticatedUser,
+    Query(params): Query<BudgetQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<Budget>>, (StatusCode, Json<ErrorResponse>)> {
+    let rows = sqlx::query_as::<_, BudgetRow>(&format!(
+        "{} WHERE user_id = ? AND (? IS NULL OR year = ?) ORDER BY year DESC, month IS NOT NULL, month ASC, id ASC",
+        BUDGET_SELECT
+    ))
+    .bind(user.user_id as i64)
+    .bind(params.year)
+    .bind(params.year)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[Budgets] Failed to list budgets: {}", e);
+        database_error()
+    })?;
+
+    let mut budgets = Vec::with_capacity(rows.len());
+    for row in rows {
+        budgets.push(budget_response(&pool, row).await?);
+    }
+    Ok(Json(budgets))
+}
+
+// GET /budgets/:id
+async fn get_budget(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Budget>, (StatusCode, Json<ErrorResponse>)> {
+    let row = fetch_owned_budget(&pool, id as i64, user.user_id).await?;
+    Ok(Json(budget_response(&pool, row).await?))
+}
+
+// POST /budgets
+async fn create_budget(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewBudget>,
+) -> Result<(StatusCode, Json<Budget>), (StatusCode, Json<ErrorResponse>)> {
+    validate_budget(&payload)?;
+
+    let now = Utc::now().to_rfc3339();
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO budgets (user_id, year, month, category, target, amount, alert_percent, alert_level, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, 0, ?, ?)
+        "#,
+    )
+    .bind(user.user_id as i64)
+    .bind(payload.year)
+    .bind(payload.month)
+    .bind(trimmed_or_none(&payload.category))
+    .bind(trimmed_or_none(&payload.target))
+    .bind(payload.amount)
+    .bind(payload.alert_percent.unwrap_or(80))
+    .bind(&now)
+    .bind(&now)
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[Budgets] Failed to insert budget: {}", e);
+        if e.to_string().contains("UNIQUE constraint failed") {
+            duplicate_budget()
+        } else {
+            database_error()
+        }
+    })?;
+
+    let row = fetch_owned_budget(&pool, inserted.last_insert_rowid(), user.user_id).await?;
+    Ok((StatusCode::CREATED, Json(budget_response(&pool, row).await?)))
+}
+
+// PUT /budgets/:id
+async fn update_budget(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewBudget>,
+) -> Result<Json<Budget>, (StatusCode, Json<ErrorResponse>)> {
+    validate_budget(&payload)?;
+    fetch_owned_budget(&pool, id as i64, user.user_id).await?;
+
+    // 期間・条件を変えた場合は別の予算として扱い、通知の段階を初めからにする
+    let category = trimmed_or_none(&payload.category);
+    let target = trimmed_or_none(&payload.target);
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        r#"
+        UPDATE budgets SET
+          alert_level = CASE WHEN year = ? AND month IS ? AND category IS ? AND target IS ? THEN alert_level ELSE 0 END,
+          year = ?,
+          month = ?,
+          category = ?,
+          target = ?,
+          amount = ?,
+          alert_percent = ?,
+          updated_at = ?
+        WHERE id = ? AND user_id = ?
+        "#,
+    )
+    .bind(payload.year)
+    .bind(payload.month)
+    .bind(&category)
+    .bind(&target)
+    .bind(payload.year)
+    .bind(payload.month)
+    .bind(&category)
+    .bind(&target)
+    .bind(payload.amount)
+    .bind(payload.alert_percent.unwrap_or(80))
+    .bind(&now)
+    .bind(id)
+    .bind(user.user_id as i64)
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[Budgets] Failed to update budget {}: {}", id, e);
+        if e.to_string().contains("UNIQUE constraint failed") {
+            duplicate_budget()
+        } else {
+            database_error()
+        }
+    })?;
+
+    let row = fetch_owned_budget(&pool, id as i64, user.user_id).await?;
+    Ok(Json(budget_response(&pool, row).await?))
+}
+
+// DELETE /budgets/:id - 予算と、その予算の通知を削除する
+async fn delete_budget(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    fetch_owned_budget(&pool, id as i64, user.user_id).await?;
+
+    let mut tx = pool.begin().await.map_err(|e| {
+        eprintln!("[Budgets] Failed to start transaction: {}", e);
+        database_error()
+    })?;
+    sqlx::query("DELETE FROM notifications WHERE budget_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("[Budgets] Failed to delete notifications for budget {}: {}", id, e);
+            database_error()
+        })?;
+    sqlx::query("DELETE FROM budgets WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("[Budgets] Failed to delete budget {}: {}", id, e);
+            database_error()
+        })?;
+    tx.commit().await.map_err(|e| {
+        eprintln!("[Budgets] Failed to commit: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "予算を削除しました"
+    })))
+}
+
+// ====== 統計 ======
+// 振り返り用の集計。絞り込み条件は /schedules と共通（include_canceled・status・無料プランの過去アーカイブ制限も同じ）
+// 金額はすべて基準通貨（ロールアップ済みの値を合計する）
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StatsGroupBy {
+    Year,
+    Month,
+    Target,
+    Venue,
+    Area,
+    Category,
+    Seller,
+}
+
+impl StatsGroupBy {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "year" => Some(Self::Year),
+            "month" => Some(Self::Month),
+            "target" => Some(Self::Target),
+            "venue" => Some(Self::Venue),
+            "area" => Some(Self::Area),
+            "category" => Some(Self::Category),
+            "seller" => Some(Self::Seller),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Year => "year",
+            Self::Month => "month",
+            Self::Target => "target",
+            Self::Venue => "venue",
+            Self::Area => "area",
+            Self::Category => "category",
+            Self::Seller => "seller",
+        }
+    }
+
+    // グループのキーになるSQL式。年・月は開催日（複数日開催は初日）で判定する
+    fn sql_expr(self) -> &'static str {
+        match self {
+            Self::Year => "substr(date, 1, 4)",
+            Self::Month => "substr(date, 1, 7)",
+            Self::Target => "target",
+            Self::Venue => "venue",
+            Self::Area => "area",
+            Self::Category => "category",
+            Self::Seller => "seller",
+        }
+    }
+
+    // 年・月は古い順、それ以外は費用の多い順
+    fn is_chronological(self) -> bool {
+        matches!(self, Self::Year | Self::Month)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct StatsQuery {
+    group_by: Option<String>, // year（既定）/ month / target / venue / area / category / seller
+}
+
+// 集計値。keyはグループの値（未設定のものはNULL）
+#[derive(Serialize, sqlx::FromRow, Debug, Clone, Default, PartialEq)]
+struct StatsBucket {
+    key: Option<String>,
+    count: i64,
+    total_cost: i64,
+    net_cost: i64,   // 割り勘・譲渡を反映した実質の費用
+    goods_cost: i64, // total_costには含まれない
+}
+
+#[derive(Serialize)]
+struct StatsResponse {
+    group_by: String,
+    currency: String,
+    total: StatsBucket,
+    groups: Vec<StatsBucket>,
+}
+
+// 前年比。monthがNULLの行は年間の合計
+#[derive(Serialize, Debug, PartialEq)]
+struct StatsComparison {
+    month: Option<i32>,
+    count: i64,
+    previous_count: i64,
+    total_cost: i64,
+    previous_total_cost: i64,
+    net_cost: i64,
+    previous_net_cost: i64,
+    goods_cost: i64,
+    previous_goods_cost: i64,
+    total_cost_change_percent: Option<i64>, // 前年のtotal_costが0の場合はNULL
+}
+
+#[derive(Serialize)]
+struct StatsComparisonResponse {
+    year: i32,
+    previous_year: i32,
+    currency: String,
+    total: StatsComparison,
+    months: Vec<StatsComparison>,
+}
+
+fn sum_stats_buckets<'a>(key: Option<String>, buckets: impl Iterator<Item = &'a StatsBucket>) -> StatsBucket {
+    buckets.fold(StatsBucket { key, ..Default::default() }, |mut acc, b| {
+        acc.count += b.count;
+        acc.total_cost += b.total_cost;
+        acc.net_cost += b.net_cost;
+        acc.goods_cost += b.goods_cost;
+        acc
+    })
+}
+
+fn compare_stats(month: Option<i32>, current: &StatsBucket, previous: &StatsBucket) -> StatsComparison {
+    StatsComparison {
+        month,
+        count: current.count,
+        previous_count: previous.count,
+        total_cost: current.total_cost,
+        previous_total_cost: previous.total_cost,
+        net_cost: current.net_cost,
+        previous_net_cost: previous.net_cost,
+        goods_cost: current.goods_cost,
+        previous_goods_cost: previous.goods_cost,
+        total_cost_change_percent: (previous.total_cost != 0)
+            .then(|| (current.total_cost - previous.total_cost) * 100 / previous.total_cost),
+    }
+}
+
+// 月別（キーは YYYY-MM）の集計から、指定年と前年の年間・月別の比較を作る
+fn build_stats_comparison(year: i32, monthly: &[StatsBucket]) -> (StatsComparison, Vec<StatsComparison>) {
+    let bucket_for = |y: i32, month: Option<u32>| {
+        let prefix = match month {
+            Some(m) => format!("{:04}-{:02}", y, m),
+            None => format!("{:04}-", y),
+        };
+        sum_stats_buckets(
+            None,
+            monthly.iter().filter(|b| b.key.as_deref().is_some_and(|k| k.starts_with(&prefix))),
+        )
+    };
+    let total = compare_stats(None, &bucket_for(year, None), &bucket_for(year - 1, None));
+    let months = (1..=12)
+        .map(|m| compare_stats(Some(m as i32), &bucket_for(year, Some(m)), &bucket_for(year - 1, Some(m))))
+        .collect();
+    (total, months)
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use super::*;
+
+    fn bucket(key: &str, count: i64, total_cost: i64) -> StatsBucket {
+        StatsBucket {
+            key: Some(key.to_string()),
+            count,
+            total_cost,
+            net_cost: total_cost,
+            goods_cost: 0,
+        }
+    }
+
+    #[test]
+    fn group_by_round_trips() {
+        for name in ["year", "month", "target", "venue", "area", "category", "seller"] {
+            assert_eq!(StatsGroupBy::parse(name).map(StatsGroupBy::name), Some(name));
+        }
+        assert_eq!(StatsGroupBy::parse("title"), None);
+    }
+
+    #[test]
+    fn compares_against_previous_year() {
+        let monthly = vec![
+            bucket("2024-05", 1, 10000),
+            bucket("2025-05", 2, 15000),
+            bucket("2025-08", 1, 5000),
+        ];
+        let (total, months) = build_stats_comparison(2025, &monthly);
+        assert_eq!((total.count, total.previous_count), (3, 1));
+        assert_eq!((total.total_cost, total.previous_total_cost), (20000, 10000));
+        assert_eq!(total.total_cost_change_percent, Some(100));
+        assert_eq!(months.len(), 12);
+        assert_eq!(months[4].total_cost_change_percent, Some(50));
+        // 前年が0の月は増減率を出さない
+        assert_eq!(months[7].total_cost_change_percent, None);
+        assert_eq!(months[0], compare_stats(Some(1), &StatsBucket::default(), &StatsBucket::default()));
+    }
+}
+
+// 無料プランの過去アーカイブ制限（/schedules と同じ）。有料プラン・トライアル中はNone
+async fn fetch_earliest_visible_year(pool: &Pool<Sqlite>, user_id: i32) -> Result<Option<i32>, sqlx::Error> {
+    let (plan, trial_started_at) = fetch_user_plan(pool, user_id).await?;
+    if has_paid_access(&plan, trial_started_at.as_deref()) {
+        return Ok(None);
+    }
+    let user_timezone = fetch_user_timezone(pool, user_id).await?;
+    Ok(Some(free_plan_earliest_archive_year(Utc::now().with_timezone(&user_timezone))))
+}
+
+// 絞り込み条件に合うスケジュールをグループごとに集計する
+async fn fetch_stats_buckets(
+    pool: &Pool<Sqlite>,
+    user_id: i32,
+    params: &ScheduleQuery,
+    spec: &ScheduleListSpec,
+    group_by: StatsGroupBy,
+    years: Option<(i32, i32)>,
+) -> Result<Vec<StatsBucket>, sqlx::Error> {
+    let earliest_visible_year = fetch_earliest_visible_year(pool, user_id).await?;
+    let expr = group_by.sql_expr();
+    let mut qb = QueryBuilder::<Sqlite>::new(format!(
+        r#"
+        SELECT
+          {} AS key,
+          COUNT(*) AS count,
+          COALESCE(SUM(COALESCE(total_cost, 0)), 0) AS total_cost,
+          COALESCE(SUM(COALESCE(net_cost, 0)), 0) AS net_cost,
+          COALESCE(SUM(COALESCE(goods_cost, 0)), 0) AS goods_cost
+        FROM schedules
+        WHERE user_id = "#,
+        expr
+    ));
+    qb.push_bind(user_id);
+    push_schedule_list_filters(&mut qb, params, spec, earliest_visible_year);
+    if let Some((from_year, to_year)) = years {
+        qb.push(" AND CAST(substr(date, 1, 4) AS INTEGER) BETWEEN ")
+            .push_bind(from_year)
+            .push(" AND ")
+            .push_bind(to_year);
+    }
+    qb.push(format!(" GROUP BY {}", expr));
+    if group_by.is_chronological() {
+        qb.push(" ORDER BY key ASC");
+    } else {
+        qb.push(" ORDER BY total_cost DESC, count DESC, key ASC");
+    }
+    qb.build_query_as::<StatsBucket>().fetch_all(pool).await
+}
+
+// GET /stats?group_by=year|month|target|venue|area|category|seller（/schedules と同じ絞り込み条件を指定できる）
+async fn get_stats(
+    user: AuthenticatedUser,
+    Query(params): Query<ScheduleQuery>,
+    Query(stats): Query<StatsQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<StatsResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let spec = validate_schedule_query(&params, true)?;
+    let group_by = match stats.group_by.as_deref().map(str::trim) {
+        None | Some("") => StatsGroupBy::Year,
+        Some(value) => StatsGroupBy::parse(value).ok_or_else(|| {
+            (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: "group_byはyear, month, target, venue, area, category, sellerのいずれかを指定してください".to_string(),
+                }),
+            )
+        })?,
+    };
+
+    let groups = fetch_stats_buckets(&pool, user.user_id, &params, &spec, group_by, None)
+        .await
+        .map_err(|e| {
+            eprintln!("[Stats] Database error: {}", e);
+            database_error()
+        })?;
+    let currency = fetch_user_home_currency(&pool, user.user_id).await.map_err(|e| {
+        eprintln!("[Stats] Database error: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(StatsResponse {
+        group_by: group_by.name().to_string(),
+        currency,
+        total: sum_stats_buckets(None, groups.iter()),
+        groups,
+    }))
+}
+
+// GET /stats/compare?year=2025 - 指定年（省略時は今年）と前年の年間・月別の比較
+// yearは比較する年として使い、その他の絞り込み条件は /stats と同じ
+async fn get_stats_comparison(
+    user: AuthenticatedUser,
+    Query(mut params): Query<ScheduleQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<StatsComparisonResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let spec = validate_schedule_query(&params, true)?;
+    let year = match params.year.take() {
+        Some(year) if (2000..=2100).contains(&year) => year,
+        Some(_) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse {
+                    error: "yearは2000〜2100の範囲で指定してください".to_string(),
+                }),
+            ))
+        }
+        None => {
+            let user_timezone = fetch_user_timezone(&pool, user.user_id).await.map_err(|e| {
+                eprintln!("[StatsCompare] Failed to fetch timezone: {}", e);
+                database_error()
+            })?;
+            Utc::now().with_timezone(&user_timezone).year()
+        }
+    };
+
+    let monthly = fetch_stats_buckets(&pool, user.user_id, &params, &spec, StatsGroupBy::Month, Some((year - 1, year)))
+        .await
+        .map_err(|e| {
+            eprintln!("[StatsCompare] Database error: {}", e);
+            database_error()
+        })?;
+    let currency = fetch_user_home_currency(&pool, user.user_id).await.map_err(|e| {
+        eprintln!("[StatsCompare] Database error: {}", e);
+        database_error()
+    })?;
+    let (total, months) = build_stats_comparison(year, &monthly);
+
+    Ok(Json(StatsComparisonResponse {
+        year,
+        previous_year: year - 1,
+        currency,
+        total,
+        months,
+    }))
+}
+
+// ====== 年間の振り返り ======
+// 年末の振り返り（公演数・最多アーティスト・最多会場など）をスナップショットとしてyear_reviewsに保存する
+// 集計対象はその年に開催日があるKeep・Doneのスケジュール（ゴミ箱の分は除く）と、その交通・宿泊
+// 共有ページでは、マスク設定を表示時に適用し（生成後にマスク設定を変えても反映される）、net_costは出さない
+
+// 最多アーティスト・最多会場
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct YearReviewRanking {
+    name: String,
+    count: i64,
+}
+
+// いちばん遠くへの遠征。距離は持っていないため、交通費（total_fare）が最も高かった公演とする
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct YearReviewTrip {
+    title: String,
+    date: String,
+    area: String,
+    venue: String,
+    total_fare: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct YearReviewSpendMonth {
+    month: i32,
+    total_cost: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct YearReviewSummary {
+    show_count: i64,
+    top_artist: Option<YearReviewRanking>,
+    top_venue: Option<YearReviewRanking>,
+    farthest_trip: Option<YearReviewTrip>,
+    nights_stayed: i64,
+    total_miles: i64,
+    biggest_spend_month: Option<YearReviewSpendMonth>,
+    total_cost: i64,
+    net_cost: Option<i64>, // 共有ページではNULL
+    currency: String,      // 生成時点の基準通貨
+}
+
+#[derive(Serialize)]
+struct YearReview {
+    year: i32,
+    is_shared: bool,
+    summary: YearReviewSummary,
+    generated_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct YearReviewRow {
+    year: i64,
+    summary_json: String,
+    is_shared: i64,
+    generated_at: String,
+}
+
+// PUT /year-reviews/:year 用リクエストボディ
+#[derive(Deserialize)]
+struct UpdateYearReview {
+    is_shared: bool,
+}
+
+// 集計対象の公演（SQLの先頭に付けるCTE）。user_id・年（YYYY）の順にbindする
+const YEAR_REVIEW_SHOWS: &str = "WITH shows AS (SELECT * FROM schedules WHERE user_id = ? AND deleted_at IS NULL AND status IN ('Keep', 'Done') AND substr(date, 1, 4) = ?)";
+
+// 共有ページ用にマスク設定（会場・エリア名）を適用し、実質の費用を隠す
+fn mask_year_review(mut summary: YearReviewSummary, masked_locations: &[String]) -> YearReviewSummary {
+    let mask = |value: &mut String| {
+        if masked_locations.contains(value) {
+            *value = "***".to_string();
+        }
+    };
+    if let Some(venue) = summary.top_venue.as_mut() {
+        mask(&mut venue.name);
+    }
+    if let Some(trip) = summary.farthest_trip.as_mut() {
+        mask(&mut trip.area);
+        mask(&mut trip.venue);
+    }
+    summary.net_cost = None;
+    summary
+}
+
+#[cfg(test)]
+mod year_review_tests {
+    use super::*;
+
+    #[test]
+    fn masks_locations_and_hides_net_cost() {
+        let summary = YearReviewSummary {
+            show_count: 3,
+            top_artist: Some(YearReviewRanking { name: "バンドA".to_string(), count: 2 }),
+            top_venue: Some(YearReviewRanking { name: "自宅近くのホール".to_string(), count: 2 }),
+            farthest_trip: Some(YearReviewTrip {
+                title: "ツアー福岡".to_string(),
+                date: "2025-06-01".to_string(),
+                area: "福岡".to_string(),
+                venue: "マリンメッセ".to_string(),
+                total_fare: 42000,
+            }),
+            nights_stayed: 2,
+            total_miles: 0,
+            biggest_spend_month: None,
+            total_cost: 90000,
+            net_cost: Some(80000),
+            currency: "JPY".to_string(),
+        };
+        let masked = mask_year_review(summary.clone(), &["自宅近くのホール".to_string(), "福岡".to_string()]);
+        assert_eq!(masked.top_venue.unwrap().name, "***");
+        let trip = masked.farthest_trip.unwrap();
+        assert_eq!((trip.area.as_str(), trip.venue.as_str()), ("***", "マリンメッセ"));
+        assert_eq!(masked.top_artist, summary.top_artist);
+        assert_eq!(masked.net_cost, None);
+        assert_eq!(masked.total_cost, 90000);
+    }
+}
+
+fn year_review_not_found() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "この年の振り返りはまだ作成されていません".to_string(),
+        }),
+    )
+}
+
+fn validate_review_year(year: i32) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if (2000..=2100).contains(&year) {
+        Ok(())
+    } else {
+        Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "yearは2000〜2100の範囲で指定してください".to_string(),
+            }),
+        ))
+    }
+}
+
+fn row_to_year_review(row: YearReviewRow) -> Result<YearReview, serde_json::Error> {
+    Ok(YearReview {
+        year: row.year as i32,
+        is_shared: row.is_shared != 0,
+        summary: serde_json::from_str(&row.summary_json)?,
+        generated_at: row.generated_at,
+    })
+}
+
+async fn fetch_year_review(pool: &Pool<Sqlite>, user_id: i64, year: i32) -> Result<Option<YearReviewRow>, sqlx::Error> {
+    sqlx::query_as::<_, YearReviewRow>(
+        "SELECT year, summary_json, is_shared, generated_at FROM year_reviews WHERE user_id = ? AND year = ?"
+    )
+    .bind(user_id)
+    .bind(year)
+    .fetch_optional(pool)
+    .await
+}
+
+// スケジュール・交通・宿泊から指定年の振り返りを集計する
+async fn generate_year_review(pool: &Pool<Sqlite>, user_id: i32, year: i32) -> Result<YearReviewSummary, sqlx::Error> {
+    let year_text = format!("{:04}", year);
+    let (show_count, total_cost, net_cost): (i64, i64, i64) = sqlx::query_as(&format!(
+        "{} SELECT COUNT(*), COALESCE(SUM(COALESCE(total_cost, 0)), 0), COALESCE(SUM(COALESCE(net_cost, 0)), 0) FROM shows",
+        YEAR_REVIEW_SHOWS
+    ))
+    .bind(user_id)
+    .bind(&year_text)
+    .fetch_one(pool)
+    .await?;
+
+    // 同数の場合は先に行った方
+    let mut rankings = Vec::new();
+    for column in ["target", "venue"] {
+        let ranking: Option<(String, i64)> = sqlx::query_as(&format!(
+            "{} SELECT {column}, COUNT(*) FROM shows WHERE COALESCE({column}, '') <> '' GROUP BY {column} ORDER BY COUNT(*) DESC, MIN(date) ASC LIMIT 1",
+            YEAR_REVIEW_SHOWS
+        ))
+        .bind(user_id)
+        .bind(&year_text)
+        .fetch_optional(pool)
+        .await?;
+        rankings.push(ranking.map(|(name, count)| YearReviewRanking { name, count }));
+    }
+    let top_venue = rankings.pop().flatten();
+    let top_artist = rankings.pop().flatten();
+
+    let farthest_trip: Option<(String, String, String, String, i64)> = sqlx::query_as(&format!(
+        "{} SELECT title, date, area, venue, total_fare FROM shows WHERE COALESCE(total_fare, 0) > 0 ORDER BY total_fare DESC, date ASC LIMIT 1",
+        YEAR_REVIEW_SHOWS
+    ))
+    .bind(user_id)
+    .bind(&year_text)
+    .fetch_optional(pool)
+    .await?;
+
+    // 宿泊数はチェックイン・チェックアウトの日付の差（キャンセルした宿泊は除く）
+    let nights_stayed: i64 = sqlx::query_scalar(&format!(
+        r#"{} SELECT COALESCE(SUM(MAX(CAST(julianday(date(st.check_out)) - julianday(date(st.check_in)) AS INTEGER), 0)), 0)
+        FROM stays st INNER JOIN shows ON shows.id = st.schedule_id
+        WHERE st.deleted_at IS NULL AND st.status <> 'Canceled'"#,
+        YEAR_REVIEW_SHOWS
+    ))
+    .bind(user_id)
+    .bind(&year_text)
+    .fetch_one(pool)
+    .await?;
+
+    let total_miles: i64 = sqlx::query_scalar(&format!(
+        "{} SELECT COALESCE(SUM(COALESCE(t.miles, 0)), 0) FROM traffics t INNER JOIN shows ON shows.id = t.schedule_id WHERE t.deleted_at IS NULL",
+        YEAR_REVIEW_SHOWS
+    ))
+    .bind(user_id)
+    .bind(&year_text)
+    .fetch_one(pool)
+    .await?;
+
+    let biggest_spend_month: Option<(i64, i64)> = sqlx::query_as(&format!(
+        "{} SELECT CAST(substr(date, 6, 2) AS INTEGER) AS month, SUM(COALESCE(total_cost, 0)) AS spend FROM shows GROUP BY month HAVING spend > 0 ORDER BY spend DESC, month ASC LIMIT 1",
+        YEAR_REVIEW_SHOWS
+    ))
+    .bind(user_id)
+    .bind(&year_text)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(YearReviewSummary {
+        show_count,
+        top_artist,
+        top_venue,
+        farthest_trip: farthest_trip.map(|(title, date, area, venue, total_fare)| YearReviewTrip {
+            title,
+            date,
+            area,
+            venue,
+            total_fare,
+        }),
+        nights_stayed,
+        total_miles,
+        biggest_spend_month: biggest_spend_month.map(|(month, total_cost)| YearReviewSpendMonth {
+            month: month as i32,
+            total_cost,
+        }),
+        total_cost,
+        net_cost: Some(net_cost),
+        currency: fetch_user_home_currency(pool, user_id).await?,
+    })
+}
+
+// GET /year-reviews - 作成済みの振り返りの一覧（新しい年から）
+async fn list_year_reviews(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<YearReview>>, (StatusCode, Json<ErrorResponse>)> {
+    let rows = sqlx::query_as::<_, YearReviewRow>(
+        "SELECT year, summary_json, is_shared, generated_at FROM year_reviews WHERE user_id = ? ORDER BY year DESC"
+    )
+    .bind(user.user_id as i64)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[ListYearReviews] Database error: {}", e);
+        database_error()
+    })?;
+
+    let reviews = rows
+        .into_iter()
+        .map(row_to_year_review)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| {
+            eprintln!("[ListYearReviews] Invalid summary: {}", e);
+            database_error()
+        })?;
+    Ok(Json(reviews))
+}
+
+// GET /year-reviews/:year
+async fn get_year_review(
+    Path(year): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<YearReview>, (StatusCode, Json<ErrorResponse>)> {
+    let row = fetch_year_review(&pool, user.user_id as i64, year)
+        .await
+        .map_err(|e| {
+            eprintln!("[GetYearReview] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(year_review_not_found)?;
+
+    let review = row_to_year_review(row).map_err(|e| {
+        eprintln!("[GetYearReview] Invalid summary: {}", e);
+        database_error()
+    })?;
+    Ok(Json(review))
+}
+
+// POST /year-reviews/:year - 振り返りを作成する（作成済みの場合は集計し直す。共有設定はそのまま）
+async fn generate_year_review_handler(
+    Path(year): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<YearReview>, (StatusCode, Json<ErrorResponse>)> {
+    validate_review_year(year)?;
+    // 無料プランは過去アーカイブの閲覧制限と同じく、直近2年分のみ作成できる
+    let earliest_visible_year = fetch_earliest_visible_year(&pool, user.user_id).await.map_err(|e| {
+        eprintln!("[GenerateYearReview] Failed to fetch plan: {}", e);
+        database_error()
+    })?;
+    if earliest_visible_year.is_some_and(|earliest| year < earliest) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "premium_required".to_string(),
+            }),
+        ));
+    }
+
+    let summary = generate_year_review(&pool, user.user_id, year).await.map_err(|e| {
+        eprintln!("[GenerateYearReview] Failed to generate: {}", e);
+        database_error()
+    })?;
+    let summary_json = serde_json::to_string(&summary).unwrap_or_default();
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        r#"
+        INSERT INTO year_reviews (user_id, year, summary_json, is_shared, generated_at)
+        VALUES (?, ?, ?, 0, ?)
+        ON CONFLICT(user_id, year) DO UPDATE SET summary_json = excluded.summary_json, generated_at = excluded.generated_at
+        "#,
+    )
+    .bind(user.user_id as i64)
+    .bind(year)
+    .bind(&summary_json)
+    .bind(&now)
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[GenerateYearReview] Database error: {}", e);
+        database_error()
+    })?;
+
+    let row = fetch_year_review(&pool, user.user_id as i64, year)
+        .await
+        .map_err(|e| {
+            eprintln!("[GenerateYearReview] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or_else(year_review_not_found)?;
+    Ok(Json(YearReview {
+        year,
+        is_shared: row.is_shared != 0,
+        summary,
+        generated_at: row.generated_at,
+    }))
+}
+
+// PUT /year-reviews/:year - 共有ページに出すかどうかを設定する
+async fn update_year_review(
+    Path(year): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<UpdateYearReview>,
+) -> Result<Json<YearReview>, (StatusCode, Json<ErrorResponse>)> {
+    let result = sqlx::query("UPDATE year_reviews SET is_shared = ? WHERE user_id = ? AND year = ?")
+        .bind(payload.is_shared as i32)
+        .bind(user.user_id as i64)
+        .bind(year)
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[UpdateYearReview] Database error: {}", e);
+            database_error()
+        })?;
+    if result.rows_affected() == 0 {
+        return Err(year_review_not_found());
+    }
+
+    get_year_review(Path(year), user, Extension(pool)).await
+}
+
+// DELETE /year-reviews/:year
+async fn delete_year_review(
+    Path(year): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let result = sqlx::query("DELETE FROM year_reviews WHERE user_id = ? AND year = ?")
+        .bind(user.user_id as i64)
+        .bind(year)
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[DeleteYearReview] Database error: {}", e);
+            database_error()
+        })?;
+    if result.rows_affected() == 0 {
+        return Err(year_review_not_found());
+    }
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "振り返りを削除しました"
+    })))
+}
+
+// GET /share/:share_id/year-reviews/:year - 共有ページ用の振り返り（共有に設定したもののみ）
+async fn get_shared_year_review(
+    Path((share_id, year)): Path<(String, i32)>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<YearReview>, (StatusCode, Json<ErrorResponse>)> {
+    let user_id = resolve_active_share_owner(&pool, &share_id).await?;
+
+    let row = fetch_year_review(&pool, user_id, year)
+        .await
+        .map_err(|e| {
+            eprintln!("[GetSharedYearReview] Database error: {}", e);
+            database_error()
+        })?
+        .filter(|row| row.is_shared != 0)
+        .ok_or_else(year_review_not_found)?;
+
+    let mut review = row_to_year_review(row).map_err(|e| {
+        eprintln!("[GetSharedYearReview] Invalid summary: {}", e);
+        database_error()
+    })?;
+    let masked_locations = get_masked_locations_for_user(&pool, user_id).await.unwrap_or_default();
+    review.summary = mask_year_review(review.summary, &masked_locations);
+    Ok(Json(review))
+}
+
+// ====== 旅程チェック ======
+// スケジュール・交通・宿泊の組み合わせの矛盾を検出する（保存は妨げず、警告として返す）
+// - schedule_overlap: Keepのスケジュール同士の開催日時が重なっている
+// - stay_invalid_range: 宿泊のチェックアウトがチェックイン以前
+// - stay_not_covering: 宿泊の期間が開催日にかかっていない
+// - traffic_after_start: 会場（エリア）への到着、または最初の移動が初日の開演より後
+// 交通のdateは日付のみのため、時刻がない場合は日付だけで比較する
+
+const ITINERARY_CHECK_SELECT: &str = r#"SELECT id, title, date, end_date, open, start, "end", area, venue, status FROM schedules"#;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct ItineraryWarning {
+    code: String,
+    message: String,
+    schedule_id: i32,
+    related_schedule_id: Option<i32>, // schedule_overlapの相手
+    traffic_id: Option<i32>,
+    stay_id: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChecksQuery {
+    include_past: Option<bool>, // 既定では終了済み（最終日が今日より前）のスケジュールを除く
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+struct CheckScheduleRow {
+    id: i64,
+    title: String,
+    date: Option<String>,
+    end_date: Option<String>,
+    open: Option<String>,
+    start: Option<String>,
+    #[sqlx(rename = "end")]
+    end_time: Option<String>,
+    area: String,
+    venue: String,
+    status: String,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+struct CheckTrafficRow {
+    id: i64,
+    schedule_id: i64,
+    date: String,
+    #[sqlx(rename = "order")]
+    order_value: i64,
+    from_place: String,
+    to_place: String,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+struct CheckStayRow {
+    id: i64,
+    schedule_id: i64,
+    check_in: String,
+    check_out: String,
+    hotel_name: String,
+}
+
+// "YYYY-MM-DD" または "YYYY-MM-DD HH:MM" を日時として読む（時刻がない場合はNone）
+fn parse_check_datetime(value: &str) -> (Option<chrono::NaiveDate>, Option<chrono::NaiveDateTime>) {
+    let value = value.trim();
+    let date = value.get(0..10).and_then(|d| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d").ok());
+    let datetime = chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M")
+        .or_else(|_| chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+        .ok();
+    (date, datetime)
+}
+
+fn parse_check_time(value: Option<&str>) -> Option<chrono::NaiveTime> {
+    chrono::NaiveTime::parse_from_str(value?.trim(), "%H:%M").ok()
+}
+
+impl CheckScheduleRow {
+    // 開催期間（初日・最終日）。dateが読めない場合はNone
+    fn days(&self) -> Option<(chrono::NaiveDate, chrono::NaiveDate)> {
+        let first = parse_check_datetime(self.date.as_deref()?).0?;
+        let last = self
+            .end_date
+            .as_deref()
+            .and_then(|d| parse_check_datetime(d).0)
+            .filter(|d| *d >= first)
+            .unwrap_or(first);
+        Some((first, last))
+    }
+
+    // 重なりの判定に使う期間。開場（なければ開演）〜終演で、時刻がなければその日全体とする
+    fn span(&self) -> Option<(chrono::NaiveDateTime, chrono::NaiveDateTime)> {
+        let (first, last) = self.days()?;
+        let from = parse_check_time(self.open.as_deref())
+            .or_else(|| parse_check_time(self.start.as_deref()))
+            .unwrap_or(chrono::NaiveTime::MIN);
+        let to = parse_check_time(self.end_time.as_deref())
+            .unwrap_or_else(|| chrono::NaiveTime::from_hms_opt(23, 59, 59).unwrap_or(chrono::NaiveTime::MIN));
+        Some((first.and_time(from), last.and_time(to)))
+    }
+}
+
+fn itinerary_warning(code: &str, message: String, schedule_id: i64) -> ItineraryWarning {
+    ItineraryWarning {
+        code: code.to_string(),
+        message,
+        schedule_id: schedule_id as i32,
+        related_schedule_id: None,
+        traffic_id: None,
+        stay_id: None,
+    }
+}
+
+// Keepのスケジュール同士の重なり。組ごとに1件（schedule_idが小さい方を基準にする）
+fn check_schedule_overlaps(schedules: &[CheckScheduleRow]) -> Vec<ItineraryWarning> {
+    let mut keeps: Vec<(&CheckScheduleRow, (chrono::NaiveDateTime, chrono::NaiveDateTime))> = schedules
+        .iter()
+        .filter(|s| s.status == "Keep")
+        .filter_map(|s| Some((s, s.span()?)))
+        .collect();
+    keeps.sort_by_key(|(s, _)| s.id);
+
+    let mut warnings = Vec::new();
+    for (i, (a, (a_from, a_to))) in keeps.iter().enumerate() {
+        for (b, (b_from, b_to)) in keeps.iter().skip(i + 1) {
+            if a_from < b_to && b_from < a_to {
+                warnings.push(ItineraryWarning {
+                    related_schedule_id: Some(b.id as i32),
+                    ..itinerary_warning(
+                        "schedule_overlap",
+                        format!("「{}」と「{}」の日程が重なっています", a.title, b.title),
+                        a.id,
+                    )
+                });
+            }
+        }
+    }
+    warnings
+}
+
+fn check_schedule_stays(schedule: &CheckScheduleRow, stays: &[CheckStayRow]) -> Vec<ItineraryWarning> {
+    let Some((first, last)) = schedule.days() else {
+        return vec![];
+    };
+    let mut warnings = Vec::new();
+    for stay in stays.iter().filter(|st| st.schedule_id == schedule.id) {
+        let (check_in, check_in_at) = parse_check_datetime(&stay.check_in);
+        let (check_out, check_out_at) = parse_check_datetime(&stay.check_out);
+        let (Some(check_in), Some(check_out)) = (check_in, check_out) else {
+            continue;
+        };
+        let warning = if check_out_at.zip(check_in_at).map_or(check_out < check_in, |(out, inn)| out <= inn) {
+            Some((
+                "stay_invalid_range",
+                format!("「{}」のチェックアウトがチェックイン以前になっています", stay.hotel_name),
+            ))
+        } else if check_in > last || check_out < first {
+            Some((
+                "stay_not_covering",
+                format!("「{}」の宿泊期間が「{}」の開催日にかかっていません", stay.hotel_name, schedule.title),
+            ))
+        } else {
+            None
+        };
+        if let Some((code, message)) = warning {
+            warnings.push(ItineraryWarning {
+                stay_id: Some(stay.id as i32),
+                ..itinerary_warning(code, message, schedule.id)
+            });
+        }
+    }
+    warnings
+}
+
+fn check_schedule_traffics(schedule: &CheckScheduleRow, traffics: &[CheckTrafficRow]) -> Vec<ItineraryWarning> {
+    let Some((first, _)) = schedule.days() else {
+        return vec![];
+    };
+    let start = parse_check_time(schedule.start.as_deref()).map(|t| first.and_time(t));
+    let mut legs: Vec<&CheckTrafficRow> = traffics.iter().filter(|t| t.schedule_id == schedule.id).collect();
+    legs.sort_by(|a, b| a.date.cmp(&b.date).then(a.order_value.cmp(&b.order_value)));
+
+    let arrives_at_venue = |t: &CheckTrafficRow| {
+        let to = t.to_place.trim();
+        !to.is_empty() && (to == schedule.area.trim() || to == schedule.venue.trim())
+    };
+    let mut warnings = Vec::new();
+    for (index, leg) in legs.iter().enumerate() {
+        // 最初の移動と会場（エリア）に着く移動だけを見る（帰りの移動は開演後で当然のため）
+        if index != 0 && !arrives_at_venue(leg) {
+            continue;
+        }
+        let (date, datetime) = parse_check_datetime(&leg.date);
+        let late = match (datetime, start) {
+            (Some(datetime), Some(start)) => datetime > start,
+            _ => date.is_some_and(|d| d > first),
+        };
+        if late {
+            warnings.push(ItineraryWarning {
+                traffic_id: Some(leg.id as i32),
+                ..itinerary_warning(
+                    "traffic_after_start",
+                    format!("{}→{}の移動が「{}」の開演より後になっています", leg.from_place, leg.to_place, schedule.title),
+                    schedule.id,
+                )
+            });
+        }
+    }
+    warnings
+}
+
+// targetsのスケジュールに関する警告をまとめる。重なりの判定にはothers（同じユーザーのスケジュール）も使う
+fn check_itinerary(
+    targets: &[CheckScheduleRow],
+    others: &[CheckScheduleRow],
+    traffics: &[CheckTrafficRow],
+    stays: &[CheckStayRow],
+) -> Vec<ItineraryWarning> {
+    let target_ids: std::collections::HashSet<i64> = targets.iter().map(|s| s.id).collect();
+    let mut all: Vec<CheckScheduleRow> = targets.to_vec();
+    all.extend(others.iter().filter(|s| !target_ids.contains(&s.id)).cloned());
+
+    let mut warnings: Vec<ItineraryWarning> = check_schedule_overlaps(&all)
+        .into_iter()
+        .filter_map(|w| {
+            let related = w.related_schedule_id? as i64;
+            if target_ids.contains(&(w.schedule_id as i64)) {
+                Some(w)
+            } else if target_ids.contains(&related) {
+                // 相手側が対象の場合は、対象のスケジュールを基準にした向きで返す
+                Some(ItineraryWarning {
+                    schedule_id: related as i32,
+                    related_schedule_id: Some(w.schedule_id),
+                    ..w
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+    for schedule in targets.iter().filter(|s| s.status != "Canceled") {
+        warnings.extend(check_schedule_stays(schedule, stays));
+        warnings.extend(check_schedule_traffics(schedule, traffics));
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod itinerary_check_tests {
+    use super::*;
+
+    fn schedule(id: i64, date: &str, open: Option<&str>, end: Option<&str>) -> CheckScheduleRow {
+        CheckScheduleRow {
+            id,
+            title: format!("公演{}", id),
+            date: Some(date.to_string()),
+            end_date: None,
+            open: open.map(str::to_string),
+            start: open.map(str::to_string),
+            end_time: end.map(str::to_string),
+            area: "大阪".to_string(),
+            venue: "城ホール".to_string(),
+            status: "Keep".to_string(),
+        }
+    }
+
+    fn codes(warnings: &[ItineraryWarning]) -> Vec<&str> {
+        warnings.iter().map(|w| w.code.as_str()).collect()
+    }
+
+    #[test]
+    fn detects_overlapping_keep_schedules() {
+        let matinee = schedule(1, "2025-05-10", Some("13:00"), Some("15:00"));
+        let evening = schedule(2, "2025-05-10", Some("18:00"), Some("20:00"));
+        let untimed = schedule(3, "2025-05-10", None, None);
+        assert!(check_itinerary(std::slice::from_ref(&matinee), std::slice::from_ref(&evening), &[], &[]).is_empty());
+
+        let warnings = check_itinerary(std::slice::from_ref(&untimed), &[matinee, evening], &[], &[]);
+        assert_eq!(codes(&warnings), vec!["schedule_overlap", "schedule_overlap"]);
+        assert!(warnings.iter().all(|w| w.schedule_id == 3));
+
+        let pending = CheckScheduleRow { status: "Pending".to_string(), ..schedule(4, "2025-05-10", None, None) };
+        assert!(check_itinerary(&[untimed], &[pending], &[], &[]).is_empty());
+    }
+
+    #[test]
+    fn checks_stays_and_traffics_against_show_date() {
+        let show = schedule(1, "2025-05-10", Some("18:00"), Some("20:00"));
+        let stay = |id, check_in: &str, check_out: &str| CheckStayRow {
+            id,
+            schedule_id: 1,
+            check_in: check_in.to_string(),
+            check_out: check_out.to_string(),
+            hotel_name: "ホテル".to_string(),
+        };
+        let stays = vec![
+            stay(1, "2025-05-10 15:00", "2025-05-11 10:00"),
+            stay(2, "2025-05-12 15:00", "2025-05-13 10:00"),
+            stay(3, "2025-05-11 15:00", "2025-05-10 10:00"),
+        ];
+        let traffic = |id, date: &str, order, to: &str| CheckTrafficRow {
+            id,
+            schedule_id: 1,
+            date: date.to_string(),
+            order_value: order,
+            from_place: "東京".to_string(),
+            to_place: to.to_string(),
+        };
+        let traffics = vec![
+            traffic(1, "2025-05-10 19:00", 1, "大阪"),
+            traffic(2, "2025-05-11", 2, "東京"),
+        ];
+
+        let warnings = check_itinerary(std::slice::from_ref(&show), &[], &traffics, &stays);
+        assert_eq!(codes(&warnings), vec!["stay_not_covering", "stay_invalid_range", "traffic_after_start"]);
+        assert_eq!(warnings[0].stay_id, Some(2));
+        assert_eq!(warnings[2].traffic_id, Some(1));
+
+        // 日付のみの場合は開催日より後かどうかで判定する（帰りの移動は対象外）
+        let on_time = vec![traffic(1, "2025-05-10", 1, "大阪"), traffic(2, "2025-05-11", 2, "東京")];
+        assert!(check_itinerary(&[show], &[], &on_time, &[]).is_empty());
+    }
+}
+
+async fn fetch_check_traffics_and_stays(
+    pool: &Pool<Sqlite>,
+    user_id: i32,
+) -> Result<(Vec<CheckTrafficRow>, Vec<CheckStayRow>), sqlx::Error> {
+    let traffics = sqlx::query_as::<_, CheckTrafficRow>(
+        r#"
+        SELECT t.id, t.schedule_id, t.date, t."order", t.from_place, t.to_place
+        FROM traffics t
+        INNER JOIN schedules s ON s.id = t.schedule_id
+        WHERE s.user_id = ? AND s.deleted_at IS NULL AND t.deleted_at IS NULL
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    let stays = sqlx::query_as::<_, CheckStayRow>(
+        r#"
+        SELECT st.id, st.schedule_id, st.check_in, st.check_out, st.hotel_name
+        FROM stays st
+        INNER JOIN schedules s ON s.id = st.schedule_id
+        WHERE s.user_id = ? AND s.deleted_at IS NULL AND st.deleted_at IS NULL AND st.status <> 'Canceled'
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok((traffics, stays))
+}
+
+// 1件のスケジュールに関する警告（作成・更新時のレスポンスと /schedules/:id/checks で使う）
+async fn itinerary_warnings_for_schedule(
+    pool: &Pool<Sqlite>,
+    user_id: i32,
+    schedule_id: i64,
+) -> Result<Vec<ItineraryWarning>, sqlx::Error> {
+    let schedules = sqlx::query_as::<_, CheckScheduleRow>(&format!(
+        "{} WHERE user_id = ? AND deleted_at IS NULL AND status <> 'Canceled'",
+        ITINERARY_CHECK_SELECT
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    let (targets, others): (Vec<_>, Vec<_>) = schedules.into_iter().partition(|s| s.id == schedule_id);
+    if targets.is_empty() {
+        return Ok(vec![]);
+    }
+    let (traffics, stays) = fetch_check_traffics_and_stays(pool, user_id).await?;
+    Ok(check_itinerary(&targets, &others, &traffics, &stays))
+}
+
+// 交通・宿泊の作成・更新時は、その交通・宿泊に関する警告だけを返す。チェックに失敗しても保存は成功として扱う
+async fn itinerary_warnings_for_entity(
+    pool: &Pool<Sqlite>,
+    user_id: i32,
+    schedule_id: i64,
+    matches: impl Fn(&ItineraryWarning) -> bool,
+) -> Option<Vec<ItineraryWarning>> {
+    match itinerary_warnings_for_schedule(pool, user_id, schedule_id).await {
+        Ok(warnings) => Some(warnings.into_iter().filter(matches).collect()),
+        Err(e) => {
+            eprintln!("[ItineraryCheck] Failed to check schedule {}: {}", schedule_id, e);
+            None
+        }
+    }
+}
+
+// GET /schedules/:id/checks
+async fn get_schedule_checks(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<ItineraryWarning>>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, id as i64, user.user_id).await?;
+    let warnings = itinerary_warnings_for_schedule(&pool, user.user_id, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[ScheduleChecks] Database error: {}", e);
+            database_error()
+        })?;
+    Ok(Json(warnings))
+}
+
+// GET /checks?include_past=true - アカウント全体の警告（開催日順）
+async fn list_checks(
+    user: AuthenticatedUser,
+    Query(params): Query<ChecksQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<ItineraryWarning>>, (StatusCode, Json<ErrorResponse>)> {
+    let failed = |e: sqlx::Error| {
+        eprintln!("[Checks] Database error: {}", e);
+        database_error()
+    };
+    let mut schedules = sqlx::query_as::<_, CheckScheduleRow>(&format!(
+        "{} WHERE user_id = ? AND deleted_at IS NULL AND status <> 'Canceled' ORDER BY date ASC, start ASC, id ASC",
+        ITINERARY_CHECK_SELECT
+    ))
+    .bind(user.user_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(failed)?;
+    if params.include_past != Some(true) {
+        let user_timezone = fetch_user_timezone(&pool, user.user_id).await.map_err(failed)?;
+        let today = Utc::now().with_timezone(&user_timezone).date_naive();
+        schedules.retain(|s| s.days().is_none_or(|(_, last)| last >= today));
+    }
+    let (traffics, stays) = fetch_check_traffics_and_stays(&pool, user.user_id).await.map_err(failed)?;
+
+    let order: std::collections::HashMap<i64, usize> = schedules.iter().enumerate().map(|(i, s)| (s.id, i)).collect();
+    let mut warnings = check_itinerary(&schedules, &[], &traffics, &stays);
+    warnings.sort_by_key(|w| order.get(&(w.schedule_id as i64)).copied().unwrap_or(usize::MAX));
+    Ok(Json(warnings))
+}
+
+// ====== 交通の行程 ======
+// スケジュールの交通を日付・order順に並べ、区間のつながり（前の区間の到着地 = 次の区間の出発地）と、
+// 最初の出発地に戻っているかを確認する。return_flag（往復）の区間は、帰りの区間を行程の最後に補って扱う
+// （往路が複数ある場合は、後の往路の帰りから順に戻る）
+
+const JOURNEY_TRAFFIC_SELECT: &str = r#"SELECT id, schedule_id, date, "order", transportation, from_place, to_place, notes, fare, fare_currency, miles, return_flag, total_fare, total_miles FROM traffics"#;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct JourneyLeg {
+    traffic_id: i32,
+    is_return: bool,      // 往復の区間から補った帰り（traffic_idは往路の区間、保存はされていない）
+    date: Option<String>, // 補った帰りの区間はNULL
+    order: i32,
+    transportation: Option<String>,
+    from: String,
+    to: String,
+    layover: Option<JourneyLayover>, // 次の区間までの滞在（つながっている場合のみ）
+}
+
+// 乗り継ぎ・滞在。daysは日付の差、minutesは両方の区間に時刻がある場合のみ
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct JourneyLayover {
+    place: String,
+    days: Option<i64>,
+    minutes: Option<i64>,
+}
+
+// - gap: 前の区間の到着地と次の区間の出発地が違う
+// - unmatched_return: 往復の帰りの出発地が、直前の到着地と違う（帰りの片道を別に登録している場合など）
+// - not_returning_home: 最後の到着地が最初の出発地と違う
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct JourneyIssue {
+    code: String,
+    message: String,
+    traffic_id: i32,
+    previous_traffic_id: Option<i32>,
+}
+
+#[derive(Serialize)]
+struct Journey {
+    schedule_id: i32,
+    home: Option<String>, // 最初の区間の出発地
+    legs: Vec<JourneyLeg>,
+    issues: Vec<JourneyIssue>,
+    is_complete: bool, // 区間があり、問題がない
+}
+
+// PUT /schedules/:id/journey/order 用リクエストボディ
+#[derive(Deserialize)]
+struct ReorderJourney {
+    traffic_ids: Vec<i32>, // スケジュールのすべての交通を、行程の順に1回ずつ
+}
+
+// 同じ日の区間はorder順（時刻の有無で並びが変わらないよう、日付部分だけで比較する）
+fn journey_leg_sort_key(row: &TrafficRow) -> (String, i64, i64) {
+    (row.date.get(0..10).unwrap_or(&row.date).to_string(), row.order_value, row.id)
+}
+
+fn journey_layover(prev: &JourneyLeg, next: &JourneyLeg) -> JourneyLayover {
+    let parse = |leg: &JourneyLeg| leg.date.as_deref().map(parse_check_datetime).unwrap_or((None, None));
+    let ((prev_date, prev_at), (next_date, next_at)) = (parse(prev), parse(next));
+    JourneyLayover {
+        place: prev.to.clone(),
+        days: prev_date.zip(next_date).map(|(a, b)| (b - a).num_days()),
+        minutes: prev_at.zip(next_at).map(|(a, b)| (b - a).num_minutes()),
+    }
+}
+
+fn build_journey(mut traffics: Vec<TrafficRow>) -> (Vec<JourneyLeg>, Vec<JourneyIssue>) {
+    traffics.sort_by_key(journey_leg_sort_key);
+    let outbound = traffics.iter().map(|t| JourneyLeg {
+        traffic_id: t.id as i32,
+        is_return: false,
+        date: Some(t.date.clone()),
+        order: t.order_value as i32,
+        transportation: t.transportation.clone(),
+        from: t.from_place.trim().to_string(),
+        to: t.to_place.trim().to_string(),
+        layover: None,
+    });
+    let returns = traffics.iter().rev().filter(|t| t.return_flag != 0).map(|t| JourneyLeg {
+        traffic_id: t.id as i32,
+        is_return: true,
+        date: None,
+        order: t.order_value as i32,
+        transportation: t.transportation.clone(),
+        from: t.to_place.trim().to_string(),
+        to: t.from_place.trim().to_string(),
+        layover: None,
+    });
+    let mut legs: Vec<JourneyLeg> = outbound.chain(returns).collect();
+
+    let mut issues = Vec::new();
+    for i in 1..legs.len() {
+        let (prev, next) = (&legs[i - 1], &legs[i]);
+        if prev.to == next.from {
+            let layover = journey_layover(prev, next);
+            legs[i - 1].layover = Some(layover);
+            continue;
+        }
+        let (code, message) = if next.is_return {
+            (
+                "unmatched_return",
+                format!("往復の帰り（{}→{}）の出発地が、直前の到着地（{}）と一致しません", next.from, next.to, prev.to),
+            )
+        } else {
+            (
+                "gap",
+                format!("{}に到着した後、次の区間が{}から出発しています", prev.to, next.from),
+            )
+        };
+        issues.push(JourneyIssue {
+            code: code.to_string(),
+            message,
+            traffic_id: next.traffic_id,
+            previous_traffic_id: Some(prev.traffic_id),
+        });
+    }
+    if let (Some(first), Some(last)) = (legs.first(), legs.last()) {
+        if first.from != last.to {
+            issues.push(JourneyIssue {
+                code: "not_returning_home".to_string(),
+                message: format!("最後の到着地（{}）が出発地（{}）と一致しません", last.to, first.from),
+                traffic_id: last.traffic_id,
+                previous_traffic_id: None,
+            });
+        }
+    }
+    (legs, issues)
+}
+
+#[cfg(test)]
+mod journey_tests {
+    use super::*;
+
+    fn leg(id: i64, date: &str, order: i64, from: &str, to: &str, round_trip: bool) -> TrafficRow {
+        TrafficRow {
+            id,
+            schedule_id: 1,
+            date: date.to_string(),
+            order_value: order,
+            transportation: None,
+            from_place: from.to_string(),
+            to_place: to.to_string(),
+            notes: None,
+            fare: 0,
+            fare_currency: DEFAULT_CURRENCY.to_string(),
+            miles: None,
+            return_flag: round_trip as i32,
+            total_fare: None,
+            total_miles: None,
+        }
+    }
+
+    fn codes(issues: &[JourneyIssue]) -> Vec<&str> {
+        issues.iter().map(|i| i.code.as_str()).collect()
+    }
+
+    #[test]
+    fn orders_legs_and_computes_layovers() {
+        let (legs, issues) = build_journey(vec![
+            leg(3, "2025-05-12", 1, "新大阪", "東京", false),
+            leg(2, "2025-05-10", 2, "新大阪", "大阪", false),
+            leg(1, "2025-05-10 08:00", 1, "東京", "新大阪", false),
+            leg(4, "2025-05-11", 1, "大阪", "新大阪", false),
+        ]);
+        assert!(issues.is_empty());
+        assert_eq!(legs.iter().map(|l| l.traffic_id).collect::<Vec<_>>(), vec![1, 2, 4, 3]);
+        assert_eq!(legs[1].layover.as_ref().map(|l| (l.place.as_str(), l.days)), Some(("大阪", Some(1))));
+        assert_eq!(legs[3].layover, None);
+    }
+
+    #[test]
+    fn round_trip_legs_return_in_reverse_order() {
+        let (legs, issues) = build_journey(vec![
+            leg(1, "2025-05-10", 1, "東京", "新大阪", true),
+            leg(2, "2025-05-10", 2, "新大阪", "大阪", true),
+        ]);
+        assert!(issues.is_empty());
+        let route: Vec<(&str, &str, bool)> = legs.iter().map(|l| (l.from.as_str(), l.to.as_str(), l.is_return)).collect();
+        assert_eq!(
+            route,
+            vec![("東京", "新大阪", false), ("新大阪", "大阪", false), ("大阪", "新大阪", true), ("新大阪", "東京", true)]
+        );
+    }
+
+    #[test]
+    fn detects_gaps_and_unmatched_returns() {
+        let (_, issues) = build_journey(vec![
+            leg(1, "2025-05-10", 1, "東京", "新大阪", false),
+            leg(2, "2025-05-11", 1, "京都", "東京", false),
+        ]);
+        assert_eq!(codes(&issues), vec!["gap"]);
+        assert_eq!((issues[0].traffic_id, issues[0].previous_traffic_id), (2, Some(1)));
+
+        // 往復で登録した区間の帰りを、片道でも登録している
+        let (_, issues) = build_journey(vec![
+            leg(1, "2025-05-10", 1, "東京", "大阪", true),
+            leg(2, "2025-05-11", 1, "大阪", "東京", false),
+        ]);
+        assert_eq!(codes(&issues), vec!["unmatched_return"]);
+    }
+}
+
+async fn fetch_journey_traffics(pool: &Pool<Sqlite>, schedule_id: i64) -> Result<Vec<TrafficRow>, sqlx::Error> {
+    sqlx::query_as::<_, TrafficRow>(&format!(
+        "{} WHERE schedule_id = ? AND deleted_at IS NULL",
+        JOURNEY_TRAFFIC_SELECT
+    ))
+    .bind(schedule_id)
+    .fetch_all(pool)
+    .await
+}
+
+// GET /schedules/:id/journey
+async fn get_journey(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Journey>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, id as i64, user.user_id).await?;
+    let traffics = fetch_journey_traffics(&pool, id as i64).await.map_err(|e| {
+        eprintln!("[Journey] Database error: {}", e);
+        database_error()
+    })?;
+
+    let (legs, issues) = build_journey(traffics);
+    Ok(Json(Journey {
+        schedule_id: id,
+        home: legs.first().map(|leg| leg.from.clone()),
+        is_complete: !legs.is_empty() && issues.is_empty(),
+        legs,
+        issues,
+    }))
+}
+
+// PUT /schedules/:id/journey/order - 指定した順にorderを1から振り直す（すべて成功するか、何も変更しない）
+async fn reorder_journey(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<ReorderJourney>,
+) -> Result<Json<Journey>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, id as i64, user.user_id).await?;
+    let bad_request = |message: &str| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: message.to_string(),
+            }),
+        )
+    };
+    let failed = |e: sqlx::Error| {
+        eprintln!("[ReorderJourney] Database error: {}", e);
+        database_error()
+    };
+
+    // 検証と並べ替えの間に交通が追加・削除・日付変更されないよう、現在の交通はトランザクション内で読み直す
+    let mut tx = pool.begin().await.map_err(failed)?;
+    let dates: std::collections::HashMap<i64, String> =
+        sqlx::query_as::<_, (i64, String)>("SELECT id, date FROM traffics WHERE schedule_id = ? AND deleted_at IS NULL")
+            .bind(id)
+            .fetch_all(&mut *tx)
+            .await
+            .map_err(failed)?
+            .into_iter()
+            .collect();
+    let mut requested: Vec<i64> = payload.traffic_ids.iter().map(|id| *id as i64).collect();
+    requested.sort_unstable();
+    let mut current: Vec<i64> = dates.keys().copied().collect();
+    current.sort_unstable();
+    if requested != current {
+        return Err(bad_request("traffic_idsにはこのスケジュールのすべての交通を1回ずつ指定してください"));
+    }
+    // 行程は日付順に並べるため、日付をまたいだ並べ替えはできない（日付を変更する場合は交通を更新する）
+    let ordered_dates: Vec<&str> = payload
+        .traffic_ids
+        .iter()
+        .filter_map(|id| dates.get(&(*id as i64)))
+        .map(|date| date.get(0..10).unwrap_or(date))
+        .collect();
+    if ordered_dates.windows(2).any(|pair| pair[0] > pair[1]) {
+        return Err(bad_request("交通の日付の順序と異なる並び順は指定できません"));
+    }
+
+    let mut befores = Vec::with_capacity(payload.traffic_ids.len());
+    for traffic_id in &payload.traffic_ids {
+        befores.push(revision_snapshot(&pool, RevisionEntity::Traffic, *traffic_id as i64).await);
+    }
+
+    let now = Utc::now().to_rfc3339();
+    for (index, traffic_id) in payload.traffic_ids.iter().enumerate() {
+        sqlx::query(r#"UPDATE traffics SET "order" = ?, updated_at = ? WHERE id = ? AND schedule_id = ? AND "order" <> ?"#)
+            .bind(index as i64 + 1)
+            .bind(&now)
+            .bind(traffic_id)
+            .bind(id)
+            .bind(index as i64 + 1)
+            .execute(&mut *tx)
+            .await
+            .map_err(failed)?;
+    }
+    tx.commit().await.map_err(failed)?;
+
+    for (traffic_id, before) in payload.traffic_ids.iter().zip(befores) {
+        track_revision(&pool, user.user_id, RevisionEntity::Traffic, *traffic_id as i64, id as i64, RevisionAction::Update, before).await;
+    }
+
+    get_journey(Path(id), user, Extension(pool)).await
+}
+
+// ====== ステータス ======
+// スケジュールのステータスは Pending（申込中）→ Keep（確保済み）→ Done（参加済み）と進み、Canceledはキャンセル
+// 変更できる組み合わせをサーバー側で検証し、変更のたびにschedule_status_historyへ記録する
+// Keepのまま終演時刻を過ぎたスケジュールは、バックグラウンドタスクがDoneにする（users.auto_done_enabledがONの場合）
+
+const SCHEDULE_STATUSES: [&str; 4] = ["Pending", "Keep", "Done", "Canceled"];
+
+// ステータスを変更できるか（同じステータスのままは常に可）
+// Doneは参加の記録のため、自動更新の取り消しとしてKeepに戻す場合のみ変更できる
+// CanceledからDoneへは直接変更できない（Pending / Keepに戻してから）
+fn schedule_status_transition_allowed(from: &str, to: &str) -> bool {
+    if from == to {
+        return true;
+    }
+    match (from, to) {
+        ("Pending", "Keep" | "Done" | "Canceled") => true,
+        ("Keep", "Pending" | "Done" | "Canceled") => true,
+        ("Done", "Keep") => true,
+        ("Canceled", "Pending" | "Keep") => true,
+        // 検証の導入前に保存された不明なステータスからは、どのステータスにも変更できる
+        (from, _) => !SCHEDULE_STATUSES.contains(&from),
+    }
+}
+
+fn validate_schedule_status(value: &str) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    let value = value.trim();
+    if SCHEDULE_STATUSES.contains(&value) {
+        return Ok(value.to_string());
+    }
+    Err((
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            error: "ステータスはPending / Keep / Done / Canceledのいずれかを指定してください".to_string(),
+        }),
+    ))
+}
+
+fn ensure_schedule_status_transition(from: &str, to: &str) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    if schedule_status_transition_allowed(from, to) {
+        return Ok(());
+    }
+    Err((
+        StatusCode::CONFLICT,
+        Json(ErrorResponse {
+            error: format!("ステータスを{}から{}に変更することはできません", from, to),
+        }),
+    ))
+}
+
+// 終演時刻（最終日の終演、終演時刻が未設定の場合は最終日の終わり）。開催日が未設定の場合はNone
+// 終演が開演より前の時刻の場合は日付をまたいだものとして翌日の時刻にする
+fn schedule_end_datetime(
+    date: Option<&str>,
+    end_date: Option<&str>,
+    start: Option<&str>,
+    end: Option<&str>,
+    tz: Tz,
+) -> Option<DateTime<FixedOffset>> {
+    let parse_date = |d: &str| chrono::NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d").ok();
+    let parse_time = |t: &str| chrono::NaiveTime::parse_from_str(t.trim(), "%H:%M").ok();
+    let last_day = end_date.and_then(parse_date).or_else(|| date.and_then(parse_date))?;
+    let naive = match end.and_then(parse_time) {
+        Some(end) if start.and_then(parse_time).is_some_and(|start| end < start) => {
+            last_day.succ_opt()?.and_time(end)
+        }
+        Some(end) => last_day.and_time(end),
+        None => last_day.succ_opt()?.and_time(chrono::NaiveTime::MIN),
+    };
+    Some(localize_datetime(naive, tz))
+}
+
+// 最新のステータスごとの変更日時（同じステータスに複数回なった場合は最後の日時）
+fn schedule_status_timestamps(history: &[ScheduleStatusChange]) -> std::collections::BTreeMap<String, String> {
+    let mut timestamps = std::collections::BTreeMap::new();
+    for change in history {
+        timestamps.insert(change.to_status.clone(), change.changed_at.clone());
+    }
+    timestamps
+}
+
+#[cfg(test)]
+mod schedule_status_tests {
+    use super::{schedule_end_datetime, schedule_status_timestamps, schedule_status_transition_allowed, ScheduleStatusChange};
+
+    #[test]
+    fn transitions_follow_the_state_machine() {
+        assert!(schedule_status_transition_allowed("Pending", "Keep"));
+        assert!(schedule_status_transition_allowed("Keep", "Done"));
+        assert!(schedule_status_transition_allowed("Keep", "Canceled"));
+        assert!(schedule_status_transition_allowed("Done", "Done"));
+        // 自動でDoneになったものはKeepに戻せるが、参加済みからのキャンセル・申込中には戻せない
+        assert!(schedule_status_transition_allowed("Done", "Keep"));
+        assert!(!schedule_status_transition_allowed("Done", "Canceled"));
+        assert!(!schedule_status_transition_allowed("Done", "Pending"));
+        assert!(schedule_status_transition_allowed("Canceled", "Keep"));
+        assert!(!schedule_status_transition_allowed("Canceled", "Done"));
+        // 検証の導入前に保存された値からは変更できる
+        assert!(schedule_status_transition_allowed("keep", "Done"));
+    }
+
+    #[test]
+    fn end_datetime_uses_last_day_and_local_time() {
+        let tokyo = chrono_tz::Asia::Tokyo;
+        let end = |date, end_date, start, end| {
+            schedule_end_datetime(date, end_date, start, end, tokyo).map(|dt| dt.to_rfc3339())
+        };
+        assert_eq!(end(Some("2025-05-10"), None, Some("18:00"), Some("21:00")).as_deref(), Some("2025-05-10T21:00:00+09:00"));
+        // 終演時刻が未設定の場合は最終日の終わり
+        assert_eq!(end(Some("2025-05-10"), Some("2025-05-12"), None, None).as_deref(), Some("2025-05-13T00:00:00+09:00"));
+        // 日付をまたぐオールナイト
+        assert_eq!(end(Some("2025-05-10"), None, Some("23:00"), Some("05:00")).as_deref(), Some("2025-05-11T05:00:00+09:00"));
+        assert_eq!(end(None, None, Some("18:00"), Some("21:00")), None);
+    }
+
+    #[test]
+    fn timestamps_keep_the_latest_change_per_status() {
+        let change = |to: &str, at: &str| ScheduleStatusChange {
+            id: 0,
+            from_status: None,
+            to_status: to.to_string(),
+            source: "manual".to_string(),
+            changed_at: at.to_string(),
+        };
+        let timestamps = schedule_status_timestamps(&[
+            change("Keep", "2025-01-01T00:00:00Z"),
+            change("Done", "2025-05-11T00:00:00Z"),
+            change("Keep", "2025-05-12T00:00:00Z"),
+        ]);
+        assert_eq!(timestamps.len(), 2);
+        assert_eq!(timestamps["Keep"], "2025-05-12T00:00:00Z");
+        assert_eq!(timestamps["Done"], "2025-05-11T00:00:00Z");
+    }
+}
+
+// ステータス変更の記録元（manual: 作成・編集、auto: 終演後の自動更新、revert: 変更履歴から戻した場合）
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StatusChangeSource {
+    Manual,
+    Auto,
+    Revert,
+}
+
+impl StatusChangeSource {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Manual => "manual",
+            Self::Auto => "auto",
+            Self::Revert => "revert",
+        }
+    }
+}
+
+#[derive(Serialize, sqlx::FromRow, Debug, Clone)]
+struct ScheduleStatusChange {
+    id: i64,
+    from_status: Option<String>, // 作成時はNULL
+    to_status: String,
+    source: String,
+    changed_at: String,
+}
+
+#[derive(Serialize)]
+struct ScheduleStatusHistory {
+    schedule_id: i32,
+    status: String,
+    auto_done_at: Option<String>, // 自動でDoneになる予定の日時（Keepかつ自動更新がONの場合のみ）
+    timestamps: std::collections::BTreeMap<String, String>, // ステータスごとの最新の変更日時
+    history: Vec<ScheduleStatusChange>,   // 古い順
+}
+
+// ステータスの変更を記録する（変更履歴と同様に、記録の失敗で元の処理は失敗させない）
+async fn record_status_change(
+    pool: &Pool<Sqlite>,
+    schedule_id: i64,
+    user_id: i64,
+    from_status: Option<&str>,
+    to_status: &str,
+    source: StatusChangeSource,
+) {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO schedule_status_history (schedule_id, user_id, from_status, to_status, source, changed_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(schedule_id)
+    .bind(user_id)
+    .bind(from_status)
+    .bind(to_status)
+    .bind(source.as_str())
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await;
+    if let Err(e) = result {
+        eprintln!("[StatusHistory] Failed to record status of schedule {}: {}", schedule_id, e);
+    }
+}
+
+// GET /schedules/:id/status-history
+async fn get_schedule_status_history(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<ScheduleStatusHistory>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, id as i64, user.user_id).await?;
+    let history_failed = |e: sqlx::Error| {
+        eprintln!("[StatusHistory] Database error: {}", e);
+        database_error()
+    };
+
+    let row: AutoDoneRow = sqlx::query_as(&format!("{} WHERE s.id = ?", AUTO_DONE_SELECT))
+        .bind(id)
+        .fetch_one(&pool)
+        .await
+        .map_err(history_failed)?;
+    let history: Vec<ScheduleStatusChange> = sqlx::query_as(
+        r#"
+        SELECT id, from_status, to_status, source, changed_at
+        FROM schedule_status_history
+        WHERE schedule_id = ?
+        ORDER BY changed_at, id
+        "#,
+    )
+    .bind(id)
+    .fetch_all(&pool)
+    .await
+    .map_err(history_failed)?;
+
+    let auto_done_at = if row.auto_done_pending() {
+        row.ends_at().map(|dt| dt.to_rfc3339())
+    } else {
+        None
+    };
+    Ok(Json(ScheduleStatusHistory {
+        schedule_id: id,
+        status: row.status,
+        auto_done_at,
+        timestamps: schedule_status_timestamps(&history),
+        history,
+    }))
+}
+
+// 終演時刻の判定に使う列。複数日開催で最終日の日程に時刻がある場合はそちらを優先する
+// auto_done_onceは自動でDoneにしたことがあるか（ユーザーがKeepに戻した場合は再度Doneにしない）
+const AUTO_DONE_SELECT: &str = r#"
+    SELECT
+      s.id,
+      s.user_id,
+      s.status,
+      s.date,
+      s.end_date,
+      COALESCE((SELECT d.start FROM schedule_days d WHERE d.schedule_id = s.id AND d.date = s.end_date), s.start) AS start,
+      COALESCE((SELECT d."end" FROM schedule_days d WHERE d.schedule_id = s.id AND d.date = s.end_date), s."end") AS end_time,
+      s.timezone,
+      u.timezone AS user_timezone,
+      u.auto_done_enabled,
+      EXISTS (SELECT 1 FROM schedule_status_history h WHERE h.schedule_id = s.id AND h.source = 'auto') AS auto_done_once
+    FROM schedules s
+    JOIN users u ON u.id = s.user_id
+"#;
+
+#[derive(sqlx::FromRow)]
+struct AutoDoneRow {
+    id: i64,
+    user_id: i64,
+    status: String,
+    date: Option<String>,
+    end_date: Option<String>,
+    start: Option<String>,
+    end_time: Option<String>,
+    timezone: Option<String>,
+    user_timezone: Option<String>,
+    auto_done_enabled: i32,
+    auto_done_once: i32,
+}
+
+impl AutoDoneRow {
+    fn auto_done_pending(&self) -> bool {
+        self.status == "Keep" && self.auto_done_enabled != 0 && self.auto_done_once == 0
+    }
+
+    fn ends_at(&self) -> Option<DateTime<FixedOffset>> {
+        let tz = resolve_timezone(self.timezone.as_deref(), self.user_timezone.as_deref());
+        schedule_end_datetime(
+            self.date.as_deref(),
+            self.end_date.as_deref(),
+            self.start.as_deref(),
+            self.end_time.as_deref(),
+            tz,
+        )
+    }
+}
+
+// 終演時刻を過ぎたKeepのスケジュールをDoneにする（バックグラウンドタスクから呼ぶ）
+async fn auto_complete_schedules(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    // 開催日は現地の日付のため、UTCの翌日までを候補にして終演時刻は個別に判定する
+    let latest_date = (now + chrono::Duration::days(1)).format("%Y-%m-%d").to_string();
+    let rows: Vec<AutoDoneRow> = sqlx::query_as(&format!(
+        r#"{}
+        WHERE s.status = 'Keep'
+          AND s.deleted_at IS NULL
+          AND u.auto_done_enabled = 1
+          AND s.date IS NOT NULL
+          AND s.date <= ?
+        "#,
+        AUTO_DONE_SELECT
+    ))
+    .bind(&latest_date)
+    .fetch_all(pool)
+    .await?;
+
+    let mut completed = 0;
+    for row in rows {
+        if !row.auto_done_pending() || row.ends_at().is_none_or(|ends_at| ends_at > now) {
+            continue;
+        }
+        let (id, user_id) = (row.id, row.user_id);
+
+        let before = revision_snapshot(pool, RevisionEntity::Schedule, id).await;
+        // 判定中にユーザーが変更した場合は上書きしない
+        let result = sqlx::query("UPDATE schedules SET status = 'Done', updated_at = ? WHERE id = ? AND status = 'Keep'")
+            .bind(now.to_rfc3339())
+            .bind(id)
+            .execute(pool)
+            .await?;
+        if result.rows_affected() == 0 {
+            continue;
+        }
+        record_status_change(pool, id, user_id, Some("Keep"), "Done", StatusChangeSource::Auto).await;
+        track_revision(pool, user_id as i32, RevisionEntity::Schedule, id, id, RevisionAction::Update, before).await;
+        completed += 1;
+    }
+    if completed > 0 {
+        eprintln!("[AutoComplete] Marked {} schedules as Done", completed);
+    }
+    Ok(())
+}
+
+// ====== セットリスト ======
+
+#[derive(Serialize, Clone)]
+struct SetlistEntry {
+    id: i32,
+    position: i32,
+    song_title: String,
+    artist: Option<String>, // 対バン・フェスなど複数組出演の場合の演奏アーティスト
+    is_encore: bool,
+    notes: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+struct PublicSetlistEntry {
+    position: i32,
+    song_title: String,
+    artist: Option<String>,
+    is_encore: bool,
+    notes: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct SetlistEntryRow {
+    id: i64,
+    position: i64,
+    song_title: String,
+    artist: Option<String>,
+    is_encore: i32,
+    notes: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Setlist {
+    schedule_id: i32,
+    is_public: bool, // 共有ページ（/share/:share_id/schedules/:id）で公開するか
+    songs: Vec<SetlistEntry>,
+}
+
+#[derive(Deserialize)]
+struct NewSetlistEntry {
+    song_title: String,
+    artist: Option<String>,
+    is_encore: Option<bool>,
+    notes: Option<String>,
+}
+
+// PUT /schedules/:id/setlist 用リクエストボディ（songsの並び順がそのまま曲順になる）
+#[derive(Deserialize)]
+struct SaveSetlistRequest {
+    songs: Vec<NewSetlistEntry>,
+    is_public: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SongStatsQuery {
+    artist: Option<String>,
+    q: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SongHistoryQuery {
+    title: String,
+    artist: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SongStats {
+    song_title: String,
+    artist: Option<String>,
+    count: i32, // 聴いた回数（公演数）
+    first_heard_date: Option<String>,
+    first_heard_schedule_id: i32,
+    last_heard_date: Option<String>,
+    last_heard_schedule_id: i32,
+}
+
+#[derive(Serialize)]
+struct SongHistoryEntry {
+    schedule_id: i32,
+    title: String,
+    date: Option<String>,
+    venue: String,
+    position: i32,
+    is_encore: bool,
+}
+
+#[derive(sqlx::FromRow)]
+struct HeardSongRow {
+    schedule_id: i64,
+    title: String,
+    date: Option<String>,
+    venue: String,
+    position: i64,
+    song_title: String,
+    artist: Option<String>,
+    is_encore: i32,
+}
+
+fn row_to_setlist_entry(row: SetlistEntryRow) -> SetlistEntry {
+    SetlistEntry {
+        id: row.id as i32,
+        position: row.position as i32,
+        song_title: row.song_title,
+        artist: row.artist,
+        is_encore: row.is_encore != 0,
+        notes: row.notes,
+    }
+}
+
+// 曲名・アーティスト名の表記ゆれ（前後の空白・大文字小文字）を吸収した集計キー
+fn song_key(song_title: &str, artist: Option<&str>) -> (String, String) {
+    (
+        song_title.trim().to_lowercase(),
+        artist.map(|a| a.trim().to_lowercase()).unwrap_or_default(),
+    )
+}
+
+async fn fetch_setlist_rows(pool: &Pool<Sqlite>, schedule_id: i64) -> Result<Vec<SetlistEntryRow>, sqlx::Error> {
+    sqlx::query_as::<_, SetlistEntryRow>(
+        "SELECT id, position, song_title, artist, is_encore, notes FROM setlist_entries WHERE schedule_id = ? ORDER BY position ASC"
+    )
+    .bind(schedule_id)
+    .fetch_all(pool)
+    .await
+}
+
+// 共有ページ用のセットリストを取得する（公開設定がOFFの場合はNone）
+async fn fetch_public_setlist(
+    pool: &Pool<Sqlite>,
+    schedule_id: i64,
+) -> Result<Option<Vec<PublicSetlistEntry>>, sqlx::Error> {
+    let setlist_public: Option<i32> = sqlx::query_scalar("SELECT setlist_public FROM schedules WHERE id = ?")
+        .bind(schedule_id)
+        .fetch_optional(pool)
+        .await?;
+    if setlist_public.unwrap_or(0) == 0 {
+        return Ok(None);
+    }
+    let rows = fetch_setlist_rows(pool, schedule_id).await?;
+    Ok(Some(
+        rows.into_iter()
+            .map(|row| PublicSetlistEntry {
+                position: row.position as i32,
+                song_title: row.song_title,
+                artist: row.artist,
+                is_encore: row.is_encore != 0,
+                notes: row.notes,
+            })
+            .collect(),
+    ))
+}
+
+// GET /schedules/:id/setlist
+async fn get_setlist(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Setlist>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, id as i64, user.user_id).await?;
+
+    let setlist_public: i32 = sqlx::query_scalar("SELECT setlist_public FROM schedules WHERE id = ?")
+        .bind(id)
+        .fetch_one(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[GetSetlist] Database error: {}", e);
+            database_error()
+        })?;
+    let rows = fetch_setlist_rows(&pool, id as i64).await.map_err(|e| {
+        eprintln!("[GetSetlist] Database error: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(Setlist {
+        schedule_id: id,
+        is_public: setlist_public != 0,
+        songs: rows.into_iter().map(row_to_setlist_entry).collect(),
+    }))
+}
+
+// PUT /schedules/:id/setlist - セットリストを丸ごと置き換える
+async fn save_setlist(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<SaveSetlistRequest>,
+) -> Result<Json<Setlist>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, id as i64, user.user_id).await?;
+
+    if payload.songs.iter().any(|song| song.song_title.trim().is_empty()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "曲名を入力してください".to_string(),
+            }),
+        ));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await.map_err(|e| {
+        eprintln!("[SaveSetlist] Database error: {}", e);
+        database_error()
+    })?;
+
+    sqlx::query("DELETE FROM setlist_entries WHERE schedule_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("[SaveSetlist] Database error: {}", e);
+            database_error()
+        })?;
+
+    for (index, song) in payload.songs.iter().enumerate() {
+        let artist = song.artist.as_deref().map(str::trim).filter(|a| !a.is_empty());
+        sqlx::query(
+            r#"
+            INSERT INTO setlist_entries (schedule_id, position, song_title, artist, is_encore, notes, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(id)
+        .bind(index as i64 + 1)
+        .bind(song.song_title.trim())
+        .bind(artist)
+        .bind(song.is_encore.unwrap_or(false) as i32)
+        .bind(&song.notes)
+        .bind(&now)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("[SaveSetlist] Database error: {}", e);
+            database_error()
+        })?;
+    }
+
+    if let Some(is_public) = payload.is_public {
+        sqlx::query("UPDATE schedules SET setlist_public = ? WHERE id = ?")
+            .bind(is_public as i32)
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                eprintln!("[SaveSetlist] Database error: {}", e);
+                database_error()
+            })?;
+    }
+
+    tx.commit().await.map_err(|e| {
+        eprintln!("[SaveSetlist] Database error: {}", e);
+        database_error()
+    })?;
+
+    get_setlist(Path(id), user, Extension(pool)).await
+}
+
+// Doneのスケジュールで聴いた曲を日付順に取得する
+// artistが未入力の曲は、スケジュールのお目当て（target）の曲として扱う
+async fn fetch_heard_songs(pool: &Pool<Sqlite>, user_id: i32) -> Result<Vec<HeardSongRow>, sqlx::Error> {
+    sqlx::query_as::<_, HeardSongRow>(
+        r#"
+        SELECT
+          s.id AS schedule_id,
+          s.title,
+          s.date,
+          s.venue,
+          se.position,
+          se.song_title,
+          COALESCE(se.artist, NULLIF(TRIM(s.target), '')) AS artist,
+          se.is_encore
+        FROM setlist_entries se
+        INNER JOIN schedules s ON se.schedule_id = s.id
+        WHERE s.user_id = ? AND s.status = 'Done' AND s.deleted_at IS NULL
+        ORDER BY s.date ASC, s.start ASC, se.position ASC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+// GET /setlist/songs?artist=&q= - 曲ごとの聴いた回数と初めて/最後に聴いた公演
+async fn list_song_stats(
+    user: AuthenticatedUser,
+    Query(params): Query<SongStatsQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<SongStats>>, (StatusCode, Json<ErrorResponse>)> {
+    let rows = fetch_heard_songs(&pool, user.user_id).await.map_err(|e| {
+        eprintln!("[ListSongStats] Database error: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(build_song_stats(rows, params.artist.as_deref(), params.q.as_deref())))
+}
+
+// GET /setlist/songs/history?title=&artist= - 指定した曲を聴いた公演の一覧
+async fn get_song_history(
+    user: AuthenticatedUser,
+    Query(params): Query<SongHistoryQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<SongHistoryEntry>>, (StatusCode, Json<ErrorResponse>)> {
+    let rows = fetch_heard_songs(&pool, user.user_id).await.map_err(|e| {
+        eprintln!("[GetSongHistory] Database error: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(build_song_history(rows, &params.title, params.artist.as_deref())))
+}
+
+// 聴いた曲をタイトルとアーティスト（大文字小文字・前後の空白を区別しない）ごとに集計する
+// rowsは日付順（fetch_heard_songsの並び）であること。同じ公演で複数回演奏された曲は1回と数える
+fn build_song_stats(rows: Vec<HeardSongRow>, artist: Option<&str>, q: Option<&str>) -> Vec<SongStats> {
+    let artist_filter = artist.map(|a| a.trim().to_lowercase()).filter(|a| !a.is_empty());
+    let query = q.map(|q| q.trim().to_lowercase()).filter(|q| !q.is_empty());
+
+    // 日付順に走査するため、最初に出現した公演が初めて聴いた公演、最後に出現した公演が最後に聴いた公演になる
+    let mut order: Vec<(String, String)> = Vec::new();
+    let mut stats: std::collections::HashMap<(String, String), (SongStats, std::collections::HashSet<i64>)> =
+        std::collections::HashMap::new();
+    for row in rows {
+        let key = song_key(&row.song_title, row.artist.as_deref());
+        if artist_filter.as_ref().is_some_and(|a| *a != key.1) {
+            continue;
+        }
+        if query.as_ref().is_some_and(|q| !key.0.contains(q.as_str())) {
+            continue;
+        }
+        let (entry, schedules) = stats.entry(key.clone()).or_insert_with(|| {
+            order.push(key.clone());
+            (
+                SongStats {
+                    song_title: row.song_title.clone(),
+                    artist: row.artist.clone(),
+                    count: 0,
+                    first_heard_date: row.date.clone(),
+                    first_heard_schedule_id: row.schedule_id as i32,
+                    last_heard_date: row.date.clone(),
+                    last_heard_schedule_id: row.schedule_id as i32,
+                },
+                std::collections::HashSet::new(),
+            )
+        });
+        if schedules.insert(row.schedule_id) {
+            entry.count += 1;
+        }
+        entry.last_heard_date = row.date;
+        entry.last_heard_schedule_id = row.schedule_id as i32;
+    }
+
+    let mut result: Vec<SongStats> = order
+        .into_iter()
+        .filter_map(|key| stats.remove(&key).map(|(entry, _)| entry))
+        .collect();
+    // 聴いた回数の多い順（同数の場合は最後に聴いた日が新しい順）
+    result.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| b.last_heard_date.cmp(&a.last_heard_date))
+    });
+
+    result
+}
+
+// 指定した曲を聴いた公演の一覧（rowsの並び順のまま）
+fn build_song_history(rows: Vec<HeardSongRow>, title: &str, artist: Option<&str>) -> Vec<SongHistoryEntry> {
+    let (title_key, artist_key) = song_key(title, artist);
+    rows
+        .into_iter()
+        .filter(|row| {
+            let key = song_key(&row.song_title, row.artist.as_deref());
+            key.0 == title_key && (artist_key.is_empty() || key.1 == artist_key)
+        })
+        .map(|row| SongHistoryEntry {
+            schedule_id: row.schedule_id as i32,
+            title: row.title,
+            date: row.date,
+            venue: row.venue,
+            position: row.position as i32,
+            is_encore: row.is_encore != 0,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod song_stats_tests {
+    use super::{build_song_history, build_song_stats, HeardSongRow};
+
+    fn heard(schedule_id: i64, date: &str, position: i64, song_title: &str, artist: Option<&str>) -> HeardSongRow {
+        HeardSongRow {
+            schedule_id,
+            title: format!("ライブ{}", schedule_id),
+            date: Some(date.to_string()),
+            venue: "会場".to_string(),
+            position,
+            song_title: song_title.to_string(),
+            artist: artist.map(str::to_string),
+            is_encore: 0,
+        }
+    }
+
+    #[test]
+    fn repeat_plays_in_one_show_count_once() {
+        let rows = vec![
+            heard(1, "2025-01-10", 1, "Song A", Some("Band")),
+            heard(1, "2025-01-10", 12, "Song A", Some("Band")),
+            heard(2, "2025-02-10", 3, "Song A", Some("Band")),
+        ];
+        let stats = build_song_stats(rows, None, None);
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].count, 2);
+    }
+
+    #[test]
+    fn folds_case_and_surrounding_whitespace() {
+        let rows = vec![
+            heard(1, "2025-01-10", 1, "Song A", Some("Band")),
+            heard(2, "2025-02-10", 1, "  song a ", Some("BAND ")),
+            heard(3, "2025-03-10", 1, "Song A", Some("Other Band")),
+        ];
+        let stats = build_song_stats(rows, Some(" band"), None);
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].count, 2);
+        // 表記は最初に聴いたときのもの
+        assert_eq!(stats[0].song_title, "Song A");
+
+        let rows = vec![
+            heard(1, "2025-01-10", 1, "Song A", Some("Band")),
+            heard(2, "2025-02-10", 1, "  song a ", Some("BAND ")),
+        ];
+        let history = build_song_history(rows, "SONG A", Some("band"));
+        assert_eq!(history.iter().map(|h| h.schedule_id).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn first_and_last_follow_date_order_and_sort_by_count() {
+        let rows = vec![
+            heard(1, "2025-01-10", 1, "Song A", None),
+            heard(1, "2025-01-10", 2, "Song B", None),
+            heard(2, "2025-02-10", 1, "Song C", None),
+            heard(3, "2025-03-10", 1, "Song A", None),
+        ];
+        let stats = build_song_stats(rows, None, None);
+        let titles: Vec<&str> = stats.iter().map(|s| s.song_title.as_str()).collect();
+        // 回数の多い順、同数なら最後に聴いた日が新しい順
+        assert_eq!(titles, vec!["Song A", "Song C", "Song B"]);
+        assert_eq!(stats[0].first_heard_schedule_id, 1);
+        assert_eq!(stats[0].first_heard_date.as_deref(), Some("2025-01-10"));
+        assert_eq!(stats[0].last_heard_schedule_id, 3);
+        assert_eq!(stats[0].last_heard_date.as_deref(), Some("2025-03-10"));
+    }
+}
+
+// ====== ツアー管理 ======
+// 複数のスケジュールを1つのツアー（シリーズ）にまとめる。schedules.groupの自由記述とは別に、
+// ツアー単位で公演一覧・参加数・費用合計を確認するために使う
+
+#[derive(Serialize, Clone)]
+struct Tour {
+    id: i32,
+    name: String,
+    artist: Option<String>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    official_url: Option<String>,
+    show_count: i32,     // キャンセルを除いた公演数
+    attended_count: i32, // 参加済み（Done）の公演数
+    canceled_count: i32,
+    total_cost: i32, // キャンセルを除いた公演のtotal_costの合計
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct TourRow {
+    id: i64,
+    name: String,
+    artist: Option<String>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    official_url: Option<String>,
+    show_count: i64,
+    attended_count: i64,
+    canceled_count: i64,
+    total_cost: i64,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TourDetail {
+    tour: Tour,
+    schedules: Vec<Schedule>,
+}
+
+// POST /tours, PUT /tours/:id 用リクエストボディ
+#[derive(Deserialize)]
+struct NewTour {
+    name: String,
+    artist: Option<String>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    official_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TourScheduleRequest {
+    schedule_id: i32,
+}
+
+// 既存のgroupからツアーを作成する候補
+#[derive(Serialize, Debug, PartialEq)]
+struct TourGroupCandidate {
+    group: String,
+    artist: Option<String>, // 最も多いお目当て（target）
+    start_date: Option<String>,
+    end_date: Option<String>,
+    schedule_count: i32,
+    existing_tour_id: Option<i32>, // 同名のツアーが既にある場合はそのツアーに追加される
+}
+
+#[derive(Deserialize)]
+struct ImportTourGroupsRequest {
+    groups: Option<Vec<String>>, // 省略時は全候補を取り込む
+}
+
+#[derive(sqlx::FromRow)]
+struct GroupedScheduleRow {
+    group_name: String,
+    target: Option<String>,
+    date: Option<String>,
+}
+
+fn row_to_tour(row: TourRow) -> Tour {
+    Tour {
+        id: row.id as i32,
+        name: row.name,
+        artist: row.artist,
+        start_date: row.start_date,
+        end_date: row.end_date,
+        official_url: row.official_url,
+        show_count: row.show_count as i32,
+        attended_count: row.attended_count as i32,
+        canceled_count: row.canceled_count as i32,
+        total_cost: row.total_cost as i32,
+        created_at: row.created_at,
+        updated_at: row.updated_at,
+    }
+}
+
+// ツアー未所属のスケジュールをgroupごとにまとめ、ツアー作成の候補にする（groupは前後の空白を除いて比較）
+fn build_tour_group_candidates(rows: Vec<GroupedScheduleRow>) -> Vec<TourGroupCandidate> {
+    let mut groups: std::collections::BTreeMap<String, Vec<GroupedScheduleRow>> = std::collections::BTreeMap::new();
+    for row in rows {
+        let name = row.group_name.trim().to_string();
+        if name.is_empty() {
+            continue;
+        }
+        groups.entry(name).or_default().push(row);
+    }
+
+    groups
+        .into_iter()
+        .map(|(group, rows)| {
+            let mut target_counts: std::collections::BTreeMap<&str, usize> = std::collections::BTreeMap::new();
+            for target in rows.iter().filter_map(|r| r.target.as_deref()).map(str::trim).filter(|t| !t.is_empty()) {
+                *target_counts.entry(target).or_default() += 1;
+            }
+            // 同数の場合は名前順で先のものを採用する（結果を安定させるため）
+            let artist = target_counts
+                .iter()
+                .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+                .map(|(target, _)| target.to_string());
+            let dates: Vec<&str> = rows.iter().filter_map(|r| r.date.as_deref()).collect();
+            TourGroupCandidate {
+                group,
+                artist,
+                start_date: dates.iter().min().map(|d| d.to_string()),
+                end_date: dates.iter().max().map(|d| d.to_string()),
+                schedule_count: rows.len() as i32,
+                existing_tour_id: None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tour_group_tests {
+    use super::{build_tour_group_candidates, GroupedScheduleRow};
+
+    fn row(group: &str, target: Option<&str>, date: &str) -> GroupedScheduleRow {
+        GroupedScheduleRow {
+            group_name: group.to_string(),
+            target: target.map(str::to_string),
+            date: Some(date.to_string()),
+        }
+    }
+
+    #[test]
+    fn groups_by_trimmed_name_with_date_range() {
+        let candidates = build_tour_group_candidates(vec![
+            row("Tour 2025", Some("A"), "2025-06-01"),
+            row(" Tour 2025 ", Some("B"), "2025-05-01"),
+            row("Tour 2025", Some("A"), "2025-07-01"),
+            row("  ", Some("A"), "2025-07-01"),
+        ]);
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].group, "Tour 2025");
+        assert_eq!(candidates[0].artist.as_deref(), Some("A"));
+        assert_eq!(candidates[0].start_date.as_deref(), Some("2025-05-01"));
+        assert_eq!(candidates[0].end_date.as_deref(), Some("2025-07-01"));
+        assert_eq!(candidates[0].schedule_count, 3);
+    }
+
+    #[test]
+    fn artist_tie_breaks_by_name() {
+        let candidates = build_tour_group_candidates(vec![
+            row("Fes", Some("B"), "2025-08-01"),
+            row("Fes", Some("A"), "2025-08-02"),
+            row("Fes", None, "2025-08-03"),
+        ]);
+        assert_eq!(candidates[0].artist.as_deref(), Some("A"));
+    }
+}
+
+fn validate_tour(payload: &NewTour) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let bad_request = |message: &str| {
+        Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: message.to_string(),
+            }),
+        ))
+    };
+    if payload.name.trim().is_empty() {
+        return bad_request("ツアー名を入力してください");
+    }
+    let parse_date = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(|d| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d"))
+            .transpose()
+    };
+    let (Ok(start_date), Ok(end_date)) = (parse_date(&payload.start_date), parse_date(&payload.end_date)) else {
+        return bad_request("日付はYYYY-MM-DD形式で入力してください");
+    };
+    if let (Some(start), Some(end)) = (start_date, end_date) {
+        if end < start {
+            return bad_request("終了日は開始日以降の日付を指定してください");
+        }
+    }
+    let official_url = payload.official_url.as_deref().map(str::trim).filter(|u| !u.is_empty());
+    if official_url.is_some_and(|url| !url.starts_with("http://") && !url.starts_with("https://")) {
+        return bad_request("公式サイトのURLはhttp://またはhttps://で始まる形式で入力してください");
+    }
+    Ok(())
+}
+
+// 空文字をNULLとして保存するための正規化
+fn trimmed_or_none(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+async fn ensure_tour_owner(
+    pool: &Pool<Sqlite>,
+    tour_id: i64,
+    user_id: i32,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let tour_user_id: Option<i64> = sqlx::query_scalar("SELECT user_id FROM tours WHERE id = ?")
+        .bind(tour_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[EnsureTourOwner] Database error: {}", e);
+            database_error()
+        })?;
+
+    match tour_user_id {
+        None => Err(tour_not_found()),
+        Some(owner_id) if owner_id == user_id as i64 => Ok(()),
+        Some(_) => Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "このツアーを編集する権限がありません".to_string(),
+            }),
+        )),
+    }
+}
+
+fn tour_not_found() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "ツアーが見つかりませんでした".to_string(),
+        }),
+    )
+}
+
+// ツアーと所属公演の集計を取得する（tour_idを省略した場合はユーザーの全ツアー）
+async fn fetch_tour_rows(
+    pool: &Pool<Sqlite>,
+    user_id: i32,
+    tour_id: Option<i64>,
+) -> Result<Vec<TourRow>, sqlx::Error> {
+    sqlx::query_as::<_, TourRow>(
+        r#"
+        SELECT
+          t.id,
+          t.name,
+          t.artist,
+          t.start_date,
+          t.end_date,
+          t.official_url,
+          COALESCE(SUM(CASE WHEN s.status != 'Canceled' THEN 1 ELSE 0 END), 0) AS show_count,
+          COALESCE(SUM(CASE WHEN s.status = 'Done' THEN 1 ELSE 0 END), 0) AS attended_count,
+          COALESCE(SUM(CASE WHEN s.status = 'Canceled' THEN 1 ELSE 0 END), 0) AS canceled_count,
+          COALESCE(SUM(CASE WHEN s.status != 'Canceled' THEN COALESCE(s.total_cost, 0) ELSE 0 END), 0) AS total_cost,
+          t.created_at,
+          t.updated_at
+        FROM tours t
+        LEFT JOIN schedules s ON s.tour_id = t.id AND s.deleted_at IS NULL
+        WHERE t.user_id = ? AND (? IS NULL OR t.id = ?)
+        GROUP BY t.id
+        ORDER BY COALESCE(t.start_date, MIN(s.date)) DESC, t.id DESC
+        "#,
+    )
+    .bind(user_id)
+    .bind(tour_id)
+    .bind(tour_id)
+    .fetch_all(pool)
+    .await
+}
+
+async fn fetch_tour(pool: &Pool<Sqlite>, user_id: i32, tour_id: i64) -> Result<Tour, (StatusCode, Json<ErrorResponse>)> {
+    fetch_tour_rows(pool, user_id, Some(tour_id))
+        .await
+        .map_err(|e| {
+            eprintln!("[FetchTour] Database error: {}", e);
+            database_error()
+        })?
+        .into_iter()
+        .next()
+        .map(row_to_tour)
+        .ok_or_else(tour_not_found)
+}
+
+// GET /tours
+async fn list_tours(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<Tour>>, (StatusCode, Json<ErrorResponse>)> {
+    // 費用の合計が最新のロールアップ結果になるよう、先にツアー所属公演のロールアップを再計算する
+    let schedule_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM schedules WHERE user_id = ? AND tour_id IS NOT NULL AND deleted_at IS NULL")
+        .bind(user.user_id)
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[ListTours] Database error: {}", e);
+            database_error()
+        })?;
+    for schedule_id in &schedule_ids {
+        calculate_rollup(&pool, *schedule_id).await.ok();
+    }
+
+    let rows = fetch_tour_rows(&pool, user.user_id, None).await.map_err(|e| {
+        eprintln!("[ListTours] Database error: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(rows.into_iter().map(row_to_tour).collect()))
+}
+
+// GET /tours/:id - ツアーの詳細と所属公演の一覧（日付順）
+async fn get_tour(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<TourDetail>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_tour_owner(&pool, id as i64, user.user_id).await?;
+
+    // 費用の合計が最新のロールアップ結果になるよう、先に所属公演のロールアップを再計算する
+    let schedule_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM schedules WHERE tour_id = ? AND user_id = ? AND deleted_at IS NULL")
+        .bind(id)
+        .bind(user.user_id)
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[GetTour] Database error: {}", e);
+            database_error()
+        })?;
+    for schedule_id in &schedule_ids {
+        calculate_rollup(&pool, *schedule_id).await.ok();
+    }
+
+    let rows: Vec<ScheduleRow> = sqlx::query_as::<_, ScheduleRow>(
+        r#"
+        SELECT
+          id,
+          title,
+          "group",
+          date,
+          open,
+          start,
+          "end",
+          end_date,
+          timezone,
+          tour_id,
+          (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+          (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
+          notes,
+          category,
+          area,
+          venue,
+          target,
+          lineup,
+          seller,
+          ticket_fee,
+          drink_fee,
+          ticket_fee_currency,
+          drink_fee_currency,
+          ticket_fee_effective,
+          drink_fee_effective,
+          total_fare,
+          stay_fee,
+          travel_cost,
+          other_cost,
+          total_cost,
+          my_share,
+          net_cost,
+          goods_cost,
+          status,
+          related_schedule_ids,
+          user_id,
+          CAST(is_public AS INTEGER) as is_public,
+          created_at,
+          updated_at
+        FROM schedules
+        WHERE tour_id = ? AND user_id = ? AND deleted_at IS NULL
+        ORDER BY date ASC, start ASC, id ASC
+        "#,
+    )
+    .bind(id)
+    .bind(user.user_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[GetTour] Database error: {}", e);
+        database_error()
+    })?;
+
+    let mut schedules: Vec<Schedule> = rows.into_iter().map(row_to_schedule).collect();
+    attach_schedule_days(&pool, &mut schedules).await.map_err(|e| {
+        eprintln!("[GetTour] Failed to fetch schedule days: {}", e);
+        database_error()
+    })?;
+    attach_schedule_tickets(&pool, &mut schedules).await.map_err(|e| {
+        eprintln!("[GetTour] Failed to fetch schedule tickets: {}", e);
+        database_error()
+    })?;
+
+    let tour = fetch_tour(&pool, user.user_id, id as i64).await?;
+    Ok(Json(TourDetail { tour, schedules }))
+}
+
+// POST /tours
+async fn create_tour(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewTour>,
+) -> Result<(StatusCode, Json<Tour>), (StatusCode, Json<ErrorResponse>)> {
+    validate_tour(&payload)?;
+
+    let now = Utc::now().to_rfc3339();
+    let inserted = sqlx::query(
+        r#"
+        INSERT INTO tours (user_id, name, artist, start_date, end_date, official_url, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(user.user_id)
+    .bind(payload.name.trim())
+    .bind(trimmed_or_none(&payload.artist))
+    .bind(trimmed_or_none(&payload.start_date))
+    .bind(trimmed_or_none(&payload.end_date))
+    .bind(trimmed_or_none(&payload.official_url))
+    .bind(&now)
+    .bind(&now)
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[CreateTour] Database error: {}", e);
+        if e.to_string().contains("UNIQUE constraint failed") {
+            duplicate_tour_name()
+        } else {
+            database_error()
+        }
+    })?;
+
+    let tour = fetch_tour(&pool, user.user_id, inserted.last_insert_rowid()).await?;
+    Ok((StatusCode::CREATED, Json(tour)))
+}
+
+fn duplicate_tour_name() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::CONFLICT,
+        Json(ErrorResponse {
+            error: "同じ名前のツアーが既に存在します".to_string(),
+        }),
+    )
+}
+
+// PUT /tours/:id
+async fn update_tour(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewTour>,
+) -> Result<Json<Tour>, (StatusCode, Json<ErrorResponse>)> {
+    validate_tour(&payload)?;
+    ensure_tour_owner(&pool, id as i64, user.user_id).await?;
+
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        r#"
+        UPDATE tours SET
+          name = ?,
+          artist = ?,
+          start_date = ?,
+          end_date = ?,
+          official_url = ?,
+          updated_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(payload.name.trim())
+    .bind(trimmed_or_none(&payload.artist))
+    .bind(trimmed_or_none(&payload.start_date))
+    .bind(trimmed_or_none(&payload.end_date))
+    .bind(trimmed_or_none(&payload.official_url))
+    .bind(&now)
+    .bind(id)
+    .execute(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[UpdateTour] Database error: {}", e);
+        if e.to_string().contains("UNIQUE constraint failed") {
+            duplicate_tour_name()
+        } else {
+            database_error()
+        }
+    })?;
+
+    Ok(Json(fetch_tour(&pool, user.user_id, id as i64).await?))
+}
+
+// DELETE /tours/:id - ツアーのみ削除し、所属していた公演はツアー未所属に戻す
+async fn delete_tour(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_tour_owner(&pool, id as i64, user.user_id).await?;
+
+    let mut tx = pool.begin().await.map_err(|e| {
+        eprintln!("[DeleteTour] Database error: {}", e);
+        database_error()
+    })?;
+    sqlx::query("UPDATE schedules SET tour_id = NULL WHERE tour_id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("[DeleteTour] Database error: {}", e);
+            database_error()
+        })?;
+    sqlx::query("DELETE FROM tours WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("[DeleteTour] Database error: {}", e);
+            database_error()
+        })?;
+    tx.commit().await.map_err(|e| {
+        eprintln!("[DeleteTour] Database error: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "ツアーを削除しました"
+    })))
+}
+
+// POST /tours/:id/schedules - 公演をツアーに追加する（別のツアーに所属していた場合は付け替える）
+async fn add_tour_schedule(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<TourScheduleRequest>,
+) -> Result<Json<Tour>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_tour_owner(&pool, id as i64, user.user_id).await?;
+    ensure_schedule_owner(&pool, payload.schedule_id as i64, user.user_id).await?;
+
+    sqlx::query("UPDATE schedules SET tour_id = ? WHERE id = ?")
+        .bind(id)
+        .bind(payload.schedule_id)
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[AddTourSchedule] Database error: {}", e);
+            database_error()
+        })?;
+
+    Ok(Json(fetch_tour(&pool, user.user_id, id as i64).await?))
+}
+
+// DELETE /tours/:id/schedules/:schedule_id - 公演をツアーから外す
+async fn remove_tour_schedule(
+    Path((id, schedule_id)): Path<(i32, i32)>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Tour>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_tour_owner(&pool, id as i64, user.user_id).await?;
+    ensure_schedule_owner(&pool, schedule_id as i64, user.user_id).await?;
+
+    sqlx::query("UPDATE schedules SET tour_id = NULL WHERE id = ? AND tour_id = ?")
+        .bind(schedule_id)
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[RemoveTourSchedule] Database error: {}", e);
+            database_error()
+        })?;
+
+    Ok(Json(fetch_tour(&pool, user.user_id, id as i64).await?))
+}
+
+// ツアー未所属のスケジュールからgroupごとの候補を作り、同名の既存ツアーがあれば紐付ける
+async fn fetch_tour_group_candidates(
+    pool: &Pool<Sqlite>,
+    user_id: i32,
+) -> Result<Vec<TourGroupCandidate>, sqlx::Error> {
+    let rows: Vec<GroupedScheduleRow> = sqlx::query_as::<_, GroupedScheduleRow>(
+        r#"
+        SELECT "group" AS group_name, target, date
+        FROM schedules
+        WHERE user_id = ? AND tour_id IS NULL AND deleted_at IS NULL AND TRIM(COALESCE("group", '')) != ''
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let existing: Vec<(i64, String)> = sqlx::query_as("SELECT id, name FROM tours WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+    let mut candidates = build_tour_group_candidates(rows);
+    for candidate in &mut candidates {
+        candidate.existing_tour_id = existing
+            .iter()
+            .find(|(_, name)| *name == candidate.group)
+            .map(|(id, _)| *id as i32);
+    }
+    Ok(candidates)
+}
+
+// GET /tours/group-candidates - 既存のgroupから作成できるツアーの候補（取り込み前の確認用）
+async fn list_tour_group_candidates(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<TourGroupCandidate>>, (StatusCode, Json<ErrorResponse>)> {
+    let candidates = fetch_tour_group_candidates(&pool, user.user_id).await.map_err(|e| {
+        eprintln!("[ListTourGroupCandidates] Database error: {}", e);
+        database_error()
+    })?;
+    Ok(Json(candidates))
+}
+
+// POST /tours/import-groups - groupごとにツアーを作成（同名のツアーがあればそれを使う）し、
+// ツアー未所属のスケジュールを所属させる。groupsを指定した場合はそのgroupのみ取り込む
+async fn import_tour_groups(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<ImportTourGroupsRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let candidates = fetch_tour_group_candidates(&pool, user.user_id).await.map_err(|e| {
+        eprintln!("[ImportTourGroups] Database error: {}", e);
+        database_error()
+    })?;
+    let selected: Option<Vec<String>> = payload
+        .groups
+        .map(|groups| groups.iter().map(|g| g.trim().to_string()).collect());
+
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await.map_err(|e| {
+        eprintln!("[ImportTourGroups] Database error: {}", e);
+        database_error()
+    })?;
+
+    let mut created_tours = 0;
+    let mut assigned_schedules = 0;
+    for candidate in candidates
+        .iter()
+        .filter(|c| selected.as_ref().is_none_or(|groups| groups.contains(&c.group)))
+    {
+        let tour_id = match candidate.existing_tour_id {
+            Some(tour_id) => tour_id as i64,
+            None => {
+                created_tours += 1;
+                sqlx::query(
+                    r#"
+                    INSERT INTO tours (user_id, name, artist, start_date, end_date, official_url, created_at, updated_at)
+                    VALUES (?, ?, ?, ?, ?, NULL, ?, ?)
+                    "#,
+                )
+                .bind(user.user_id)
+                .bind(&candidate.group)
+                .bind(&candidate.artist)
+                .bind(&candidate.start_date)
+                .bind(&candidate.end_date)
+                .bind(&now)
+                .bind(&now)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| {
+                    eprintln!("[ImportTourGroups] Database error: {}", e);
+                    database_error()
+                })?
+                .last_insert_rowid()
+            }
+        };
+
+        let result = sqlx::query(
+            r#"UPDATE schedules SET tour_id = ? WHERE user_id = ? AND tour_id IS NULL AND TRIM("group") = ?"#,
+        )
+        .bind(tour_id)
+        .bind(user.user_id)
+        .bind(&candidate.group)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| {
+            eprintln!("[ImportTourGroups] Database error: {}", e);
+            database_error()
+        })?;
+        assigned_schedules += result.rows_affected();
+    }
+
+    tx.commit().await.map_err(|e| {
+        eprintln!("[ImportTourGroups] Database error: {}", e);
+        database_error()
+    })?;
+
+    let tours = fetch_tour_rows(&pool, user.user_id, None).await.map_err(|e| {
+        eprintln!("[ImportTourGroups] Database error: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "created_tours": created_tours,
+        "assigned_schedules": assigned_schedules,
+        "tours": tours.into_iter().map(row_to_tour).collect::<Vec<_>>(),
+    })))
+}
+
+// ====== 楽観的排他制御（ETag / If-Match） ======
+// スケジュール・交通・宿泊・選択肢のETagは行のupdated_at（更新トリガー等で書き込みごとに変わる）から作る。
+// 更新時にIf-Matchが指定されていれば、取得時点から変更されていない場合だけ書き込み（UPDATEの条件にも含める）、
+// 変更されていた場合は412と現在の内容（ETagヘッダー付き）を返してクライアント側でマージできるようにする
+
+// updated_atからETagを作る（値をそのまま見せないようhexにする。未保存の場合は空のETag）
+fn etag_for(updated_at: Option<&str>) -> String {
+    format!("\"{}\"", hex::encode(updated_at.unwrap_or_default()))
+}
+
+fn etag_header(etag: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        headers.insert(axum::http::header::ETAG, value);
+    }
+    headers
+}
+
+// If-Matchが現在のETagと一致するか。ヘッダーがない場合と "*" は一致として扱う
+// カンマ区切りの複数指定と、弱いETag（W/"..."）の指定にも対応する
+fn if_match_satisfied(headers: &HeaderMap, current_etag: &str) -> bool {
+    if !headers.contains_key(axum::http::header::IF_MATCH) {
+        return true;
+    }
+    headers
+        .get_all(axum::http::header::IF_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == current_etag)
+}
+
+// 条件付きUPDATEに使うupdated_at（`updated_at IS COALESCE(?, updated_at)`にバインドする）
+// If-Matchが指定されていない場合はNoneで、条件なしの更新になる
+fn if_match_guard(headers: &HeaderMap, current_updated_at: Option<&str>) -> Option<String> {
+    if headers.contains_key(axum::http::header::IF_MATCH) {
+        current_updated_at.map(str::to_string)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod etag_tests {
+    use super::*;
+
+    fn if_match(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::IF_MATCH, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn etag_changes_with_updated_at() {
+        let etag = etag_for(Some("2026-10-17T05:03:03.102Z"));
+        assert!(etag.starts_with('"') && etag.ends_with('"'));
+        assert_ne!(etag, etag_for(Some("2026-10-17T05:03:03.103Z")));
+        assert_eq!(etag_for(None), "\"\"");
+    }
+
+    #[test]
+    fn if_match_compares_with_current_etag() {
+        let current = etag_for(Some("2026-10-17T05:03:03.102Z"));
+        let stale = etag_for(Some("2026-10-16T00:00:00.000Z"));
+        assert!(if_match_satisfied(&HeaderMap::new(), &current));
+        assert!(if_match_satisfied(&if_match("*"), &current));
+        assert!(if_match_satisfied(&if_match(&current), &current));
+        assert!(if_match_satisfied(&if_match(&format!("W/{}", current)), &current));
+        assert!(if_match_satisfied(&if_match(&format!("{}, {}", stale, current)), &current));
+        assert!(!if_match_satisfied(&if_match(&stale), &current));
+    }
+
+    #[test]
+    fn guard_is_only_set_with_if_match() {
+        assert_eq!(if_match_guard(&HeaderMap::new(), Some("t")), None);
+        assert_eq!(if_match_guard(&if_match("*"), Some("t")).as_deref(), Some("t"));
+    }
+}
+
+// 更新系ハンドラのエラー。If-Matchが一致しない場合は412と現在の内容・ETagを返す
+enum WriteError<E> {
+    Failed(E),
+    PreconditionFailed { etag: String, current: serde_json::Value },
+}
+
+impl<E> From<E> for WriteError<E> {
+    fn from(error: E) -> Self {
+        WriteError::Failed(error)
+    }
+}
+
+impl<E: axum::response::IntoResponse> axum::response::IntoResponse for WriteError<E> {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            WriteError::Failed(error) => error.into_response(),
+            WriteError::PreconditionFailed { etag, current } => {
+                (StatusCode::PRECONDITION_FAILED, etag_header(&etag), Json(current)).into_response()
+            }
+        }
+    }
+}
+
+// 現在の内容を取得して412を組み立てる。既に存在しない場合・取得に失敗した場合はそれぞれのエラーを返す
+fn precondition_failed<T: Serialize, E>(
+    current: Result<Option<(T, String)>, sqlx::Error>,
+    not_found: E,
+    failed: E,
+) -> WriteError<E> {
+    match current {
+        Ok(Some((current, etag))) => WriteError::PreconditionFailed {
+            etag,
+            current: serde_json::to_value(current).unwrap_or_default(),
+        },
+        Ok(None) => WriteError::Failed(not_found),
+        Err(e) => {
+            eprintln!("[PreconditionFailed] Database error: {}", e);
+            WriteError::Failed(failed)
+        }
+    }
+}
+
+async fn row_updated_at(pool: &Pool<Sqlite>, table: &str, id: i64) -> Result<Option<String>, sqlx::Error> {
+    let updated_at: Option<Option<String>> =
+        sqlx::query_scalar(&format!("SELECT updated_at FROM {} WHERE id = ?", table))
+            .bind(id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(updated_at.flatten())
+}
+
+// スケジュール1件とETagを取得する（ゴミ箱にあるものは除く。所有者の確認は呼び出し側で行う）
+async fn fetch_schedule_with_etag(pool: &Pool<Sqlite>, id: i64) -> Result<Option<(Schedule, String)>, sqlx::Error> {
+    let row: Option<ScheduleRow> = sqlx::query_as::<_, ScheduleRow>(
+        r#"
+        SELECT
+          id,
+          title,
+          "group",
+          date,
+          open,
+          start,
+          "end",
+          end_date,
+          timezone,
+          tour_id,
+          (SELECT users.timezone FROM users WHERE users.id = schedules.user_id) AS user_timezone,
+          (SELECT users.home_currency FROM users WHERE users.id = schedules.user_id) AS cost_currency,
+          notes,
+          category,
+          area,
+          venue,
+          target,
+          lineup,
+          seller,
+          ticket_fee,
+          drink_fee,
+          ticket_fee_currency,
+          drink_fee_currency,
+          ticket_fee_effective,
+          drink_fee_effective,
+          total_fare,
+          stay_fee,
+          travel_cost,
+          other_cost,
+          total_cost,
+          my_share,
+          net_cost,
+          goods_cost,
+          status,
+          related_schedule_ids,
+          user_id,
+          CAST(is_public AS INTEGER) as is_public,
+          created_at,
+          updated_at
+        FROM schedules
+        WHERE id = ? AND deleted_at IS NULL
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(row) = row else { return Ok(None) };
+
+    let etag = etag_for(row.updated_at.as_deref());
+    let mut schedule = row_to_schedule(row);
+    attach_schedule_days(pool, std::slice::from_mut(&mut schedule)).await?;
+    attach_schedule_tickets(pool, std::slice::from_mut(&mut schedule)).await?;
+    Ok(Some((schedule, etag)))
+}
+
+async fn schedule_precondition_failed(
+    pool: &Pool<Sqlite>,
+    id: i64,
+) -> WriteError<(StatusCode, Json<ErrorResponse>)> {
+    let not_found = (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "スケジュールが見つかりませんでした".to_string(),
+        }),
+    );
+    precondition_failed(fetch_schedule_with_etag(pool, id).await, not_found, database_error())
+}
+
+// 交通情報1件とETagを取得する（ゴミ箱にあるものは除く。所有者の確認は呼び出し側で行う）
+async fn fetch_traffic_with_etag(pool: &Pool<Sqlite>, id: i64) -> Result<Option<(Traffic, String)>, sqlx::Error> {
+    let row: Option<TrafficRow> = sqlx::query_as::<_, TrafficRow>(
+        r#"
+        SELECT
+          id,
+          schedule_id,
+          date,
+          "order",
+          transportation,
+          from_place,
+          to_place,
+          notes,
+          fare,
+          fare_currency,
+          miles,
+          return_flag,
+          total_fare,
+          total_miles
+        FROM traffics
+        WHERE id = ? AND deleted_at IS NULL
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(row) = row else { return Ok(None) };
+
+    let etag = etag_for(row_updated_at(pool, "traffics", id).await?.as_deref());
+    Ok(Some((row_to_traffic(row), etag)))
+}
+
+// 宿泊情報1件とETagを取得する（ゴミ箱にあるものは除く。所有者の確認は呼び出し側で行う）
+async fn fetch_stay_with_etag(pool: &Pool<Sqlite>, id: i64) -> Result<Option<(Stay, String)>, sqlx::Error> {
+    let row: Option<StayRow> = sqlx::query_as::<_, StayRow>(
+        r#"
+        SELECT
+          id,
+          schedule_id,
+          check_in,
+          check_out,
+          hotel_name,
+          website,
+          fee,
+          fee_currency,
+          breakfast_flag,
+          deadline,
+          penalty,
+          status
+        FROM stays
+        WHERE id = ? AND deleted_at IS NULL
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(row) = row else { return Ok(None) };
+
+    let etag = etag_for(row_updated_at(pool, "stays", id).await?.as_deref());
+    Ok(Some((row_to_stay(row), etag)))
+}
+
+// GET /schedules/:id - スケジュールを1件取得する（ETag付き）
+async fn get_schedule(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<(HeaderMap, Json<Schedule>), (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, id as i64, user.user_id).await?;
+    let (schedule, etag) = fetch_schedule_with_etag(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[GetSchedule] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "スケジュールが見つかりませんでした".to_string(),
+            }),
+        ))?;
+    Ok((etag_header(&etag), Json(schedule)))
+}
+
+// ====== 部分更新（JSON Merge Patch） ======
+// PATCH /schedules/:id・/traffic/:id・/stay/:id は RFC 7396 のJSON Merge Patchを受け付ける。
+// 現在の内容に差分を当ててPUTと同じリクエストボディを組み立て、PUTの更新処理（関連スケジュールの双方向リンク・
+// ロールアップ・変更履歴・If-Match）にそのまま渡すため、指定しなかった項目は現在の値のまま残る
+
+// JSON Merge Patchを適用する（nullは項目の削除、オブジェクトは再帰的にマージ、配列やその他の値は置き換え）
+fn apply_merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+    if let serde_json::Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                apply_merge_patch(target.entry(key.clone()).or_insert(serde_json::Value::Null), value);
+            }
+        }
+    }
+}
+
+// 現在の内容（レスポンスと同じ形）に差分を当て、PUT用のリクエストボディに変換する
+// パッチがオブジェクトでない場合や、必須項目を削除した場合はエラー文言を返す
+fn merge_patch_payload<T: serde::de::DeserializeOwned>(
+    mut current: serde_json::Value,
+    patch: &serde_json::Value,
+) -> Result<T, String> {
+    if !patch.is_object() {
+        return Err("パッチはJSONオブジェクトで指定してください".to_string());
+    }
+    apply_merge_patch(&mut current, patch);
+    serde_json::from_value(current).map_err(|e| format!("パッチを適用した内容が不正です: {}", e))
+}
+
+#[cfg(test)]
+mod merge_patch_tests {
+    use super::*;
+
+    #[test]
+    fn merge_patch_follows_rfc7396() {
+        let mut target = serde_json::json!({ "a": "b", "c": { "d": "e", "f": "g" }, "tags": [1, 2] });
+        apply_merge_patch(
+            &mut target,
+            &serde_json::json!({ "a": "z", "c": { "f": null }, "tags": [3], "new": true }),
+        );
+        assert_eq!(target, serde_json::json!({ "a": "z", "c": { "d": "e" }, "tags": [3], "new": true }));
+    }
+
+    #[test]
+    fn payload_requires_object_patch_and_required_fields() {
+        #[derive(Deserialize, Debug)]
+        struct Payload {
+            title: String,
+            notes: Option<String>,
+        }
+        let current = serde_json::json!({ "id": 1, "title": "Live", "notes": "memo" });
+        let payload: Payload = merge_patch_payload(current.clone(), &serde_json::json!({ "notes": null })).unwrap();
+        assert_eq!((payload.title.as_str(), payload.notes), ("Live", None));
+        assert!(merge_patch_payload::<Payload>(current.clone(), &serde_json::json!(["x"])).is_err());
+        assert!(merge_patch_payload::<Payload>(current, &serde_json::json!({ "title": null })).is_err());
+    }
+}
+
+// PATCH /schedules/:id
+async fn patch_schedule(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    headers: HeaderMap,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(patch): Json<serde_json::Value>,
+) -> Result<(HeaderMap, Json<Schedule>), WriteError<(StatusCode, Json<ErrorResponse>)>> {
+    ensure_schedule_owner(&pool, id as i64, user.user_id).await?;
+    let invalid_patch = |message: String| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ErrorResponse { error: message }),
+        )
+    };
+    // ツアーからの除外はPUTと同様に専用のエンドポイントで行う
+    if patch.get("tour_id").is_some_and(|v| v.is_null()) {
+        return Err(invalid_patch(
+            "ツアーから外す場合は DELETE /tours/:id/schedules/:schedule_id を使用してください".to_string(),
+        )
+        .into());
+    }
+
+    let (current, _) = fetch_schedule_with_etag(&pool, id as i64)
+        .await
+        .map_err(|e| {
+            eprintln!("[PatchSchedule] Database error: {}", e);
+            database_error()
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "スケジュールが見つかりませんでした".to_string(),
+            }),
+        ))?;
+    let mut current = serde_json::to_value(current).unwrap_or_default();
+    if let Some(current) = current.as_object_mut() {
+        // 日程はパッチで指定した場合だけ置き換える（省略時はPUTと同様に開催期間から自動生成・維持）
+        if patch.get("days").is_none() {
+            current.remove("days");
+        }
+        // チケットも同様に、パッチで指定した場合だけ置き換える
+        if patch.get("tickets").is_none() {
+            current.remove("tickets");
+        }
+    }
+    let mut payload: NewSchedule = merge_patch_payload(current, &patch).map_err(invalid_patch)?;
+    // timezoneの削除はユーザー設定に戻す（PUTでは空文字で指定する）
+    if patch.get("timezone").is_some_and(|v| v.is_null()) {
+        payload.timezone = Some(String::new());
+    }
+
+    update_schedule(Path(id), user, headers, Extension(pool), Json(payload)).await
+}
+
+// PATCH /traffic/:id
+async fn patch_traffic(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    headers: HeaderMap,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(patch): Json<serde_json::Value>,
+) -> Result<(HeaderMap, Json<Traffic>), WriteError<StatusCode>> {
+    let (current, _) = fetch_traffic_with_etag(&pool, id as i64)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let payload: NewTraffic = merge_patch_payload(serde_json::to_value(current).unwrap_or_default(), &patch)
+        .map_err(|e| {
+            eprintln!("[PatchTraffic] {}", e);
+            StatusCode::UNPROCESSABLE_ENTITY
+        })?;
+
+    // 所有者の確認はPUTの更新処理で行う
+    update_traffic(Path(id), user, headers, Extension(pool), Json(payload)).await
+}
+
+// PATCH /stay/:id
+async fn patch_stay(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    headers: HeaderMap,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(patch): Json<serde_json::Value>,
+) -> Result<(HeaderMap, Json<Stay>), WriteError<StatusCode>> {
+    let (current, _) = fetch_stay_with_etag(&pool, id as i64)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let payload: NewStay = merge_patch_payload(serde_json::to_value(current).unwrap_or_default(), &patch)
+        .map_err(|e| {
+            eprintln!("[PatchStay] {}", e);
+            StatusCode::UNPROCESSABLE_ENTITY
+        })?;
+
+    // 所有者の確認はPUTの更新処理で行う
+    update_stay(Path(id), user, headers, Extension(pool), Json(payload)).await
+}
+
+// ====== ゴミ箱（論理削除） ======
+// スケジュール・交通・宿泊はdeleted_atを設定してゴミ箱に移動し、保持期間（TRASH_RETENTION_DAYS）の経過後に完全削除する
+// スケジュールと一緒にゴミ箱へ移動した交通・宿泊は同じdeleted_atを持ち、スケジュールの復元時にまとめて戻す
+
+#[derive(Serialize)]
+struct TrashItem {
+    entity_type: String, // "schedule" / "traffic" / "stay"
+    id: i32,
+    schedule_id: i32,
+    title: String, // スケジュール名、交通は「出発地 → 到着地」、宿泊は宿泊先名
+    date: Option<String>,
+    deleted_at: String,
+    purge_at: Option<String>, // この日時以降にバックグラウンドで完全削除される
+}
+
+#[derive(sqlx::FromRow)]
+struct TrashRow {
+    entity_type: String,
+    id: i64,
+    schedule_id: i64,
+    title: String,
+    date: Option<String>,
+    deleted_at: String,
+}
+
+fn parse_related_schedule_ids(json: Option<&str>) -> Vec<i32> {
+    json.and_then(|json| serde_json::from_str::<Vec<i32>>(json).ok())
+        .unwrap_or_default()
+}
+
+fn related_schedule_ids_json(ids: &[i32]) -> Option<String> {
+    if ids.is_empty() {
+        None
+    } else {
+        serde_json::to_string(ids).ok()
+    }
+}
+
+// deleted_at（RFC 3339）から完全削除の予定日時を求める
+fn trash_purge_at(deleted_at: &str, retention_days: i64) -> Option<String> {
+    DateTime::parse_from_rfc3339(deleted_at)
+        .ok()?
+        .with_timezone(&Utc)
+        .checked_add_signed(chrono::Duration::days(retention_days))
+        .map(|at| at.to_rfc3339())
+}
+
+#[cfg(test)]
+mod trash_tests {
+    use super::*;
+
+    #[test]
+    fn related_ids_round_trip() {
+        assert_eq!(parse_related_schedule_ids(Some("[3,5]")), vec![3, 5]);
+        assert_eq!(parse_related_schedule_ids(Some("broken")), Vec::<i32>::new());
+        assert_eq!(related_schedule_ids_json(&[]), None);
+        assert_eq!(related_schedule_ids_json(&[3]), Some("[3]".to_string()));
+    }
+
+    #[test]
+    fn purge_at_adds_retention_days() {
+        assert_eq!(
+            trash_purge_at("2026-01-31T10:00:00+00:00", 30).as_deref(),
+            Some("2026-03-02T10:00:00+00:00")
+        );
+        assert_eq!(trash_purge_at("not a date", 30), None);
+    }
+}
+
+// スケジュールのrelated_schedule_idsを current から desired に変更するにあたり、関連先の双方向リンクを合わせる
+// 関連先がゴミ箱にある場合は自分側からは外し、関連先のrelated_schedule_idsに自分を入れておく（関連先の復元時に再リンクされる）
+// 存在しないIDは外す。自分側に保存すべきIDの一覧を返す
+async fn sync_related_schedule_links(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    schedule_id: i32,
+    user_id: i32,
+    current: &[i32],
+    desired: &[i32],
+    now: &str,
+) -> Result<Vec<i32>, sqlx::Error> {
+    let mut own_ids = Vec::new();
+    let mut visited = Vec::new();
+    for &related_id in current.iter().chain(desired) {
+        if related_id == schedule_id || visited.contains(&related_id) {
+            continue;
+        }
+        visited.push(related_id);
+        let related: Option<(Option<String>, Option<String>)> = sqlx::query_as(
+            "SELECT related_schedule_ids, deleted_at FROM schedules WHERE id = ? AND user_id = ?",
+        )
+        .bind(related_id)
+        .bind(user_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+        let Some((other_json, other_deleted_at)) = related else { continue };
+
+        let mut other_ids = parse_related_schedule_ids(other_json.as_deref());
+        let linked = other_ids.contains(&schedule_id);
+        if desired.contains(&related_id) {
+            if other_deleted_at.is_none() {
+                own_ids.push(related_id);
+            }
+            if linked {
+                continue;
+            }
+            other_ids.push(schedule_id);
+        } else {
+            if !linked {
+                continue;
+            }
+            other_ids.retain(|&x| x != schedule_id);
+        }
+        sqlx::query("UPDATE schedules SET related_schedule_ids = ?, updated_at = ? WHERE id = ?")
+            .bind(related_schedule_ids_json(&other_ids))
+            .bind(now)
+            .bind(related_id)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(own_ids)
+}
+
+// スケジュールと関連データを完全に削除する
+// 添付ファイルは行だけ削除し、ファイル本体の保存キーを返す（コミット後にremove_attachment_filesで消す）
+async fn purge_schedule(tx: &mut sqlx::Transaction<'_, Sqlite>, schedule_id: i64) -> Result<Vec<String>, sqlx::Error> {
+    // 関連先に残っている参照を外す（ゴミ箱にある関連先も含む）
+    let related_json: Option<Option<String>> =
+        sqlx::query_scalar("SELECT related_schedule_ids FROM schedules WHERE id = ?")
+            .bind(schedule_id)
+            .fetch_optional(&mut **tx)
+            .await?;
+    for related_id in parse_related_schedule_ids(related_json.flatten().as_deref()) {
+        let other_json: Option<Option<String>> =
+            sqlx::query_scalar("SELECT related_schedule_ids FROM schedules WHERE id = ?")
+                .bind(related_id)
+                .fetch_optional(&mut **tx)
+                .await?;
+        let Some(other_json) = other_json else { continue };
+        let mut other_ids = parse_related_schedule_ids(other_json.as_deref());
+        if other_ids.contains(&(schedule_id as i32)) {
+            other_ids.retain(|&x| x != schedule_id as i32);
+            sqlx::query("UPDATE schedules SET related_schedule_ids = ? WHERE id = ?")
+                .bind(related_schedule_ids_json(&other_ids))
+                .bind(related_id)
+                .execute(&mut **tx)
+                .await?;
+        }
+    }
+
+    // 交通・宿泊・チケット申込を参照する通知を先に削除
+    sqlx::query(
+        r#"
+        DELETE FROM notifications
+        WHERE schedule_id = ?
+           OR stay_id IN (SELECT id FROM stays WHERE schedule_id = ?)
+           OR ticket_application_id IN (SELECT id FROM ticket_applications WHERE schedule_id = ?)
+        "#,
+    )
+    .bind(schedule_id)
+    .bind(schedule_id)
+    .bind(schedule_id)
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query("DELETE FROM cost_split_members WHERE split_id IN (SELECT id FROM cost_splits WHERE schedule_id = ?)")
+        .bind(schedule_id)
+        .execute(&mut **tx)
+        .await?;
+    for table in [
+        "cost_splits",
+        "traffics",
+        "stays",
+        "search_index",
+        "ticket_applications",
+        "ticket_transfers",
+        "goods",
+        "expenses",
+        "schedule_days",
+        "schedule_tickets",
+        "setlist_entries",
+        "revisions",
+        "schedule_status_history",
+    ] {
+        sqlx::query(&format!("DELETE FROM {} WHERE schedule_id = ?", table))
+            .bind(schedule_id)
+            .execute(&mut **tx)
+            .await?;
+    }
+    let attachment_keys: Vec<String> =
+        sqlx::query_scalar("DELETE FROM attachments WHERE schedule_id = ? RETURNING storage_key")
+            .bind(schedule_id)
+            .fetch_all(&mut **tx)
+            .await?;
+    sqlx::query("DELETE FROM schedules WHERE id = ?")
+        .bind(schedule_id)
+        .execute(&mut **tx)
+        .await?;
+    Ok(attachment_keys)
+}
+
+// 保持期間を過ぎたゴミ箱の中身を完全削除する（バックグラウンドタスクから呼ぶ）
+async fn purge_expired_trash(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+    let cutoff = (Utc::now() - chrono::Duration::days(get_trash_retention_days())).to_rfc3339();
+
+    let schedule_ids: Vec<i64> =
+        sqlx::query_scalar("SELECT id FROM schedules WHERE deleted_at IS NOT NULL AND deleted_at < ?")
+            .bind(&cutoff)
+            .fetch_all(pool)
+            .await?;
+    for schedule_id in &schedule_ids {
+        let mut tx = pool.begin().await?;
+        let attachment_keys = purge_schedule(&mut tx, *schedule_id).await?;
+        tx.commit().await?;
+        remove_attachment_files(&attachment_keys).await;
+    }
+
+    let mut tx = pool.begin().await?;
+    let attachment_keys: Vec<String> = sqlx::query_scalar(
+        r#"
+        DELETE FROM attachments
+        WHERE (entity_type = 'traffic' AND entity_id IN (SELECT id FROM traffics WHERE deleted_at IS NOT NULL AND deleted_at < ?))
+           OR (entity_type = 'stay' AND entity_id IN (SELECT id FROM stays WHERE deleted_at IS NOT NULL AND deleted_at < ?))
+        RETURNING storage_key
+        "#,
+    )
+    .bind(&cutoff)
+    .bind(&cutoff)
+    .fetch_all(&mut *tx)
+    .await?;
+    for entity_type in ["traffic", "stay"] {
+        let table = if entity_type == "traffic" { "traffics" } else { "stays" };
+        let expired = format!(
+            "SELECT id FROM cost_splits WHERE entity_type = '{}' AND entity_id IN (SELECT id FROM {} WHERE deleted_at IS NOT NULL AND deleted_at < ?)",
+            entity_type, table
+        );
+        sqlx::query(&format!("DELETE FROM cost_split_members WHERE split_id IN ({})", expired))
+            .bind(&cutoff)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&format!("DELETE FROM cost_splits WHERE id IN ({})", expired))
+            .bind(&cutoff)
+            .execute(&mut *tx)
+            .await?;
+    }
+    let traffics = sqlx::query("DELETE FROM traffics WHERE deleted_at IS NOT NULL AND deleted_at < ?")
+        .bind(&cutoff)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    sqlx::query(
+        "DELETE FROM notifications WHERE stay_id IN (SELECT id FROM stays WHERE deleted_at IS NOT NULL AND deleted_at < ?)",
+    )
+    .bind(&cutoff)
+    .execute(&mut *tx)
+    .await?;
+    let stays = sqlx::query("DELETE FROM stays WHERE deleted_at IS NOT NULL AND deleted_at < ?")
+        .bind(&cutoff)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    tx.commit().await?;
+    remove_attachment_files(&attachment_keys).await;
+
+    if !schedule_ids.is_empty() || traffics > 0 || stays > 0 {
+        eprintln!(
+            "[Trash] Purged {} schedules, {} traffics, {} stays deleted before {}",
+            schedule_ids.len(),
+            traffics,
+            stays,
+            cutoff
+        );
+    }
+    Ok(())
+}
+
+fn trash_item_not_found() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "ゴミ箱に該当するデータが見つかりませんでした".to_string(),
+        }),
+    )
+}
+
+// GET /trash - ゴミ箱の一覧（削除日時の新しい順）
+// スケジュールと一緒に削除された交通・宿泊はスケジュールの復元で戻るため、個別には表示しない
+async fn list_trash(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<TrashItem>>, (StatusCode, Json<ErrorResponse>)> {
+    let rows: Vec<TrashRow> = sqlx::query_as::<_, TrashRow>(
+        r#"
+        SELECT 'schedule' AS entity_type, id, id AS schedule_id, title, date, deleted_at
+        FROM schedules
+        WHERE user_id = ? AND deleted_at IS NOT NULL
+        UNION ALL
+        SELECT 'traffic' AS entity_type, t.id, t.schedule_id, t.from_place || ' → ' || t.to_place AS title, t.date, t.deleted_at
+        FROM traffics t
+        INNER JOIN schedules s ON s.id = t.schedule_id
+        WHERE s.user_id = ? AND t.deleted_at IS NOT NULL AND s.deleted_at IS NULL
+        UNION ALL
+        SELECT 'stay' AS entity_type, st.id, st.schedule_id, st.hotel_name AS title, st.check_in AS date, st.deleted_at
+        FROM stays st
+        INNER JOIN schedules s ON s.id = st.schedule_id
+        WHERE s.user_id = ? AND st.deleted_at IS NOT NULL AND s.deleted_at IS NULL
+        ORDER BY deleted_at DESC
+        "#,
+    )
+    .bind(user.user_id)
+    .bind(user.user_id)
+    .bind(user.user_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[ListTrash] Database error: {}", e);
+        database_error()
+    })?;
+
+    let retention_days = get_trash_retention_days();
+    let items = rows
+        .into_iter()
+        .map(|row| TrashItem {
+            purge_at: trash_purge_at(&row.deleted_at, retention_days),
+            entity_type: row.entity_type,
+            id: row.id as i32,
+            schedule_id: row.schedule_id as i32,
+            title: row.title,
+            date: row.date,
+            deleted_at: row.deleted_at,
+        })
+        .collect();
+    Ok(Json(items))
+}
+
+// POST /trash/schedules/:id/restore - スケジュールを一緒に削除された交通・宿泊とともに復元する
+async fn restore_schedule(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let restore_failed = |e: sqlx::Error| {
+        eprintln!("[RestoreSchedule] Database error: {}", e);
+        database_error()
+    };
+
+    let trashed: Option<(String, Option<String>)> = sqlx::query_as(
+        "SELECT deleted_at, related_schedule_ids FROM schedules WHERE id = ? AND user_id = ? AND deleted_at IS NOT NULL",
+    )
+    .bind(id)
+    .bind(user.user_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(restore_failed)?;
+    let (deleted_at, related_json) = trashed.ok_or_else(trash_item_not_found)?;
+
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await.map_err(restore_failed)?;
+
+    sqlx::query("UPDATE traffics SET deleted_at = NULL WHERE schedule_id = ? AND deleted_at = ?")
+        .bind(id)
+        .bind(&deleted_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(restore_failed)?;
+    sqlx::query("UPDATE stays SET deleted_at = NULL WHERE schedule_id = ? AND deleted_at = ?")
+        .bind(id)
+        .bind(&deleted_at)
+        .execute(&mut *tx)
+        .await
+        .map_err(restore_failed)?;
+
+    // 関連スケジュールとの双方向リンクを張り直す（関連先からは削除時に参照を外しているため、現在のリンクは空として扱う）
+    let own_ids = sync_related_schedule_links(
+        &mut tx,
+        id,
+        user.user_id,
+        &[],
+        &parse_related_schedule_ids(related_json.as_deref()),
+        &now,
+    )
+    .await
+    .map_err(restore_failed)?;
+
+    sqlx::query("UPDATE schedules SET deleted_at = NULL, related_schedule_ids = ? WHERE id = ?")
+        .bind(related_schedule_ids_json(&own_ids))
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(restore_failed)?;
+
+    tx.commit().await.map_err(restore_failed)?;
+
+    calculate_rollup(&pool, id as i64).await.ok();
+    refresh_search_index(&pool, id as i64).await;
+    track_revision(&pool, user.user_id, RevisionEntity::Schedule, id as i64, id as i64, RevisionAction::Restore, None).await;
+
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "スケジュールを復元しました"
+    })))
+}
+
+// DELETE /traffic/:id・DELETE /stay/:id - 交通・宿泊を個別にゴミ箱へ移動する
+async fn trash_schedule_child(
+    pool: &Pool<Sqlite>,
+    entity: RevisionEntity,
+    id: i32,
+    user_id: i32,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let schedule_id: Option<i64> = sqlx::query_scalar(&format!(
+        "SELECT schedule_id FROM {} WHERE id = ? AND deleted_at IS NULL",
+        entity.table()
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[Trash] Database error: {}", e);
+        database_error()
+    })?;
+    let schedule_id = schedule_id.ok_or((
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "データが見つかりませんでした".to_string(),
+        }),
+    ))?;
+    ensure_schedule_owner(pool, schedule_id, user_id).await?;
+
+    let before = revision_snapshot(pool, entity, id as i64).await;
+    sqlx::query(&format!("UPDATE {} SET deleted_at = ? WHERE id = ?", entity.table()))
+        .bind(Utc::now().to_rfc3339())
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[Trash] Database error: {}", e);
+            database_error()
+        })?;
+
+    calculate_rollup(pool, schedule_id).await.ok();
+    refresh_search_index(pool, schedule_id).await;
+    track_revision(pool, user_id, entity, id as i64, schedule_id, RevisionAction::Delete, before).await;
+    Ok(())
+}
+
+// POST /trash/traffics/:id/restore・POST /trash/stays/:id/restore
+// 親スケジュールがゴミ箱にある場合は、先にスケジュールを復元してもらう
+async fn restore_schedule_child(
+    pool: &Pool<Sqlite>,
+    entity: RevisionEntity,
+    id: i32,
+    user_id: i32,
+) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+    let restore_failed = |e: sqlx::Error| {
+        eprintln!("[Trash] Database error: {}", e);
+        database_error()
+    };
+
+    let trashed: Option<(i64, Option<String>)> = sqlx::query_as(&format!(
+        r#"
+        SELECT c.schedule_id, s.deleted_at
+        FROM {} c
+        INNER JOIN schedules s ON s.id = c.schedule_id
+        WHERE c.id = ? AND s.user_id = ? AND c.deleted_at IS NOT NULL
+        "#,
+        entity.table()
+    ))
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(restore_failed)?;
+    let (schedule_id, schedule_deleted_at) = trashed.ok_or_else(trash_item_not_found)?;
+    if schedule_deleted_at.is_some() {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: "スケジュールがゴミ箱にあります。先にスケジュールを復元してください".to_string(),
+            }),
+        ));
+    }
+
+    sqlx::query(&format!("UPDATE {} SET deleted_at = NULL WHERE id = ?", entity.table()))
+        .bind(id)
+        .execute(pool)
+        .await
+        .map_err(restore_failed)?;
+
+    calculate_rollup(pool, schedule_id).await.ok();
+    refresh_search_index(pool, schedule_id).await;
+    track_revision(pool, user_id, entity, id as i64, schedule_id, RevisionAction::Restore, None).await;
+    Ok(())
+}
+
+async fn delete_traffic(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    trash_schedule_child(&pool, RevisionEntity::Traffic, id, user.user_id).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "交通情報をゴミ箱に移動しました"
+    })))
+}
+
+async fn delete_stay(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    trash_schedule_child(&pool, RevisionEntity::Stay, id, user.user_id).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "宿泊情報をゴミ箱に移動しました"
+    })))
+}
+
+async fn restore_traffic(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    restore_schedule_child(&pool, RevisionEntity::Traffic, id, user.user_id).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "交通情報を復元しました"
+    })))
+}
+
+async fn restore_stay(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    restore_schedule_child(&pool, RevisionEntity::Stay, id, user.user_id).await?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "message": "宿泊情報を復元しました"
+    })))
+}
+
+// ====== 変更履歴（リビジョン） ======
+// スケジュール・交通・宿泊の作成・更新・削除・復元を、変更後のスナップショットと項目ごとの差分で記録する
+// スナップショットはテーブルの列をそのままJSONにしたもの（ロールアップ値や作成・更新日時は除く）
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RevisionEntity {
+    Schedule,
+    Traffic,
+    Stay,
+}
+
+impl RevisionEntity {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "schedule" => Some(Self::Schedule),
+            "traffic" => Some(Self::Traffic),
+            "stay" => Some(Self::Stay),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Schedule => "schedule",
+            Self::Traffic => "traffic",
+            Self::Stay => "stay",
+        }
+    }
+
+    fn table(self) -> &'static str {
+        match self {
+            Self::Schedule => "schedules",
+            Self::Traffic => "traffics",
+            Self::Stay => "stays",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RevisionAction {
+    Create,
+    Update,
+    Delete,
+    Restore,
+    Revert,
+}
+
+impl RevisionAction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+            Self::Restore => "restore",
+            Self::Revert => "revert",
+        }
+    }
+}
+
+type RevisionSnapshot = serde_json::Map<String, serde_json::Value>;
+
+// 履歴に含めない列（計算値・自動更新される日時・ゴミ箱の状態）
+const REVISION_IGNORED_COLUMNS: &[&str] = &[
+    "created_at",
+    "updated_at",
+    "deleted_at",
+    "ticket_fee_effective",
+    "drink_fee_effective",
+    "total_fare",
+    "total_miles",
+    "stay_fee",
+    "travel_cost",
+    "other_cost",
+    "total_cost",
+    "my_share",
+    "net_cost",
+    "goods_cost",
+];
+
+// 差し戻しで書き換えない列（所有関係・識別子）
+const REVISION_FIXED_COLUMNS: &[&str] = &["id", "user_id", "schedule_id", "public_id"];
+
+// 差し戻しの前に、基準通貨に換算できるか確認する列（スケジュール・交通・宿泊の費用の通貨）
+const REVISION_CURRENCY_COLUMNS: &[&str] = &["ticket_fee_currency", "drink_fee_currency", "fare_currency", "fee_currency"];
+
+#[derive(Serialize)]
+struct Revision {
+    id: i64,
+    entity_type: String,
+    entity_id: i64,
+    schedule_id: i64,
+    action: String,
+    user_id: i64, // 変更したユーザー
+    changes: serde_json::Value, // { 項目名: { "before": 変更前, "after": 変更後 } }
+    created_at: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct RevisionRow {
+    id: i64,
+    entity_type: String,
+    entity_id: i64,
+    schedule_id: i64,
+    action: String,
+    user_id: i64,
+    snapshot: Option<String>,
+    changes: String,
+    created_at: String,
+}
+
+fn row_to_revision(row: RevisionRow) -> Revision {
+    Revision {
+        id: row.id,
+        entity_type: row.entity_type,
+        entity_id: row.entity_id,
+        schedule_id: row.schedule_id,
+        action: row.action,
+        user_id: row.user_id,
+        changes: serde_json::from_str(&row.changes).unwrap_or_else(|_| serde_json::json!({})),
+        created_at: row.created_at,
+    }
+}
+
+// 変更前後のスナップショットから、値が変わった項目だけの差分を作る
+fn revision_changes(before: Option<&RevisionSnapshot>, after: Option<&RevisionSnapshot>) -> RevisionSnapshot {
+    let null = serde_json::Value::Null;
+    let mut keys: Vec<&String> = before.into_iter().chain(after).flat_map(|s| s.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    let mut changes = RevisionSnapshot::new();
+    for key in keys {
+        let old = before.and_then(|s| s.get(key)).unwrap_or(&null);
+        let new = after.and_then(|s| s.get(key)).unwrap_or(&null);
+        if old != new {
+            changes.insert(key.clone(), serde_json::json!({ "before": old, "after": new }));
+        }
+    }
+    changes
+}
+
+// 履歴の直前の状態を組み立てる（変更後のスナップショットに changes の before を重ねる）
+fn revision_state_before(snapshot: &RevisionSnapshot, changes: &RevisionSnapshot) -> RevisionSnapshot {
+    let mut state = snapshot.clone();
+    for (key, change) in changes {
+        let before = change.get("before").cloned().unwrap_or(serde_json::Value::Null);
+        state.insert(key.clone(), before);
+    }
+    state
+}
+
+#[cfg(test)]
+mod revision_tests {
+    use super::*;
+
+    fn snapshot(value: serde_json::Value) -> RevisionSnapshot {
+        value.as_object().cloned().unwrap()
+    }
+
+    #[test]
+    fn changes_contain_only_modified_fields() {
+        let before = snapshot(serde_json::json!({ "id": 1, "title": "A", "status": "Pending", "notes": null }));
+        let after = snapshot(serde_json::json!({ "id": 1, "title": "A", "status": "Keep", "notes": "memo" }));
+        let changes = revision_changes(Some(&before), Some(&after));
+        assert_eq!(
+            serde_json::Value::Object(changes),
+            serde_json::json!({
+                "notes": { "before": null, "after": "memo" },
+                "status": { "before": "Pending", "after": "Keep" },
+            })
+        );
+    }
+
+    #[test]
+    fn create_lists_every_set_field() {
+        let after = snapshot(serde_json::json!({ "id": 5, "title": "B", "notes": null }));
+        let changes = revision_changes(None, Some(&after));
+        assert_eq!(changes.len(), 2);
+        assert!(changes.contains_key("id") && changes.contains_key("title"));
+    }
+
+    #[test]
+    fn state_before_undoes_the_changes() {
+        let before = snapshot(serde_json::json!({ "id": 1, "title": "A", "ticket_fee": 8000, "notes": null }));
+        let after = snapshot(serde_json::json!({ "id": 1, "title": "B", "ticket_fee": 8000, "notes": "memo" }));
+        let changes = revision_changes(Some(&before), Some(&after));
+        assert_eq!(revision_state_before(&after, &changes), before);
+    }
+}
+
+async fn table_columns(conn: &mut sqlx::SqliteConnection, table: &str) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+        .bind(table)
+        .fetch_all(&mut *conn)
+        .await
+}
+
+// 行の現在の状態をスナップショットとして取得する（ゴミ箱にある行も含む）
+async fn fetch_revision_snapshot(
+    conn: &mut sqlx::SqliteConnection,
+    entity: RevisionEntity,
+    id: i64,
+) -> Result<Option<RevisionSnapshot>, sqlx::Error> {
+    let columns = table_columns(conn, entity.table()).await?;
+    let pairs: Vec<String> = columns
+        .iter()
+        .filter(|c| !REVISION_IGNORED_COLUMNS.contains(&c.as_str()))
+        .map(|c| format!("'{}', \"{}\"", c, c))
+        .collect();
+    let json: Option<String> = sqlx::query_scalar(&format!(
+        "SELECT json_object({}) FROM {} WHERE id = ?",
+        pairs.join(", "),
+        entity.table()
+    ))
+    .bind(id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    Ok(json.and_then(|json| serde_json::from_str::<RevisionSnapshot>(&json).ok()))
+}
+
+// 履歴を1件記録する。スナップショットは削除の場合は削除前、それ以外は変更後の状態
+// 更新で値が何も変わっていない場合は記録しない
+#[allow(clippy::too_many_arguments)]
+async fn record_revision(
+    pool: &Pool<Sqlite>,
+    user_id: i32,
+    entity: RevisionEntity,
+    entity_id: i64,
+    schedule_id: i64,
+    action: RevisionAction,
+    before: Option<&RevisionSnapshot>,
+    after: Option<&RevisionSnapshot>,
+) -> Result<Option<i64>, sqlx::Error> {
+    let changes = revision_changes(before, after);
+    if action == RevisionAction::Update && changes.is_empty() {
+        return Ok(None);
+    }
+    let snapshot = if action == RevisionAction::Delete { before } else { after };
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO revisions (user_id, entity_type, entity_id, schedule_id, action, snapshot, changes, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(user_id)
+    .bind(entity.name())
+    .bind(entity_id)
+    .bind(schedule_id)
+    .bind(action.as_str())
+    .bind(snapshot.map(|s| serde_json::Value::Object(s.clone()).to_string()))
+    .bind(serde_json::Value::Object(changes).to_string())
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok(Some(result.last_insert_rowid()))
+}
+
+// 書き込み系ハンドラから呼ぶ。変更前のスナップショットを取得する（失敗しても本来の処理は失敗させない）
+async fn revision_snapshot(pool: &Pool<Sqlite>, entity: RevisionEntity, id: i64) -> Option<RevisionSnapshot> {
+    let snapshot = match pool.acquire().await {
+        Ok(mut conn) => fetch_revision_snapshot(&mut conn, entity, id).await,
+        Err(e) => Err(e),
+    };
+    snapshot.unwrap_or_else(|e| {
+        eprintln!("[Revision] Failed to fetch {} {}: {}", entity.name(), id, e);
+        None
+    })
+}
+
+// 書き込み系ハンドラから呼ぶ。変更後の状態を取得して履歴に記録する（失敗しても本来の処理は失敗させない）
+async fn track_revision(
+    pool: &Pool<Sqlite>,
+    user_id: i32,
+    entity: RevisionEntity,
+    entity_id: i64,
+    schedule_id: i64,
+    action: RevisionAction,
+    before: Option<RevisionSnapshot>,
+) {
+    let after = if action == RevisionAction::Delete {
+        None
+    } else {
+        revision_snapshot(pool, entity, entity_id).await
+    };
+    if let Err(e) = record_revision(
+        pool,
+        user_id,
+        entity,
+        entity_id,
+        schedule_id,
+        action,
+        before.as_ref(),
+        after.as_ref(),
+    )
+    .await
+    {
+        eprintln!("[Revision] Failed to record {} {} {}: {}", action.as_str(), entity.name(), entity_id, e);
+    }
+}
+
+fn bind_json_value<'q>(
+    query: sqlx::query::Query<'q, Sqlite, sqlx::sqlite::SqliteArguments<'q>>,
+    value: &serde_json::Value,
+) -> sqlx::query::Query<'q, Sqlite, sqlx::sqlite::SqliteArguments<'q>> {
+    match value {
+        serde_json::Value::Null => query.bind(None::<String>),
+        serde_json::Value::Bool(b) => query.bind(*b as i64),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => query.bind(i),
+            None => query.bind(n.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(s) => query.bind(s.clone()),
+        other => query.bind(other.to_string()),
+    }
+}
+
+// スナップショットの内容で行を書き戻す。行が完全削除済みの場合（交通・宿泊）は同じIDで作り直す
+async fn apply_revision_snapshot(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    entity: RevisionEntity,
+    entity_id: i64,
+    schedule_id: i64,
+    snapshot: &RevisionSnapshot,
+) -> Result<(), sqlx::Error> {
+    let columns = table_columns(tx, entity.table()).await?;
+    let values: Vec<(&String, &serde_json::Value)> = snapshot
+        .iter()
+        .filter(|(key, _)| columns.contains(key))
+        .filter(|(key, _)| !REVISION_IGNORED_COLUMNS.contains(&key.as_str()))
+        .filter(|(key, _)| !REVISION_FIXED_COLUMNS.contains(&key.as_str()))
+        .collect();
+
+    let exists: Option<i64> = sqlx::query_scalar(&format!("SELECT id FROM {} WHERE id = ?", entity.table()))
+        .bind(entity_id)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+    if exists.is_some() {
+        let assignments: Vec<String> = values.iter().map(|(key, _)| format!("\"{}\" = ?", key)).collect();
+        let sql = format!(
+            "UPDATE {} SET {}, deleted_at = NULL WHERE id = ?",
+            entity.table(),
+            assignments.join(", ")
+        );
+        let mut query = sqlx::query(&sql);
+        for (_, value) in &values {
+            query = bind_json_value(query, value);
+        }
+        query.bind(entity_id).execute(&mut **tx).await?;
+    } else {
+        let names: Vec<String> = values.iter().map(|(key, _)| format!("\"{}\"", key)).collect();
+        let placeholders = vec!["?"; values.len()].join(", ");
+        let sql = format!(
+            "INSERT INTO {} (id, schedule_id, public_id, {}) VALUES (?, ?, ?, {})",
+            entity.table(),
+            names.join(", "),
+            placeholders
+        );
+        let public_id = snapshot
+            .get("public_id")
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(generate_public_id);
+        let mut query = sqlx::query(&sql).bind(entity_id).bind(schedule_id).bind(public_id);
+        for (_, value) in &values {
+            query = bind_json_value(query, value);
+        }
+        query.execute(&mut **tx).await?;
+    }
+    Ok(())
 }
 
-// DELETE /schedules/:id
-async fn delete_schedule(
+// GET /schedules/:id/revisions - スケジュールと、その交通・宿泊の変更履歴（新しい順）
+async fn list_schedule_revisions(
     Path(id): Path<i32>,
     user: AuthenticatedUser,
     Extension(pool): Extension<Pool<Sqlite>>,
-) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
-    // スケジュールが存在し、ユーザーが所有しているかチェック
-    let existing: Option<ScheduleRow> = sqlx::query_as::<_, ScheduleRow>(
+) -> Result<Json<Vec<Revision>>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, id as i64, user.user_id).await?;
+
+    let rows: Vec<RevisionRow> = sqlx::query_as::<_, RevisionRow>(
         r#"
-        SELECT
-          id,
-          title,
-          "group",
-          date,
-          open,
-          start,
-          "end",
-          notes,
-          category,
-          area,
-          venue,
-          target,
-          lineup,
-          seller,
-          ticket_fee,
-          drink_fee,
-          total_fare,
-          stay_fee,
-          travel_cost,
-          total_cost,
-          status,
-          related_schedule_ids,
-          user_id,
-          is_public,
-          created_at,
-          updated_at
-        FROM schedules
-        WHERE id = ?
+        SELECT id, entity_type, entity_id, schedule_id, action, user_id, snapshot, changes, created_at
+        FROM revisions
+        WHERE schedule_id = ?
+        ORDER BY id DESC
         "#,
     )
     .bind(id)
-    .fetch_optional(&pool)
+    .fetch_all(&pool)
     .await
-    .map_err(|_| {
+    .map_err(|e| {
+        eprintln!("[ListScheduleRevisions] Database error: {}", e);
+        database_error()
+    })?;
+
+    Ok(Json(rows.into_iter().map(row_to_revision).collect()))
+}
+
+#[derive(Debug, Deserialize)]
+struct RevertRevisionQuery {
+    before: Option<bool>, // trueの場合はその変更が行われる直前の内容に戻す
+}
+
+// 戻す内容のスケジュールを検証し、開催期間の日付一覧を返す（PUT /schedules/:id と同じ条件）
+fn validate_reverted_schedule(
+    snapshot: &RevisionSnapshot,
+) -> Result<Vec<chrono::NaiveDate>, (StatusCode, Json<ErrorResponse>)> {
+    let cannot_revert = |reason: &str| {
         (
-            StatusCode::INTERNAL_SERVER_ERROR,
+            StatusCode::CONFLICT,
             Json(ErrorResponse {
-                error: "データベースエラーが発生しました".to_string(),
+                error: format!("この変更履歴には戻せません（{}）", reason),
             }),
         )
-    })?;
+    };
+    let field = |key: &str| snapshot.get(key).and_then(|v| v.as_str());
+    let end_date = normalize_end_date(field("date"), field("end_date"));
+    let range = schedule_date_range(field("date"), end_date.as_deref()).map_err(cannot_revert)?;
+    if let Some(timezone) = field("timezone").filter(|tz| !tz.trim().is_empty()) {
+        if parse_timezone(timezone).is_none() {
+            return Err(cannot_revert("タイムゾーンが正しくありません"));
+        }
+    }
+    Ok(range)
+}
 
-    let existing = existing.ok_or((
+// POST /schedules/:id/revisions/:revision_id/revert - 指定した履歴の時点の内容に戻す
+// 削除の履歴を指定した場合は削除前の内容で復元する。戻した後に日程・ロールアップと関連スケジュールのリンクを整える
+// ゴミ箱にあるスケジュール自体は対象外（先に /trash から復元する）
+async fn revert_schedule_revision(
+    Path((id, revision_id)): Path<(i32, i64)>,
+    Query(params): Query<RevertRevisionQuery>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Revision>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, id as i64, user.user_id).await?;
+    let revert_failed = |e: sqlx::Error| {
+        eprintln!("[RevertScheduleRevision] Database error: {}", e);
+        database_error()
+    };
+
+    let revision: RevisionRow = sqlx::query_as::<_, RevisionRow>(
+        r#"
+        SELECT id, entity_type, entity_id, schedule_id, action, user_id, snapshot, changes, created_at
+        FROM revisions
+        WHERE id = ? AND schedule_id = ?
+        "#,
+    )
+    .bind(revision_id)
+    .bind(id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(revert_failed)?
+    .ok_or((
         StatusCode::NOT_FOUND,
         Json(ErrorResponse {
-            error: "スケジュールが見つかりませんでした".to_string(),
+            error: "変更履歴が見つかりませんでした".to_string(),
         }),
     ))?;
-    
-    if existing.user_id.map(|uid| uid as i32) != Some(user.user_id) {
+
+    let entity = RevisionEntity::parse(&revision.entity_type);
+    let snapshot = revision
+        .snapshot
+        .as_deref()
+        .and_then(|json| serde_json::from_str::<RevisionSnapshot>(json).ok());
+    // 直前の内容に戻す場合。作成の履歴は直前の状態が存在しないため対象外、削除の履歴は削除前の内容をそのまま使う
+    let snapshot = match (params.before.unwrap_or(false), revision.action.as_str()) {
+        (false, _) => snapshot,
+        (true, "create") => None,
+        (true, "delete") => snapshot,
+        (true, _) => snapshot.map(|snapshot| {
+            let changes = serde_json::from_str::<RevisionSnapshot>(&revision.changes).unwrap_or_default();
+            revision_state_before(&snapshot, &changes)
+        }),
+    };
+    let (Some(entity), Some(mut snapshot)) = (entity, snapshot) else {
         return Err((
-            StatusCode::FORBIDDEN,
+            StatusCode::CONFLICT,
             Json(ErrorResponse {
-                error: "このスケジュールを削除する権限がありません".to_string(),
+                error: "この変更履歴には戻せません".to_string(),
             }),
         ));
-    }
+    };
 
-    let mut transaction = pool.begin().await.map_err(|_| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+    // スケジュールは更新（PUT）と同じく開催期間・タイムゾーンを検証する。日程は戻した開催期間に合わせて後で同期する
+    let schedule_range = match entity {
+        RevisionEntity::Schedule => Some(validate_reverted_schedule(&snapshot)?),
+        _ => None,
+    };
+    // 費用の通貨は書き込み時と同じく基準通貨に換算できるものに限る（履歴の後で為替レートを削除した場合など）
+    let rates = fetch_exchange_rates(&pool, user.user_id as i64).await.map_err(revert_failed)?;
+    let currencies: Vec<String> = REVISION_CURRENCY_COLUMNS
+        .iter()
+        .filter_map(|column| snapshot.get(*column).and_then(|v| v.as_str()))
+        .map(str::to_string)
+        .collect();
+    if let Some(currency) = rates.unconvertible(&currencies).first() {
+        return Err((
+            StatusCode::CONFLICT,
             Json(ErrorResponse {
-                error: "データベースエラーが発生しました".to_string(),
+                error: format!(
+                    "この変更履歴には戻せません（{}から基準通貨{}への為替レートが登録されていません）",
+                    currency, rates.home_currency
+                ),
             }),
-        )
-    })?;
+        ));
+    }
 
-    // 関連スケジュールからこのスケジュールへの参照を削除
-    let related_ids: Vec<i32> = existing.related_schedule_ids
-        .as_ref()
-        .and_then(|json| serde_json::from_str::<Vec<i32>>(json).ok())
-        .unwrap_or_default();
-    
-    let now = Utc::now().to_rfc3339();
-    for related_id in related_ids {
-        let related_row: Option<ScheduleRow> = sqlx::query_as::<_, ScheduleRow>(
-            r#"
-            SELECT
-              id,
-              title,
-              "group",
-              date,
-              open,
-              start,
-              "end",
-              notes,
-              category,
-              area,
-              venue,
-              target,
-              lineup,
-              seller,
-              ticket_fee,
-              drink_fee,
-              total_fare,
-              stay_fee,
-              travel_cost,
-              total_cost,
-              status,
-              related_schedule_ids,
-              user_id,
-              is_public,
-              created_at,
-              updated_at
-            FROM schedules
-            WHERE id = ?
-            "#,
-        )
-        .bind(related_id)
-        .fetch_optional(&pool)
+    // 戻し先のツアーが削除済みの場合は未所属に戻す
+    if let Some(tour_id) = snapshot.get("tour_id").and_then(|v| v.as_i64()) {
+        let tour_exists: Option<i64> = sqlx::query_scalar("SELECT id FROM tours WHERE id = ? AND user_id = ?")
+            .bind(tour_id)
+            .bind(user.user_id)
+            .fetch_optional(&pool)
+            .await
+            .map_err(revert_failed)?;
+        if tour_exists.is_none() {
+            snapshot.insert("tour_id".to_string(), serde_json::Value::Null);
+        }
+    }
+
+    let mut tx = pool.begin().await.map_err(revert_failed)?;
+    // ゴミ箱にある交通・宿泊は戻すと復元されるため、変更前は存在しなかったものとして差分を記録する
+    let trashed: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM {} WHERE id = ? AND deleted_at IS NOT NULL",
+        entity.table()
+    ))
+    .bind(revision.entity_id)
+    .fetch_one(&mut *tx)
+    .await
+    .map_err(revert_failed)?;
+    let before = if trashed > 0 {
+        None
+    } else {
+        fetch_revision_snapshot(&mut tx, entity, revision.entity_id)
+            .await
+            .map_err(revert_failed)?
+    };
+
+    apply_revision_snapshot(&mut tx, entity, revision.entity_id, id as i64, &snapshot)
         .await
-        .ok()
-        .flatten();
-        
-        if let Some(related) = related_row {
-            let mut related_ids_vec: Vec<i32> = related.related_schedule_ids
+        .map_err(revert_failed)?;
+
+    if entity == RevisionEntity::Schedule {
+        let current = parse_related_schedule_ids(
+            before
                 .as_ref()
-                .and_then(|json| serde_json::from_str::<Vec<i32>>(json).ok())
-                .unwrap_or_default();
-            
-            related_ids_vec.retain(|&x| x != id);
-            let updated_json = if related_ids_vec.is_empty() {
-                None
-            } else {
-                serde_json::to_string(&related_ids_vec).ok()
-            };
-            
-            let _ = sqlx::query(
-                r#"
-                UPDATE schedules SET
-                  related_schedule_ids = ?,
-                  updated_at = ?
-                WHERE id = ?
-                "#,
-            )
-            .bind(&updated_json)
-            .bind(&now)
-            .bind(related_id)
-            .execute(&mut *transaction)
+                .and_then(|s| s.get("related_schedule_ids"))
+                .and_then(|v| v.as_str()),
+        );
+        let desired = parse_related_schedule_ids(snapshot.get("related_schedule_ids").and_then(|v| v.as_str()));
+        let now = Utc::now().to_rfc3339();
+        let own_ids = sync_related_schedule_links(&mut tx, id, user.user_id, &current, &desired, &now)
             .await
-            .map_err(|_| (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse { error: "関連スケジュールの更新に失敗しました".to_string() }),
-            ))?;
+            .map_err(revert_failed)?;
+        sqlx::query("UPDATE schedules SET related_schedule_ids = ? WHERE id = ?")
+            .bind(related_schedule_ids_json(&own_ids))
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(revert_failed)?;
+    }
+
+    tx.commit().await.map_err(revert_failed)?;
+
+    // 期間外になった日程（とその費用）を削除し、不足する日付をスケジュールの時刻で補う
+    if let Some(range) = &schedule_range {
+        let field = |key: &str| snapshot.get(key).and_then(|v| v.as_str());
+        sync_schedule_days(&pool, id as i64, range, None, field("open"), field("start"), field("end"))
+            .await
+            .map_err(revert_failed)?;
+    }
+
+    calculate_rollup(&pool, id as i64).await.ok();
+    refresh_search_index(&pool, id as i64).await;
+
+    let after = revision_snapshot(&pool, entity, revision.entity_id).await;
+    if entity == RevisionEntity::Schedule {
+        // 変更履歴から戻す場合は遷移の制限をかけない（戻した結果のステータスだけを記録する）
+        let status_of = |s: &Option<RevisionSnapshot>| {
+            s.as_ref().and_then(|s| s.get("status")).and_then(|v| v.as_str()).map(str::to_string)
+        };
+        let (from_status, to_status) = (status_of(&before), status_of(&after));
+        if let Some(to_status) = to_status.filter(|to| from_status.as_ref() != Some(to)) {
+            record_status_change(&pool, id as i64, user.user_id as i64, from_status.as_deref(), &to_status, StatusChangeSource::Revert)
+                .await;
+        }
+    }
+    let revert_id = record_revision(
+        &pool,
+        user.user_id,
+        entity,
+        revision.entity_id,
+        id as i64,
+        RevisionAction::Revert,
+        before.as_ref(),
+        after.as_ref(),
+    )
+    .await
+    .map_err(revert_failed)?
+    .unwrap_or_default();
+
+    let row: RevisionRow = sqlx::query_as::<_, RevisionRow>(
+        r#"
+        SELECT id, entity_type, entity_id, schedule_id, action, user_id, snapshot, changes, created_at
+        FROM revisions
+        WHERE id = ?
+        "#,
+    )
+    .bind(revert_id)
+    .fetch_one(&pool)
+    .await
+    .map_err(revert_failed)?;
+
+    Ok(Json(row_to_revision(row)))
+}
+
+// ====== スケジュールの複製 ======
+// 同じ会場のツアー公演や同じ遠征の繰り返し用に、スケジュールを交通・宿泊ごと複製する。
+// 列は pragma_table_info から取得してそのままコピーし、日付は元の開催日からの差分だけずらす
+
+// 複製しない列（ID・公開ID・関連スケジュール・ロールアップ値・作成更新日時は複製先で新しく決める）
+const CLONE_EXCLUDED_COLUMNS: &[&str] = &[
+    "id",
+    "public_id",
+    "related_schedule_ids",
+    "created_at",
+    "updated_at",
+    "deleted_at",
+    "ticket_fee_effective",
+    "drink_fee_effective",
+    "total_fare",
+    "total_miles",
+    "stay_fee",
+    "travel_cost",
+    "other_cost",
+    "total_cost",
+    "my_share",
+    "net_cost",
+    "goods_cost",
+];
+
+#[derive(Debug, Deserialize)]
+struct CloneScheduleRequest {
+    date: Option<String>,           // 複製先の開催日（YYYY-MM-DD、省略時は同じ日付）
+    include_traffics: Option<bool>, // 交通情報も複製するか（省略時はtrue）
+    include_stays: Option<bool>,    // 宿泊情報も複製するか（省略時はtrue）
+    link_related: Option<bool>,     // 複製元と関連スケジュールとしてリンクするか（省略時はfalse）
+}
+
+// 日付・日時文字列の先頭の日付部分（YYYY-MM-DD）をdays日ずらす。時刻やタイムゾーンの表記はそのまま残す
+fn shift_date_text(value: &str, days: i64) -> Option<String> {
+    let date = chrono::NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok()?;
+    let shifted = date.checked_add_signed(chrono::Duration::days(days))?;
+    Some(format!("{}{}", shifted.format("%Y-%m-%d"), &value[10..]))
+}
+
+// 日付をずらせない値（空・形式違い）は元の値のまま複製する
+fn shift_optional_date_text(value: Option<String>, days: i64) -> Option<String> {
+    value.map(|v| shift_date_text(&v, days).unwrap_or(v))
+}
+
+#[cfg(test)]
+mod clone_tests {
+    use super::*;
+
+    #[test]
+    fn shift_keeps_time_part() {
+        assert_eq!(shift_date_text("2026-05-10", 7).as_deref(), Some("2026-05-17"));
+        assert_eq!(shift_date_text("2026-05-30 18:00", 3).as_deref(), Some("2026-06-02 18:00"));
+        assert_eq!(
+            shift_date_text("2026-01-01T09:00:00+09:00", -1).as_deref(),
+            Some("2025-12-31T09:00:00+09:00")
+        );
+    }
+
+    #[test]
+    fn unparsable_dates_are_kept() {
+        assert_eq!(shift_date_text("未定", 1), None);
+        assert_eq!(shift_optional_date_text(Some("未定".to_string()), 1).as_deref(), Some("未定"));
+        assert_eq!(shift_optional_date_text(None, 1), None);
+    }
+}
+
+// 行を複製して新しいIDを返す。overridesに指定した列は元の値の代わりに指定値を入れる
+async fn clone_row(
+    tx: &mut sqlx::Transaction<'_, Sqlite>,
+    table: &str,
+    source_id: i64,
+    overrides: &[(&str, serde_json::Value)],
+) -> Result<i64, sqlx::Error> {
+    let copied: Vec<String> = table_columns(tx, table)
+        .await?
+        .into_iter()
+        .filter(|c| !CLONE_EXCLUDED_COLUMNS.contains(&c.as_str()))
+        .filter(|c| !overrides.iter().any(|(name, _)| name == c))
+        .map(|c| format!("\"{}\"", c))
+        .collect();
+    let overridden: Vec<String> = overrides.iter().map(|(name, _)| format!("\"{}\"", name)).collect();
+    let placeholders = vec!["?"; overrides.len()].join(", ");
+    let sql = format!(
+        "INSERT INTO {table} ({}, {}) SELECT {}, {} FROM {table} WHERE id = ?",
+        copied.join(", "),
+        overridden.join(", "),
+        copied.join(", "),
+        placeholders,
+    );
+    let mut query = sqlx::query(&sql);
+    for (_, value) in overrides {
+        query = bind_json_value(query, value);
+    }
+    let result = query.bind(source_id).execute(&mut **tx).await?;
+    Ok(result.last_insert_rowid())
+}
+
+// POST /schedules/:id/clone - スケジュールを交通・宿泊ごと複製する
+// 複製したスケジュールはステータスをPendingにし、ゴミ箱にある交通・宿泊は複製しない
+async fn clone_schedule(
+    Path(id): Path<i32>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<CloneScheduleRequest>,
+) -> Result<(StatusCode, HeaderMap, Json<Schedule>), (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, id as i64, user.user_id).await?;
+    let clone_failed = |e: sqlx::Error| {
+        eprintln!("[CloneSchedule] Database error: {}", e);
+        database_error()
+    };
+
+    let (source_date, source_end_date): (Option<String>, Option<String>) =
+        sqlx::query_as("SELECT date, end_date FROM schedules WHERE id = ?")
+            .bind(id)
+            .fetch_one(&pool)
+            .await
+            .map_err(clone_failed)?;
+
+    // 開催日の差分（日数）。元の開催日が未設定の場合は交通・宿泊の日付はずらさない
+    let (date, days) = match payload.date.as_deref().map(str::trim).filter(|d| !d.is_empty()) {
+        None => (source_date.clone(), 0),
+        Some(date) => {
+            let new_date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorResponse {
+                        error: "開催日はYYYY-MM-DD形式で指定してください".to_string(),
+                    }),
+                )
+            })?;
+            let days = source_date
+                .as_deref()
+                .and_then(|d| chrono::NaiveDate::parse_from_str(d.trim(), "%Y-%m-%d").ok())
+                .map_or(0, |source| (new_date - source).num_days());
+            (Some(date.to_string()), days)
         }
-    }
+    };
+    let end_date = shift_optional_date_text(source_end_date, days);
 
-    // 宿泊情報を参照する通知を先に削除
-    sqlx::query("DELETE FROM notifications WHERE schedule_id = ?")
-        .bind(id)
-        .execute(&mut *transaction)
-        .await
-        .map_err(|_| (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse { error: "関連通知の削除に失敗しました".to_string() }),
-        ))?;
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await.map_err(clone_failed)?;
+
+    let clone_id = clone_row(
+        &mut tx,
+        "schedules",
+        id as i64,
+        &[
+            ("date", serde_json::json!(date)),
+            ("end_date", serde_json::json!(end_date)),
+            ("status", serde_json::json!("Pending")),
+            ("public_id", serde_json::json!(generate_public_id())),
+        ],
+    )
+    .await
+    .map_err(clone_failed)?;
 
-    // 関連する交通・宿泊情報を同じトランザクションで削除
-    sqlx::query("DELETE FROM traffics WHERE schedule_id = ?")
+    // 複数日開催の日程
+    let day_rows: Vec<(i64, String)> = sqlx::query_as("SELECT id, date FROM schedule_days WHERE schedule_id = ?")
         .bind(id)
-        .execute(&mut *transaction)
+        .fetch_all(&mut *tx)
         .await
-        .map_err(|_| (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse { error: "関連する交通情報の削除に失敗しました".to_string() }),
-        ))?;
+        .map_err(clone_failed)?;
+    for (day_id, day_date) in day_rows {
+        let day_date = shift_date_text(&day_date, days).unwrap_or(day_date);
+        clone_row(
+            &mut tx,
+            "schedule_days",
+            day_id,
+            &[("schedule_id", serde_json::json!(clone_id)), ("date", serde_json::json!(day_date))],
+        )
+        .await
+        .map_err(clone_failed)?;
+    }
 
-    sqlx::query("DELETE FROM stays WHERE schedule_id = ?")
+    let mut traffic_ids = Vec::new();
+    if payload.include_traffics.unwrap_or(true) {
+        let rows: Vec<(i64, String)> = sqlx::query_as(
+            r#"SELECT id, date FROM traffics WHERE schedule_id = ? AND deleted_at IS NULL ORDER BY "order", id"#,
+        )
         .bind(id)
-        .execute(&mut *transaction)
+        .fetch_all(&mut *tx)
         .await
-        .map_err(|_| (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse { error: "関連する宿泊情報の削除に失敗しました".to_string() }),
-        ))?;
+        .map_err(clone_failed)?;
+        for (traffic_id, traffic_date) in rows {
+            let traffic_date = shift_date_text(&traffic_date, days).unwrap_or(traffic_date);
+            let new_id = clone_row(
+                &mut tx,
+                "traffics",
+                traffic_id,
+                &[
+                    ("schedule_id", serde_json::json!(clone_id)),
+                    ("date", serde_json::json!(traffic_date)),
+                    ("public_id", serde_json::json!(generate_public_id())),
+                ],
+            )
+            .await
+            .map_err(clone_failed)?;
+            traffic_ids.push(new_id);
+        }
+    }
 
-    // スケジュールを削除
-    let result = sqlx::query("DELETE FROM schedules WHERE id = ? AND user_id = ?")
+    let mut stay_ids = Vec::new();
+    if payload.include_stays.unwrap_or(true) {
+        let rows: Vec<(i64, String, String, Option<String>)> = sqlx::query_as(
+            "SELECT id, check_in, check_out, deadline FROM stays WHERE schedule_id = ? AND deleted_at IS NULL ORDER BY id",
+        )
         .bind(id)
-        .bind(user.user_id)
-        .execute(&mut *transaction)
+        .fetch_all(&mut *tx)
         .await
-        .map_err(|_| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "データベースエラーが発生しました".to_string(),
-                }),
+        .map_err(clone_failed)?;
+        for (stay_id, check_in, check_out, deadline) in rows {
+            let check_in = shift_date_text(&check_in, days).unwrap_or(check_in);
+            let check_out = shift_date_text(&check_out, days).unwrap_or(check_out);
+            let new_id = clone_row(
+                &mut tx,
+                "stays",
+                stay_id,
+                &[
+                    ("schedule_id", serde_json::json!(clone_id)),
+                    ("check_in", serde_json::json!(check_in)),
+                    ("check_out", serde_json::json!(check_out)),
+                    ("deadline", serde_json::json!(shift_optional_date_text(deadline, days))),
+                    ("public_id", serde_json::json!(generate_public_id())),
+                ],
             )
-        })?;
+            .await
+            .map_err(clone_failed)?;
+            stay_ids.push(new_id);
+        }
+    }
 
-    if result.rows_affected() == 0 {
-        return Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "スケジュールが見つかりませんでした".to_string(),
-            }),
-        ));
+    if payload.link_related.unwrap_or(false) {
+        let own_ids = sync_related_schedule_links(&mut tx, clone_id as i32, user.user_id, &[], &[id], &now)
+            .await
+            .map_err(clone_failed)?;
+        sqlx::query("UPDATE schedules SET related_schedule_ids = ? WHERE id = ?")
+            .bind(related_schedule_ids_json(&own_ids))
+            .bind(clone_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(clone_failed)?;
     }
 
-    transaction.commit().await.map_err(|_| (
-        StatusCode::INTERNAL_SERVER_ERROR,
-        Json(ErrorResponse { error: "削除処理の確定に失敗しました".to_string() }),
-    ))?;
+    tx.commit().await.map_err(clone_failed)?;
 
-    Ok(Json(serde_json::json!({
-        "success": true,
-        "message": "スケジュールを削除しました"
-    })))
-}
+    calculate_rollup(&pool, clone_id).await.ok();
+    refresh_search_index(&pool, clone_id).await;
+    track_revision(&pool, user.user_id, RevisionEntity::Schedule, clone_id, clone_id, RevisionAction::Create, None).await;
+    record_status_change(&pool, clone_id, user.user_id as i64, None, "Pending", StatusChangeSource::Manual).await;
+    for traffic_id in traffic_ids {
+        track_revision(&pool, user.user_id, RevisionEntity::Traffic, traffic_id, clone_id, RevisionAction::Create, None).await;
+    }
+    for stay_id in stay_ids {
+        track_revision(&pool, user.user_id, RevisionEntity::Stay, stay_id, clone_id, RevisionAction::Create, None).await;
+    }
 
-// GET /public/schedules - 公開されているスケジュール一覧
-// DISABLE_AUTHが設定されている場合、DEFAULT_USER_IDのスケジュールも返す
-async fn list_public_schedules(
-    Query(params): Query<ScheduleQuery>,
-    Extension(pool): Extension<Pool<Sqlite>>,
-) -> Json<Vec<PublicSchedule>> {
-    // DISABLE_AUTHが設定されている場合、全てのスケジュールを取得（ユーザーIDによるフィルタリングなし）
-    let rows: Vec<PublicScheduleRow> = if std::env::var("DISABLE_AUTH").is_ok() {
-        // 認証が無効化されている場合、全てのスケジュールを返す
-        println!("[PUBLIC SCHEDULES] DISABLE_AUTH is set, returning ALL schedules (ignoring user_id and is_public)");
-        let rows = sqlx::query_as::<_, PublicScheduleRow>(
-            r#"
-            SELECT
-              id,
-              public_id,
-              title,
-              "group",
-              date,
-              open,
-              start,
-              "end",
-              notes,
-              category,
-              area,
-              venue,
-              target,
-              lineup,
-              seller,
-              ticket_fee,
-              drink_fee,
-              total_fare,
-              stay_fee,
-              travel_cost,
-              total_cost,
-              status,
-              related_schedule_ids,
-              CAST(is_public AS INTEGER) as is_public
-            FROM schedules
-            "#,
-        )
-        .fetch_all(&pool)
+    let (schedule, etag) = fetch_schedule_with_etag(&pool, clone_id)
         .await
-        .expect("failed to fetch all schedules");
+        .map_err(clone_failed)?
+        .ok_or_else(database_error)?;
 
-        println!("[PUBLIC SCHEDULES] Found {} schedules in database", rows.len());
-        if rows.is_empty() {
-            println!("[PUBLIC SCHEDULES] WARNING: No schedules found in database. The database might be empty.");
-        } else {
-            println!("[PUBLIC SCHEDULES] First schedule: id={}, title={}",
-                rows[0].id,
-                rows[0].title
-            );
+    Ok((StatusCode::CREATED, etag_header(&etag), Json(schedule)))
+}
+
+// ====== 添付ファイル ======
+// 電子チケットのQRコード・宿泊予約の確認書・領収書・グッズの写真などを、スケジュール・交通・宿泊・グッズに紐づけて保存する。
+// ファイル本体はAttachmentStorageの実装に置き、DBにはメタデータと保存キーだけを持つ
+
+// ファイル本体の保存先。S3などに置く場合はこのトレイトを実装し、attachment_storage()で切り替える
+#[async_trait]
+trait AttachmentStorage: Send + Sync {
+    async fn put(&self, key: &str, data: &[u8]) -> std::io::Result<()>;
+    async fn get(&self, key: &str) -> std::io::Result<Vec<u8>>;
+    async fn delete(&self, key: &str) -> std::io::Result<()>;
+}
+
+// ローカルファイルシステムに保存する実装（キーはroot配下の相対パス）
+struct LocalAttachmentStorage {
+    root: std::path::PathBuf,
+}
+
+impl LocalAttachmentStorage {
+    fn path_for(&self, key: &str) -> std::io::Result<std::path::PathBuf> {
+        // キーはサーバー側で生成するが、念のためroot外を指すキーは拒否する
+        if key.split('/').any(|part| part.is_empty() || part == "." || part == ".." || part.contains('\\')) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid attachment key"));
         }
+        Ok(self.root.join(key))
+    }
+}
 
-        rows
-    } else {
-        // 通常の動作：公開スケジュールのみ
-        sqlx::query_as::<_, PublicScheduleRow>(
-            r#"
-            SELECT
-              id,
-              public_id,
-              title,
-              "group",
-              date,
-              open,
-              start,
-              "end",
-              notes,
-              category,
-              area,
-              venue,
-              target,
-              lineup,
-              seller,
-              ticket_fee,
-              drink_fee,
-              total_fare,
-              stay_fee,
-              travel_cost,
-              total_cost,
-              status,
-              related_schedule_ids,
-              CAST(is_public AS INTEGER) as is_public
-            FROM schedules
-            WHERE CAST(is_public AS INTEGER) = 1
-            "#,
-        )
-        .fetch_all(&pool)
-        .await
-        .expect("failed to fetch public schedules")
-    };
+#[async_trait]
+impl AttachmentStorage for LocalAttachmentStorage {
+    async fn put(&self, key: &str, data: &[u8]) -> std::io::Result<()> {
+        let path = self.path_for(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, data).await
+    }
 
-    let mut schedules = Vec::with_capacity(rows.len());
-    for row in rows {
-        schedules.push(row_to_public_schedule(&pool, row).await);
+    async fn get(&self, key: &str) -> std::io::Result<Vec<u8>> {
+        tokio::fs::read(self.path_for(key)?).await
     }
 
-    if let Some(year) = params.year {
-        schedules = schedules
-            .into_iter()
-            .filter(|s| s.datetime.year() == year)
-            .collect();
+    async fn delete(&self, key: &str) -> std::io::Result<()> {
+        // 既に存在しない場合は削除済みとして扱う
+        match tokio::fs::remove_file(self.path_for(key)?).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
     }
+}
 
-    if params.include_canceled != Some(true) {
-        schedules = schedules
-            .into_iter()
-            .filter(|s| s.status != "Canceled")
-            .collect();
+// 環境変数ATTACHMENT_STORAGEで選んだ保存先（現在はlocalのみ）
+fn attachment_storage() -> &'static dyn AttachmentStorage {
+    static STORAGE: std::sync::OnceLock<Box<dyn AttachmentStorage>> = std::sync::OnceLock::new();
+    STORAGE
+        .get_or_init(|| {
+            let kind = get_attachment_storage_kind();
+            if kind != "local" {
+                eprintln!("[Attachments] Unknown ATTACHMENT_STORAGE \"{}\", falling back to local", kind);
+            }
+            Box::new(LocalAttachmentStorage { root: std::path::PathBuf::from(get_attachment_dir()) })
+        })
+        .as_ref()
+}
+
+// DBの行を消した後にファイル本体を削除する（失敗してもログだけ残し、行の削除は取り消さない）
+async fn remove_attachment_files(storage_keys: &[String]) {
+    for key in storage_keys {
+        if let Err(e) = attachment_storage().delete(key).await {
+            eprintln!("[Attachments] Failed to delete stored file {}: {}", key, e);
+        }
     }
+}
 
-    Json(schedules)
+// 添付先（スケジュール・交通・宿泊・グッズ）がゴミ箱に入っていない添付ファイルだけに絞り込む条件（attachmentsの別名はa）
+const ATTACHMENT_ACTIVE_CONDITION: &str = r#"
+    EXISTS (SELECT 1 FROM schedules s WHERE s.id = a.schedule_id AND s.deleted_at IS NULL)
+    AND (
+      a.entity_type = 'schedule'
+      OR (a.entity_type = 'traffic' AND EXISTS (SELECT 1 FROM traffics t WHERE t.id = a.entity_id AND t.deleted_at IS NULL))
+      OR (a.entity_type = 'stay' AND EXISTS (SELECT 1 FROM stays st WHERE st.id = a.entity_id AND st.deleted_at IS NULL))
+      OR (a.entity_type = 'goods' AND EXISTS (SELECT 1 FROM goods g WHERE g.id = a.entity_id))
+    )
+"#;
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct Attachment {
+    id: i64,
+    public_id: String,
+    schedule_id: i64,
+    entity_type: String, // "schedule" / "traffic" / "stay" / "goods"
+    entity_id: i64,
+    file_name: String,
+    content_type: String,
+    size: i64,
+    shareable: bool, // trueの場合のみ共有ページから閲覧・ダウンロードできる
+    #[serde(skip_serializing)]
+    storage_key: String,
+    created_at: String,
 }
 
-// GET /public/schedules/:id - 公開されているスケジュール詳細
-// DISABLE_AUTHが設定されている場合、全てのスケジュールを返す
-async fn get_public_schedule(
-    Path(public_id): Path<String>,
-    Extension(pool): Extension<Pool<Sqlite>>,
-) -> Result<Json<PublicSchedule>, StatusCode> {
-    // DISABLE_AUTHが設定されている場合、is_publicの条件を無視
-    let row: PublicScheduleRow = if std::env::var("DISABLE_AUTH").is_ok() {
-        sqlx::query_as::<_, PublicScheduleRow>(
-            r#"
-            SELECT
-              id,
-              public_id,
-              title,
-              "group",
-              date,
-              open,
-              start,
-              "end",
-              notes,
-              category,
-              area,
-              venue,
-              target,
-              lineup,
-              seller,
-              ticket_fee,
-              drink_fee,
-              total_fare,
-              stay_fee,
-              travel_cost,
-              total_cost,
-              status,
-              related_schedule_ids,
-              is_public
-            FROM schedules
-            WHERE public_id = ?
-            "#,
-        )
-        .bind(&public_id)
-        .fetch_one(&pool)
-        .await
-        .map_err(|_| StatusCode::NOT_FOUND)?
-    } else {
-        sqlx::query_as::<_, PublicScheduleRow>(
-            r#"
-            SELECT
-              id,
-              public_id,
-              title,
-              "group",
-              date,
-              open,
-              start,
-              "end",
-              notes,
-              category,
-              area,
-              venue,
-              target,
-              lineup,
-              seller,
-              ticket_fee,
-              drink_fee,
-              total_fare,
-              stay_fee,
-              travel_cost,
-              total_cost,
-              status,
-              related_schedule_ids,
-              CAST(is_public AS INTEGER) as is_public
-            FROM schedules
-            WHERE public_id = ? AND CAST(is_public AS INTEGER) = 1
-            "#,
-        )
-        .bind(&public_id)
-        .fetch_one(&pool)
-        .await
-        .map_err(|_| StatusCode::NOT_FOUND)?
-    };
+// 共有ページ向けの添付ファイル（内部IDと保存キーは含めない）
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct SharedAttachment {
+    public_id: String,
+    entity_type: String,
+    entity_public_id: Option<String>,
+    file_name: String,
+    content_type: String,
+    size: i64,
+    created_at: String,
+}
 
-    let schedule = row_to_public_schedule(&pool, row).await;
-    Ok(Json(schedule))
+#[derive(Debug, Deserialize)]
+struct UpdateAttachmentRequest {
+    file_name: Option<String>,
+    shareable: Option<bool>,
 }
 
-// GET /public/traffic?schedule_id=... - 公開スケジュールの交通情報
-// DISABLE_AUTHが設定されている場合、全てのスケジュールの交通情報を返す
-async fn list_public_traffics(
-    Query(params): Query<PublicTrafficQuery>,
-    Extension(pool): Extension<Pool<Sqlite>>,
-) -> Json<Vec<PublicTraffic>> {
-    // DISABLE_AUTHが設定されている場合、is_publicの条件を無視
-    let schedule_exists: Option<i64> = if std::env::var("DISABLE_AUTH").is_ok() {
-        sqlx::query_scalar("SELECT id FROM schedules WHERE public_id = ?")
-            .bind(&params.schedule_id)
-            .fetch_optional(&pool)
-            .await
-            .expect("failed to check schedule")
+// クライアントから送られたファイル名からディレクトリ部分と制御文字を除き、長さを制限する
+fn sanitize_attachment_file_name(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or("");
+    let cleaned: String = base.chars().filter(|c| !c.is_control()).take(200).collect();
+    let cleaned = cleaned.trim();
+    if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+        "attachment".to_string()
     } else {
-        sqlx::query_scalar("SELECT id FROM schedules WHERE public_id = ? AND CAST(is_public AS INTEGER) = 1")
-            .bind(&params.schedule_id)
-            .fetch_optional(&pool)
-            .await
-            .expect("failed to check schedule")
-    };
+        cleaned.to_string()
+    }
+}
 
-    let Some(schedule_internal_id) = schedule_exists else {
-        return Json(vec![]);
+// Content-Typeを「type/subtype」の形に正規化する（不正な値はapplication/octet-stream）
+fn normalize_attachment_content_type(value: Option<&str>) -> String {
+    let Some(value) = value else {
+        return "application/octet-stream".to_string();
     };
+    let essence = value.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    let is_token = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$&-^_.+".contains(c))
+    };
+    match essence.split_once('/') {
+        Some((kind, subtype)) if is_token(kind) && is_token(subtype) => essence,
+        _ => "application/octet-stream".to_string(),
+    }
+}
 
-    let rows: Vec<PublicTrafficRow> = sqlx::query_as::<_, PublicTrafficRow>(
-        r#"
-        SELECT
-          public_id,
-          date,
-          "order",
-          transportation,
-          from_place,
-          to_place,
-          notes,
-          fare,
-          miles,
-          return_flag,
-          total_fare,
-          total_miles
-        FROM traffics
-        WHERE schedule_id = ?
-        ORDER BY date ASC, "order" ASC
-        "#,
-    )
-    .bind(schedule_internal_id)
-    .fetch_all(&pool)
-    .await
-    .expect("failed to fetch traffics");
-
-    let mut traffics: Vec<PublicTraffic> = rows
-        .into_iter()
-        .map(|row| row_to_public_traffic(row, &params.schedule_id))
+// ダウンロード用のContent-Disposition（日本語のファイル名はRFC 5987のfilename*で渡す）
+fn attachment_content_disposition(file_name: &str) -> String {
+    let fallback: String = file_name
+        .chars()
+        .map(|c| if c.is_ascii_graphic() && c != '"' && c != '\\' || c == ' ' { c } else { '_' })
         .collect();
+    let encoded: String = file_name
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, encoded)
+}
 
-    // スケジュールのユーザーIDを取得してマスク処理を適用
-    let user_id: Option<i64> = sqlx::query_scalar("SELECT user_id FROM schedules WHERE id = ?")
-        .bind(schedule_internal_id)
-        .fetch_optional(&pool)
-        .await
-        .ok()
-        .flatten();
-
-    if let Some(uid) = user_id {
-        if let Ok(masked_locations) = get_masked_locations_for_user(&pool, uid).await {
-            traffics = traffics.into_iter()
-                .map(|t| apply_mask_to_public_traffic(t, &masked_locations))
-                .collect();
-        }
+// エラーメッセージ用の容量表記（1MB未満はKBで表示する）
+fn attachment_size_label(bytes: i64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{}MB", bytes / (1024 * 1024))
+    } else {
+        format!("{}KB", bytes / 1024)
     }
+}
 
-    Json(traffics)
+// 既存の使用量に新しいファイルを加えると容量上限を超えるか
+fn attachment_quota_exceeded(used_bytes: i64, incoming_bytes: i64, quota_bytes: i64) -> bool {
+    used_bytes.saturating_add(incoming_bytes) > quota_bytes
 }
 
-// GET /public/stay?schedule_id=... - 公開スケジュールの宿泊情報
-// DISABLE_AUTHが設定されている場合、全てのスケジュールの宿泊情報を返す
-async fn list_public_stays(
-    Query(params): Query<PublicStayQuery>,
-    Extension(pool): Extension<Pool<Sqlite>>,
-) -> Json<Vec<PublicStay>> {
-    // DISABLE_AUTHが設定されている場合、is_publicの条件を無視
-    let schedule_exists: Option<i64> = if std::env::var("DISABLE_AUTH").is_ok() {
-        sqlx::query_scalar("SELECT id FROM schedules WHERE public_id = ?")
-            .bind(&params.schedule_id)
-            .fetch_optional(&pool)
-            .await
-            .expect("failed to check schedule")
-    } else {
-        sqlx::query_scalar("SELECT id FROM schedules WHERE public_id = ? AND CAST(is_public AS INTEGER) = 1")
-            .bind(&params.schedule_id)
-            .fetch_optional(&pool)
-            .await
-            .expect("failed to check schedule")
-    };
+#[cfg(test)]
+mod attachment_tests {
+    use super::*;
 
-    let Some(schedule_internal_id) = schedule_exists else {
-        return Json(vec![]);
-    };
+    #[test]
+    fn file_name_drops_directories_and_control_chars() {
+        assert_eq!(sanitize_attachment_file_name("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_attachment_file_name("C:\\Users\\me\\チケット.pdf"), "チケット.pdf");
+        assert_eq!(sanitize_attachment_file_name("a\nb\u{0}.png"), "ab.png");
+        assert_eq!(sanitize_attachment_file_name("  "), "attachment");
+        assert_eq!(sanitize_attachment_file_name("dir/.."), "attachment");
+    }
 
-    let rows: Vec<PublicStayRow> = sqlx::query_as::<_, PublicStayRow>(
-        r#"
-        SELECT
-          public_id,
-          check_in,
-          check_out,
-          hotel_name,
-          website,
-          fee,
-          breakfast_flag,
-          deadline,
-          penalty,
-          status
-        FROM stays
-        WHERE schedule_id = ?
-        "#,
-    )
-    .bind(schedule_internal_id)
-    .fetch_all(&pool)
-    .await
-    .expect("failed to fetch stays");
+    #[test]
+    fn content_type_is_normalized() {
+        assert_eq!(normalize_attachment_content_type(Some("Image/PNG")), "image/png");
+        assert_eq!(normalize_attachment_content_type(Some("application/pdf; charset=binary")), "application/pdf");
+        assert_eq!(normalize_attachment_content_type(Some("text/html\r\nX-Evil: 1")), "application/octet-stream");
+        assert_eq!(normalize_attachment_content_type(Some("pdf")), "application/octet-stream");
+        assert_eq!(normalize_attachment_content_type(None), "application/octet-stream");
+    }
 
-    let stays = rows
-        .into_iter()
-        .map(|row| row_to_public_stay(row, &params.schedule_id))
-        .collect();
-    Json(stays)
-}
+    #[test]
+    fn content_disposition_encodes_non_ascii_names() {
+        assert_eq!(
+            attachment_content_disposition("e ticket.pdf"),
+            "attachment; filename=\"e ticket.pdf\"; filename*=UTF-8''e%20ticket.pdf"
+        );
+        assert_eq!(
+            attachment_content_disposition("領収書\".pdf"),
+            "attachment; filename=\"____.pdf\"; filename*=UTF-8''%E9%A0%98%E5%8F%8E%E6%9B%B8%22.pdf"
+        );
+    }
 
-// GET /public/traffic/:id - 公開スケジュールの交通情報（個別）
-async fn get_public_traffic(
-    Path(public_id): Path<String>,
-    Extension(pool): Extension<Pool<Sqlite>>,
-) -> Result<Json<PublicTraffic>, StatusCode> {
-    let schedule_internal_id: Option<i64> = sqlx::query_scalar(
-        "SELECT schedule_id FROM traffics WHERE public_id = ?",
-    )
-    .bind(&public_id)
-    .fetch_optional(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    #[test]
+    fn size_label_switches_to_kb_below_one_mb() {
+        assert_eq!(attachment_size_label(10 * 1024 * 1024), "10MB");
+        assert_eq!(attachment_size_label(100_000), "97KB");
+    }
 
-    let schedule_internal_id = schedule_internal_id.ok_or(StatusCode::NOT_FOUND)?;
+    #[test]
+    fn quota_check_includes_incoming_file() {
+        assert!(!attachment_quota_exceeded(90, 10, 100));
+        assert!(attachment_quota_exceeded(90, 11, 100));
+        assert!(!attachment_quota_exceeded(0, 0, 0));
+    }
+}
 
-    let row: Option<PublicTrafficRow> = sqlx::query_as::<_, PublicTrafficRow>(
-        r#"
-        SELECT
-          public_id,
-          date,
-          "order",
-          transportation,
-          from_place,
-          to_place,
-          notes,
-          fare,
-          miles,
-          return_flag,
-          total_fare,
-          total_miles
-        FROM traffics
-        WHERE public_id = ?
-        "#,
+fn attachment_not_found() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "添付ファイルが見つかりませんでした".to_string(),
+        }),
     )
-    .bind(&public_id)
-    .fetch_optional(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+}
 
-    let row = row.ok_or(StatusCode::NOT_FOUND)?;
+fn attachment_bad_request(message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            error: message.to_string(),
+        }),
+    )
+}
 
-    // 関連するスケジュールが公開されているか確認
-    let schedule: Option<(i64, Option<String>)> = if std::env::var("DISABLE_AUTH").is_ok() {
-        sqlx::query_as("SELECT id, public_id FROM schedules WHERE id = ?")
-            .bind(schedule_internal_id)
-            .fetch_optional(&pool)
+// 添付先がログインユーザーの所有であることを確認し、保存するentity_typeと属するスケジュールのIDを返す
+// グッズは変更履歴の対象ではないため、RevisionEntityとは別に扱う
+async fn resolve_attachment_target(
+    pool: &Pool<Sqlite>,
+    user_id: i32,
+    entity_type: &str,
+    entity_id: i64,
+) -> Result<(&'static str, i64), (StatusCode, Json<ErrorResponse>)> {
+    if entity_type == "goods" {
+        let schedule_id: Option<i64> = sqlx::query_scalar("SELECT schedule_id FROM goods WHERE id = ?")
+            .bind(entity_id)
+            .fetch_optional(pool)
             .await
-            .expect("failed to check schedule")
-    } else {
-        sqlx::query_as("SELECT id, public_id FROM schedules WHERE id = ? AND CAST(is_public AS INTEGER) = 1")
-            .bind(schedule_internal_id)
-            .fetch_optional(&pool)
+            .map_err(|e| {
+                eprintln!("[Attachments] Failed to resolve goods {}: {}", entity_id, e);
+                database_error()
+            })?;
+        let schedule_id = schedule_id.ok_or_else(attachment_not_found)?;
+        ensure_schedule_owner(pool, schedule_id, user_id).await?;
+        return Ok(("goods", schedule_id));
+    }
+    let entity = RevisionEntity::parse(entity_type)
+        .ok_or_else(|| attachment_bad_request("entity_typeはschedule・traffic・stay・goodsのいずれかを指定してください"))?;
+    let schedule_id = match entity {
+        RevisionEntity::Schedule => entity_id,
+        RevisionEntity::Traffic | RevisionEntity::Stay => {
+            let schedule_id: Option<i64> = sqlx::query_scalar(&format!(
+                "SELECT schedule_id FROM {} WHERE id = ? AND deleted_at IS NULL",
+                entity.table()
+            ))
+            .bind(entity_id)
+            .fetch_optional(pool)
             .await
-            .expect("failed to check schedule")
+            .map_err(|e| {
+                eprintln!("[Attachments] Failed to resolve {} {}: {}", entity.name(), entity_id, e);
+                database_error()
+            })?;
+            schedule_id.ok_or_else(attachment_not_found)?
+        }
     };
+    ensure_schedule_owner(pool, schedule_id, user_id).await?;
+    Ok((entity.name(), schedule_id))
+}
 
-    let Some((_, Some(schedule_public_id))) = schedule else {
-        return Err(StatusCode::NOT_FOUND);
+// ログインユーザーの添付ファイルを取得する（添付先がゴミ箱にあるものは見つからない扱い）
+async fn fetch_owned_attachment(
+    pool: &Pool<Sqlite>,
+    id: i64,
+    user_id: i32,
+) -> Result<Attachment, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as::<_, Attachment>(&format!(
+        "SELECT a.* FROM attachments a WHERE a.id = ? AND a.user_id = ? AND {}",
+        ATTACHMENT_ACTIVE_CONDITION
+    ))
+    .bind(id)
+    .bind(user_id as i64)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[Attachments] Failed to fetch attachment {}: {}", id, e);
+        database_error()
+    })?
+    .ok_or_else(attachment_not_found)
+}
+
+// POST /attachments - 添付ファイルのアップロード（multipart/form-data）
+// フィールド: entity_type（schedule / traffic / stay / goods）、entity_id、shareable（任意、true/false）、file
+async fn upload_attachment(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    mut multipart: axum::extract::Multipart,
+) -> Result<(StatusCode, Json<Attachment>), (StatusCode, Json<ErrorResponse>)> {
+    let multipart_error = |e: axum::extract::multipart::MultipartError| {
+        let status = e.status();
+        eprintln!("[Attachments] Failed to read multipart body: {}", e);
+        let message = if status == StatusCode::PAYLOAD_TOO_LARGE {
+            format!(
+                "ファイルサイズが上限（{}）を超えています",
+                attachment_size_label(get_attachment_max_file_bytes() as i64)
+            )
+        } else {
+            "アップロードされたデータを読み取れませんでした".to_string()
+        };
+        (status, Json(ErrorResponse { error: message }))
     };
 
-    let mut traffic = row_to_public_traffic(row, &schedule_public_id);
+    let mut entity_type: Option<String> = None;
+    let mut entity_id: Option<String> = None;
+    let mut shareable: Option<String> = None;
+    let mut file: Option<(String, String, Bytes)> = None;
+    while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+        match field.name().unwrap_or("") {
+            "entity_type" => entity_type = Some(field.text().await.map_err(multipart_error)?),
+            "entity_id" => entity_id = Some(field.text().await.map_err(multipart_error)?),
+            "shareable" => shareable = Some(field.text().await.map_err(multipart_error)?),
+            "file" => {
+                let file_name = sanitize_attachment_file_name(field.file_name().unwrap_or(""));
+                let content_type = normalize_attachment_content_type(field.content_type());
+                let data = field.bytes().await.map_err(multipart_error)?;
+                file = Some((file_name, content_type, data));
+            }
+            _ => {}
+        }
+    }
+
+    let entity_type = entity_type
+        .as_deref()
+        .map(str::trim)
+        .ok_or_else(|| attachment_bad_request("entity_typeはschedule・traffic・stay・goodsのいずれかを指定してください"))?;
+    let entity_id = entity_id
+        .as_deref()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .ok_or_else(|| attachment_bad_request("entity_idを指定してください"))?;
+    let shareable = match shareable.as_deref().map(str::trim) {
+        None | Some("") | Some("false") | Some("0") => false,
+        Some("true") | Some("1") => true,
+        Some(_) => return Err(attachment_bad_request("shareableはtrueまたはfalseを指定してください")),
+    };
+    let (file_name, content_type, data) = file.ok_or_else(|| attachment_bad_request("fileを指定してください"))?;
+    if data.is_empty() {
+        return Err(attachment_bad_request("空のファイルはアップロードできません"));
+    }
+    if data.len() > get_attachment_max_file_bytes() {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(ErrorResponse {
+                error: format!(
+                    "ファイルサイズが上限（{}）を超えています",
+                    attachment_size_label(get_attachment_max_file_bytes() as i64)
+                ),
+            }),
+        ));
+    }
+
+    let (entity_type, schedule_id) = resolve_attachment_target(&pool, user.user_id, entity_type, entity_id).await?;
+
+    // 容量上限はプランごと（ゴミ箱にある添付先のファイルも完全削除されるまでは使用量に含める）
+    let (plan, trial_started_at) = fetch_user_plan(&pool, user.user_id).await.map_err(|e| {
+        eprintln!("[Attachments] Failed to fetch plan: {}", e);
+        database_error()
+    })?;
+    let paid = has_paid_access(&plan, trial_started_at.as_deref());
+    let quota = get_attachment_quota_bytes(paid);
+    let used: i64 = sqlx::query_scalar("SELECT COALESCE(SUM(size), 0) FROM attachments WHERE user_id = ?")
+        .bind(user.user_id as i64)
+        .fetch_one(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[Attachments] Failed to sum usage: {}", e);
+            database_error()
+        })?;
+    if attachment_quota_exceeded(used, data.len() as i64, quota) {
+        let message = if paid {
+            format!("添付ファイルの容量上限（{}）を超えています", attachment_size_label(quota))
+        } else {
+            format!(
+                "添付ファイルの容量上限（{}）を超えています。プレミアムプランでは{}まで保存できます",
+                attachment_size_label(quota),
+                attachment_size_label(get_attachment_quota_bytes(true))
+            )
+        };
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, Json(ErrorResponse { error: message })));
+    }
+
+    // 先にファイル本体を保存し、行の作成に失敗した場合は保存したファイルを消す
+    let storage_key = format!("{}/{}", user.user_id, generate_token());
+    attachment_storage().put(&storage_key, &data).await.map_err(|e| {
+        eprintln!("[Attachments] Failed to store file {}: {}", storage_key, e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "ファイルの保存に失敗しました".to_string(),
+            }),
+        )
+    })?;
 
-    // スケジュールのユーザーIDを取得してマスク処理を適用
-    let user_id: Option<i64> = sqlx::query_scalar("SELECT user_id FROM schedules WHERE id = ?")
-        .bind(schedule_internal_id)
-        .fetch_optional(&pool)
-        .await
-        .ok()
-        .flatten();
+    let now = Utc::now().to_rfc3339();
+    let inserted = sqlx::query_as::<_, Attachment>(
+        r#"
+        INSERT INTO attachments (public_id, user_id, schedule_id, entity_type, entity_id, file_name, content_type, size, shareable, storage_key, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        RETURNING id, public_id, schedule_id, entity_type, entity_id, file_name, content_type, size, shareable, storage_key, created_at
+        "#,
+    )
+    .bind(generate_public_id())
+    .bind(user.user_id as i64)
+    .bind(schedule_id)
+    .bind(entity_type)
+    .bind(entity_id)
+    .bind(&file_name)
+    .bind(&content_type)
+    .bind(data.len() as i64)
+    .bind(shareable)
+    .bind(&storage_key)
+    .bind(&now)
+    .fetch_one(&pool)
+    .await;
 
-    if let Some(uid) = user_id {
-        if let Ok(masked_locations) = get_masked_locations_for_user(&pool, uid).await {
-            traffic = apply_mask_to_public_traffic(traffic, &masked_locations);
+    match inserted {
+        Ok(attachment) => Ok((StatusCode::CREATED, Json(attachment))),
+        Err(e) => {
+            eprintln!("[Attachments] Failed to insert attachment: {}", e);
+            remove_attachment_files(&[storage_key]).await;
+            Err(database_error())
         }
     }
-
-    Ok(Json(traffic))
 }
 
-// GET /public/stay/:id - 公開スケジュールの宿泊情報（個別）
-async fn get_public_stay(
-    Path(public_id): Path<String>,
+// GET /schedules/:id/attachments - スケジュールとその交通・宿泊・グッズに添付されたファイルの一覧
+async fn list_schedule_attachments(
+    Path(id): Path<i64>,
+    user: AuthenticatedUser,
     Extension(pool): Extension<Pool<Sqlite>>,
-) -> Result<Json<PublicStay>, StatusCode> {
-    let schedule_internal_id: Option<i64> = sqlx::query_scalar(
-        "SELECT schedule_id FROM stays WHERE public_id = ?",
-    )
-    .bind(&public_id)
-    .fetch_optional(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let schedule_internal_id = schedule_internal_id.ok_or(StatusCode::NOT_FOUND)?;
+) -> Result<Json<Vec<Attachment>>, (StatusCode, Json<ErrorResponse>)> {
+    ensure_schedule_owner(&pool, id, user.user_id).await?;
 
-    let row: Option<PublicStayRow> = sqlx::query_as::<_, PublicStayRow>(
-        r#"
-        SELECT
-          public_id,
-          check_in,
-          check_out,
-          hotel_name,
-          website,
-          fee,
-          breakfast_flag,
-          deadline,
-          penalty,
-          status
-        FROM stays
-        WHERE public_id = ?
-        "#,
-    )
-    .bind(&public_id)
-    .fetch_optional(&pool)
+    let attachments = sqlx::query_as::<_, Attachment>(&format!(
+        "SELECT a.* FROM attachments a WHERE a.schedule_id = ? AND {} ORDER BY a.created_at ASC, a.id ASC",
+        ATTACHMENT_ACTIVE_CONDITION
+    ))
+    .bind(id)
+    .fetch_all(&pool)
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map_err(|e| {
+        eprintln!("[Attachments] Failed to list attachments for schedule {}: {}", id, e);
+        database_error()
+    })?;
+    Ok(Json(attachments))
+}
 
-    let row = row.ok_or(StatusCode::NOT_FOUND)?;
+// ファイル本体を読み出してダウンロード用のレスポンスにする
+async fn attachment_download_response(
+    attachment: &Attachment,
+) -> Result<(HeaderMap, Vec<u8>), (StatusCode, Json<ErrorResponse>)> {
+    let data = attachment_storage().get(&attachment.storage_key).await.map_err(|e| {
+        eprintln!("[Attachments] Failed to read stored file {}: {}", attachment.storage_key, e);
+        if e.kind() == std::io::ErrorKind::NotFound {
+            attachment_not_found()
+        } else {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse {
+                    error: "ファイルの読み込みに失敗しました".to_string(),
+                }),
+            )
+        }
+    })?;
 
-    // 関連するスケジュールが公開されているか確認
-    let schedule: Option<(i64, Option<String>)> = if std::env::var("DISABLE_AUTH").is_ok() {
-        sqlx::query_as("SELECT id, public_id FROM schedules WHERE id = ?")
-            .bind(schedule_internal_id)
-            .fetch_optional(&pool)
-            .await
-            .expect("failed to check schedule")
-    } else {
-        sqlx::query_as("SELECT id, public_id FROM schedules WHERE id = ? AND CAST(is_public AS INTEGER) = 1")
-            .bind(schedule_internal_id)
-            .fetch_optional(&pool)
-            .await
-            .expect("failed to check schedule")
-    };
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(&attachment.content_type) {
+        headers.insert(axum::http::header::CONTENT_TYPE, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&attachment_content_disposition(&attachment.file_name)) {
+        headers.insert(axum::http::header::CONTENT_DISPOSITION, value);
+    }
+    // アップロードされたHTMLなどをブラウザが解釈して実行しないようにする
+    headers.insert(axum::http::header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    Ok((headers, data))
+}
 
-    let Some((_, Some(schedule_public_id))) = schedule else {
-        return Err(StatusCode::NOT_FOUND);
-    };
+// GET /attachments/:id - 添付ファイルのダウンロード
+async fn download_attachment(
+    Path(id): Path<i64>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<(HeaderMap, Vec<u8>), (StatusCode, Json<ErrorResponse>)> {
+    let attachment = fetch_owned_attachment(&pool, id, user.user_id).await?;
+    attachment_download_response(&attachment).await
+}
 
-    Ok(Json(row_to_public_stay(row, &schedule_public_id)))
+// PUT /attachments/:id - ファイル名と共有可否の変更
+async fn update_attachment(
+    Path(id): Path<i64>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<UpdateAttachmentRequest>,
+) -> Result<Json<Attachment>, (StatusCode, Json<ErrorResponse>)> {
+    let mut attachment = fetch_owned_attachment(&pool, id, user.user_id).await?;
+    if let Some(file_name) = payload.file_name.as_deref() {
+        attachment.file_name = sanitize_attachment_file_name(file_name);
+    }
+    if let Some(shareable) = payload.shareable {
+        attachment.shareable = shareable;
+    }
+
+    sqlx::query("UPDATE attachments SET file_name = ?, shareable = ? WHERE id = ?")
+        .bind(&attachment.file_name)
+        .bind(attachment.shareable)
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[Attachments] Failed to update attachment {}: {}", id, e);
+            database_error()
+        })?;
+    Ok(Json(attachment))
 }
 
-// GET /share/:share_id/traffic/:id - 共有ページ用の交通情報（個別）
-async fn list_shared_traffics(
-    Path(share_id): Path<String>,
+// DELETE /attachments/:id - 添付ファイルの削除（ゴミ箱には入れず、ファイル本体もすぐに消す）
+async fn delete_attachment(
+    Path(id): Path<i64>,
+    user: AuthenticatedUser,
     Extension(pool): Extension<Pool<Sqlite>>,
-) -> Result<Json<Vec<PublicTraffic>>, (StatusCode, Json<ErrorResponse>)> {
-    let user_id = resolve_active_share_owner(&pool, &share_id).await?;
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let attachment = fetch_owned_attachment(&pool, id, user.user_id).await?;
+    sqlx::query("DELETE FROM attachments WHERE id = ?")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[Attachments] Failed to delete attachment {}: {}", id, e);
+            database_error()
+        })?;
+    remove_attachment_files(&[attachment.storage_key]).await;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
 
-    let rows: Vec<SharedTrafficRow> = sqlx::query_as::<_, SharedTrafficRow>(
-        r#"
-        SELECT t.public_id, t.date, t."order", t.transportation,
-               t.from_place, t.to_place, t.notes, t.fare, t.miles,
-               t.return_flag, t.total_fare, t.total_miles,
-               s.public_id AS schedule_public_id
-        FROM traffics t
-        INNER JOIN schedules s ON s.id = t.schedule_id
-        WHERE s.user_id = ? AND CAST(s.is_public AS INTEGER) = 1
-        ORDER BY t.date ASC, t."order" ASC
-        "#,
+// share_idから共有中のユーザーIDを取得する（共有が無効、またはプランが対象外の場合は403）
+async fn resolve_sharing_user(pool: &Pool<Sqlite>, share_id: &str) -> Result<i64, (StatusCode, Json<ErrorResponse>)> {
+    let user_row: Option<(i64, i32, String, Option<String>)> = sqlx::query_as(
+        "SELECT id, sharing_enabled, plan, trial_started_at FROM users WHERE share_id = ?"
     )
-    .bind(user_id)
-    .fetch_all(&pool)
+    .bind(share_id)
+    .fetch_optional(pool)
     .await
-    .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: "Database error".to_string() })))?;
+    .map_err(|e| {
+        eprintln!("[SharedAttachments] Database error: {}", e);
+        database_error()
+    })?;
 
-    let masked_locations = get_masked_locations_for_user(&pool, user_id).await.unwrap_or_default();
-    let traffics = rows.into_iter()
-        .map(shared_row_to_public_traffic)
-        .map(|traffic| apply_mask_to_public_traffic(traffic, &masked_locations))
-        .collect();
-    Ok(Json(traffics))
+    let Some((user_id, sharing_enabled, plan, trial_started_at)) = user_row else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "ユーザーが見つかりません".to_string(),
+            }),
+        ));
+    };
+    if sharing_enabled == 0 || !has_paid_access(&plan, trial_started_at.as_deref()) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse {
+                error: "このユーザーのスケジュールは共有されていません".to_string(),
+            }),
+        ));
+    }
+    Ok(user_id)
 }
 
-async fn get_shared_traffic(
+// GET /share/:share_id/schedules/:id/attachments - 共有ページ用の添付ファイル一覧
+// 公開スケジュールのうち、所有者が共有可（shareable）にしたファイルだけを返す
+async fn list_shared_attachments(
     Path((share_id, public_id)): Path<(String, String)>,
     Extension(pool): Extension<Pool<Sqlite>>,
-) -> Result<Json<PublicTraffic>, (StatusCode, Json<ErrorResponse>)> {
-    // share_idからユーザーIDを取得
-    let user_row: Option<(i64, i32, String, Option<String>)> = sqlx::query_as(
-        "SELECT id, sharing_enabled, plan, trial_started_at FROM users WHERE share_id = ?"
+) -> Result<Json<Vec<SharedAttachment>>, (StatusCode, Json<ErrorResponse>)> {
+    let user_id = resolve_sharing_user(&pool, &share_id).await?;
+
+    let schedule_id: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM schedules WHERE public_id = ? AND user_id = ? AND CAST(is_public AS INTEGER) = 1 AND deleted_at IS NULL",
     )
-    .bind(&share_id)
+    .bind(&public_id)
+    .bind(user_id)
     .fetch_optional(&pool)
     .await
     .map_err(|e| {
-        eprintln!("[GetSharedTraffic] Database error: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
+        eprintln!("[SharedAttachments] Failed to fetch schedule: {}", e);
+        database_error()
+    })?;
+    let Some(schedule_id) = schedule_id else {
+        return Err((
+            StatusCode::NOT_FOUND,
             Json(ErrorResponse {
-                error: "Database error".to_string(),
+                error: "スケジュールが見つかりません".to_string(),
             }),
-        )
+        ));
+    };
+
+    let attachments = sqlx::query_as::<_, SharedAttachment>(&format!(
+        r#"
+        SELECT
+          a.public_id,
+          a.entity_type,
+          CASE a.entity_type
+            WHEN 'schedule' THEN (SELECT public_id FROM schedules WHERE id = a.entity_id)
+            WHEN 'traffic' THEN (SELECT public_id FROM traffics WHERE id = a.entity_id)
+            WHEN 'stay' THEN (SELECT public_id FROM stays WHERE id = a.entity_id)
+          END AS entity_public_id,
+          a.file_name,
+          a.content_type,
+          a.size,
+          a.created_at
+        FROM attachments a
+        WHERE a.schedule_id = ? AND a.shareable = 1 AND {}
+        ORDER BY a.created_at ASC, a.id ASC
+        "#,
+        ATTACHMENT_ACTIVE_CONDITION
+    ))
+    .bind(schedule_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[SharedAttachments] Failed to list attachments: {}", e);
+        database_error()
     })?;
+    Ok(Json(attachments))
+}
 
-    if let Some((user_id, sharing_enabled, plan, trial_started_at)) = user_row {
-        if sharing_enabled == 0 || !has_paid_access(&plan, trial_started_at.as_deref()) {
-            return Err((
-                StatusCode::FORBIDDEN,
-                Json(ErrorResponse {
-                    error: "このユーザーのスケジュールは共有されていません".to_string(),
-                }),
-            ));
-        }
+// GET /share/:share_id/attachments/:id - 共有ページ用の添付ファイルダウンロード（idは添付ファイルのpublic_id）
+async fn download_shared_attachment(
+    Path((share_id, public_id)): Path<(String, String)>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<(HeaderMap, Vec<u8>), (StatusCode, Json<ErrorResponse>)> {
+    let user_id = resolve_sharing_user(&pool, &share_id).await?;
 
-        // 交通情報を取得（このユーザーの公開スケジュールに属するもののみ）
-        let row: Option<SharedTrafficRow> = sqlx::query_as::<_, SharedTrafficRow>(
-            r#"
-            SELECT t.public_id, t.date, t."order", t.transportation,
-                   t.from_place, t.to_place, t.notes, t.fare, t.miles,
-                   t.return_flag, t.total_fare, t.total_miles,
-                   s.public_id AS schedule_public_id
-            FROM traffics t
-            INNER JOIN schedules s ON s.id = t.schedule_id
-            WHERE t.public_id = ? AND s.user_id = ? AND CAST(s.is_public AS INTEGER) = 1
-            "#,
-        )
-        .bind(&public_id)
-        .bind(user_id)
-        .fetch_optional(&pool)
-        .await
-        .map_err(|e| {
-            eprintln!("[GetSharedTraffic] Database error: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Database error".to_string(),
-                }),
-            )
-        })?;
+    let attachment = sqlx::query_as::<_, Attachment>(&format!(
+        r#"
+        SELECT a.* FROM attachments a
+        INNER JOIN schedules sc ON sc.id = a.schedule_id
+        WHERE a.public_id = ? AND a.user_id = ? AND a.shareable = 1 AND CAST(sc.is_public AS INTEGER) = 1 AND {}
+        "#,
+        ATTACHMENT_ACTIVE_CONDITION
+    ))
+    .bind(&public_id)
+    .bind(user_id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| {
+        eprintln!("[SharedAttachments] Failed to fetch attachment: {}", e);
+        database_error()
+    })?
+    .ok_or_else(attachment_not_found)?;
+    attachment_download_response(&attachment).await
+}
 
-        let row = row.ok_or((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "Traffic not found".to_string(),
-            }),
-        ))?;
+// ====== 通貨・為替レート ======
+// 海外公演などで現地通貨で支払った費用は、元の通貨・金額のまま保存し、ロールアップ（total_fare〜my_share）だけを
+// ユーザーの基準通貨（users.home_currency）に換算する。為替レートはユーザーが登録したもの（exchange_rates）を使い、外部からは取得しない
+// 換算・ロールアップ計算の本体はライブラリ（rollup.rs）にある
 
-        let mut traffic = shared_row_to_public_traffic(row);
+// 通貨コード（ISO 4217の英字3文字）を正規化する。全角・小文字も受け付ける
+fn normalize_currency_code(value: &str) -> Option<String> {
+    use unicode_normalization::UnicodeNormalization;
+    let code = value.trim().nfkc().collect::<String>().to_ascii_uppercase();
+    (code.len() == 3 && code.chars().all(|c| c.is_ascii_uppercase())).then_some(code)
+}
 
-        // マスク処理を適用
-        if let Ok(masked_locations) = get_masked_locations_for_user(&pool, user_id).await {
-            traffic = apply_mask_to_public_traffic(traffic, &masked_locations);
+#[cfg(test)]
+mod currency_tests {
+    use super::*;
+
+    fn rates(home: &str, pairs: &[(&str, &str, f64)]) -> ExchangeRates {
+        ExchangeRates {
+            home_currency: home.to_string(),
+            rates: pairs
+                .iter()
+                .map(|(base, quote, rate)| ((base.to_string(), quote.to_string()), *rate))
+                .collect(),
         }
+    }
 
-        Ok(Json(traffic))
-    } else {
-        Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "User not found".to_string(),
-            }),
-        ))
+    #[test]
+    fn normalizes_currency_codes() {
+        assert_eq!(normalize_currency_code(" krw "), Some("KRW".to_string()));
+        assert_eq!(normalize_currency_code("ＴＷＤ"), Some("TWD".to_string()));
+        assert_eq!(normalize_currency_code("YEN1"), None);
+        assert_eq!(normalize_currency_code("円"), None);
+    }
+
+    #[test]
+    fn converts_with_direct_or_inverse_rate() {
+        let rates = rates("JPY", &[("KRW", "JPY", 0.11), ("JPY", "TWD", 0.2)]);
+        assert_eq!(rates.to_home(12000, "JPY"), 12000);
+        assert_eq!(rates.to_home(100000, "KRW"), 11000);
+        assert_eq!(rates.to_home(1500, "TWD"), 7500);
+        // 小数点以下は四捨五入
+        assert_eq!(rates.to_home(5, "KRW"), 1);
+    }
+
+    #[test]
+    fn reports_currencies_without_rate() {
+        let rates = rates("JPY", &[("KRW", "JPY", 0.11)]);
+        let currencies = vec!["JPY".to_string(), "KRW".to_string(), "USD".to_string()];
+        assert_eq!(rates.unconvertible(&currencies), vec!["USD".to_string()]);
+        assert_eq!(rates.to_home(10, "USD"), 10);
     }
 }
 
-// GET /share/:share_id/stay/:id - 共有ページ用の宿泊情報（個別）
-async fn get_shared_stay(
-    Path((share_id, public_id)): Path<(String, String)>,
-    Extension(pool): Extension<Pool<Sqlite>>,
-) -> Result<Json<PublicStay>, (StatusCode, Json<ErrorResponse>)> {
-    // share_idからユーザーIDを取得
-    let user_row: Option<(i64, i32, String, Option<String>)> = sqlx::query_as(
-        "SELECT id, sharing_enabled, plan, trial_started_at FROM users WHERE share_id = ?"
+// スケジュール・チケット申込・交通・宿泊・譲渡・グッズ・諸経費で使われている通貨（ゴミ箱にあるものも含む）
+async fn currencies_in_use(pool: &Pool<Sqlite>, user_id: i64) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT ticket_fee_currency FROM schedules WHERE user_id = ?
+        UNION SELECT drink_fee_currency FROM schedules WHERE user_id = ?
+        UNION SELECT ta.currency FROM ticket_applications ta INNER JOIN schedules s ON s.id = ta.schedule_id WHERE s.user_id = ?
+        UNION SELECT t.fare_currency FROM traffics t INNER JOIN schedules s ON s.id = t.schedule_id WHERE s.user_id = ?
+        UNION SELECT st.fee_currency FROM stays st INNER JOIN schedules s ON s.id = st.schedule_id WHERE s.user_id = ?
+        UNION SELECT tt.currency FROM ticket_transfers tt INNER JOIN schedules s ON s.id = tt.schedule_id WHERE s.user_id = ?
+        UNION SELECT g.currency FROM goods g INNER JOIN schedules s ON s.id = g.schedule_id WHERE s.user_id = ?
+        UNION SELECT e.currency FROM expenses e INNER JOIN schedules s ON s.id = e.schedule_id WHERE s.user_id = ?
+        "#,
     )
-    .bind(&share_id)
-    .fetch_optional(&pool)
+    .bind(user_id)
+    .bind(user_id)
+    .bind(user_id)
+    .bind(user_id)
+    .bind(user_id)
+    .bind(user_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_all(pool)
     .await
-    .map_err(|e| {
-        eprintln!("[GetSharedStay] Database error: {}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Database error".to_string(),
-            }),
-        )
+}
+
+// 費用の通貨を検証する（省略時はNone）。基準通貨に換算できない通貨は400
+async fn resolve_currency(
+    pool: &Pool<Sqlite>,
+    user_id: i32,
+    value: Option<&str>,
+) -> Result<Option<String>, (StatusCode, Json<ErrorResponse>)> {
+    let Some(value) = value else { return Ok(None) };
+    let currency = normalize_currency_code(value).ok_or_else(|| currency_bad_request("通貨はISO 4217の通貨コード（例: JPY, KRW）で指定してください"))?;
+    let rates = fetch_exchange_rates(pool, user_id as i64).await.map_err(|e| {
+        eprintln!("[Currency] Failed to fetch exchange rates: {}", e);
+        database_error()
     })?;
+    if rates.rate_to_home(&currency).is_none() {
+        return Err(currency_bad_request(&format!(
+            "{}から基準通貨{}への為替レートが登録されていません",
+            currency, rates.home_currency
+        )));
+    }
+    Ok(Some(currency))
+}
 
-    if let Some((user_id, sharing_enabled, plan, trial_started_at)) = user_row {
-        if sharing_enabled == 0 || !has_paid_access(&plan, trial_started_at.as_deref()) {
-            return Err((
-                StatusCode::FORBIDDEN,
-                Json(ErrorResponse {
-                    error: "このユーザーのスケジュールは共有されていません".to_string(),
-                }),
-            ));
-        }
+// 基準通貨で保存する費用の既定値（新規作成で通貨を省略した場合）
+async fn fetch_user_home_currency(pool: &Pool<Sqlite>, user_id: i32) -> Result<String, sqlx::Error> {
+    let home_currency: Option<String> = sqlx::query_scalar("SELECT home_currency FROM users WHERE id = ?")
+        .bind(user_id as i64)
+        .fetch_optional(pool)
+        .await?;
+    Ok(home_currency.unwrap_or_else(|| DEFAULT_CURRENCY.to_string()))
+}
 
-        // 宿泊情報を取得（このユーザーの公開スケジュールに属するもののみ）
-        let row: Option<SharedStayRow> = sqlx::query_as::<_, SharedStayRow>(
-            r#"
-            SELECT st.public_id, st.check_in, st.check_out, st.hotel_name,
-                   st.website, st.fee, st.breakfast_flag, st.deadline,
-                   st.penalty, st.status,
-                   s.public_id AS schedule_public_id
-            FROM stays st
-            INNER JOIN schedules s ON s.id = st.schedule_id
-            WHERE st.public_id = ? AND s.user_id = ? AND CAST(s.is_public AS INTEGER) = 1
-            "#,
-        )
-        .bind(&public_id)
+// レート・基準通貨の変更後に、ユーザーのすべてのスケジュール（ゴミ箱にあるものも含む）のロールアップを再計算する
+async fn recalculate_user_rollups(pool: &Pool<Sqlite>, user_id: i64) -> Result<(), sqlx::Error> {
+    let schedule_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM schedules WHERE user_id = ?")
         .bind(user_id)
-        .fetch_optional(&pool)
-        .await
-        .map_err(|e| {
-            eprintln!("[GetSharedStay] Database error: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: "Database error".to_string(),
-                }),
-            )
-        })?;
+        .fetch_all(pool)
+        .await?;
+    for schedule_id in schedule_ids {
+        calculate_rollup(pool, schedule_id).await?;
+    }
+    Ok(())
+}
 
-        let row = row.ok_or((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "Stay not found".to_string(),
-            }),
-        ))?;
+fn currency_bad_request(message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            error: message.to_string(),
+        }),
+    )
+}
 
-        Ok(Json(shared_row_to_public_stay(row)))
-    } else {
-        Err((
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "User not found".to_string(),
-            }),
-        ))
+// 基準通貨に換算できなくなる通貨がある場合の409
+fn unconvertible_currencies_conflict(currencies: &[String], home_currency: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::CONFLICT,
+        Json(ErrorResponse {
+            error: format!(
+                "{}の費用を基準通貨{}に換算できなくなります。先に為替レートを登録してください",
+                currencies.join(", "),
+                home_currency
+            ),
+        }),
+    )
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct ExchangeRate {
+    base_currency: String,
+    quote_currency: String,
+    rate: f64, // 1 base_currency = rate quote_currency
+    updated_at: String,
+}
+
+#[derive(Serialize)]
+struct ExchangeRateList {
+    home_currency: String,
+    rates: Vec<ExchangeRate>,
+}
+
+#[derive(Deserialize)]
+struct UpdateExchangeRateRequest {
+    rate: f64,
+}
+
+fn parse_currency_pair(base: &str, quote: &str) -> Result<(String, String), (StatusCode, Json<ErrorResponse>)> {
+    let (Some(base), Some(quote)) = (normalize_currency_code(base), normalize_currency_code(quote)) else {
+        return Err(currency_bad_request("通貨はISO 4217の通貨コード（例: JPY, KRW）で指定してください"));
+    };
+    if base == quote {
+        return Err(currency_bad_request("同じ通貨同士のレートは登録できません"));
     }
+    Ok((base, quote))
 }
 
-// GET /traffic?schedule_id=...
-async fn list_traffics(
+// GET /exchange-rates - 基準通貨と登録済みの為替レート
+async fn list_exchange_rates(
     user: AuthenticatedUser,
-    Query(params): Query<TrafficQuery>,
     Extension(pool): Extension<Pool<Sqlite>>,
-) -> Json<Vec<Traffic>> {
-    let rows: Vec<TrafficRow> = sqlx::query_as::<_, TrafficRow>(
-        r#"
-        SELECT
-          t.id,
-          t.schedule_id,
-          t.date,
-          t."order",
-          t.transportation,
-          t.from_place,
-          t.to_place,
-          t.notes,
-          t.fare,
-          t.miles,
-          t.return_flag,
-          t.total_fare,
-          t.total_miles
-        FROM traffics t
-        INNER JOIN schedules s ON t.schedule_id = s.id
-        WHERE t.schedule_id = ? AND s.user_id = ?
-        ORDER BY t."order" ASC
-        "#,
+) -> Result<Json<ExchangeRateList>, (StatusCode, Json<ErrorResponse>)> {
+    let failed = |e: sqlx::Error| {
+        eprintln!("[ExchangeRates] Failed to list exchange rates: {}", e);
+        database_error()
+    };
+    let home_currency = fetch_user_home_currency(&pool, user.user_id).await.map_err(failed)?;
+    let rates = sqlx::query_as::<_, ExchangeRate>(
+        "SELECT base_currency, quote_currency, rate, updated_at FROM exchange_rates WHERE user_id = ? ORDER BY base_currency ASC, quote_currency ASC",
     )
-    .bind(params.schedule_id)
     .bind(user.user_id as i64)
     .fetch_all(&pool)
     .await
-    .expect("failed to fetch traffics");
-
-    let traffics = rows.into_iter().map(row_to_traffic).collect();
-    Json(traffics)
+    .map_err(failed)?;
+    Ok(Json(ExchangeRateList { home_currency, rates }))
 }
 
-// GET /traffic/:id
-async fn get_traffic(
-    Path(id): Path<i32>,
+// PUT /exchange-rates/:base/:quote - 為替レートの登録・更新（1 base = rate quote）
+async fn save_exchange_rate(
+    Path((base, quote)): Path<(String, String)>,
     user: AuthenticatedUser,
     Extension(pool): Extension<Pool<Sqlite>>,
-) -> Result<Json<Traffic>, StatusCode> {
-    let row: Option<TrafficRow> = sqlx::query_as::<_, TrafficRow>(
+    Json(payload): Json<UpdateExchangeRateRequest>,
+) -> Result<Json<ExchangeRate>, (StatusCode, Json<ErrorResponse>)> {
+    let (base, quote) = parse_currency_pair(&base, &quote)?;
+    if !payload.rate.is_finite() || payload.rate <= 0.0 {
+        return Err(currency_bad_request("レートは0より大きい数値で指定してください"));
+    }
+    let failed = |e: sqlx::Error| {
+        eprintln!("[ExchangeRates] Failed to save exchange rate: {}", e);
+        database_error()
+    };
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
         r#"
-        SELECT
-          id,
-          schedule_id,
-          date,
-          "order",
-          transportation,
-          from_place,
-          to_place,
-          notes,
-          fare,
-          miles,
-          return_flag,
-          total_fare,
-          total_miles
-        FROM traffics
-        WHERE id = ?
+        INSERT INTO exchange_rates (user_id, base_currency, quote_currency, rate, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT(user_id, base_currency, quote_currency) DO UPDATE SET rate = excluded.rate, updated_at = excluded.updated_at
         "#,
     )
-    .bind(id)
-    .fetch_optional(&pool)
+    .bind(user.user_id as i64)
+    .bind(&base)
+    .bind(&quote)
+    .bind(payload.rate)
+    .bind(&now)
+    .bind(&now)
+    .execute(&pool)
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map_err(failed)?;
+    recalculate_user_rollups(&pool, user.user_id as i64).await.map_err(failed)?;
+
+    Ok(Json(ExchangeRate {
+        base_currency: base,
+        quote_currency: quote,
+        rate: payload.rate,
+        updated_at: now,
+    }))
+}
 
-    let row = row.ok_or(StatusCode::NOT_FOUND)?;
+// DELETE /exchange-rates/:base/:quote - 為替レートの削除（使用中の通貨が換算できなくなる場合は409）
+async fn delete_exchange_rate(
+    Path((base, quote)): Path<(String, String)>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let (base, quote) = parse_currency_pair(&base, &quote)?;
+    let failed = |e: sqlx::Error| {
+        eprintln!("[ExchangeRates] Failed to delete exchange rate: {}", e);
+        database_error()
+    };
+    let mut rates = fetch_exchange_rates(&pool, user.user_id as i64).await.map_err(failed)?;
+    if rates.rates.remove(&(base.clone(), quote.clone())).is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "為替レートが見つかりませんでした".to_string(),
+            }),
+        ));
+    }
+    let in_use = currencies_in_use(&pool, user.user_id as i64).await.map_err(failed)?;
+    let unconvertible = rates.unconvertible(&in_use);
+    if !unconvertible.is_empty() {
+        return Err(unconvertible_currencies_conflict(&unconvertible, &rates.home_currency));
+    }
 
-    // スケジュールの所有者を確認（スケジュールが存在しない場合も含め、
-    // 所有者が確認できなければアクセスを拒否する）
-    let schedule_user_id: Option<i64> = sqlx::query_scalar(
-        "SELECT user_id FROM schedules WHERE id = ?",
+    sqlx::query("DELETE FROM exchange_rates WHERE user_id = ? AND base_currency = ? AND quote_currency = ?")
+        .bind(user.user_id as i64)
+        .bind(&base)
+        .bind(&quote)
+        .execute(&pool)
+        .await
+        .map_err(failed)?;
+    recalculate_user_rollups(&pool, user.user_id as i64).await.map_err(failed)?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+// ====== 同行者・割り勘 ======
+// 友人と一緒に行く遠征で、チケット代・交通費・宿泊費を誰がいくら負担するかを記録する。
+// 同行者はユーザーごとのアドレス帳（companions）に登録し、スケジュール・交通・宿泊ごとに割り勘の設定（cost_splits）を持つ。
+// メンバーのcompanion_idがNULLの行は自分を表す
+
+const COST_SPLIT_MAX_MEMBERS: usize = 20;
+
+// 割り勘ごとの「支払った人」と「負担額」から、人ごとの収支（プラスは受け取る側）と、
+// 2人の間で相殺した貸し借り（(借りている人, 貸している人) → 金額）を求める
+type BalanceDebts = std::collections::BTreeMap<(Option<i64>, Option<i64>), i64>;
+
+fn settle_balances(
+    entries: &[(Option<i64>, SplitShares)],
+) -> (std::collections::BTreeMap<Option<i64>, i64>, BalanceDebts) {
+    let mut net: std::collections::BTreeMap<Option<i64>, i64> = std::collections::BTreeMap::new();
+    let mut owed: BalanceDebts = std::collections::BTreeMap::new();
+    for (payer, shares) in entries {
+        for (person, share) in shares {
+            if person == payer || *share == 0 {
+                continue;
+            }
+            *net.entry(*person).or_default() -= share;
+            *net.entry(*payer).or_default() += share;
+            *owed.entry((*person, *payer)).or_default() += share;
+        }
+    }
+
+    let mut debts: BalanceDebts = std::collections::BTreeMap::new();
+    for (&(from, to), &amount) in &owed {
+        let reverse = owed.get(&(to, from)).copied().unwrap_or(0);
+        if amount > reverse {
+            debts.insert((from, to), amount - reverse);
+        }
+    }
+    net.retain(|_, amount| *amount != 0);
+    (net, debts)
+}
+
+#[cfg(test)]
+mod cost_split_tests {
+    use super::*;
+    use live_schedule_api::rollup::SplitParticipant;
+
+    fn member(companion_id: Option<i64>, weight: i64, fixed_amount: Option<i64>) -> SplitParticipant {
+        SplitParticipant { companion_id, weight, fixed_amount }
+    }
+
+    #[test]
+    fn equal_split_hands_out_remainder_in_order() {
+        let members = [member(None, 1, None), member(Some(1), 1, None), member(Some(2), 1, None)];
+        assert_eq!(
+            split_cost(10000, SplitRule::Equal, &members, None),
+            vec![(None, 3334), (Some(1), 3333), (Some(2), 3333)]
+        );
+    }
+
+    #[test]
+    fn share_split_is_proportional_and_sums_to_amount() {
+        let members = [member(None, 2, None), member(Some(1), 1, None)];
+        assert_eq!(split_cost(1000, SplitRule::Share, &members, None), vec![(None, 667), (Some(1), 333)]);
+        let members = [member(None, 1, None), member(Some(1), 1, None), member(Some(2), 1, None)];
+        let shares = split_cost(100, SplitRule::Share, &members, None);
+        assert_eq!(shares.iter().map(|(_, s)| s).sum::<i64>(), 100);
+    }
+
+    #[test]
+    fn fixed_split_leaves_difference_to_payer() {
+        let members = [member(None, 1, None), member(Some(1), 1, Some(3000))];
+        assert_eq!(split_cost(8000, SplitRule::Fixed, &members, None), vec![(None, 5000), (Some(1), 3000)]);
+        // 友人が立て替え、自分は固定額だけ負担する
+        let members = [member(None, 1, Some(2000))];
+        assert_eq!(split_cost(5000, SplitRule::Fixed, &members, Some(1)), vec![(None, 2000), (Some(1), 3000)]);
+    }
+
+    #[test]
+    fn balances_are_netted_between_pairs() {
+        let entries = vec![
+            // 自分が払ったホテル代を友人1と折半
+            (None, vec![(None, 5000), (Some(1), 5000)]),
+            // 友人1が払った交通費を3人で割る
+            (Some(1), vec![(None, 1000), (Some(1), 1000), (Some(2), 1000)]),
+        ];
+        let (net, debts) = settle_balances(&entries);
+        assert_eq!(net.get(&None), Some(&4000));
+        assert_eq!(net.get(&Some(1)), Some(&-3000));
+        assert_eq!(net.get(&Some(2)), Some(&-1000));
+        assert_eq!(debts.get(&(Some(1), None)), Some(&4000));
+        assert_eq!(debts.get(&(None, Some(1))), None);
+        assert_eq!(debts.get(&(Some(2), Some(1))), Some(&1000));
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct Companion {
+    id: i64,
+    name: String,
+    linked_share_id: Option<String>,     // 登録ユーザーと紐づけている場合、そのユーザーのshare_id
+    linked_display_name: Option<String>, // 同、表示名
+    created_at: String,
+    updated_at: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NewCompanion {
+    name: String,
+    share_id: Option<String>, // 登録ユーザーと紐づける場合のshare_id（共有を有効にしているユーザーのみ。空文字で解除）
+}
+
+const COMPANION_SELECT: &str = r#"
+    SELECT c.id, c.name, u.share_id AS linked_share_id, u.display_name AS linked_display_name, c.created_at, c.updated_at
+    FROM companions c
+    LEFT JOIN users u ON u.id = c.linked_user_id
+"#;
+
+fn companion_not_found() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "同行者が見つかりませんでした".to_string(),
+        }),
     )
-    .bind(row.schedule_id)
-    .fetch_optional(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+}
+
+fn cost_split_bad_request(message: &str) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse {
+            error: message.to_string(),
+        }),
+    )
+}
+
+// share_idから紐づけ先のユーザーIDを解決する（共有を有効にしていないユーザーとは紐づけない）
+async fn resolve_companion_link(
+    pool: &Pool<Sqlite>,
+    user_id: i32,
+    share_id: Option<&str>,
+) -> Result<Option<i64>, (StatusCode, Json<ErrorResponse>)> {
+    let Some(share_id) = share_id.map(str::trim).filter(|s| !s.is_empty()) else {
+        return Ok(None);
+    };
+    let linked: Option<i64> = sqlx::query_scalar("SELECT id FROM users WHERE share_id = ? AND sharing_enabled = 1")
+        .bind(share_id)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[Companions] Failed to resolve share_id: {}", e);
+            database_error()
+        })?;
+    match linked {
+        Some(id) if id == user_id as i64 => Err(cost_split_bad_request("自分自身は同行者として紐づけられません")),
+        Some(id) => Ok(Some(id)),
+        None => Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse {
+                error: "紐づけるユーザーが見つかりませんでした".to_string(),
+            }),
+        )),
+    }
+}
 
-    match schedule_user_id {
-        Some(schedule_user_id) if schedule_user_id == user.user_id as i64 => {}
-        _ => return Err(StatusCode::FORBIDDEN),
+fn validate_companion_name(name: &str) -> Result<String, (StatusCode, Json<ErrorResponse>)> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 50 {
+        return Err(cost_split_bad_request("同行者の名前は1〜50文字で入力してください"));
     }
+    Ok(name.to_string())
+}
 
-    Ok(Json(row_to_traffic(row)))
+async fn fetch_companion(pool: &Pool<Sqlite>, id: i64, user_id: i32) -> Result<Companion, (StatusCode, Json<ErrorResponse>)> {
+    sqlx::query_as::<_, Companion>(&format!("{} WHERE c.id = ? AND c.user_id = ?", COMPANION_SELECT))
+        .bind(id)
+        .bind(user_id as i64)
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[Companions] Failed to fetch companion {}: {}", id, e);
+            database_error()
+        })?
+        .ok_or_else(companion_not_found)
 }
 
-// POST /traffic
-async fn create_traffic(
+// GET /companions - 同行者の一覧（名前順）
+async fn list_companions(
     user: AuthenticatedUser,
     Extension(pool): Extension<Pool<Sqlite>>,
-    Json(payload): Json<NewTraffic>,
-) -> Result<(StatusCode, Json<Traffic>), StatusCode> {
-    // スケジュールの所有者を確認
-    let schedule_user_id: Option<i64> = sqlx::query_scalar(
-        "SELECT user_id FROM schedules WHERE id = ?",
-    )
-    .bind(payload.schedule_id as i64)
-    .fetch_optional(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    if let Some(schedule_user_id) = schedule_user_id {
-        if schedule_user_id != user.user_id as i64 {
-            return Err(StatusCode::FORBIDDEN);
-        }
-    } else {
-        // スケジュールが存在しない場合
-        return Err(StatusCode::NOT_FOUND);
-    }
+) -> Result<Json<Vec<Companion>>, (StatusCode, Json<ErrorResponse>)> {
+    let companions = sqlx::query_as::<_, Companion>(&format!("{} WHERE c.user_id = ? ORDER BY c.name ASC, c.id ASC", COMPANION_SELECT))
+        .bind(user.user_id as i64)
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[Companions] Failed to list companions: {}", e);
+            database_error()
+        })?;
+    Ok(Json(companions))
+}
 
+// POST /companions - 同行者の登録
+async fn create_companion(
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewCompanion>,
+) -> Result<(StatusCode, Json<Companion>), (StatusCode, Json<ErrorResponse>)> {
+    let name = validate_companion_name(&payload.name)?;
+    let linked_user_id = resolve_companion_link(&pool, user.user_id, payload.share_id.as_deref()).await?;
     let now = Utc::now().to_rfc3339();
-    let result = sqlx::query(
-        r#"
-        INSERT INTO traffics (
-          schedule_id,
-          date,
-          "order",
-          transportation,
-          from_place,
-          to_place,
-          notes,
-          fare,
-          miles,
-          return_flag,
-          total_fare,
-          total_miles,
-          public_id,
-          created_at,
-          updated_at
-        ) VALUES (
-          ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, NULL, NULL, ?, ?, ?
-        )
-        "#,
+    let id = sqlx::query(
+        "INSERT INTO companions (user_id, name, linked_user_id, created_at, updated_at) VALUES (?, ?, ?, ?, ?)",
     )
-    .bind(payload.schedule_id)
-    .bind(&payload.date)
-    .bind(payload.order)
-    .bind(&payload.transportation)
-    .bind(&payload.from)
-    .bind(&payload.to)
-    .bind(&payload.notes)
-    .bind(payload.fare)
-    .bind(payload.miles)
-    .bind(if payload.return_flag { 1 } else { 0 })
-    .bind(generate_public_id())
+    .bind(user.user_id as i64)
+    .bind(&name)
+    .bind(linked_user_id)
     .bind(&now)
     .bind(&now)
     .execute(&pool)
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let last_id = result.last_insert_rowid();
-
-    // 関連するスケジュールのロールアップ計算を実行
-    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
-
-    let row: TrafficRow = sqlx::query_as::<_, TrafficRow>(
-        r#"
-        SELECT
-          id,
-          schedule_id,
-          date,
-          "order",
-          transportation,
-          from_place,
-          to_place,
-          notes,
-          fare,
-          miles,
-          return_flag,
-          total_fare,
-          total_miles
-        FROM traffics
-        WHERE id = ?
-        "#,
-    )
-    .bind(last_id)
-    .fetch_one(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map_err(|e| {
+        eprintln!("[Companions] Failed to create companion: {}", e);
+        database_error()
+    })?
+    .last_insert_rowid();
+    Ok((StatusCode::CREATED, Json(fetch_companion(&pool, id, user.user_id).await?)))
+}
 
-    Ok((StatusCode::CREATED, Json(row_to_traffic(row))))
+// PUT /companions/:id - 同行者の名前・紐づけ先の変更（share_idを省略した場合は紐づけを変更しない）
+async fn update_companion(
+    Path(id): Path<i64>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+    Json(payload): Json<NewCompanion>,
+) -> Result<Json<Companion>, (StatusCode, Json<ErrorResponse>)> {
+    fetch_companion(&pool, id, user.user_id).await?;
+    let name = validate_companion_name(&payload.name)?;
+    let now = Utc::now().to_rfc3339();
+    let result = if payload.share_id.is_some() {
+        let linked_user_id = resolve_companion_link(&pool, user.user_id, payload.share_id.as_deref()).await?;
+        sqlx::query("UPDATE companions SET name = ?, linked_user_id = ?, updated_at = ? WHERE id = ?")
+            .bind(&name)
+            .bind(linked_user_id)
+            .bind(&now)
+            .bind(id)
+            .execute(&pool)
+            .await
+    } else {
+        sqlx::query("UPDATE companions SET name = ?, updated_at = ? WHERE id = ?")
+            .bind(&name)
+            .bind(&now)
+            .bind(id)
+            .execute(&pool)
+            .await
+    };
+    result.map_err(|e| {
+        eprintln!("[Companions] Failed to update companion {}: {}", id, e);
+        database_error()
+    })?;
+    Ok(Json(fetch_companion(&pool, id, user.user_id).await?))
 }
 
-// PUT /traffic/:id
-async fn update_traffic(
-    Path(id): Path<i32>,
+// DELETE /companions/:id - 同行者の削除（割り勘に使われている場合は409）
+async fn delete_companion(
+    Path(id): Path<i64>,
     user: AuthenticatedUser,
     Extension(pool): Extension<Pool<Sqlite>>,
-    Json(payload): Json<NewTraffic>,
-) -> Result<Json<Traffic>, StatusCode> {
-    // 更新対象のtrafficが現在所属しているスケジュールの所有者を確認
-    let current_schedule_id: Option<i64> = sqlx::query_scalar(
-        "SELECT schedule_id FROM traffics WHERE id = ?",
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    fetch_companion(&pool, id, user.user_id).await?;
+    let in_use: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM cost_splits cs WHERE cs.paid_by = ? OR EXISTS (SELECT 1 FROM cost_split_members m WHERE m.split_id = cs.id AND m.companion_id = ?)",
     )
     .bind(id)
-    .fetch_optional(&pool)
+    .bind(id)
+    .fetch_one(&pool)
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let current_schedule_id = current_schedule_id.ok_or(StatusCode::NOT_FOUND)?;
-
-    // 付け替え先（またはそのまま）のスケジュールの所有者も併せて確認し、
-    // 他人のスケジュールへ再割り当てできないようにする
-    for schedule_id in [current_schedule_id, payload.schedule_id as i64] {
-        let schedule_user_id: Option<i64> = sqlx::query_scalar(
-            "SELECT user_id FROM schedules WHERE id = ?",
-        )
-        .bind(schedule_id)
-        .fetch_optional(&pool)
+    .map_err(|e| {
+        eprintln!("[Companions] Failed to check companion usage: {}", e);
+        database_error()
+    })?;
+    if in_use > 0 {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(ErrorResponse {
+                error: "割り勘に使われている同行者は削除できません。先に割り勘の設定から外してください".to_string(),
+            }),
+        ));
+    }
+    sqlx::query("DELETE FROM companions WHERE id = ?")
+        .bind(id)
+        .execute(&pool)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map_err(|e| {
+            eprintln!("[Companions] Failed to delete companion {}: {}", id, e);
+            database_error()
+        })?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
 
-        match schedule_user_id {
-            Some(schedule_user_id) if schedule_user_id == user.user_id as i64 => {}
-            _ => return Err(StatusCode::FORBIDDEN),
-        }
-    }
+#[derive(Debug, Deserialize)]
+struct NewCostSplitMember {
+    companion_id: Option<i64>, // 省略・nullは自分
+    weight: Option<i64>,       // rule = share のときの口数（省略時は1）
+    amount: Option<i64>,       // rule = fixed のときの負担額
+}
 
-    let now = Utc::now().to_rfc3339();
-    let result = sqlx::query(
-        r#"
-        UPDATE traffics SET
-          schedule_id = ?,
-          date = ?,
-          "order" = ?,
-          transportation = ?,
-          from_place = ?,
-          to_place = ?,
-          notes = ?,
-          fare = ?,
-          miles = ?,
-          return_flag = ?,
-          updated_at = ?
-        WHERE id = ?
-        "#,
-    )
-    .bind(payload.schedule_id)
-    .bind(&payload.date)
-    .bind(payload.order)
-    .bind(&payload.transportation)
-    .bind(&payload.from)
-    .bind(&payload.to)
-    .bind(&payload.notes)
-    .bind(payload.fare)
-    .bind(payload.miles)
-    .bind(if payload.return_flag { 1 } else { 0 })
-    .bind(&now)
-    .bind(id)
-    .execute(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+#[derive(Debug, Deserialize)]
+struct NewCostSplit {
+    rule: String,         // equal / share / fixed
+    paid_by: Option<i64>, // 支払った人の同行者ID（省略・nullは自分）
+    members: Vec<NewCostSplitMember>,
+}
 
-    if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
-    }
+#[derive(Serialize)]
+struct CostSplitShare {
+    companion_id: Option<i64>,
+    name: Option<String>, // 自分の場合はnull
+    weight: i64,
+    fixed_amount: Option<i64>,
+    share: i64, // 負担額
+}
 
-    // 関連するスケジュールのロールアップ計算を実行
-    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+#[derive(Serialize)]
+struct CostSplit {
+    schedule_id: i64,
+    entity_type: String,
+    entity_id: i64,
+    rule: String,
+    paid_by: Option<i64>,
+    amount: i64,      // 基準通貨に換算した費用
+    currency: String, // 基準通貨（amount・shareの通貨）
+    shares: Vec<CostSplitShare>,
+}
 
-    let row: TrafficRow = sqlx::query_as::<_, TrafficRow>(
-        r#"
-        SELECT
-          id,
-          schedule_id,
-          date,
-          "order",
-          transportation,
-          from_place,
-          to_place,
-          notes,
-          fare,
-          miles,
-          return_flag,
-          total_fare,
-          total_miles
-        FROM traffics
-        WHERE id = ?
-        "#,
-    )
-    .bind(id)
-    .fetch_one(&pool)
-    .await
-    .map_err(|_| StatusCode::NOT_FOUND)?;
+// 割り勘の対象（スケジュール・交通・宿泊）がログインユーザーの所有であることを確認し、属するスケジュールのIDを返す
+async fn resolve_cost_split_target(
+    pool: &Pool<Sqlite>,
+    user_id: i32,
+    entity_type: &str,
+    entity_id: i64,
+) -> Result<(RevisionEntity, i64), (StatusCode, Json<ErrorResponse>)> {
+    let entity = RevisionEntity::parse(entity_type)
+        .ok_or_else(|| cost_split_bad_request("対象はschedule・traffic・stayのいずれかを指定してください"))?;
+    let schedule_id = match entity {
+        RevisionEntity::Schedule => entity_id,
+        RevisionEntity::Traffic | RevisionEntity::Stay => {
+            let schedule_id: Option<i64> = sqlx::query_scalar(&format!(
+                "SELECT schedule_id FROM {} WHERE id = ? AND deleted_at IS NULL",
+                entity.table()
+            ))
+            .bind(entity_id)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                eprintln!("[CostSplits] Failed to resolve {} {}: {}", entity.name(), entity_id, e);
+                database_error()
+            })?;
+            schedule_id.ok_or((
+                StatusCode::NOT_FOUND,
+                Json(ErrorResponse {
+                    error: "対象の交通・宿泊情報が見つかりませんでした".to_string(),
+                }),
+            ))?
+        }
+    };
+    ensure_schedule_owner(pool, schedule_id, user_id).await?;
+    Ok((entity, schedule_id))
+}
+
+// 割り勘の設定を負担額つきで組み立てる
+async fn build_cost_split(
+    pool: &Pool<Sqlite>,
+    user_id: i32,
+    entity: RevisionEntity,
+    entity_id: i64,
+) -> Result<Option<CostSplit>, sqlx::Error> {
+    let split: Option<CostSplitRow> = sqlx::query_as::<_, CostSplitRow>(&format!(
+        "{} WHERE cs.entity_type = ? AND cs.entity_id = ?",
+        COST_SPLIT_SELECT
+    ))
+    .bind(entity.name())
+    .bind(entity_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(split) = split else { return Ok(None) };
+    let members = fetch_cost_split_members(pool, &[split.id]).await?.remove(&split.id).unwrap_or_default();
+    let names: std::collections::HashMap<i64, String> =
+        sqlx::query_as::<_, (i64, String)>("SELECT id, name FROM companions WHERE user_id = ?")
+            .bind(user_id as i64)
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .collect();
+
+    let rates = fetch_exchange_rates(pool, user_id as i64).await?;
+    let amount = split.home_amount(&rates).unwrap_or(0);
+    let rule = SplitRule::parse(&split.rule).unwrap_or(SplitRule::Equal);
+    let shares = split_cost(amount, rule, &members, split.paid_by)
+        .into_iter()
+        .map(|(companion_id, share)| {
+            let member = members.iter().find(|m| m.companion_id == companion_id);
+            CostSplitShare {
+                companion_id,
+                name: companion_id.and_then(|id| names.get(&id).cloned()),
+                weight: member.map(|m| m.weight).unwrap_or(0),
+                fixed_amount: member.and_then(|m| m.fixed_amount),
+                share,
+            }
+        })
+        .collect();
+    Ok(Some(CostSplit {
+        schedule_id: split.schedule_id,
+        entity_type: split.entity_type,
+        entity_id: split.entity_id,
+        rule: split.rule,
+        paid_by: split.paid_by,
+        amount,
+        currency: rates.home_currency,
+        shares,
+    }))
+}
 
-    Ok(Json(row_to_traffic(row)))
+fn cost_split_not_found() -> (StatusCode, Json<ErrorResponse>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+            error: "割り勘の設定がありません".to_string(),
+        }),
+    )
 }
 
-// GET /traffic/all - ユーザーが所有するすべてのTrafficを取得
-async fn list_all_traffics(
+// GET /cost-splits/:entity_type/:entity_id - 割り勘の設定と各メンバーの負担額
+async fn get_cost_split(
+    Path((entity_type, entity_id)): Path<(String, i64)>,
     user: AuthenticatedUser,
     Extension(pool): Extension<Pool<Sqlite>>,
-) -> Json<Vec<Traffic>> {
-    let rows: Vec<TrafficRow> = sqlx::query_as::<_, TrafficRow>(
-        r#"
-        SELECT
-          t.id,
-          t.schedule_id,
-          t.date,
-          t."order",
-          t.transportation,
-          t.from_place,
-          t.to_place,
-          t.notes,
-          t.fare,
-          t.miles,
-          t.return_flag,
-          t.total_fare,
-          t.total_miles
-        FROM traffics t
-        INNER JOIN schedules s ON t.schedule_id = s.id
-        WHERE s.user_id = ?
-        ORDER BY t.date ASC, t."order" ASC
-        "#,
-    )
-    .bind(user.user_id as i64)
-    .fetch_all(&pool)
-    .await
-    .unwrap_or_else(|e| {
-        eprintln!("Error fetching traffics: {}", e);
-        vec![]
-    });
-
-    let traffics = rows.into_iter().map(row_to_traffic).collect();
-    Json(traffics)
+) -> Result<Json<CostSplit>, (StatusCode, Json<ErrorResponse>)> {
+    let (entity, _) = resolve_cost_split_target(&pool, user.user_id, &entity_type, entity_id).await?;
+    build_cost_split(&pool, user.user_id, entity, entity_id)
+        .await
+        .map_err(|e| {
+            eprintln!("[CostSplits] Failed to fetch cost split: {}", e);
+            database_error()
+        })?
+        .map(Json)
+        .ok_or_else(cost_split_not_found)
 }
 
-// GET /stay/all - ユーザーが所有するすべてのStayを取得
-async fn list_all_stays(
+// PUT /cost-splits/:entity_type/:entity_id - 割り勘の設定（既存の設定は置き換える）
+async fn save_cost_split(
+    Path((entity_type, entity_id)): Path<(String, i64)>,
     user: AuthenticatedUser,
     Extension(pool): Extension<Pool<Sqlite>>,
-) -> Result<Json<Vec<Stay>>, (StatusCode, Json<ErrorResponse>)> {
-    let rows: Vec<StayRow> = sqlx::query_as::<_, StayRow>(
-        r#"
-        SELECT
-          st.id,
-          st.schedule_id,
-          st.check_in,
-          st.check_out,
-          st.hotel_name,
-          st.website,
-          st.fee,
-          st.breakfast_flag,
-          st.deadline,
-          st.penalty,
-          st.status
-        FROM stays st
-        INNER JOIN schedules s ON st.schedule_id = s.id
-        WHERE s.user_id = ?
-        ORDER BY st.check_in ASC
-        "#,
-    )
-    .bind(user.user_id as i64)
-    .fetch_all(&pool)
-    .await
-    .unwrap_or_else(|e| {
-        eprintln!("Error fetching stays: {}", e);
-        vec![]
-    });
+    Json(payload): Json<NewCostSplit>,
+) -> Result<Json<CostSplit>, (StatusCode, Json<ErrorResponse>)> {
+    let (entity, schedule_id) = resolve_cost_split_target(&pool, user.user_id, &entity_type, entity_id).await?;
+    let rule = SplitRule::parse(payload.rule.trim())
+        .ok_or_else(|| cost_split_bad_request("ruleはequal・share・fixedのいずれかを指定してください"))?;
+    if payload.members.is_empty() || payload.members.len() > COST_SPLIT_MAX_MEMBERS {
+        return Err(cost_split_bad_request("メンバーは1〜20人で指定してください"));
+    }
 
-    let mut stays: Vec<Stay> = rows.into_iter().map(row_to_stay).collect();
+    let owned_ids: std::collections::HashSet<i64> = sqlx::query_scalar("SELECT id FROM companions WHERE user_id = ?")
+        .bind(user.user_id as i64)
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| {
+            eprintln!("[CostSplits] Failed to fetch companions: {}", e);
+            database_error()
+        })?
+        .into_iter()
+        .collect();
+    let mut seen = std::collections::HashSet::new();
+    for member in &payload.members {
+        if member.companion_id.is_some_and(|id| !owned_ids.contains(&id)) {
+            return Err(companion_not_found());
+        }
+        if !seen.insert(member.companion_id) {
+            return Err(cost_split_bad_request("同じメンバーが重複しています"));
+        }
+        if member.weight.is_some_and(|w| !(1..=100).contains(&w)) {
+            return Err(cost_split_bad_request("口数は1〜100で指定してください"));
+        }
+        if rule == SplitRule::Fixed && member.amount.is_none_or(|a| a < 0) {
+            return Err(cost_split_bad_request("固定額の割り勘では各メンバーの負担額（0以上）を指定してください"));
+        }
+    }
+    if payload.paid_by.is_some_and(|id| !owned_ids.contains(&id)) {
+        return Err(companion_not_found());
+    }
 
-    let (plan, trial_started_at) = fetch_user_plan(&pool, user.user_id).await.map_err(|e| {
-        eprintln!("Error fetching plan for user {}: {}", user.user_id, e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Database error".to_string(),
-            }),
+    let now = Utc::now().to_rfc3339();
+    let save = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM cost_split_members WHERE split_id IN (SELECT id FROM cost_splits WHERE entity_type = ? AND entity_id = ?)")
+            .bind(entity.name())
+            .bind(entity_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM cost_splits WHERE entity_type = ? AND entity_id = ?")
+            .bind(entity.name())
+            .bind(entity_id)
+            .execute(&mut *tx)
+            .await?;
+        let split_id = sqlx::query(
+            "INSERT INTO cost_splits (user_id, schedule_id, entity_type, entity_id, rule, paid_by, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
         )
+        .bind(user.user_id as i64)
+        .bind(schedule_id)
+        .bind(entity.name())
+        .bind(entity_id)
+        .bind(payload.rule.trim())
+        .bind(payload.paid_by)
+        .bind(&now)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        for member in &payload.members {
+            sqlx::query("INSERT INTO cost_split_members (split_id, companion_id, weight, fixed_amount) VALUES (?, ?, ?, ?)")
+                .bind(split_id)
+                .bind(member.companion_id)
+                .bind(member.weight.unwrap_or(1))
+                .bind(if rule == SplitRule::Fixed { member.amount } else { None })
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    };
+    save.await.map_err(|e: sqlx::Error| {
+        eprintln!("[CostSplits] Failed to save cost split: {}", e);
+        database_error()
     })?;
-    if !has_paid_access(&plan, trial_started_at.as_deref()) {
-        // 無料プランは過去アーカイブを直近2年分（今年+去年）のみ閲覧可能。未来の予定は制限しない
-        let earliest_visible_year = free_plan_earliest_archive_year(Utc::now());
-        stays = stays
-            .into_iter()
-            .filter(|s| parse_year_prefix(&s.check_in).map_or(true, |y| y >= earliest_visible_year))
-            .collect();
+
+    calculate_rollup(&pool, schedule_id).await.ok();
+    build_cost_split(&pool, user.user_id, entity, entity_id)
+        .await
+        .map_err(|e| {
+            eprintln!("[CostSplits] Failed to fetch cost split: {}", e);
+            database_error()
+        })?
+        .map(Json)
+        .ok_or_else(cost_split_not_found)
+}
+
+// DELETE /cost-splits/:entity_type/:entity_id - 割り勘の設定を外す（全額を自分の負担に戻す）
+async fn delete_cost_split(
+    Path((entity_type, entity_id)): Path<(String, i64)>,
+    user: AuthenticatedUser,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+    let (entity, schedule_id) = resolve_cost_split_target(&pool, user.user_id, &entity_type, entity_id).await?;
+    let delete = async {
+        let mut tx = pool.begin().await?;
+        sqlx::query("DELETE FROM cost_split_members WHERE split_id IN (SELECT id FROM cost_splits WHERE entity_type = ? AND entity_id = ?)")
+            .bind(entity.name())
+            .bind(entity_id)
+            .execute(&mut *tx)
+            .await?;
+        let deleted = sqlx::query("DELETE FROM cost_splits WHERE entity_type = ? AND entity_id = ?")
+            .bind(entity.name())
+            .bind(entity_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        tx.commit().await?;
+        Ok::<u64, sqlx::Error>(deleted)
+    };
+    let deleted = delete.await.map_err(|e| {
+        eprintln!("[CostSplits] Failed to delete cost split: {}", e);
+        database_error()
+    })?;
+    if deleted == 0 {
+        return Err(cost_split_not_found());
     }
+    calculate_rollup(&pool, schedule_id).await.ok();
+    Ok(Json(serde_json::json!({ "success": true })))
+}
 
-    Ok(Json(stays))
+#[derive(Serialize)]
+struct BalancePerson {
+    companion_id: Option<i64>, // nullは自分
+    name: Option<String>,
 }
 
-// GET /stay?schedule_id=...
-async fn list_stays(
+#[derive(Serialize)]
+struct BalanceEntry {
+    person: BalancePerson,
+    net: i64, // プラスは受け取る側、マイナスは支払う側
+}
+
+#[derive(Serialize)]
+struct BalanceDebt {
+    from: BalancePerson, // 支払う人
+    to: BalancePerson,   // 受け取る人
+    amount: i64,
+}
+
+#[derive(Serialize)]
+struct Balances {
+    currency: String, // 基準通貨（金額はすべて換算済み）
+    balances: Vec<BalanceEntry>,
+    debts: Vec<BalanceDebt>,
+}
+
+// GET /balances - ゴミ箱にないすべてのスケジュールの割り勘から、人ごとの収支と誰が誰にいくら払うかを返す
+async fn get_balances(
     user: AuthenticatedUser,
-    Query(params): Query<StayQuery>,
     Extension(pool): Extension<Pool<Sqlite>>,
-) -> Json<Vec<Stay>> {
-    let rows: Vec<StayRow> = sqlx::query_as::<_, StayRow>(
-        r#"
-        SELECT
-          st.id,
-          st.schedule_id,
-          st.check_in,
-          st.check_out,
-          st.hotel_name,
-          st.website,
-          st.fee,
-          st.breakfast_flag,
-          st.deadline,
-          st.penalty,
-          st.status
-        FROM stays st
-        INNER JOIN schedules s ON st.schedule_id = s.id
-        WHERE st.schedule_id = ? AND s.user_id = ?
-        "#,
-    )
-    .bind(params.schedule_id)
+) -> Result<Json<Balances>, (StatusCode, Json<ErrorResponse>)> {
+    let failed = |e: sqlx::Error| {
+        eprintln!("[Balances] Failed to calculate balances: {}", e);
+        database_error()
+    };
+    let splits: Vec<CostSplitRow> = sqlx::query_as::<_, CostSplitRow>(&format!(
+        "{} WHERE cs.user_id = ? AND s.deleted_at IS NULL",
+        COST_SPLIT_SELECT
+    ))
     .bind(user.user_id as i64)
     .fetch_all(&pool)
     .await
-    .expect("failed to fetch stays");
+    .map_err(failed)?;
+    let ids: Vec<i64> = splits.iter().map(|s| s.id).collect();
+    let mut members = fetch_cost_split_members(&pool, &ids).await.map_err(failed)?;
+    let rates = fetch_exchange_rates(&pool, user.user_id as i64).await.map_err(failed)?;
 
-    let stays = rows.into_iter().map(row_to_stay).collect();
-    Json(stays)
+    let entries: Vec<(Option<i64>, SplitShares)> = splits
+        .into_iter()
+        .filter_map(|split| {
+            let amount = split.home_amount(&rates)?;
+            let rule = SplitRule::parse(&split.rule)?;
+            let members = members.remove(&split.id).unwrap_or_default();
+            Some((split.paid_by, split_cost(amount, rule, &members, split.paid_by)))
+        })
+        .collect();
+    let (net, debts) = settle_balances(&entries);
+
+    let names: std::collections::HashMap<i64, String> =
+        sqlx::query_as::<_, (i64, String)>("SELECT id, name FROM companions WHERE user_id = ?")
+            .bind(user.user_id as i64)
+            .fetch_all(&pool)
+            .await
+            .map_err(failed)?
+            .into_iter()
+            .collect();
+    let person = |companion_id: Option<i64>| BalancePerson {
+        companion_id,
+        name: companion_id.and_then(|id| names.get(&id).cloned()),
+    };
+
+    Ok(Json(Balances {
+        currency: rates.home_currency,
+        balances: net
+            .into_iter()
+            .map(|(companion_id, net)| BalanceEntry { person: person(companion_id), net })
+            .collect(),
+        debts: debts
+            .into_iter()
+            .map(|((from, to), amount)| BalanceDebt { from: person(from), to: person(to), amount })
+            .collect(),
+    }))
 }
 
-// GET /stay/:id
-async fn get_stay(
-    Path(id): Path<i32>,
-    user: AuthenticatedUser,
-    Extension(pool): Extension<Pool<Sqlite>>,
-) -> Result<Json<Stay>, StatusCode> {
-    let row: Option<StayRow> = sqlx::query_as::<_, StayRow>(
-        r#"
-        SELECT
-          id,
-          schedule_id,
-          check_in,
-          check_out,
-          hotel_name,
-          website,
-          fee,
-          breakfast_flag,
-          deadline,
-          penalty,
-          status
-        FROM stays
-        WHERE id = ?
-        "#,
-    )
-    .bind(id)
-    .fetch_optional(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+// ====== 全文検索 ======
+// search_index（FTS5、trigramトークナイザ）にスケジュール・交通・宿泊の検索対象テキストを正規化して保存する。
+// 正規化はRust側で行うため、各データの作成・更新時に refresh_search_index で該当スケジュール分を作り直す
 
-    let row = row.ok_or(StatusCode::NOT_FOUND)?;
+#[derive(Debug, Deserialize)]
+struct SearchQuery {
+    q: String,
+    limit: Option<i64>,
+}
 
-    // スケジュールの所有者を確認（スケジュールが存在しない場合も含め、
-    // 所有者が確認できなければアクセスを拒否する）
-    let schedule_user_id: Option<i64> = sqlx::query_scalar(
-        "SELECT user_id FROM schedules WHERE id = ?",
-    )
-    .bind(row.schedule_id)
-    .fetch_optional(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+#[derive(Serialize)]
+struct SearchResult {
+    entity_type: String, // "schedule" / "traffic" / "stay"
+    id: i32,
+    schedule_id: i32,
+    title: String, // スケジュール名、交通は「出発地 → 到着地」、宿泊は宿泊先名
+    date: Option<String>,
+    schedule_title: String,
+}
 
-    match schedule_user_id {
-        Some(schedule_user_id) if schedule_user_id == user.user_id as i64 => {}
-        _ => return Err(StatusCode::FORBIDDEN),
-    }
+#[derive(sqlx::FromRow)]
+struct SearchResultRow {
+    entity_type: String,
+    entity_id: i64,
+    schedule_id: i64,
+    schedule_title: String,
+    schedule_date: Option<String>,
+    schedule_end_date: Option<String>,
+    from_place: Option<String>,
+    to_place: Option<String>,
+    traffic_date: Option<String>,
+    hotel_name: Option<String>,
+    check_in: Option<String>,
+}
 
-    Ok(Json(row_to_stay(row)))
+#[derive(sqlx::FromRow)]
+struct SearchScheduleSource {
+    user_id: i64,
+    title: String,
+    group: Option<String>,
+    venue: String,
+    lineup: Option<String>,
+    notes: Option<String>,
 }
 
-// POST /stay
-async fn create_stay(
-    user: AuthenticatedUser,
-    Extension(pool): Extension<Pool<Sqlite>>,
-    Json(payload): Json<NewStay>,
-) -> Result<(StatusCode, Json<Stay>), StatusCode> {
-    // スケジュールの所有者を確認
-    let schedule_user_id: Option<i64> = sqlx::query_scalar(
-        "SELECT user_id FROM schedules WHERE id = ?",
-    )
-    .bind(payload.schedule_id as i64)
-    .fetch_optional(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+// 検索用にテキストを正規化する
+// NFKCで全角英数・半角カナを統一し（"ｼｼｬﾓ" → "シシャモ"）、ひらがなはカタカナに、英字は小文字に揃える
+fn normalize_search_text(text: &str) -> String {
+    use unicode_normalization::UnicodeNormalization;
+
+    text.nfkc()
+        .map(|c| match c {
+            'ぁ'..='ゖ' | 'ゝ'..='ゞ' => char::from_u32(c as u32 + 0x60).unwrap_or(c),
+            _ => c,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod search_text_tests {
+    use super::normalize_search_text;
+
+    #[test]
+    fn folds_width_and_kana() {
+        assert_eq!(normalize_search_text("ｼｼｬﾓ"), "シシャモ");
+        assert_eq!(normalize_search_text("ｶﾞｰﾙｽﾞ"), "ガールズ");
+        assert_eq!(normalize_search_text("ししゃも"), "シシャモ");
+        assert_eq!(normalize_search_text("ＺＥＰＰ Ｈａｎｅｄａ"), "zepp haneda");
+    }
+}
 
-    if let Some(schedule_user_id) = schedule_user_id {
-        if schedule_user_id != user.user_id as i64 {
-            return Err(StatusCode::FORBIDDEN);
+// 検索語をFTS5のMATCH式（3文字以上の語）とLIKEパターン（trigramで検索できない2文字以下の語）に分ける
+fn build_search_conditions(query: &str) -> (Option<String>, Vec<String>) {
+    let normalized = normalize_search_text(query);
+    let mut phrases = Vec::new();
+    let mut like_patterns = Vec::new();
+    for term in normalized.split_whitespace() {
+        if term.chars().count() >= 3 {
+            phrases.push(format!("\"{}\"", term.replace('"', "\"\"")));
+        } else {
+            let escaped = term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            like_patterns.push(format!("%{}%", escaped));
         }
-    } else {
-        // スケジュールが存在しない場合
-        return Err(StatusCode::NOT_FOUND);
     }
+    let match_expr = if phrases.is_empty() { None } else { Some(phrases.join(" AND ")) };
+    (match_expr, like_patterns)
+}
 
-    let now = Utc::now().to_rfc3339();
-    let result = sqlx::query(
-        r#"
-        INSERT INTO stays (
-          schedule_id,
-          check_in,
-          check_out,
-          hotel_name,
-          website,
-          fee,
-          breakfast_flag,
-          deadline,
-          penalty,
-          status,
-          public_id,
-          created_at,
-          updated_at
-        ) VALUES (
-          ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?
-        )
-        "#,
+// 指定スケジュール（とその交通・宿泊）の検索インデックスを作り直す。スケジュールが存在しない場合は削除のみ行う
+async fn reindex_schedule_search(pool: &Pool<Sqlite>, schedule_id: i64) -> Result<(), sqlx::Error> {
+    let schedule: Option<SearchScheduleSource> = sqlx::query_as(
+        r#"SELECT user_id, title, "group", venue, lineup, notes FROM schedules WHERE id = ? AND user_id IS NOT NULL AND deleted_at IS NULL"#,
     )
-    .bind(payload.schedule_id)
-    .bind(&payload.check_in)
-    .bind(&payload.check_out)
-    .bind(&payload.hotel_name)
-    .bind(&payload.website)
-    .bind(payload.fee)
-    .bind(if payload.breakfast_flag { 1 } else { 0 })
-    .bind(&payload.deadline)
-    .bind(payload.penalty)
-    .bind(payload.status.as_deref().unwrap_or("Keep"))
-    .bind(generate_public_id())
-    .bind(&now)
-    .bind(&now)
-    .execute(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .bind(schedule_id)
+    .fetch_optional(pool)
+    .await?;
 
-    let last_id = result.last_insert_rowid();
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM search_index WHERE schedule_id = ?")
+        .bind(schedule_id)
+        .execute(&mut *tx)
+        .await?;
 
-    // 関連するスケジュールのロールアップ計算を実行
-    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+    if let Some(schedule) = schedule {
+        let user_id = schedule.user_id;
+        let mut documents: Vec<(&str, i64, String)> = vec![(
+            "schedule",
+            schedule_id,
+            [Some(schedule.title), schedule.group, Some(schedule.venue), schedule.lineup, schedule.notes]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )];
+
+        let traffics: Vec<(i64, String, String, Option<String>)> =
+            sqlx::query_as("SELECT id, from_place, to_place, notes FROM traffics WHERE schedule_id = ? AND deleted_at IS NULL")
+                .bind(schedule_id)
+                .fetch_all(&mut *tx)
+                .await?;
+        for (id, from_place, to_place, notes) in traffics {
+            let text = [Some(from_place), Some(to_place), notes].into_iter().flatten().collect::<Vec<_>>().join("\n");
+            documents.push(("traffic", id, text));
+        }
 
-    let row: StayRow = sqlx::query_as::<_, StayRow>(
-        r#"
-        SELECT
-          id,
-          schedule_id,
-          check_in,
-          check_out,
-          hotel_name,
-          website,
-          fee,
-          breakfast_flag,
-          deadline,
-          penalty,
-          status
-        FROM stays
-        WHERE id = ?
-        "#,
-    )
-    .bind(last_id)
-    .fetch_one(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let stays: Vec<(i64, String)> = sqlx::query_as("SELECT id, hotel_name FROM stays WHERE schedule_id = ? AND deleted_at IS NULL")
+            .bind(schedule_id)
+            .fetch_all(&mut *tx)
+            .await?;
+        for (id, hotel_name) in stays {
+            documents.push(("stay", id, hotel_name));
+        }
+
+        for (entity_type, entity_id, text) in documents {
+            sqlx::query(
+                "INSERT INTO search_index (body, entity_type, entity_id, schedule_id, user_id) VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(normalize_search_text(&text))
+            .bind(entity_type)
+            .bind(entity_id)
+            .bind(schedule_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+    }
 
-    Ok((StatusCode::CREATED, Json(row_to_stay(row))))
+    tx.commit().await
 }
 
-// PUT /stay/:id
-async fn update_stay(
-    Path(id): Path<i32>,
-    user: AuthenticatedUser,
-    Extension(pool): Extension<Pool<Sqlite>>,
-    Json(payload): Json<NewStay>,
-) -> Result<Json<Stay>, StatusCode> {
-    // 更新対象のstayが現在所属しているスケジュールの所有者を確認
-    let current_schedule_id: Option<i64> = sqlx::query_scalar(
-        "SELECT schedule_id FROM stays WHERE id = ?",
-    )
-    .bind(id)
-    .fetch_optional(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let current_schedule_id = current_schedule_id.ok_or(StatusCode::NOT_FOUND)?;
+// 書き込み系ハンドラから呼ぶ。検索インデックスの更新に失敗しても本来の処理は失敗させない
+async fn refresh_search_index(pool: &Pool<Sqlite>, schedule_id: i64) {
+    if let Err(e) = reindex_schedule_search(pool, schedule_id).await {
+        eprintln!("[SearchIndex] Failed to reindex schedule {}: {}", schedule_id, e);
+    }
+}
 
-    // 付け替え先（またはそのまま）のスケジュールの所有者も併せて確認し、
-    // 他人のスケジュールへ再割り当てできないようにする
-    for schedule_id in [current_schedule_id, payload.schedule_id as i64] {
-        let schedule_user_id: Option<i64> = sqlx::query_scalar(
-            "SELECT user_id FROM schedules WHERE id = ?",
-        )
+// 交通・宿泊の更新で紐付くスケジュールが変わった場合に、移動前のスケジュール側に残るエントリを消す
+async fn refresh_search_index_for_entity(pool: &Pool<Sqlite>, entity_type: &str, entity_id: i64, schedule_id: i64) {
+    if let Err(e) = sqlx::query("DELETE FROM search_index WHERE entity_type = ? AND entity_id = ? AND schedule_id <> ?")
+        .bind(entity_type)
+        .bind(entity_id)
         .bind(schedule_id)
-        .fetch_optional(&pool)
+        .execute(pool)
         .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    {
+        eprintln!("[SearchIndex] Failed to remove stale {} {}: {}", entity_type, entity_id, e);
+    }
+    refresh_search_index(pool, schedule_id).await;
+}
 
-        match schedule_user_id {
-            Some(schedule_user_id) if schedule_user_id == user.user_id as i64 => {}
-            _ => return Err(StatusCode::FORBIDDEN),
-        }
+// 検索インデックスが空の場合（導入直後など）に全スケジュール分を作成する
+async fn rebuild_search_index_if_empty(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+    let indexed: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM search_index").fetch_one(pool).await?;
+    if indexed > 0 {
+        return Ok(());
+    }
+    let schedule_ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM schedules WHERE user_id IS NOT NULL AND deleted_at IS NULL")
+        .fetch_all(pool)
+        .await?;
+    for schedule_id in &schedule_ids {
+        reindex_schedule_search(pool, *schedule_id).await?;
     }
+    if !schedule_ids.is_empty() {
+        eprintln!("[Migration] Built search index for {} schedules", schedule_ids.len());
+    }
+    Ok(())
+}
 
-    let now = Utc::now().to_rfc3339();
-    let result = sqlx::query(
+// GET /search?q=... - 自分のスケジュール・交通・宿泊を横断検索（日付の新しい順）
+async fn search(
+    user: AuthenticatedUser,
+    Query(params): Query<SearchQuery>,
+    Extension(pool): Extension<Pool<Sqlite>>,
+) -> Result<Json<Vec<SearchResult>>, (StatusCode, Json<ErrorResponse>)> {
+    let (match_expr, like_patterns) = build_search_conditions(&params.q);
+    if match_expr.is_none() && like_patterns.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse {
+                error: "検索キーワードを入力してください".to_string(),
+            }),
+        ));
+    }
+    let limit = params.limit.unwrap_or(50).clamp(1, 200) as usize;
+
+    let mut sql = String::from(
         r#"
-        UPDATE stays SET
-          schedule_id = ?,
-          check_in = ?,
-          check_out = ?,
-          hotel_name = ?,
-          website = ?,
-          fee = ?,
-          breakfast_flag = ?,
-          deadline = ?,
-          penalty = ?,
-          status = ?,
-          updated_at = ?
-        WHERE id = ?
+        SELECT
+          si.entity_type,
+          CAST(si.entity_id AS INTEGER) AS entity_id,
+          CAST(si.schedule_id AS INTEGER) AS schedule_id,
+          s.title AS schedule_title,
+          s.date AS schedule_date,
+          s.end_date AS schedule_end_date,
+          t.from_place,
+          t.to_place,
+          t.date AS traffic_date,
+          st.hotel_name,
+          st.check_in
+        FROM search_index si
+        INNER JOIN schedules s ON s.id = si.schedule_id
+        LEFT JOIN traffics t ON si.entity_type = 'traffic' AND t.id = si.entity_id
+        LEFT JOIN stays st ON si.entity_type = 'stay' AND st.id = si.entity_id
+        WHERE si.user_id = ? AND s.user_id = ?
         "#,
-    )
-    .bind(payload.schedule_id)
-    .bind(&payload.check_in)
-    .bind(&payload.check_out)
-    .bind(&payload.hotel_name)
-    .bind(&payload.website)
-    .bind(payload.fee)
-    .bind(if payload.breakfast_flag { 1 } else { 0 })
-    .bind(&payload.deadline)
-    .bind(payload.penalty)
-    .bind(payload.status.as_deref().unwrap_or("Keep"))
-    .bind(&now)
-    .bind(id)
-    .execute(&pool)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    );
+    if match_expr.is_some() {
+        sql.push_str(" AND search_index MATCH ?");
+    }
+    for _ in &like_patterns {
+        sql.push_str(" AND si.body LIKE ? ESCAPE '\\'");
+    }
 
-    if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
+    let mut query = sqlx::query_as::<_, SearchResultRow>(&sql)
+        .bind(user.user_id)
+        .bind(user.user_id);
+    if let Some(match_expr) = &match_expr {
+        query = query.bind(match_expr);
+    }
+    for pattern in &like_patterns {
+        query = query.bind(pattern);
     }
+    let rows = query.fetch_all(&pool).await.map_err(|e| {
+        eprintln!("[Search] Database error: {}", e);
+        database_error()
+    })?;
 
-    // 関連するスケジュールのロールアップ計算を実行
-    calculate_rollup(&pool, payload.schedule_id as i64).await.ok();
+    let (plan, trial_started_at) = fetch_user_plan(&pool, user.user_id).await.map_err(|e| {
+        eprintln!("[Search] Failed to fetch plan: {}", e);
+        database_error()
+    })?;
+    // 無料プランは一覧と同じく過去アーカイブを直近2年分（今年+去年）のみ対象にする
+    let earliest_visible_year = if has_paid_access(&plan, trial_started_at.as_deref()) {
+        None
+    } else {
+        let user_timezone = fetch_user_timezone(&pool, user.user_id).await.map_err(|e| {
+            eprintln!("[Search] Failed to fetch timezone: {}", e);
+            database_error()
+        })?;
+        Some(free_plan_earliest_archive_year(Utc::now().with_timezone(&user_timezone)))
+    };
 
-    let row: StayRow = sqlx::query_as::<_, StayRow>(
-        r#"
-        SELECT
-          id,
-          schedule_id,
-          check_in,
-          check_out,
-          hotel_name,
-          website,
-          fee,
-          breakfast_flag,
-          deadline,
-          penalty,
-          status
-        FROM stays
-        WHERE id = ?
-        "#,
-    )
-    .bind(id)
-    .fetch_one(&pool)
-    .await
-    .map_err(|_| StatusCode::NOT_FOUND)?;
+    let mut results: Vec<SearchResult> = rows
+        .into_iter()
+        .filter_map(|row| {
+            let (title, date, year) = match row.entity_type.as_str() {
+                "traffic" => (
+                    format!(
+                        "{} → {}",
+                        row.from_place.unwrap_or_default(),
+                        row.to_place.unwrap_or_default()
+                    ),
+                    row.traffic_date.clone(),
+                    row.traffic_date.as_deref().and_then(parse_year_prefix),
+                ),
+                "stay" => (
+                    row.hotel_name.unwrap_or_default(),
+                    row.check_in.clone(),
+                    row.check_in.as_deref().and_then(parse_year_prefix),
+                ),
+                _ => (
+                    row.schedule_title.clone(),
+                    row.schedule_date.clone(),
+                    row.schedule_date
+                        .as_deref()
+                        .and_then(parse_year_prefix)
+                        .map(|start_year| schedule_end_year(start_year, row.schedule_end_date.as_deref())),
+                ),
+            };
+            if let (Some(earliest), Some(year)) = (earliest_visible_year, year) {
+                if year < earliest {
+                    return None;
+                }
+            }
+            Some(SearchResult {
+                entity_type: row.entity_type,
+                id: row.entity_id as i32,
+                schedule_id: row.schedule_id as i32,
+                title,
+                date,
+                schedule_title: row.schedule_title,
+            })
+        })
+        .collect();
 
-    Ok(Json(row_to_stay(row)))
+    results.sort_by(|a, b| b.date.cmp(&a.date));
+    results.truncate(limit);
+
+    Ok(Json(results))
 }
 
 // ====== 選択肢管理 ======
@@ -6505,7 +17623,7 @@ async fn get_select_options(
     user: AuthenticatedUser,
     Path(option_type): Path<String>,
     Extension(pool): Extension<Pool<Sqlite>>,
-) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<(HeaderMap, Json<serde_json::Value>), (StatusCode, Json<ErrorResponse>)> {
     // DISABLE_AUTHが有効な場合、データベースから実際のユーザーIDを取得
     let actual_user_id = if std::env::var("DISABLE_AUTH").is_ok() {
         // 環境変数DEFAULT_USER_IDが設定されている場合はそれを使用
@@ -6530,8 +17648,8 @@ async fn get_select_options(
     eprintln!("[GetSelectOptions] User ID (i32): {}, Actual User ID: {}, Option Type: {}", 
         user.user_id, actual_user_id, option_type);
     
-    let row: Option<(String, Option<String>)> = sqlx::query_as::<_, (String, Option<String>)>(
-        "SELECT options_json, sort_order FROM select_options WHERE user_id = ? AND option_type = ?"
+    let row: Option<(String, Option<String>, Option<String>)> = sqlx::query_as::<_, (String, Option<String>, Option<String>)>(
+        "SELECT options_json, sort_order, updated_at FROM select_options WHERE user_id = ? AND option_type = ?"
     )
     .bind(actual_user_id as i64)
     .bind(&option_type)
@@ -6547,7 +17665,7 @@ async fn get_select_options(
         )
     })?;
 
-    if let Some((options_json, sort_order)) = row {
+    if let Some((options_json, sort_order, updated_at)) = row {
         let options: Vec<serde_json::Value> = serde_json::from_str(&options_json)
             .map_err(|_| {
                 (
@@ -6557,30 +17675,77 @@ async fn get_select_options(
                     }),
                 )
             })?;
-        Ok(Json(serde_json::json!({
-            "options": options,
-            "sort_order": sort_order.unwrap_or_else(|| "custom".to_string()),
-            "exists": true,
-        })))
+        Ok((
+            etag_header(&etag_for(updated_at.as_deref())),
+            Json(serde_json::json!({
+                "options": options,
+                "sort_order": sort_order.unwrap_or_else(|| "custom".to_string()),
+                "exists": true,
+            })),
+        ))
     } else {
         // データベースに保存されていない場合は空配列を返す
         // existsをfalseにすることで、フロントは「未保存（初回）」と
         // 「保存済みだが全件削除されて0件」を区別できる
-        Ok(Json(serde_json::json!({
-            "options": [],
-            "sort_order": "custom",
-            "exists": false,
-        })))
+        Ok((
+            etag_header(&etag_for(None)),
+            Json(serde_json::json!({
+                "options": [],
+                "sort_order": "custom",
+                "exists": false,
+            })),
+        ))
     }
 }
 
+// 412の応答用に、GET /select-options/:type と同じ形で現在の選択肢とETagを返す
+async fn select_options_precondition_failed(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    option_type: &str,
+) -> WriteError<(StatusCode, Json<ErrorResponse>)> {
+    let current: Result<Option<(serde_json::Value, String)>, sqlx::Error> = sqlx::query_as::<_, (String, Option<String>, Option<String>)>(
+        "SELECT options_json, sort_order, updated_at FROM select_options WHERE user_id = ? AND option_type = ?"
+    )
+    .bind(user_id)
+    .bind(option_type)
+    .fetch_optional(pool)
+    .await
+    .map(|row| {
+        Some(match row {
+            Some((options_json, sort_order, updated_at)) => (
+                serde_json::json!({
+                    "options": serde_json::from_str::<Vec<serde_json::Value>>(&options_json).unwrap_or_default(),
+                    "sort_order": sort_order.unwrap_or_else(|| "custom".to_string()),
+                    "exists": true,
+                }),
+                etag_for(updated_at.as_deref()),
+            ),
+            None => (
+                serde_json::json!({ "options": [], "sort_order": "custom", "exists": false }),
+                etag_for(None),
+            ),
+        })
+    });
+    let failed = || {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(ErrorResponse {
+                error: "Database error".to_string(),
+            }),
+        )
+    };
+    precondition_failed(current, failed(), failed())
+}
+
 // POST /select-options/:type - 選択肢を保存
 async fn save_select_options(
     user: AuthenticatedUser,
     Path(option_type): Path<String>,
+    headers: HeaderMap,
     Extension(pool): Extension<Pool<Sqlite>>,
     Json(payload): Json<SelectOptionsRequest>,
-) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<(HeaderMap, Json<serde_json::Value>), WriteError<(StatusCode, Json<ErrorResponse>)>> {
     // DISABLE_AUTHが有効な場合、データベースから実際のユーザーIDを取得
     let actual_user_id = if std::env::var("DISABLE_AUTH").is_ok() {
         // 環境変数DEFAULT_USER_IDが設定されている場合はそれを使用
@@ -6650,15 +17815,15 @@ async fn save_select_options(
             Json(ErrorResponse {
                 error: format!("User ID {} does not exist", actual_user_id),
             }),
-        ));
+        ).into());
     }
     
     eprintln!("[SaveSelectOptions] User ID {} exists in users table", user_id_i64);
     
     // 既存のレコードを確認
     eprintln!("[SaveSelectOptions] Checking for existing record: user_id={}, option_type={}", user_id_i64, option_type);
-    let existing: Option<(i64,)> = sqlx::query_as(
-        "SELECT id FROM select_options WHERE user_id = ? AND option_type = ?"
+    let existing: Option<(i64, Option<String>)> = sqlx::query_as(
+        "SELECT id, updated_at FROM select_options WHERE user_id = ? AND option_type = ?"
     )
     .bind(user_id_i64)
     .bind(&option_type)
@@ -6675,6 +17840,13 @@ async fn save_select_options(
     })?;
     
     eprintln!("[SaveSelectOptions] Existing record check result: {:?}", existing);
+
+    // If-Matchが指定されている場合は、取得時点から変更されていないことを確認する（未保存の場合のETagは空）
+    let current_updated_at = existing.as_ref().and_then(|(_, updated_at)| updated_at.clone());
+    if !if_match_satisfied(&headers, &etag_for(current_updated_at.as_deref())) {
+        return Err(select_options_precondition_failed(&pool, user_id_i64, &option_type).await);
+    }
+    let guard = if_match_guard(&headers, current_updated_at.as_deref());
     
     let sort_order = payload.sort_order.unwrap_or_else(|| "custom".to_string());
     
@@ -6687,7 +17859,7 @@ async fn save_select_options(
             SET options_json = ?,
                 sort_order = ?,
                 updated_at = ?
-            WHERE user_id = ? AND option_type = ?
+            WHERE user_id = ? AND option_type = ? AND updated_at IS COALESCE(?, updated_at)
             "#
         )
         .bind(&options_json)
@@ -6695,6 +17867,7 @@ async fn save_select_options(
         .bind(&now)
         .bind(user_id_i64)
         .bind(&option_type)
+        .bind(&guard)
         .execute(&pool)
         .await
     } else {
@@ -6725,9 +17898,13 @@ async fn save_select_options(
         )
     })?;
 
+    if result.rows_affected() == 0 && guard.is_some() {
+        return Err(select_options_precondition_failed(&pool, user_id_i64, &option_type).await);
+    }
+
     eprintln!("[SaveSelectOptions] Successfully saved. Rows affected: {}", result.rows_affected());
 
-    Ok(Json(serde_json::json!({ "success": true })))
+    Ok((etag_header(&etag_for(Some(&now))), Json(serde_json::json!({ "success": true }))))
 }
 
 // GET /share/:share_id/select-options/:type - 共有ページ用の選択肢を取得（認証不要）
@@ -7327,8 +18504,10 @@ async fn get_readings(
 struct Notification {
     id: i32,
     user_id: i32,
-    stay_id: i32,
-    schedule_id: i32,
+    stay_id: Option<i32>,
+    ticket_application_id: Option<i32>,
+    budget_id: Option<i32>,
+    schedule_id: Option<i32>, // 予算の通知はスケジュールに紐づかないためNULL
     title: String,
     message: String,
     is_read: bool,
@@ -7340,8 +18519,10 @@ struct Notification {
 struct NotificationRow {
     id: i64,
     user_id: i64,
-    stay_id: i64,
-    schedule_id: i64,
+    stay_id: Option<i64>,
+    ticket_application_id: Option<i64>,
+    budget_id: Option<i64>,
+    schedule_id: Option<i64>,
     title: String,
     message: String,
     is_read: i32,
@@ -7353,8 +18534,10 @@ fn row_to_notification(row: NotificationRow) -> Notification {
     Notification {
         id: row.id as i32,
         user_id: row.user_id as i32,
-        stay_id: row.stay_id as i32,
-        schedule_id: row.schedule_id as i32,
+        stay_id: row.stay_id.map(|id| id as i32),
+        ticket_application_id: row.ticket_application_id.map(|id| id as i32),
+        budget_id: row.budget_id.map(|id| id as i32),
+        schedule_id: row.schedule_id.map(|id| id as i32),
         title: row.title,
         message: row.message,
         is_read: row.is_read != 0,
@@ -7368,7 +18551,7 @@ async fn list_notifications(
     Extension(pool): Extension<Pool<Sqlite>>,
 ) -> Result<Json<Vec<Notification>>, StatusCode> {
     let rows: Vec<NotificationRow> = sqlx::query_as(
-        "SELECT id, user_id, stay_id, schedule_id, title, message, is_read, created_at FROM notifications WHERE user_id = ? ORDER BY created_at DESC"
+        "SELECT id, user_id, stay_id, ticket_application_id, budget_id, schedule_id, title, message, is_read, created_at FROM notifications WHERE user_id = ? ORDER BY created_at DESC"
     )
     .bind(user.user_id as i64)
     .fetch_all(&pool)
@@ -7521,14 +18704,37 @@ async fn delete_push_token(
     Ok(Json(serde_json::json!({ "success": true })))
 }
 
+// 期限日時の文字列をパースする（RFC3339形式、または "YYYY-MM-DD HH:MM" 形式）
+// オフセットのない "YYYY-MM-DD HH:MM" 形式は、スケジュールのタイムゾーンでの現地時刻として扱う
+fn parse_deadline_datetime(value: &str, tz: Tz) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = value.parse::<DateTime<Utc>>() {
+        return Some(dt);
+    }
+    chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M")
+        .ok()
+        .map(|naive_dt| localize_datetime(naive_dt, tz).with_timezone(&Utc))
+}
+
+#[derive(sqlx::FromRow)]
+struct StayDeadlineRow {
+    id: i64,
+    schedule_id: i64,
+    user_id: i64,
+    hotel_name: String,
+    deadline: String,
+    title: String,
+    timezone: Option<String>,
+    user_timezone: Option<String>,
+}
+
 // キャンセル期限が24時間以内の宿泊情報をチェックし、通知を作成・送信
 async fn check_deadline_notifications(pool: &Pool<Sqlite>) -> Result<(), Box<dyn std::error::Error>> {
     let now = Utc::now();
     let one_day_later = now + chrono::Duration::hours(24);
     
     // 期限が24時間以内の宿泊情報を取得
-    // deadlineは "YYYY-MM-DD HH:MM" 形式で保存されていると仮定
-    let stays: Vec<(i64, i64, i64, String, String, String)> = sqlx::query_as(
+    // deadlineは "YYYY-MM-DD HH:MM" 形式（スケジュールのタイムゾーンでの現地時刻）で保存されていると仮定
+    let stays: Vec<StayDeadlineRow> = sqlx::query_as(
         r#"
         SELECT 
             st.id,
@@ -7536,35 +18742,38 @@ async fn check_deadline_notifications(pool: &Pool<Sqlite>) -> Result<(), Box<dyn
             s.user_id,
             st.hotel_name,
             st.deadline,
-            s.title
+            s.title,
+            s.timezone,
+            (SELECT users.timezone FROM users WHERE users.id = s.user_id) AS user_timezone
         FROM stays st
         INNER JOIN schedules s ON st.schedule_id = s.id
         WHERE st.deadline IS NOT NULL
           AND st.deadline != ''
           AND st.status != 'Canceled'
           AND s.user_id IS NOT NULL
+          AND s.deleted_at IS NULL
+          AND st.deleted_at IS NULL
         "#
     )
     .fetch_all(pool)
     .await?;
     
-    for (stay_id, schedule_id, user_id, hotel_name, deadline_str, schedule_title) in stays {
+    for stay in stays {
+        let StayDeadlineRow {
+            id: stay_id,
+            schedule_id,
+            user_id,
+            hotel_name,
+            deadline: deadline_str,
+            title: schedule_title,
+            timezone,
+            user_timezone,
+        } = stay;
+        let tz = resolve_timezone(timezone.as_deref(), user_timezone.as_deref());
         // deadlineをパース（複数の形式に対応）
-        let deadline = match deadline_str.parse::<DateTime<Utc>>() {
-            Ok(dt) => dt,
-            Err(_) => {
-                // RFC3339形式でパースできない場合は、"YYYY-MM-DD HH:MM" 形式を試す
-                match chrono::NaiveDateTime::parse_from_str(&deadline_str, "%Y-%m-%d %H:%M") {
-                    Ok(naive_dt) => {
-                        // UTCとして扱う（実際のタイムゾーンに応じて調整が必要な場合あり）
-                        DateTime::from_naive_utc_and_offset(naive_dt, Utc)
-                    }
-                    Err(_) => {
-                        eprintln!("[DEADLINE_CHECK] Failed to parse deadline: {}", deadline_str);
-                        continue;
-                    }
-                }
-            }
+        let Some(deadline) = parse_deadline_datetime(&deadline_str, tz) else {
+            eprintln!("[DEADLINE_CHECK] Failed to parse deadline: {}", deadline_str);
+            continue;
         };
         
         // 期限が24時間以内かチェック
@@ -7612,7 +18821,7 @@ async fn check_deadline_notifications(pool: &Pool<Sqlite>) -> Result<(), Box<dyn
             }
 
             let formatted_deadline = deadline
-                .with_timezone(&chrono::FixedOffset::east_opt(9 * 60 * 60).expect("valid JST offset"))
+                .with_timezone(&tz)
                 .format("%Y.%m.%d %H:%M")
                 .to_string();
 
@@ -7669,7 +18878,7 @@ async fn check_deadline_notifications(pool: &Pool<Sqlite>) -> Result<(), Box<dyn
                     &push_tokens,
                     &notification_title,
                     &format!("期限日時: {}", formatted_deadline),
-                    schedule_id,
+                    serde_json::json!({ "schedule_id": schedule_id }),
                 ).await {
                     Ok(()) => {
                         sqlx::query("UPDATE notifications SET push_sent_at = ? WHERE id = ?")
@@ -7689,6 +18898,351 @@ async fn check_deadline_notifications(pool: &Pool<Sqlite>) -> Result<(), Box<dyn
     Ok(())
 }
 
+#[derive(sqlx::FromRow)]
+struct PaymentDeadlineRow {
+    id: i64,
+    schedule_id: i64,
+    user_id: i64,
+    seller: Option<String>,
+    seat_type: Option<String>,
+    payment_deadline: String,
+    title: String,
+    timezone: Option<String>,
+    user_timezone: Option<String>,
+}
+
+// 支払期限が24時間以内の当選チケット申込をチェックし、通知を作成・送信
+// （宿泊のキャンセル期限通知と同じく、チャネルごとの送信済み時刻で重複送信を防ぐ）
+async fn check_payment_deadline_notifications(pool: &Pool<Sqlite>) -> Result<(), Box<dyn std::error::Error>> {
+    let now = Utc::now();
+    let one_day_later = now + chrono::Duration::hours(24);
+
+    let applications: Vec<PaymentDeadlineRow> = sqlx::query_as(
+        r#"
+        SELECT
+            ta.id,
+            ta.schedule_id,
+            s.user_id,
+            ta.seller,
+            ta.seat_type,
+            ta.payment_deadline,
+            s.title,
+            s.timezone,
+            (SELECT users.timezone FROM users WHERE users.id = s.user_id) AS user_timezone
+        FROM ticket_applications ta
+        INNER JOIN schedules s ON ta.schedule_id = s.id
+        WHERE ta.payment_deadline IS NOT NULL
+          AND ta.payment_deadline != ''
+          AND ta.result = 'won'
+          AND s.user_id IS NOT NULL
+          AND s.deleted_at IS NULL
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for application in applications {
+        let PaymentDeadlineRow {
+            id: application_id,
+            schedule_id,
+            user_id,
+            seller,
+            seat_type,
+            payment_deadline: deadline_str,
+            title: schedule_title,
+            timezone,
+            user_timezone,
+        } = application;
+        let tz = resolve_timezone(timezone.as_deref(), user_timezone.as_deref());
+        let Some(deadline) = parse_deadline_datetime(&deadline_str, tz) else {
+            eprintln!("[PAYMENT_DEADLINE_CHECK] Failed to parse payment deadline: {}", deadline_str);
+            continue;
+        };
+
+        if deadline <= now || deadline > one_day_later {
+            continue;
+        }
+
+        let existing_notification: Option<(i64, Option<String>, Option<String>)> = sqlx::query_as(
+            "SELECT id, email_sent_at, push_sent_at FROM notifications WHERE ticket_application_id = ? AND user_id = ? AND created_at > datetime('now', '-1 day') ORDER BY id DESC LIMIT 1"
+        )
+        .bind(application_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+        let user_row: Option<(String, i32, i32, i32)> = sqlx::query_as(
+            "SELECT email, email_verified, notify_email_enabled, notify_push_enabled FROM users WHERE id = ?"
+        )
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+        let Some((email, email_verified, notify_email_enabled, notify_push_enabled)) = user_row else {
+            continue;
+        };
+        let notify_email_enabled = notify_email_enabled != 0;
+        let notify_push_enabled = notify_push_enabled != 0;
+
+        let push_tokens: Vec<String> = if notify_push_enabled {
+            sqlx::query_scalar("SELECT token FROM push_tokens WHERE user_id = ?")
+                .bind(user_id)
+                .fetch_all(pool)
+                .await?
+        } else {
+            Vec::new()
+        };
+
+        let email_already_sent = matches!(existing_notification.as_ref(), Some((_, Some(_), _)));
+        let push_already_sent = matches!(existing_notification.as_ref(), Some((_, _, Some(_))));
+        let should_send_email = email_verified != 0 && notify_email_enabled && !email_already_sent;
+        let should_send_push = notify_push_enabled && !push_tokens.is_empty() && !push_already_sent;
+
+        if !should_send_email && !should_send_push && existing_notification.is_some() {
+            continue;
+        }
+
+        let formatted_deadline = deadline
+            .with_timezone(&tz)
+            .format("%Y.%m.%d %H:%M")
+            .to_string();
+
+        // 販売元・席種から申込を識別するラベルを作る（どちらも未入力ならイベント名のみ）
+        let application_label = [seller.as_deref(), seat_type.as_deref()]
+            .into_iter()
+            .flatten()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" / ");
+        let application_label = if application_label.is_empty() {
+            schedule_title.clone()
+        } else {
+            application_label
+        };
+
+        let notification_title = format!("支払期限が近づいています: {}", application_label);
+        let notification_message = format!("期限日時: {}\n関連イベント: {}", formatted_deadline, schedule_title);
+        let created_at = Utc::now().to_rfc3339();
+
+        let notification_id = if let Some((notification_id, _, _)) = existing_notification {
+            notification_id
+        } else {
+            sqlx::query_scalar::<_, i64>(
+                r#"
+                INSERT INTO notifications (user_id, stay_id, ticket_application_id, schedule_id, title, message, is_read, created_at, email_sent_at, push_sent_at)
+                VALUES (?, NULL, ?, ?, ?, ?, 0, ?, NULL, NULL)
+                RETURNING id
+                "#
+            )
+            .bind(user_id)
+            .bind(application_id)
+            .bind(schedule_id)
+            .bind(&notification_title)
+            .bind(&notification_message)
+            .bind(&created_at)
+            .fetch_one(pool)
+            .await?
+        };
+
+        if should_send_email {
+            match send_payment_deadline_notification_email(
+                &email,
+                &application_label,
+                &formatted_deadline,
+                &schedule_title,
+            ).await {
+                Ok(()) => {
+                    sqlx::query("UPDATE notifications SET email_sent_at = ? WHERE id = ?")
+                        .bind(Utc::now().to_rfc3339())
+                        .bind(notification_id)
+                        .execute(pool)
+                        .await?;
+                }
+                Err(e) => {
+                    eprintln!("[PAYMENT_DEADLINE_CHECK] Failed to send notification email; it will be retried: {:?}", e);
+                }
+            }
+        }
+
+        if should_send_push {
+            match send_expo_push_notifications(
+                pool,
+                &push_tokens,
+                &notification_title,
+                &format!("期限日時: {}", formatted_deadline),
+                serde_json::json!({ "schedule_id": schedule_id }),
+            ).await {
+                Ok(()) => {
+                    sqlx::query("UPDATE notifications SET push_sent_at = ? WHERE id = ?")
+                        .bind(Utc::now().to_rfc3339())
+                        .bind(notification_id)
+                        .execute(pool)
+                        .await?;
+                }
+                Err(e) => {
+                    eprintln!("[PAYMENT_DEADLINE_CHECK] Failed to send push notification; it will be retried: {:?}", e);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// 予算のしきい値を超えた（または予算を超過した）ことを通知する
+// 通知の段階はbudgets.alert_levelに保存し、段階が上がったときだけアプリ内通知を作成する。
+// 実績が減って段階が下がった場合はalert_levelを戻し、再び超えたときに通知する。
+// メール・プッシュは期限通知と同じく、チャネルごとの送信済み時刻で重複送信を防ぎ、失敗時は次回に再送する
+async fn check_budget_notifications(pool: &Pool<Sqlite>) -> Result<(), Box<dyn std::error::Error>> {
+    let budgets: Vec<BudgetRow> = sqlx::query_as(BUDGET_SELECT).fetch_all(pool).await?;
+
+    for budget in budgets {
+        let (actual, _) = fetch_budget_spend(pool, &budget).await?;
+        let level = budget_alert_level(actual, budget.amount, budget.alert_percent);
+        let stored_level = budget.alert_level as i32;
+
+        if level < stored_level {
+            sqlx::query("UPDATE budgets SET alert_level = ? WHERE id = ?")
+                .bind(level)
+                .bind(budget.id)
+                .execute(pool)
+                .await?;
+            continue;
+        }
+        if level == BUDGET_ALERT_NONE {
+            continue;
+        }
+
+        // 同じ段階の通知を作成済みの場合は、送信に失敗したメール・プッシュの再送だけを行う
+        let existing_notification: Option<(i64, Option<String>, Option<String>)> = if level == stored_level {
+            let existing = sqlx::query_as(
+                "SELECT id, email_sent_at, push_sent_at FROM notifications WHERE budget_id = ? AND user_id = ? AND created_at > datetime('now', '-1 day') ORDER BY id DESC LIMIT 1"
+            )
+            .bind(budget.id)
+            .bind(budget.user_id)
+            .fetch_optional(pool)
+            .await?;
+            if existing.is_none() {
+                continue;
+            }
+            existing
+        } else {
+            None
+        };
+
+        let user_row: Option<(String, i32, i32, i32)> = sqlx::query_as(
+            "SELECT email, email_verified, notify_email_enabled, notify_push_enabled FROM users WHERE id = ?"
+        )
+        .bind(budget.user_id)
+        .fetch_optional(pool)
+        .await?;
+
+        let Some((email, email_verified, notify_email_enabled, notify_push_enabled)) = user_row else {
+            continue;
+        };
+        let notify_email_enabled = notify_email_enabled != 0;
+        let notify_push_enabled = notify_push_enabled != 0;
+
+        let push_tokens: Vec<String> = if notify_push_enabled {
+            sqlx::query_scalar("SELECT token FROM push_tokens WHERE user_id = ?")
+                .bind(budget.user_id)
+                .fetch_all(pool)
+                .await?
+        } else {
+            Vec::new()
+        };
+
+        let email_already_sent = matches!(existing_notification.as_ref(), Some((_, Some(_), _)));
+        let push_already_sent = matches!(existing_notification.as_ref(), Some((_, _, Some(_))));
+        let should_send_email = email_verified != 0 && notify_email_enabled && !email_already_sent;
+        let should_send_push = notify_push_enabled && !push_tokens.is_empty() && !push_already_sent;
+
+        if !should_send_email && !should_send_push && existing_notification.is_some() {
+            continue;
+        }
+
+        let label = budget_label(budget.year, budget.month, budget.category.as_deref(), budget.target.as_deref());
+        let currency = fetch_user_home_currency(pool, budget.user_id as i32).await?;
+        let notification_title = if level == BUDGET_ALERT_OVER {
+            format!("予算を超えました: {}", label)
+        } else {
+            format!("予算の{}%に達しました: {}", budget.alert_percent, label)
+        };
+        let notification_message = format!(
+            "実績: {} {} / 予算: {} {}（{}%）",
+            actual,
+            currency,
+            budget.amount,
+            currency,
+            actual * 100 / budget.amount.max(1)
+        );
+
+        let notification_id = if let Some((notification_id, _, _)) = existing_notification {
+            notification_id
+        } else {
+            let notification_id = sqlx::query_scalar::<_, i64>(
+                r#"
+                INSERT INTO notifications (user_id, stay_id, ticket_application_id, budget_id, schedule_id, title, message, is_read, created_at, email_sent_at, push_sent_at)
+                VALUES (?, NULL, NULL, ?, NULL, ?, ?, 0, ?, NULL, NULL)
+                RETURNING id
+                "#
+            )
+            .bind(budget.user_id)
+            .bind(budget.id)
+            .bind(&notification_title)
+            .bind(&notification_message)
+            .bind(Utc::now().to_rfc3339())
+            .fetch_one(pool)
+            .await?;
+            sqlx::query("UPDATE budgets SET alert_level = ? WHERE id = ?")
+                .bind(level)
+                .bind(budget.id)
+                .execute(pool)
+                .await?;
+            notification_id
+        };
+
+        if should_send_email {
+            match send_budget_alert_email(&email, &notification_title, &notification_message).await {
+                Ok(()) => {
+                    sqlx::query("UPDATE notifications SET email_sent_at = ? WHERE id = ?")
+                        .bind(Utc::now().to_rfc3339())
+                        .bind(notification_id)
+                        .execute(pool)
+                        .await?;
+                }
+                Err(e) => {
+                    eprintln!("[BUDGET_CHECK] Failed to send notification email; it will be retried: {:?}", e);
+                }
+            }
+        }
+
+        if should_send_push {
+            match send_expo_push_notifications(
+                pool,
+                &push_tokens,
+                &notification_title,
+                &notification_message,
+                serde_json::json!({ "budget_id": budget.id }),
+            ).await {
+                Ok(()) => {
+                    sqlx::query("UPDATE notifications SET push_sent_at = ? WHERE id = ?")
+                        .bind(Utc::now().to_rfc3339())
+                        .bind(notification_id)
+                        .execute(pool)
+                        .await?;
+                }
+                Err(e) => {
+                    eprintln!("[BUDGET_CHECK] Failed to send push notification; it will be retried: {:?}", e);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
 // ====== メイン ======
 
 // メールアドレスからユーザーIDを取得するヘルパー関数
@@ -7936,6 +19490,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
+    // 一覧のページング用カーソルをブラウザから読めるようにする
+    let cors = cors.expose_headers([
+        axum::http::HeaderName::from_static(NEXT_CURSOR_HEADER),
+        axum::http::header::ETAG,
+    ]);
+
     let app = Router::new()
         .route("/health", get(health_check))
         .route("/contact", post(submit_contact))
@@ -7968,6 +19528,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/share/:share_id/select-options/:type", get(get_shared_select_options))
         .route("/share/:share_id/stay-select-options/:type", get(get_shared_stay_select_options))
         .route("/share/:share_id/stay/:id", get(get_shared_stay))
+        .route("/share/:share_id/year-reviews/:year", get(get_shared_year_review))
         .route("/public/schedules", get(list_public_schedules))
         .route("/public/schedules/:id", get(get_public_schedule))
         .route("/public/traffic", get(list_public_traffics))
@@ -7975,14 +19536,73 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/public/stay", get(list_public_stays))
         .route("/public/stay/:id", get(get_public_stay))
         .route("/schedules", get(list_schedules).post(create_schedule))
-        .route("/schedules/:id", put(update_schedule).delete(delete_schedule))
+        .route("/schedules/:id", get(get_schedule).put(update_schedule).patch(patch_schedule).delete(delete_schedule))
         .route("/schedules/upcoming", get(list_upcoming))
+        .route("/tickets", get(list_day_tickets))
+        .route("/companions", get(list_companions).post(create_companion))
+        .route("/companions/:id", put(update_companion).delete(delete_companion))
+        .route("/cost-splits/:entity_type/:entity_id", get(get_cost_split).put(save_cost_split).delete(delete_cost_split))
+        .route("/balances", get(get_balances))
+        .route("/exchange-rates", get(list_exchange_rates))
+        .route("/exchange-rates/:base/:quote", put(save_exchange_rate).delete(delete_exchange_rate))
         .route("/traffic", get(list_traffics).post(create_traffic))
         .route("/traffic/all", get(list_all_traffics))
-        .route("/traffic/:id", get(get_traffic).put(update_traffic))
+        .route("/traffic/:id", get(get_traffic).put(update_traffic).patch(patch_traffic).delete(delete_traffic))
         .route("/stay", get(list_stays).post(create_stay))
         .route("/stay/all", get(list_all_stays))
-        .route("/stay/:id", get(get_stay).put(update_stay))
+        .route("/stay/:id", get(get_stay).put(update_stay).patch(patch_stay).delete(delete_stay))
+        .route("/ticket-applications", get(list_ticket_applications).post(create_ticket_application))
+        .route("/ticket-applications/:id", get(get_ticket_application).put(update_ticket_application).delete(delete_ticket_application))
+        .route("/ticket-transfers", get(list_ticket_transfers).post(create_ticket_transfer))
+        .route("/ticket-transfers/:id", get(get_ticket_transfer).put(update_ticket_transfer).delete(delete_ticket_transfer))
+        .route("/goods", get(list_goods).post(create_goods))
+        .route("/goods/collection", get(get_goods_collection))
+        .route("/goods/:id", get(get_goods).put(update_goods).delete(delete_goods))
+        .route("/expenses", get(list_expenses).post(create_expense))
+        .route("/expenses/:id", get(get_expense).put(update_expense).delete(delete_expense))
+        .route("/budgets", get(list_budgets).post(create_budget))
+        .route("/budgets/:id", get(get_budget).put(update_budget).delete(delete_budget))
+        .route("/stats", get(get_stats))
+        .route("/checks", get(list_checks))
+        .route("/schedules/:id/checks", get(get_schedule_checks))
+        .route("/schedules/:id/journey", get(get_journey))
+        .route("/schedules/:id/journey/order", put(reorder_journey))
+        .route("/schedules/:id/status-history", get(get_schedule_status_history))
+        .route("/stats/compare", get(get_stats_comparison))
+        .route("/year-reviews", get(list_year_reviews))
+        .route(
+            "/year-reviews/:year",
+            get(get_year_review)
+                .post(generate_year_review_handler)
+                .put(update_year_review)
+                .delete(delete_year_review),
+        )
+        .route("/schedules/:id/setlist", get(get_setlist).put(save_setlist))
+        .route("/setlist/songs", get(list_song_stats))
+        .route("/setlist/songs/history", get(get_song_history))
+        .route("/tours", get(list_tours).post(create_tour))
+        .route("/tours/group-candidates", get(list_tour_group_candidates))
+        .route("/tours/import-groups", post(import_tour_groups))
+        .route("/tours/:id", get(get_tour).put(update_tour).delete(delete_tour))
+        .route("/tours/:id/schedules", post(add_tour_schedule))
+        .route("/tours/:id/schedules/:schedule_id", delete(remove_tour_schedule))
+        .route("/search", get(search))
+        .route("/trash", get(list_trash))
+        .route("/trash/schedules/:id/restore", post(restore_schedule))
+        .route("/trash/traffics/:id/restore", post(restore_traffic))
+        .route("/trash/stays/:id/restore", post(restore_stay))
+        .route("/schedules/:id/revisions", get(list_schedule_revisions))
+        .route("/schedules/:id/revisions/:revision_id/revert", post(revert_schedule_revision))
+        .route("/schedules/:id/clone", post(clone_schedule))
+        .route("/schedules/:id/attachments", get(list_schedule_attachments))
+        .route(
+            "/attachments",
+            post(upload_attachment)
+                .layer(axum::extract::DefaultBodyLimit::max(get_attachment_max_file_bytes() + 64 * 1024)),
+        )
+        .route("/attachments/:id", get(download_attachment).put(update_attachment).delete(delete_attachment))
+        .route("/share/:share_id/schedules/:id/attachments", get(list_shared_attachments))
+        .route("/share/:share_id/attachments/:id", get(download_shared_attachment))
         .route("/select-options/:type", get(get_select_options).post(save_select_options))
         .route("/stay-select-options/:type", get(get_stay_select_options).post(save_stay_select_options))
         .route("/reading", post(get_readings))
@@ -8014,6 +19634,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             if let Err(e) = check_deadline_notifications(&pool_clone).await {
                 eprintln!("[BACKGROUND_TASK] Error checking deadline notifications: {:?}", e);
             }
+            if let Err(e) = check_payment_deadline_notifications(&pool_clone).await {
+                eprintln!("[BACKGROUND_TASK] Error checking payment deadline notifications: {:?}", e);
+            }
+            if let Err(e) = check_budget_notifications(&pool_clone).await {
+                eprintln!("[BACKGROUND_TASK] Error checking budget notifications: {:?}", e);
+            }
+            if let Err(e) = auto_complete_schedules(&pool_clone).await {
+                eprintln!("[BACKGROUND_TASK] Error completing finished schedules: {:?}", e);
+            }
+            if let Err(e) = purge_expired_trash(&pool_clone).await {
+                eprintln!("[BACKGROUND_TASK] Error purging expired trash: {:?}", e);
+            }
         }
     });
 
@@ -8209,33 +19841,337 @@ async fn init_db(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
     );
     "#;
 
-    let create_stay_select_options = r#"
-    CREATE TABLE IF NOT EXISTS stay_select_options (
+    let create_stay_select_options = r#"
+    CREATE TABLE IF NOT EXISTS stay_select_options (
+      id           INTEGER PRIMARY KEY AUTOINCREMENT,
+      user_id      INTEGER NOT NULL,
+      option_type  TEXT NOT NULL,
+      options_json TEXT NOT NULL,
+      created_at   TEXT,
+      updated_at   TEXT,
+      UNIQUE(user_id, option_type)
+    );
+    "#;
+
+    let create_notifications = r#"
+    CREATE TABLE IF NOT EXISTS notifications (
+      id           INTEGER PRIMARY KEY AUTOINCREMENT,
+      user_id      INTEGER NOT NULL,
+      stay_id      INTEGER,
+      ticket_application_id INTEGER,
+      budget_id    INTEGER,
+      schedule_id  INTEGER,
+      title         TEXT NOT NULL,
+      message       TEXT NOT NULL,
+      is_read       INTEGER NOT NULL DEFAULT 0,
+      created_at    TEXT,
+      email_sent_at TEXT,
+      push_sent_at  TEXT,
+      FOREIGN KEY (user_id) REFERENCES users(id),
+      FOREIGN KEY (stay_id) REFERENCES stays(id),
+      FOREIGN KEY (ticket_application_id) REFERENCES ticket_applications(id),
+      FOREIGN KEY (budget_id) REFERENCES budgets(id),
+      FOREIGN KEY (schedule_id) REFERENCES schedules(id)
+    );
+    "#;
+
+    // チケットの抽選申込。1スケジュールに対して複数の申込（先行・一般など）を持てる。
+    // result: applied / won / lost / paid / expired（won・paidの申込はticket_fee_effectiveのロールアップ対象）
+    let create_ticket_applications = r#"
+    CREATE TABLE IF NOT EXISTS ticket_applications (
+      id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+      schedule_id         INTEGER NOT NULL,
+      seller              TEXT,
+      seat_type           TEXT,
+      quantity            INTEGER NOT NULL DEFAULT 1,
+      price               INTEGER,
+      currency            TEXT NOT NULL DEFAULT 'JPY',
+      application_start   TEXT,
+      application_end     TEXT,
+      result_announced_at TEXT,
+      payment_deadline    TEXT,
+      result              TEXT NOT NULL DEFAULT 'applied',
+      notes               TEXT,
+      created_at          TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      updated_at          TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      FOREIGN KEY (schedule_id) REFERENCES schedules(id) ON DELETE CASCADE
+    );
+    "#;
+
+    // チケットの譲渡・リセールの記録。受取額から手数料を引いた金額をschedules.net_costから差し引く
+    // method: resale / transfer / other
+    let create_ticket_transfers = r#"
+    CREATE TABLE IF NOT EXISTS ticket_transfers (
+      id             INTEGER PRIMARY KEY AUTOINCREMENT,
+      schedule_id    INTEGER NOT NULL,
+      method         TEXT NOT NULL DEFAULT 'transfer',
+      counterparty   TEXT,
+      transferred_on TEXT NOT NULL,
+      quantity       INTEGER NOT NULL DEFAULT 1,
+      price_received INTEGER NOT NULL DEFAULT 0,
+      fees           INTEGER NOT NULL DEFAULT 0,
+      currency       TEXT NOT NULL DEFAULT 'JPY',
+      notes          TEXT,
+      created_at     TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      updated_at     TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      FOREIGN KEY (schedule_id) REFERENCES schedules(id) ON DELETE CASCADE
+    );
+    "#;
+
+    // 公演で買ったグッズ。価格 × 個数の合計をschedules.goods_costに集計する
+    // channel: venue / online / mail_order / other
+    let create_goods = r#"
+    CREATE TABLE IF NOT EXISTS goods (
+      id          INTEGER PRIMARY KEY AUTOINCREMENT,
+      schedule_id INTEGER NOT NULL,
+      name        TEXT NOT NULL,
+      target      TEXT,
+      price       INTEGER,
+      quantity    INTEGER NOT NULL DEFAULT 1,
+      currency    TEXT NOT NULL DEFAULT 'JPY',
+      channel     TEXT NOT NULL DEFAULT 'venue',
+      notes       TEXT,
+      created_at  TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      updated_at  TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      FOREIGN KEY (schedule_id) REFERENCES schedules(id) ON DELETE CASCADE
+    );
+    "#;
+
+    // 諸経費の明細（食事・コインロッカー・タクシーなど）。合計をschedules.other_costに集計し、total_costに含める
+    // categoryの選択肢はselect_options（option_type = EXPENSE_CATEGORIES）で管理する
+    let create_expenses = r#"
+    CREATE TABLE IF NOT EXISTS expenses (
+      id          INTEGER PRIMARY KEY AUTOINCREMENT,
+      schedule_id INTEGER NOT NULL,
+      category    TEXT,
+      description TEXT,
+      amount      INTEGER NOT NULL DEFAULT 0,
+      currency    TEXT NOT NULL DEFAULT 'JPY',
+      spent_on    TEXT,
+      notes       TEXT,
+      created_at  TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      updated_at  TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      FOREIGN KEY (schedule_id) REFERENCES schedules(id) ON DELETE CASCADE
+    );
+    "#;
+
+    // 年間の振り返りのスナップショット。summary_jsonは生成時点の集計結果（マスク前）
+    let create_year_reviews = r#"
+    CREATE TABLE IF NOT EXISTS year_reviews (
+      id           INTEGER PRIMARY KEY AUTOINCREMENT,
+      user_id      INTEGER NOT NULL,
+      year         INTEGER NOT NULL,
+      summary_json TEXT NOT NULL,
+      is_shared    INTEGER NOT NULL DEFAULT 0,
+      generated_at TEXT NOT NULL,
+      UNIQUE (user_id, year),
+      FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+    );
+    "#;
+
+    // 年間・月間の予算（monthがNULLの場合は年間予算）。amountは基準通貨。
+    // alert_level: 通知済みの段階（0: なし, 1: alert_percent%に到達, 2: 超過）
+    let create_budgets = r#"
+    CREATE TABLE IF NOT EXISTS budgets (
+      id            INTEGER PRIMARY KEY AUTOINCREMENT,
+      user_id       INTEGER NOT NULL,
+      year          INTEGER NOT NULL,
+      month         INTEGER,
+      category      TEXT,
+      target        TEXT,
+      amount        INTEGER NOT NULL,
+      alert_percent INTEGER NOT NULL DEFAULT 80,
+      alert_level   INTEGER NOT NULL DEFAULT 0,
+      created_at    TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      updated_at    TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+    );
+    "#;
+
+    // 複数日開催（フェス等）の各日程。schedules.end_dateが設定されているスケジュールのみ持つ
+    let create_schedule_days = r#"
+    CREATE TABLE IF NOT EXISTS schedule_days (
+      id           INTEGER PRIMARY KEY AUTOINCREMENT,
+      schedule_id  INTEGER NOT NULL,
+      date         TEXT NOT NULL,
+      open         TEXT,
+      start        TEXT,
+      "end"        TEXT,
+      lineup       TEXT,
+      attended     INTEGER NOT NULL DEFAULT 1,
+      ticket_fee   INTEGER,
+      drink_fee    INTEGER,
+      created_at   TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      updated_at   TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      FOREIGN KEY (schedule_id) REFERENCES schedules(id) ON DELETE CASCADE,
+      UNIQUE(schedule_id, date)
+    );
+    "#;
+
+    // チケット・座席情報。複数枚購入した場合は1枚ずつ行を持ち、positionは入力順（1始まり）
+    let create_schedule_tickets = r#"
+    CREATE TABLE IF NOT EXISTS schedule_tickets (
+      id           INTEGER PRIMARY KEY AUTOINCREMENT,
+      schedule_id  INTEGER NOT NULL,
+      position     INTEGER NOT NULL,
+      ticket_type  TEXT,
+      seat_block   TEXT,
+      seat_row     TEXT,
+      seat_number  TEXT,
+      entry_number TEXT,
+      gate         TEXT,
+      created_at   TEXT NOT NULL,
+      updated_at   TEXT NOT NULL,
+      FOREIGN KEY (schedule_id) REFERENCES schedules(id) ON DELETE CASCADE
+    );
+    "#;
+
+    // 為替レート（ユーザーが登録する。1 base_currency = rate quote_currency）
+    let create_exchange_rates = r#"
+    CREATE TABLE IF NOT EXISTS exchange_rates (
+      id             INTEGER PRIMARY KEY AUTOINCREMENT,
+      user_id        INTEGER NOT NULL,
+      base_currency  TEXT NOT NULL,
+      quote_currency TEXT NOT NULL,
+      rate           REAL NOT NULL,
+      created_at     TEXT NOT NULL,
+      updated_at     TEXT NOT NULL,
+      UNIQUE(user_id, base_currency, quote_currency)
+    );
+    "#;
+
+    // 同行者（ユーザーごとのアドレス帳）。linked_user_idは共有を有効にしている登録ユーザーと紐づけた場合のみ
+    let create_companions = r#"
+    CREATE TABLE IF NOT EXISTS companions (
+      id             INTEGER PRIMARY KEY AUTOINCREMENT,
+      user_id        INTEGER NOT NULL,
+      name           TEXT NOT NULL,
+      linked_user_id INTEGER,
+      created_at     TEXT NOT NULL,
+      updated_at     TEXT NOT NULL
+    );
+    "#;
+
+    // 割り勘の設定（スケジュール・交通・宿泊ごとに1件）。paid_byがNULLの場合は自分が支払った
+    let create_cost_splits = r#"
+    CREATE TABLE IF NOT EXISTS cost_splits (
+      id          INTEGER PRIMARY KEY AUTOINCREMENT,
+      user_id     INTEGER NOT NULL,
+      schedule_id INTEGER NOT NULL,
+      entity_type TEXT NOT NULL,
+      entity_id   INTEGER NOT NULL,
+      rule        TEXT NOT NULL,
+      paid_by     INTEGER,
+      created_at  TEXT NOT NULL,
+      updated_at  TEXT NOT NULL,
+      UNIQUE(entity_type, entity_id)
+    );
+    "#;
+
+    // 割り勘のメンバー。companion_idがNULLの行は自分
+    let create_cost_split_members = r#"
+    CREATE TABLE IF NOT EXISTS cost_split_members (
+      id           INTEGER PRIMARY KEY AUTOINCREMENT,
+      split_id     INTEGER NOT NULL,
+      companion_id INTEGER,
+      weight       INTEGER NOT NULL DEFAULT 1,
+      fixed_amount INTEGER,
+      FOREIGN KEY (split_id) REFERENCES cost_splits(id) ON DELETE CASCADE
+    );
+    "#;
+
+    // ツアー（シリーズ）。名前はユーザーごとに一意（groupからの取り込み時に同名ツアーへまとめるため）
+    let create_tours = r#"
+    CREATE TABLE IF NOT EXISTS tours (
+      id            INTEGER PRIMARY KEY AUTOINCREMENT,
+      user_id       INTEGER NOT NULL,
+      name          TEXT NOT NULL,
+      artist        TEXT,
+      start_date    TEXT,
+      end_date      TEXT,
+      official_url  TEXT,
+      created_at    TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      updated_at    TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+      UNIQUE(user_id, name)
+    );
+    "#;
+
+    // 変更履歴。snapshotは変更後（削除の場合は削除前）の行のJSON、changesは項目ごとの変更前後
+    let create_revisions = r#"
+    CREATE TABLE IF NOT EXISTS revisions (
+      id          INTEGER PRIMARY KEY AUTOINCREMENT,
+      user_id     INTEGER NOT NULL,
+      entity_type TEXT NOT NULL,
+      entity_id   INTEGER NOT NULL,
+      schedule_id INTEGER NOT NULL,
+      action      TEXT NOT NULL,
+      snapshot    TEXT,
+      changes     TEXT NOT NULL,
+      created_at  TEXT NOT NULL
+    );
+    "#;
+
+    // スケジュールのステータス変更の記録。from_statusは作成時はNULL、sourceはmanual / auto / revert
+    let create_schedule_status_history = r#"
+    CREATE TABLE IF NOT EXISTS schedule_status_history (
+      id          INTEGER PRIMARY KEY AUTOINCREMENT,
+      schedule_id INTEGER NOT NULL,
+      user_id     INTEGER NOT NULL,
+      from_status TEXT,
+      to_status   TEXT NOT NULL,
+      source      TEXT NOT NULL DEFAULT 'manual',
+      changed_at  TEXT NOT NULL,
+      FOREIGN KEY (schedule_id) REFERENCES schedules(id) ON DELETE CASCADE
+    );
+    "#;
+
+    // 添付ファイルのメタデータ。ファイル本体はstorage_keyでAttachmentStorageから読み書きする
+    // schedule_idは添付先が交通・宿泊の場合も所属スケジュールを持ち、一覧と削除に使う
+    let create_attachments = r#"
+    CREATE TABLE IF NOT EXISTS attachments (
       id           INTEGER PRIMARY KEY AUTOINCREMENT,
+      public_id    TEXT NOT NULL UNIQUE,
       user_id      INTEGER NOT NULL,
-      option_type  TEXT NOT NULL,
-      options_json TEXT NOT NULL,
-      created_at   TEXT,
-      updated_at   TEXT,
-      UNIQUE(user_id, option_type)
+      schedule_id  INTEGER NOT NULL,
+      entity_type  TEXT NOT NULL,
+      entity_id    INTEGER NOT NULL,
+      file_name    TEXT NOT NULL,
+      content_type TEXT NOT NULL,
+      size         INTEGER NOT NULL,
+      shareable    INTEGER NOT NULL DEFAULT 0,
+      storage_key  TEXT NOT NULL,
+      created_at   TEXT NOT NULL
     );
     "#;
 
-    let create_notifications = r#"
-    CREATE TABLE IF NOT EXISTS notifications (
+    // 全文検索インデックス。bodyには正規化済みテキスト（normalize_search_text）を保存する
+    // trigramトークナイザは日本語のように単語区切りのない文字列でも部分一致で検索できる
+    let create_search_index = r#"
+    CREATE VIRTUAL TABLE IF NOT EXISTS search_index USING fts5(
+      body,
+      entity_type UNINDEXED,
+      entity_id UNINDEXED,
+      schedule_id UNINDEXED,
+      user_id UNINDEXED,
+      tokenize = 'trigram'
+    );
+    "#;
+
+    // セットリスト。positionは1始まりの曲順（アンコールもis_encoreを付けて通し番号で並べる）
+    let create_setlist_entries = r#"
+    CREATE TABLE IF NOT EXISTS setlist_entries (
       id           INTEGER PRIMARY KEY AUTOINCREMENT,
-      user_id      INTEGER NOT NULL,
-      stay_id      INTEGER NOT NULL,
       schedule_id  INTEGER NOT NULL,
-      title         TEXT NOT NULL,
-      message       TEXT NOT NULL,
-      is_read       INTEGER NOT NULL DEFAULT 0,
-      created_at    TEXT,
-      email_sent_at TEXT,
-      push_sent_at  TEXT,
-      FOREIGN KEY (user_id) REFERENCES users(id),
-      FOREIGN KEY (stay_id) REFERENCES stays(id),
-      FOREIGN KEY (schedule_id) REFERENCES schedules(id)
+      position     INTEGER NOT NULL,
+      song_title   TEXT NOT NULL,
+      artist       TEXT,
+      is_encore    INTEGER NOT NULL DEFAULT 0,
+      notes        TEXT,
+      created_at   TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      updated_at   TEXT DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ','now')),
+      FOREIGN KEY (schedule_id) REFERENCES schedules(id) ON DELETE CASCADE,
+      UNIQUE(schedule_id, position)
     );
     "#;
 
@@ -8293,6 +20229,24 @@ async fn init_db(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
     sqlx::query(create_push_tokens).execute(pool).await?;
     sqlx::query(create_masked_locations).execute(pool).await?;
     sqlx::query(create_subscriptions).execute(pool).await?;
+    sqlx::query(create_ticket_applications).execute(pool).await?;
+    sqlx::query(create_ticket_transfers).execute(pool).await?;
+    sqlx::query(create_goods).execute(pool).await?;
+    sqlx::query(create_expenses).execute(pool).await?;
+    sqlx::query(create_budgets).execute(pool).await?;
+    sqlx::query(create_year_reviews).execute(pool).await?;
+    sqlx::query(create_schedule_days).execute(pool).await?;
+    sqlx::query(create_schedule_tickets).execute(pool).await?;
+    sqlx::query(create_companions).execute(pool).await?;
+    sqlx::query(create_cost_splits).execute(pool).await?;
+    sqlx::query(create_cost_split_members).execute(pool).await?;
+    sqlx::query(create_exchange_rates).execute(pool).await?;
+    sqlx::query(create_setlist_entries).execute(pool).await?;
+    sqlx::query(create_tours).execute(pool).await?;
+    sqlx::query(create_search_index).execute(pool).await?;
+    sqlx::query(create_revisions).execute(pool).await?;
+    sqlx::query(create_schedule_status_history).execute(pool).await?;
+    sqlx::query(create_attachments).execute(pool).await?;
     
     // 既存のselect_optionsテーブルからFOREIGN KEY制約を削除（マイグレーション）
     // SQLiteではALTER TABLEでFOREIGN KEY制約を削除できないため、
@@ -8386,6 +20340,139 @@ async fn init_db(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
         eprintln!("[Migration] Added notifications.push_sent_at column");
     }
 
+    // notifications.stay_idをNULL許可にし、ticket_application_idを追加する（既存のデータベース用マイグレーション）
+    // チケット申込の支払期限通知は宿泊情報に紐づかないため、stay_idのNOT NULL制約を外す必要がある
+    // SQLiteではALTER TABLE MODIFY COLUMNがサポートされていないため、テーブルを再作成する
+    let notifications_stay_id_not_null: i64 = sqlx::query_scalar(
+        "SELECT \"notnull\" FROM pragma_table_info('notifications') WHERE name = 'stay_id'"
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if notifications_stay_id_not_null != 0 {
+        let mut conn = pool.acquire().await?;
+        sqlx::query("PRAGMA foreign_keys = OFF")
+            .execute(&mut *conn)
+            .await?;
+
+        let mut tx = conn.begin().await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE notifications_new (
+              id           INTEGER PRIMARY KEY AUTOINCREMENT,
+              user_id      INTEGER NOT NULL,
+              stay_id      INTEGER,
+              ticket_application_id INTEGER,
+              schedule_id  INTEGER NOT NULL,
+              title         TEXT NOT NULL,
+              message       TEXT NOT NULL,
+              is_read       INTEGER NOT NULL DEFAULT 0,
+              created_at    TEXT,
+              email_sent_at TEXT,
+              push_sent_at  TEXT,
+              FOREIGN KEY (user_id) REFERENCES users(id),
+              FOREIGN KEY (stay_id) REFERENCES stays(id),
+              FOREIGN KEY (ticket_application_id) REFERENCES ticket_applications(id),
+              FOREIGN KEY (schedule_id) REFERENCES schedules(id)
+            )
+            "#
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO notifications_new (
+              id, user_id, stay_id, schedule_id, title, message, is_read, created_at,
+              email_sent_at, push_sent_at
+            )
+            SELECT
+              id, user_id, stay_id, schedule_id, title, message, is_read, created_at,
+              email_sent_at, push_sent_at
+            FROM notifications
+            "#
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DROP TABLE notifications").execute(&mut *tx).await?;
+        sqlx::query("ALTER TABLE notifications_new RENAME TO notifications")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        sqlx::query("PRAGMA foreign_keys = ON")
+            .execute(&mut *conn)
+            .await?;
+
+        eprintln!("[Migration] notifications.stay_id is now nullable; added notifications.ticket_application_id");
+    }
+
+    // notifications.schedule_idをNULL許可にし、budget_idを追加する（既存のデータベース用マイグレーション）
+    // 予算の通知は特定のスケジュールに紐づかないため、schedule_idのNOT NULL制約を外す
+    let notifications_schedule_id_not_null: i64 = sqlx::query_scalar(
+        "SELECT \"notnull\" FROM pragma_table_info('notifications') WHERE name = 'schedule_id'"
+    )
+    .fetch_one(pool)
+    .await?;
+
+    if notifications_schedule_id_not_null != 0 {
+        let mut conn = pool.acquire().await?;
+        sqlx::query("PRAGMA foreign_keys = OFF")
+            .execute(&mut *conn)
+            .await?;
+
+        let mut tx = conn.begin().await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE notifications_new (
+              id           INTEGER PRIMARY KEY AUTOINCREMENT,
+              user_id      INTEGER NOT NULL,
+              stay_id      INTEGER,
+              ticket_application_id INTEGER,
+              budget_id    INTEGER,
+              schedule_id  INTEGER,
+              title         TEXT NOT NULL,
+              message       TEXT NOT NULL,
+              is_read       INTEGER NOT NULL DEFAULT 0,
+              created_at    TEXT,
+              email_sent_at TEXT,
+              push_sent_at  TEXT,
+              FOREIGN KEY (user_id) REFERENCES users(id),
+              FOREIGN KEY (stay_id) REFERENCES stays(id),
+              FOREIGN KEY (ticket_application_id) REFERENCES ticket_applications(id),
+              FOREIGN KEY (budget_id) REFERENCES budgets(id),
+              FOREIGN KEY (schedule_id) REFERENCES schedules(id)
+            )
+            "#
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO notifications_new (
+              id, user_id, stay_id, ticket_application_id, schedule_id, title, message, is_read, created_at,
+              email_sent_at, push_sent_at
+            )
+            SELECT
+              id, user_id, stay_id, ticket_application_id, schedule_id, title, message, is_read, created_at,
+              email_sent_at, push_sent_at
+            FROM notifications
+            "#
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DROP TABLE notifications").execute(&mut *tx).await?;
+        sqlx::query("ALTER TABLE notifications_new RENAME TO notifications")
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        sqlx::query("PRAGMA foreign_keys = ON")
+            .execute(&mut *conn)
+            .await?;
+
+        eprintln!("[Migration] notifications.schedule_id is now nullable; added notifications.budget_id");
+    }
+
     // targetカラムがNULL許可であることを確認（既存のデータベース用マイグレーション）
     // SQLiteではALTER TABLE MODIFY COLUMNがサポートされていないため、
     // 既存のテーブルを再作成する必要があるが、データ損失を避けるため、
@@ -8785,6 +20872,113 @@ async fn init_db(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
     backfill_public_ids(pool, "traffics").await?;
     backfill_public_ids(pool, "stays").await?;
 
+    // 総費用に使うチケット代（calculate_rollupで更新する。既存行にチケット申込はないため手入力のticket_feeと同じ）
+    if !column_exists(pool, "schedules", "ticket_fee_effective").await? {
+        sqlx::query("ALTER TABLE schedules ADD COLUMN ticket_fee_effective INTEGER")
+            .execute(pool)
+            .await?;
+        sqlx::query("UPDATE schedules SET ticket_fee_effective = ticket_fee")
+            .execute(pool)
+            .await?;
+        eprintln!("[Migration] Added schedules.ticket_fee_effective column");
+    }
+
+    // 複数日開催（フェス等）の最終日カラムを追加（マイグレーション）
+    // 既存のスケジュールはすべて単日開催のため、NULLのままでよい
+    if !column_exists(pool, "schedules", "end_date").await? {
+        sqlx::query("ALTER TABLE schedules ADD COLUMN end_date TEXT")
+            .execute(pool)
+            .await?;
+        eprintln!("[Migration] Added schedules.end_date column");
+    }
+
+    // 総費用に使うドリンク代（calculate_rollupで更新する。既存行に日別のドリンク代はないため手入力のdrink_feeと同じ）
+    if !column_exists(pool, "schedules", "drink_fee_effective").await? {
+        sqlx::query("ALTER TABLE schedules ADD COLUMN drink_fee_effective INTEGER")
+            .execute(pool)
+            .await?;
+        sqlx::query("UPDATE schedules SET drink_fee_effective = drink_fee")
+            .execute(pool)
+            .await?;
+        eprintln!("[Migration] Added schedules.drink_fee_effective column");
+    }
+
+    // セットリストを共有ページで公開するかのフラグ（既存のスケジュールは非公開）
+    if !column_exists(pool, "schedules", "setlist_public").await? {
+        sqlx::query("ALTER TABLE schedules ADD COLUMN setlist_public INTEGER NOT NULL DEFAULT 0")
+            .execute(pool)
+            .await?;
+        eprintln!("[Migration] Added schedules.setlist_public column");
+    }
+
+    // 公開・共有ページで座席・整理番号を表示するか（既定は非表示）
+    if !column_exists(pool, "schedules", "seat_public").await? {
+        sqlx::query("ALTER TABLE schedules ADD COLUMN seat_public INTEGER NOT NULL DEFAULT 0")
+            .execute(pool)
+            .await?;
+        eprintln!("[Migration] Added schedules.seat_public column");
+    }
+
+    // 割り勘を反映した自分の負担額（calculate_rollupで更新する。未計算の既存行はtotal_costと同じ）
+    if !column_exists(pool, "schedules", "my_share").await? {
+        sqlx::query("ALTER TABLE schedules ADD COLUMN my_share INTEGER")
+            .execute(pool)
+            .await?;
+        sqlx::query("UPDATE schedules SET my_share = total_cost")
+            .execute(pool)
+            .await?;
+        eprintln!("[Migration] Added schedules.my_share column");
+    }
+
+    // 譲渡・リセールの受取額を差し引いた実質の費用（calculate_rollupで更新する。既存行に譲渡の記録はないためmy_shareと同じ）
+    if !column_exists(pool, "schedules", "net_cost").await? {
+        sqlx::query("ALTER TABLE schedules ADD COLUMN net_cost INTEGER")
+            .execute(pool)
+            .await?;
+        sqlx::query("UPDATE schedules SET net_cost = my_share")
+            .execute(pool)
+            .await?;
+        eprintln!("[Migration] Added schedules.net_cost column");
+    }
+
+    // グッズ代の合計（calculate_rollupで更新する。既存行にグッズの記録はないため0）
+    if !column_exists(pool, "schedules", "goods_cost").await? {
+        sqlx::query("ALTER TABLE schedules ADD COLUMN goods_cost INTEGER NOT NULL DEFAULT 0")
+            .execute(pool)
+            .await?;
+        eprintln!("[Migration] Added schedules.goods_cost column");
+    }
+
+    // 諸経費の合計（calculate_rollupで更新する。既存行に明細はないため0）
+    if !column_exists(pool, "schedules", "other_cost").await? {
+        sqlx::query("ALTER TABLE schedules ADD COLUMN other_cost INTEGER NOT NULL DEFAULT 0")
+            .execute(pool)
+            .await?;
+        eprintln!("[Migration] Added schedules.other_cost column");
+    }
+
+    // 費用の通貨（既存の金額はすべて円）とユーザーの基準通貨
+    if !column_exists(pool, "users", "home_currency").await? {
+        sqlx::query("ALTER TABLE users ADD COLUMN home_currency TEXT NOT NULL DEFAULT 'JPY'")
+            .execute(pool)
+            .await?;
+        eprintln!("[Migration] Added users.home_currency column");
+    }
+    for (table, column) in [
+        ("schedules", "ticket_fee_currency"),
+        ("schedules", "drink_fee_currency"),
+        ("ticket_applications", "currency"),
+        ("traffics", "fare_currency"),
+        ("stays", "fee_currency"),
+    ] {
+        if !column_exists(pool, table, column).await? {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} TEXT NOT NULL DEFAULT 'JPY'", table, column))
+                .execute(pool)
+                .await?;
+            eprintln!("[Migration] Added {}.{} column", table, column);
+        }
+    }
+
     // 有料プラン（premium）と1ヶ月お試し期間の管理用カラムを追加（マイグレーション）
     // trial_ends_atは持たず、trial_started_at + 1ヶ月を都度計算して判定する（has_paid_accessを参照）
     if !column_exists(pool, "users", "plan").await? {
@@ -8820,6 +21014,65 @@ async fn init_db(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
             .await?;
     }
 
+    // タイムゾーン設定。既存ユーザーの日時はすべて日本時間で入力されているため、既定値をAsia/Tokyoにして意味を保つ
+    // schedules.timezoneはNULL（= ユーザー設定に従う）のまま追加する
+    if !column_exists(pool, "users", "timezone").await? {
+        sqlx::query(&format!(
+            "ALTER TABLE users ADD COLUMN timezone TEXT NOT NULL DEFAULT '{}'",
+            DEFAULT_TIMEZONE
+        ))
+        .execute(pool)
+        .await?;
+        eprintln!("[Migration] Added users.timezone column");
+    }
+    if !column_exists(pool, "schedules", "timezone").await? {
+        sqlx::query("ALTER TABLE schedules ADD COLUMN timezone TEXT")
+            .execute(pool)
+            .await?;
+        eprintln!("[Migration] Added schedules.timezone column");
+    }
+
+    // 終演後にKeepのスケジュールを自動でDoneにするか。既定はON（既存のKeepのうち終演済みのものは次回のバックグラウンドタスクでDoneになる）
+    if !column_exists(pool, "users", "auto_done_enabled").await? {
+        sqlx::query("ALTER TABLE users ADD COLUMN auto_done_enabled INTEGER NOT NULL DEFAULT 1")
+            .execute(pool)
+            .await?;
+        eprintln!("[Migration] Added users.auto_done_enabled column");
+    }
+
+    // ツアーへの所属。既存のスケジュールは未所属のまま追加し、groupからのツアー作成は
+    // ユーザーが GET /tours/group-candidates で確認してから POST /tours/import-groups で行う
+    if !column_exists(pool, "schedules", "tour_id").await? {
+        sqlx::query("ALTER TABLE schedules ADD COLUMN tour_id INTEGER REFERENCES tours(id) ON DELETE SET NULL")
+            .execute(pool)
+            .await?;
+        eprintln!("[Migration] Added schedules.tour_id column");
+    }
+
+    // ゴミ箱（論理削除）。NULLは通常のデータ
+    for table in ["schedules", "traffics", "stays"] {
+        if !column_exists(pool, table, "deleted_at").await? {
+            sqlx::query(&format!("ALTER TABLE {} ADD COLUMN deleted_at TEXT", table))
+                .execute(pool)
+                .await?;
+            eprintln!("[Migration] Added {}.deleted_at column", table);
+        }
+    }
+
+    // 一覧取得時のロールアップ再計算をやめたため、未計算のまま残っているスケジュールをここで計算しておく
+    let missing_rollups: Vec<i64> = sqlx::query_scalar("SELECT id FROM schedules WHERE total_cost IS NULL")
+        .fetch_all(pool)
+        .await?;
+    for schedule_id in &missing_rollups {
+        calculate_rollup(pool, *schedule_id).await?;
+    }
+    if !missing_rollups.is_empty() {
+        eprintln!("[Migration] Calculated rollups for {} schedules", missing_rollups.len());
+    }
+
+    // 全文検索インデックスの初回構築（search_index導入前から存在するデータ用）
+    rebuild_search_index_if_empty(pool).await?;
+
     // パフォーマンス向上のためのインデックス追加
     // CREATE INDEX IF NOT EXISTSのため、テーブル再作成が発生した場合でも安全に再実行できる
     // (テーブル再作成を伴うマイグレーションより後、init_dbの最後で必ず実行する)
@@ -8829,6 +21082,21 @@ async fn init_db(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
     sqlx::query("CREATE INDEX IF NOT EXISTS idx_schedules_status ON schedules(status)")
         .execute(pool)
         .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_schedules_user_id_date ON schedules(user_id, date)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_schedules_deleted_at ON schedules(deleted_at)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_revisions_schedule_id ON revisions(schedule_id)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_attachments_schedule_id ON attachments(schedule_id)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_attachments_user_id ON attachments(user_id)")
+        .execute(pool)
+        .await?;
     sqlx::query("CREATE INDEX IF NOT EXISTS idx_traffics_schedule_id ON traffics(schedule_id)")
         .execute(pool)
         .await?;
@@ -8838,6 +21106,46 @@ async fn init_db(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
     sqlx::query("CREATE INDEX IF NOT EXISTS idx_subscriptions_user_id ON subscriptions(user_id)")
         .execute(pool)
         .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ticket_transfers_schedule_id ON ticket_transfers(schedule_id)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_goods_schedule_id ON goods(schedule_id)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_expenses_schedule_id ON expenses(schedule_id)")
+        .execute(pool)
+        .await?;
+    // 同じ期間・条件の予算は1つだけ（NULL同士はUNIQUEで重複とみなされないため、空文字・0に置き換える）
+    sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS idx_budgets_scope ON budgets(user_id, year, COALESCE(month, 0), COALESCE(category, ''), COALESCE(target, ''))")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ticket_applications_schedule_id ON ticket_applications(schedule_id)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_companions_user_id ON companions(user_id)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_cost_splits_schedule_id ON cost_splits(schedule_id)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_cost_split_members_split_id ON cost_split_members(split_id)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_schedule_tickets_schedule_id ON schedule_tickets(schedule_id)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_schedule_days_schedule_id ON schedule_days(schedule_id)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_setlist_entries_schedule_id ON setlist_entries(schedule_id)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_schedules_tour_id ON schedules(tour_id)")
+        .execute(pool)
+        .await?;
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_schedule_status_history_schedule_id ON schedule_status_history(schedule_id)")
+        .execute(pool)
+        .await?;
 
     // updated_atをDBトリガーで自動更新する
     // アプリケーション側でupdated_atのセットを忘れた場合でも、UPDATEが実行されれば
@@ -8881,6 +21189,54 @@ async fn init_db(pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
     )
     .execute(pool)
     .await?;
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS trg_ticket_applications_updated_at
+        AFTER UPDATE ON ticket_applications
+        FOR EACH ROW
+        BEGIN
+          UPDATE ticket_applications SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ','now') WHERE id = NEW.id;
+        END;
+        "#
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS trg_schedule_days_updated_at
+        AFTER UPDATE ON schedule_days
+        FOR EACH ROW
+        BEGIN
+          UPDATE schedule_days SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ','now') WHERE id = NEW.id;
+        END;
+        "#
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS trg_setlist_entries_updated_at
+        AFTER UPDATE ON setlist_entries
+        FOR EACH ROW
+        BEGIN
+          UPDATE setlist_entries SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ','now') WHERE id = NEW.id;
+        END;
+        "#
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        r#"
+        CREATE TRIGGER IF NOT EXISTS trg_tours_updated_at
+        AFTER UPDATE ON tours
+        FOR EACH ROW
+        BEGIN
+          UPDATE tours SET updated_at = strftime('%Y-%m-%dT%H:%M:%fZ','now') WHERE id = NEW.id;
+        END;
+        "#
+    )
+    .execute(pool)
+    .await?;
 
     Ok(())
 }
@@ -0,0 +1,446 @@
+// スケジュールのロールアップ計算（APIサーバーとcalculate_all_rollupsで共有する）
+// 交通・宿泊・諸経費の合計と、総費用に使うチケット代・ドリンク代・総費用、割り勘・譲渡を反映した負担額をスケジュールに保存する。
+// 金額はユーザーの基準通貨に換算する
+
+use chrono::Utc;
+use sqlx::{Pool, Sqlite};
+
+pub const DEFAULT_CURRENCY: &str = "JPY";
+
+// ユーザーの基準通貨と為替レート（1 base = rate quote）
+pub struct ExchangeRates {
+    pub home_currency: String,
+    pub rates: std::collections::HashMap<(String, String), f64>,
+}
+
+impl ExchangeRates {
+    // 1単位あたりの基準通貨での価値。基準通貨へのレートがなければ、基準通貨からのレートの逆数を使う
+    pub fn rate_to_home(&self, currency: &str) -> Option<f64> {
+        if currency == self.home_currency {
+            return Some(1.0);
+        }
+        if let Some(rate) = self.rates.get(&(currency.to_string(), self.home_currency.clone())) {
+            return Some(*rate);
+        }
+        self.rates
+            .get(&(self.home_currency.clone(), currency.to_string()))
+            .map(|rate| 1.0 / rate)
+    }
+
+    // 基準通貨に換算する（1未満は四捨五入）。レートがない場合はそのままの金額を返す
+    // （書き込み時・レート削除時にレートの存在を確認しているため、通常は起きない）
+    pub fn to_home(&self, amount: i64, currency: &str) -> i64 {
+        match self.rate_to_home(currency) {
+            Some(rate) => (amount as f64 * rate).round() as i64,
+            None => amount,
+        }
+    }
+
+    // 基準通貨に換算できない通貨
+    pub fn unconvertible(&self, currencies: &[String]) -> Vec<String> {
+        currencies
+            .iter()
+            .filter(|currency| self.rate_to_home(currency).is_none())
+            .cloned()
+            .collect()
+    }
+}
+
+pub async fn fetch_exchange_rates(pool: &Pool<Sqlite>, user_id: i64) -> Result<ExchangeRates, sqlx::Error> {
+    let home_currency: Option<String> = sqlx::query_scalar("SELECT home_currency FROM users WHERE id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    let rates: Vec<(String, String, f64)> =
+        sqlx::query_as("SELECT base_currency, quote_currency, rate FROM exchange_rates WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?;
+    Ok(ExchangeRates {
+        home_currency: home_currency.unwrap_or_else(|| DEFAULT_CURRENCY.to_string()),
+        rates: rates
+            .into_iter()
+            .map(|(base, quote, rate)| ((base, quote), rate))
+            .collect(),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitRule {
+    Equal, // 均等割り
+    Share, // 口数（weight）に応じて按分
+    Fixed, // メンバーごとの固定額（残りは支払った人の負担）
+}
+
+impl SplitRule {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "equal" => Some(Self::Equal),
+            "share" => Some(Self::Share),
+            "fixed" => Some(Self::Fixed),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitParticipant {
+    pub companion_id: Option<i64>, // Noneは自分
+    pub weight: i64,
+    pub fixed_amount: Option<i64>,
+}
+
+// (負担する人, 負担額) の一覧。人のNoneは自分
+pub type SplitShares = Vec<(Option<i64>, i64)>;
+
+// 金額をメンバーごとの負担額に分ける。1円未満の端数はメンバーの並び順（口数按分は端数の大きい順）に1円ずつ配る。
+// 固定額の場合、金額との差額（マイナスの場合も）は支払った人の負担とし、支払った人がメンバーにいなければ末尾に追加する
+pub fn split_cost(
+    amount: i64,
+    rule: SplitRule,
+    members: &[SplitParticipant],
+    paid_by: Option<i64>,
+) -> SplitShares {
+    if members.is_empty() {
+        return vec![(paid_by, amount)];
+    }
+    let mut shares: Vec<(Option<i64>, i64)> = match rule {
+        SplitRule::Equal => {
+            let n = members.len() as i64;
+            let base = amount.div_euclid(n);
+            let remainder = amount.rem_euclid(n);
+            members
+                .iter()
+                .enumerate()
+                .map(|(i, m)| (m.companion_id, base + i64::from((i as i64) < remainder)))
+                .collect()
+        }
+        SplitRule::Share => {
+            let total_weight: i64 = members.iter().map(|m| m.weight.max(1)).sum();
+            let mut shares: Vec<(Option<i64>, i64)> = members
+                .iter()
+                .map(|m| (m.companion_id, amount * m.weight.max(1) / total_weight))
+                .collect();
+            let mut leftover = amount - shares.iter().map(|(_, s)| s).sum::<i64>();
+            let mut order: Vec<usize> = (0..members.len()).collect();
+            order.sort_by_key(|&i| std::cmp::Reverse((amount * members[i].weight.max(1)).rem_euclid(total_weight)));
+            for i in order.into_iter().cycle() {
+                if leftover <= 0 {
+                    break;
+                }
+                shares[i].1 += 1;
+                leftover -= 1;
+            }
+            shares
+        }
+        SplitRule::Fixed => {
+            let mut shares: Vec<(Option<i64>, i64)> =
+                members.iter().map(|m| (m.companion_id, m.fixed_amount.unwrap_or(0))).collect();
+            let difference = amount - shares.iter().map(|(_, s)| s).sum::<i64>();
+            if difference != 0 {
+                match shares.iter_mut().find(|(id, _)| *id == paid_by) {
+                    Some(payer) => payer.1 += difference,
+                    None => shares.push((paid_by, difference)),
+                }
+            }
+            shares
+        }
+    };
+    shares.retain(|(id, share)| *share != 0 || members.iter().any(|m| m.companion_id == *id));
+    shares
+}
+
+#[derive(sqlx::FromRow)]
+pub struct CostSplitRow {
+    pub id: i64,
+    pub schedule_id: i64,
+    pub entity_type: String,
+    pub entity_id: i64,
+    pub rule: String,
+    pub paid_by: Option<i64>,
+    pub amount: Option<i64>, // 対象の費用（スケジュールはチケット代+ドリンク代、交通はfare、宿泊はfee）。ゴミ箱にある場合はNULL
+    pub currency: Option<String>, // NULLは基準通貨
+}
+
+impl CostSplitRow {
+    // 対象の費用を基準通貨に換算する（ゴミ箱にある場合はNone）
+    pub fn home_amount(&self, rates: &ExchangeRates) -> Option<i64> {
+        let amount = self.amount?;
+        let currency = self.currency.as_deref().unwrap_or(&rates.home_currency);
+        Some(rates.to_home(amount, currency))
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct CostSplitMemberRow {
+    split_id: i64,
+    companion_id: Option<i64>,
+    weight: i64,
+    fixed_amount: Option<i64>,
+}
+
+// 割り勘の設定と、対象の費用を取得するSELECT（WHERE句は呼び出し側で付ける）
+// スケジュールはticket_fee_effective + drink_fee_effective（基準通貨に換算済みのため、currencyはNULL）を使う
+pub const COST_SPLIT_SELECT: &str = r#"
+    SELECT
+      cs.id,
+      cs.schedule_id,
+      cs.entity_type,
+      cs.entity_id,
+      cs.rule,
+      cs.paid_by,
+      CASE cs.entity_type
+        WHEN 'schedule' THEN COALESCE(s.ticket_fee_effective, 0) + COALESCE(s.drink_fee_effective, 0)
+        WHEN 'traffic' THEN (SELECT COALESCE(fare, 0) FROM traffics WHERE id = cs.entity_id AND deleted_at IS NULL)
+        WHEN 'stay' THEN (SELECT COALESCE(fee, 0) FROM stays WHERE id = cs.entity_id AND deleted_at IS NULL)
+      END AS amount,
+      CASE cs.entity_type
+        WHEN 'schedule' THEN NULL
+        WHEN 'traffic' THEN (SELECT fare_currency FROM traffics WHERE id = cs.entity_id)
+        WHEN 'stay' THEN (SELECT fee_currency FROM stays WHERE id = cs.entity_id)
+      END AS currency
+    FROM cost_splits cs
+    INNER JOIN schedules s ON s.id = cs.schedule_id
+"#;
+
+// 割り勘の設定ごとにメンバーを取得する（入力順）
+pub async fn fetch_cost_split_members(
+    pool: &Pool<Sqlite>,
+    split_ids: &[i64],
+) -> Result<std::collections::HashMap<i64, Vec<SplitParticipant>>, sqlx::Error> {
+    let mut members: std::collections::HashMap<i64, Vec<SplitParticipant>> = std::collections::HashMap::new();
+    if split_ids.is_empty() {
+        return Ok(members);
+    }
+    let placeholders = vec!["?"; split_ids.len()].join(", ");
+    let sql = format!(
+        "SELECT split_id, companion_id, weight, fixed_amount FROM cost_split_members WHERE split_id IN ({}) ORDER BY split_id ASC, id ASC",
+        placeholders
+    );
+    let mut query = sqlx::query_as::<_, CostSplitMemberRow>(&sql);
+    for id in split_ids {
+        query = query.bind(id);
+    }
+    for row in query.fetch_all(pool).await? {
+        members.entry(row.split_id).or_default().push(SplitParticipant {
+            companion_id: row.companion_id,
+            weight: row.weight,
+            fixed_amount: row.fixed_amount,
+        });
+    }
+    Ok(members)
+}
+
+// 割り勘の設定を反映した、スケジュールの自分の負担額（calculate_rollupから呼ぶ）
+// total_costから割り勘を設定した費用を除き、その費用のうち自分の負担分を足し戻す（いずれも基準通貨）
+async fn calculate_my_share(
+    pool: &Pool<Sqlite>,
+    schedule_id: i64,
+    total_cost: i64,
+    rates: &ExchangeRates,
+) -> Result<i64, sqlx::Error> {
+    let splits: Vec<CostSplitRow> = sqlx::query_as::<_, CostSplitRow>(&format!("{} WHERE cs.schedule_id = ?", COST_SPLIT_SELECT))
+        .bind(schedule_id)
+        .fetch_all(pool)
+        .await?;
+    let ids: Vec<i64> = splits.iter().map(|s| s.id).collect();
+    let mut members = fetch_cost_split_members(pool, &ids).await?;
+
+    let mut my_share = total_cost;
+    for split in splits {
+        let (Some(amount), Some(rule)) = (split.home_amount(rates), SplitRule::parse(&split.rule)) else { continue };
+        let members = members.remove(&split.id).unwrap_or_default();
+        let mine: i64 = split_cost(amount, rule, &members, split.paid_by)
+            .into_iter()
+            .filter(|(id, _)| id.is_none())
+            .map(|(_, share)| share)
+            .sum();
+        my_share = my_share - amount + mine;
+    }
+    Ok(my_share)
+}
+
+// 総費用に使うチケット代・ドリンク代（いずれも基準通貨に換算済み）。費用の元が複数ある場合の優先順位はここで決める:
+// 当選（won）・支払済み（paid）のチケット申込の合計 > 複数日開催の日別の合計 > 手入力値（ドリンク代には申込がないためNone）
+// 手入力値（schedules.ticket_fee・drink_fee）はロールアップで書き換えない
+pub fn effective_fee(applications: Option<i64>, days: Option<i64>, manual: Option<i64>) -> Option<i64> {
+    applications.or(days).or(manual)
+}
+
+#[derive(sqlx::FromRow)]
+struct RollupScheduleRow {
+    ticket_fee: Option<i32>,
+    drink_fee: Option<i32>,
+    ticket_fee_currency: String,
+    drink_fee_currency: String,
+    user_id: i64,
+}
+
+// ロールアップ計算関数
+pub async fn calculate_rollup(
+    pool: &Pool<Sqlite>,
+    schedule_id: i64,
+) -> Result<(), sqlx::Error> {
+    // スケジュールのticket_feeとdrink_fee（と通貨）を取得
+    let schedule_row: Option<RollupScheduleRow> = sqlx::query_as::<_, RollupScheduleRow>(
+        "SELECT ticket_fee, drink_fee, ticket_fee_currency, drink_fee_currency, user_id FROM schedules WHERE id = ?"
+    )
+    .bind(schedule_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(schedule_row) = schedule_row else {
+        return Ok(());
+    };
+
+    // ロールアップは所有ユーザーの基準通貨に換算して合計する（ticket_fee・drink_fee・fare・feeは元の通貨のまま）
+    let rates = fetch_exchange_rates(pool, schedule_row.user_id).await?;
+
+    // total_fare: 関連するtrafficsのfareの合計
+    let fares: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT fare_currency, COALESCE(SUM(fare), 0) FROM traffics WHERE schedule_id = ? AND deleted_at IS NULL GROUP BY fare_currency"
+    )
+    .bind(schedule_id)
+    .fetch_all(pool)
+    .await?;
+    let total_fare = fares.iter().map(|(currency, sum)| rates.to_home(*sum, currency)).sum::<i64>() as i32;
+
+    // stay_fee: 関連するstaysのfeeの合計
+    let fees: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT fee_currency, COALESCE(SUM(fee), 0) FROM stays WHERE schedule_id = ? AND deleted_at IS NULL GROUP BY fee_currency"
+    )
+    .bind(schedule_id)
+    .fetch_all(pool)
+    .await?;
+    let stay_fee = fees.iter().map(|(currency, sum)| rates.to_home(*sum, currency)).sum::<i64>() as i32;
+
+    // other_cost: 諸経費の明細（expenses）の合計
+    let expenses: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT currency, COALESCE(SUM(amount), 0) FROM expenses WHERE schedule_id = ? GROUP BY currency"
+    )
+    .bind(schedule_id)
+    .fetch_all(pool)
+    .await?;
+    let other_cost = expenses.iter().map(|(currency, sum)| rates.to_home(*sum, currency)).sum::<i64>() as i32;
+
+    // 複数日開催で入力されている日別のチケット代・ドリンク代の合計
+    let (day_ticket_count, day_ticket_fee, day_drink_count, day_drink_fee): (i64, i64, i64, i64) = sqlx::query_as(
+        "SELECT COUNT(ticket_fee), COALESCE(SUM(ticket_fee), 0), COUNT(drink_fee), COALESCE(SUM(drink_fee), 0) FROM schedule_days WHERE schedule_id = ?"
+    )
+    .bind(schedule_id)
+    .fetch_one(pool)
+    .await?;
+
+    // 当選（won）・支払済み（paid）のチケット申込の合計（価格×枚数、申込ごとの通貨）
+    let won: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT currency, COALESCE(SUM(COALESCE(price, 0) * quantity), 0) FROM ticket_applications WHERE schedule_id = ? AND result IN ('won', 'paid') GROUP BY currency"
+    )
+    .bind(schedule_id)
+    .fetch_all(pool)
+    .await?;
+
+    // ticket_fee_effective・drink_fee_effective: 優先順位はeffective_feeを参照。手入力のticket_fee・drink_feeは書き換えない
+    let ticket_currency = &schedule_row.ticket_fee_currency;
+    let drink_currency = &schedule_row.drink_fee_currency;
+    let ticket_fee_effective = effective_fee(
+        (!won.is_empty()).then(|| won.iter().map(|(currency, sum)| rates.to_home(*sum, currency)).sum()),
+        (day_ticket_count > 0).then(|| rates.to_home(day_ticket_fee, ticket_currency)),
+        schedule_row.ticket_fee.map(|fee| rates.to_home(fee as i64, ticket_currency)),
+    )
+    .map(|fee| fee as i32);
+    let drink_fee_effective = effective_fee(
+        None,
+        (day_drink_count > 0).then(|| rates.to_home(day_drink_fee, drink_currency)),
+        schedule_row.drink_fee.map(|fee| rates.to_home(fee as i64, drink_currency)),
+    )
+    .map(|fee| fee as i32);
+
+    // travel_cost: total_fare + stay_fee
+    let travel_cost = total_fare.saturating_add(stay_fee);
+
+    // total_cost: ticket_fee_effective + drink_fee_effective + travel_cost + other_cost
+    let total_cost = ticket_fee_effective.unwrap_or(0)
+        .saturating_add(drink_fee_effective.unwrap_or(0))
+        .saturating_add(travel_cost)
+        .saturating_add(other_cost);
+
+    // スケジュールを更新
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        r#"
+        UPDATE schedules SET
+          ticket_fee_effective = ?,
+          drink_fee_effective = ?,
+          total_fare = ?,
+          stay_fee = ?,
+          travel_cost = ?,
+          other_cost = ?,
+          total_cost = ?,
+          updated_at = ?
+        WHERE id = ?
+        "#
+    )
+    .bind(ticket_fee_effective)
+    .bind(drink_fee_effective)
+    .bind(Some(total_fare))
+    .bind(Some(stay_fee))
+    .bind(Some(travel_cost))
+    .bind(Some(other_cost))
+    .bind(Some(total_cost))
+    .bind(&now)
+    .bind(schedule_id)
+    .execute(pool)
+    .await?;
+
+    // my_share: 割り勘の設定を反映した自分の負担額（スケジュールの費用を更新した後の値で計算する）
+    let my_share = calculate_my_share(pool, schedule_id, total_cost as i64, &rates).await?;
+
+    // net_cost: my_shareから、譲渡・リセールで戻ってきた金額（受取額 - 手数料）を引いた実質の費用
+    let proceeds: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT currency, COALESCE(SUM(price_received - fees), 0) FROM ticket_transfers WHERE schedule_id = ? GROUP BY currency"
+    )
+    .bind(schedule_id)
+    .fetch_all(pool)
+    .await?;
+    let net_cost = my_share - proceeds.iter().map(|(currency, sum)| rates.to_home(*sum, currency)).sum::<i64>();
+
+    // goods_cost: グッズ代（価格 × 個数）の合計。total_costには含めず、別に集計する
+    let goods: Vec<(String, i64)> = sqlx::query_as(
+        "SELECT currency, COALESCE(SUM(COALESCE(price, 0) * quantity), 0) FROM goods WHERE schedule_id = ? GROUP BY currency"
+    )
+    .bind(schedule_id)
+    .fetch_all(pool)
+    .await?;
+    let goods_cost = goods.iter().map(|(currency, sum)| rates.to_home(*sum, currency)).sum::<i64>();
+
+    sqlx::query("UPDATE schedules SET my_share = ?, net_cost = ?, goods_cost = ? WHERE id = ?")
+        .bind(my_share)
+        .bind(net_cost)
+        .bind(goods_cost)
+        .bind(schedule_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::effective_fee;
+
+    #[test]
+    fn applications_take_precedence_over_days_and_manual() {
+        assert_eq!(effective_fee(Some(24000), Some(15000), Some(8000)), Some(24000));
+        assert_eq!(effective_fee(Some(0), Some(15000), Some(8000)), Some(0));
+    }
+
+    #[test]
+    fn days_take_precedence_over_manual() {
+        assert_eq!(effective_fee(None, Some(15000), Some(8000)), Some(15000));
+    }
+
+    #[test]
+    fn falls_back_to_manual() {
+        assert_eq!(effective_fee(None, None, Some(8000)), Some(8000));
+        assert_eq!(effective_fee(None, None, None), None);
+    }
+}
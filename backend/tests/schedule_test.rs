@@ -0,0 +1,491 @@
+// スケジュールAPIの統合テスト
+// ハンドラーはmain.rsにあり直接呼び出せないため、ビルド済みのサーバーを一時DBで起動してHTTPで確認する
+
+use sqlx::sqlite::SqlitePoolOptions;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+use tempfile::TempDir;
+
+// 起動したサーバー。テスト終了時（パニック時も含む）に停止する
+struct TestServer {
+    child: Child,
+    base_url: String,
+    _db_dir: TempDir,
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// 認証を無効にしたサーバーを起動し、DEFAULT_USER_ID=1 のユーザーを作成する
+async fn start_server() -> TestServer {
+    let db_dir = TempDir::new().expect("Failed to create temp directory");
+    let db_path = db_dir.path().join("app.db");
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("Failed to find a free port")
+        .port();
+
+    let child = Command::new(env!("CARGO_BIN_EXE_live-schedule-api"))
+        .current_dir(db_dir.path())
+        .env("DATABASE_URL", format!("sqlite://{}", db_path.display()))
+        .env("JWT_SECRET", "test-secret-key-for-testing-only")
+        .env("DISABLE_AUTH", "1")
+        .env("DEFAULT_USER_ID", "1")
+        .env("PORT", port.to_string())
+        .env("BIND_HOST", "127.0.0.1")
+        .env_remove("RESEND_API_KEY")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("Failed to start server");
+    let server = TestServer {
+        child,
+        base_url: format!("http://127.0.0.1:{}", port),
+        _db_dir: db_dir,
+    };
+
+    let client = reqwest::Client::new();
+    let mut ready = false;
+    for _ in 0..100 {
+        if client.get(format!("{}/health", server.base_url)).send().await.is_ok() {
+            ready = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(ready, "server did not start");
+
+    // スキーマはサーバーの起動時に作成される
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect(&format!("sqlite:{}", db_path.display()))
+        .await
+        .expect("Failed to open test database");
+    sqlx::query("INSERT INTO users (id, email, password_hash, email_verified, created_at, updated_at) VALUES (1, 'test@example.com', 'x', 1, '2025-01-01T00:00:00Z', '2025-01-01T00:00:00Z')")
+        .execute(&pool)
+        .await
+        .expect("Failed to create test user");
+    pool.close().await;
+
+    server
+}
+
+// 一覧から指定したIDのスケジュールを取得する
+async fn fetch_schedule(client: &reqwest::Client, server: &TestServer, id: i64) -> serde_json::Value {
+    let schedules: Vec<serde_json::Value> = client
+        .get(format!("{}/schedules", server.base_url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    schedules
+        .into_iter()
+        .find(|s| s["id"].as_i64() == Some(id))
+        .expect("schedule in list")
+}
+
+#[tokio::test]
+async fn test_won_application_does_not_overwrite_ticket_fee() {
+    let server = start_server().await;
+    let client = reqwest::Client::new();
+
+    let schedule: serde_json::Value = client
+        .post(format!("{}/schedules", server.base_url))
+        .json(&serde_json::json!({
+            "title": "テストライブ",
+            "date": "2025-05-10",
+            "area": "東京",
+            "venue": "武道館",
+            "ticket_fee": 8000
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = schedule["id"].as_i64().expect("schedule id");
+    assert_eq!(schedule["ticket_fee_effective"], 8000);
+
+    // 当選した申込の合計が総費用に使われるが、手入力のticket_feeはそのまま
+    let application: serde_json::Value = client
+        .post(format!("{}/ticket-applications", server.base_url))
+        .json(&serde_json::json!({ "schedule_id": id, "quantity": 2, "price": 12000, "result": "won" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let schedule = fetch_schedule(&client, &server, id).await;
+    assert_eq!(schedule["ticket_fee"], 8000);
+    assert_eq!(schedule["ticket_fee_effective"], 24000);
+    assert_eq!(schedule["total_cost"], 24000);
+
+    // 落選に変えると手入力のticket_feeに戻る
+    let response = client
+        .put(format!("{}/ticket-applications/{}", server.base_url, application["id"]))
+        .json(&serde_json::json!({ "schedule_id": id, "quantity": 2, "price": 12000, "result": "lost" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let schedule = fetch_schedule(&client, &server, id).await;
+    assert_eq!(schedule["ticket_fee"], 8000);
+    assert_eq!(schedule["ticket_fee_effective"], 8000);
+    assert_eq!(schedule["total_cost"], 8000);
+}
+
+#[tokio::test]
+async fn test_ticket_application_rejects_invalid_datetimes() {
+    let server = start_server().await;
+    let client = reqwest::Client::new();
+
+    let schedule: serde_json::Value = client
+        .post(format!("{}/schedules", server.base_url))
+        .json(&serde_json::json!({ "title": "テストライブ", "date": "2025-05-10", "area": "東京", "venue": "武道館" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = schedule["id"].as_i64().expect("schedule id");
+
+    // 支払期限が読めない形式だと通知されないため、保存しない
+    let response = client
+        .post(format!("{}/ticket-applications", server.base_url))
+        .json(&serde_json::json!({ "schedule_id": id, "result": "won", "payment_deadline": "5/1 23:59" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let response = client
+        .post(format!("{}/ticket-applications", server.base_url))
+        .json(&serde_json::json!({
+            "schedule_id": id,
+            "application_start": "2025-03-10 12:00",
+            "application_end": "2025-03-01 23:59"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let response = client
+        .post(format!("{}/ticket-applications", server.base_url))
+        .json(&serde_json::json!({
+            "schedule_id": id,
+            "result": "won",
+            "application_start": "2025-03-01 12:00",
+            "application_end": "2025-03-10 23:59",
+            "payment_deadline": "2025-03-20 23:59"
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn test_day_fees_do_not_overwrite_schedule_fees() {
+    let server = start_server().await;
+    let client = reqwest::Client::new();
+
+    // 日別のドリンク代の合計が総費用に使われるが、手入力のdrink_feeはそのまま
+    let created: serde_json::Value = client
+        .post(format!("{}/schedules", server.base_url))
+        .json(&serde_json::json!({
+            "title": "テストフェス",
+            "date": "2025-08-02",
+            "end_date": "2025-08-03",
+            "area": "千葉",
+            "venue": "幕張メッセ",
+            "ticket_fee": 20000,
+            "drink_fee": 600,
+            "days": [
+                { "date": "2025-08-02", "drink_fee": 700 },
+                { "date": "2025-08-03", "drink_fee": 700 }
+            ]
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let schedule = fetch_schedule(&client, &server, created["id"].as_i64().expect("schedule id")).await;
+    assert_eq!(schedule["drink_fee"], 600);
+    assert_eq!(schedule["drink_fee_effective"], 1400);
+    assert_eq!(schedule["ticket_fee"], 20000);
+    assert_eq!(schedule["ticket_fee_effective"], 20000);
+    assert_eq!(schedule["total_cost"], 21400);
+}
+
+#[tokio::test]
+async fn test_revert_date_range_resyncs_schedule_days() {
+    let server = start_server().await;
+    let client = reqwest::Client::new();
+
+    let festival = serde_json::json!({
+        "title": "テストフェス",
+        "date": "2025-08-02",
+        "end_date": "2025-08-03",
+        "area": "千葉",
+        "venue": "幕張メッセ",
+        "drink_fee": 600,
+        "days": [
+            { "date": "2025-08-02", "drink_fee": 700 },
+            { "date": "2025-08-03", "drink_fee": 700 }
+        ]
+    });
+    let created: serde_json::Value = client
+        .post(format!("{}/schedules", server.base_url))
+        .json(&festival)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = created["id"].as_i64().expect("schedule id");
+
+    // 1日延長し、追加した日にもドリンク代を入力する
+    let mut extended = festival.clone();
+    extended["end_date"] = serde_json::json!("2025-08-04");
+    extended["days"] = serde_json::json!([
+        { "date": "2025-08-02", "drink_fee": 700 },
+        { "date": "2025-08-03", "drink_fee": 700 },
+        { "date": "2025-08-04", "drink_fee": 700 }
+    ]);
+    let response = client
+        .put(format!("{}/schedules/{}", server.base_url, id))
+        .json(&extended)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    assert_eq!(fetch_schedule(&client, &server, id).await["drink_fee_effective"], 2100);
+
+    // 作成時の内容（2日間）に戻すと、期間外になった日程とその費用は残らない
+    let revisions: Vec<serde_json::Value> = client
+        .get(format!("{}/schedules/{}/revisions", server.base_url, id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let create_revision = revisions
+        .iter()
+        .find(|r| r["entity_type"] == "schedule" && r["action"] == "create")
+        .expect("create revision");
+    let response = client
+        .post(format!(
+            "{}/schedules/{}/revisions/{}/revert",
+            server.base_url, id, create_revision["id"]
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    let schedule = fetch_schedule(&client, &server, id).await;
+    assert_eq!(schedule["end_date"], "2025-08-03");
+    let dates: Vec<&str> = schedule["days"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| d["date"].as_str().unwrap())
+        .collect();
+    assert_eq!(dates, vec!["2025-08-02", "2025-08-03"]);
+    assert_eq!(schedule["drink_fee_effective"], 1400);
+    assert_eq!(schedule["total_cost"], 1400);
+}
+
+#[tokio::test]
+async fn test_revert_rejects_currency_without_exchange_rate() {
+    let server = start_server().await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .put(format!("{}/exchange-rates/KRW/JPY", server.base_url))
+        .json(&serde_json::json!({ "rate": 0.11 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    let schedule = serde_json::json!({
+        "title": "ソウル公演",
+        "date": "2025-09-06",
+        "area": "ソウル",
+        "venue": "KSPO DOME",
+        "ticket_fee": 150000,
+        "ticket_fee_currency": "KRW"
+    });
+    let created: serde_json::Value = client
+        .post(format!("{}/schedules", server.base_url))
+        .json(&schedule)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = created["id"].as_i64().expect("schedule id");
+    assert_eq!(fetch_schedule(&client, &server, id).await["ticket_fee_effective"], 16500);
+
+    // 円に変更すると、KRWの為替レートは使われなくなるため削除できる
+    let mut in_yen = schedule.clone();
+    in_yen["ticket_fee"] = serde_json::json!(16000);
+    in_yen["ticket_fee_currency"] = serde_json::json!("JPY");
+    let response = client
+        .put(format!("{}/schedules/{}", server.base_url, id))
+        .json(&in_yen)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let response = client
+        .delete(format!("{}/exchange-rates/KRW/JPY", server.base_url))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.status().is_success());
+
+    // KRWの時点には換算できないため戻せず、スケジュールは変わらない
+    let revisions: Vec<serde_json::Value> = client
+        .get(format!("{}/schedules/{}/revisions", server.base_url, id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let create_revision = revisions
+        .iter()
+        .find(|r| r["entity_type"] == "schedule" && r["action"] == "create")
+        .expect("create revision");
+    let response = client
+        .post(format!(
+            "{}/schedules/{}/revisions/{}/revert",
+            server.base_url, id, create_revision["id"]
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CONFLICT);
+
+    let schedule = fetch_schedule(&client, &server, id).await;
+    assert_eq!(schedule["ticket_fee_currency"], "JPY");
+    assert_eq!(schedule["ticket_fee_effective"], 16000);
+}
+
+#[tokio::test]
+async fn test_won_application_price_uses_its_own_currency() {
+    let server = start_server().await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .put(format!("{}/exchange-rates/KRW/JPY", server.base_url))
+        .json(&serde_json::json!({ "rate": 0.11 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+
+    let created: serde_json::Value = client
+        .post(format!("{}/schedules", server.base_url))
+        .json(&serde_json::json!({
+            "title": "ソウル公演",
+            "date": "2025-09-06",
+            "area": "ソウル",
+            "venue": "KSPO DOME",
+            "ticket_fee": 8000,
+            "ticket_fee_currency": "JPY"
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = created["id"].as_i64().expect("schedule id");
+
+    // 現地のプレイガイドでウォン建てで当選した申込
+    let application: serde_json::Value = client
+        .post(format!("{}/ticket-applications", server.base_url))
+        .json(&serde_json::json!({
+            "schedule_id": id,
+            "seller": "Interpark",
+            "quantity": 2,
+            "price": 150000,
+            "currency": "krw",
+            "result": "won"
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(application["currency"], "KRW");
+
+    let schedule = fetch_schedule(&client, &server, id).await;
+    assert_eq!(schedule["ticket_fee"], 8000);
+    assert_eq!(schedule["ticket_fee_currency"], "JPY");
+    assert_eq!(schedule["ticket_fee_effective"], 33000);
+    assert_eq!(schedule["total_cost"], 33000);
+}
+
+#[tokio::test]
+async fn test_create_schedule_and_expense_rollup() {
+    let server = start_server().await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/schedules", server.base_url))
+        .json(&serde_json::json!({
+            "title": "テストライブ",
+            "date": "2025-05-10",
+            "area": "東京",
+            "venue": "武道館",
+            "ticket_fee": 8000,
+            "drink_fee": 600
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+    let schedule: serde_json::Value = response.json().await.unwrap();
+    let id = schedule["id"].as_i64().expect("schedule id");
+    assert_eq!(schedule["total_cost"], 8600);
+
+    // 諸経費はother_costに集計され、total_costに含まれる
+    let response = client
+        .post(format!("{}/expenses", server.base_url))
+        .json(&serde_json::json!({ "schedule_id": id, "amount": 1500, "category": "食事" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), reqwest::StatusCode::CREATED);
+
+    let schedule: serde_json::Value = client
+        .get(format!("{}/schedules/{}", server.base_url, id))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(schedule["other_cost"], 1500);
+    assert_eq!(schedule["total_cost"], 10100);
+}
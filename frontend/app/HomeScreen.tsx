@@ -36,8 +36,10 @@ export type Schedule = {
   id: number;
   title: string;
   group: string | null; // groupがNULLの場合はNULLのまま（表示時は"-"を表示）
-  datetime: string; // date + start から生成（計算フィールド）
+  datetime: string; // date + start から生成（計算フィールド、開催地のUTCオフセット付き）
   date?: string | null;
+  timezone?: string | null; // 海外公演などユーザー設定と異なる場合のみ（IANA名、例: "America/New_York"）
+  tour_id?: number | null; // 所属ツアー（tours.id）
   open?: string | null;
   start?: string | null;
   end?: string | null;
@@ -65,7 +67,8 @@ export type Schedule = {
 export type Notification = {
   id: number;
   user_id: number;
-  stay_id: number;
+  stay_id: number | null;
+  ticket_application_id: number | null;
   schedule_id: number;
   title: string;
   message: string;
@@ -74,6 +77,7 @@ export type Notification = {
 };
 
 import { authenticatedFetch, getApiUrl } from "../utils/api";
+import { formatDateTimeUTC } from "../utils/format-datetime";
 import { ScheduleCalendar } from "../components/ScheduleCalendar";
 import { NotionTag } from "../components/notion-tag";
 import { getOptionColorSync } from "../utils/get-option-color";
@@ -391,39 +395,15 @@ export default function HomeScreen() {
       const sortedYears = Array.from(years).sort((a, b) => b - a);
       setAvailableYears(sortedYears);
       
-      // 現在時刻をJST（日本標準時）で取得
-      // 現在のUTC時刻を取得
-      const nowUTC = new Date();
-      const utcTime = nowUTC.getTime(); // UTC時刻のミリ秒
-      const jstOffset = 9 * 60 * 60 * 1000; // JSTはUTC+9（ミリ秒）
-      // UTC時刻に9時間を加算してJST時刻を取得
-      const jstNow = new Date(utcTime + jstOffset);
-      
-      console.log("Current UTC time:", nowUTC.toISOString());
-      console.log("Current JST time (UTC+9):", jstNow.toISOString());
-      
-      // 未来のスケジュールのみをフィルタリング（JSTで比較）
-      // schedule.datetimeはUTC形式（例: "2025-07-03T17:00:00Z"）で来ているが、
-      // バックエンドでは、dateとstartから"YYYY-MM-DDTHH:MM:00Z"形式で生成されている
-      // これはUTC形式だが、実際にはJSTの日時を表している
-      // したがって、UTC時刻として解釈し、9時間を加算してJST時刻として扱う
+      // 未来のスケジュールのみをフィルタリング
+      // schedule.datetimeは開催地のUTCオフセット付き（例: "2025-07-03T17:00:00+09:00"）のため、
+      // そのまま現在時刻と比較できる
+      const now = Date.now();
       const futureSchedules = visibleSchedules.filter((schedule) => {
         if (!schedule.datetime) {
           return false; // datetimeがない場合は除外
         }
-        
-        // datetimeをUTC時刻として解釈
-        const scheduleDateUTC = new Date(schedule.datetime);
-        const scheduleUtcTime = scheduleDateUTC.getTime(); // UTC時刻のミリ秒
-        // JST時刻として扱うため、9時間を加算
-        const scheduleDateJST = new Date(scheduleUtcTime + jstOffset);
-        
-        // 現在のJST時刻と比較
-        const isFuture = scheduleDateJST.getTime() > jstNow.getTime();
-        
-        console.log(`Schedule ${schedule.id}: datetime=${schedule.datetime}, JST=${scheduleDateJST.toISOString()}, isFuture=${isFuture}`);
-        
-        return isFuture;
+        return new Date(schedule.datetime).getTime() > now;
       });
       
       console.log(`Found ${futureSchedules.length} future schedules out of ${data.length} total`);
@@ -1351,19 +1331,6 @@ export default function HomeScreen() {
   );
 }
 
-function formatDateTimeUTC(iso: string): string {
-  const d = new Date(iso);
-  if (isNaN(d.getTime())) {
-    return iso;
-  }
-  const year = d.getUTCFullYear();
-  const month = String(d.getUTCMonth() + 1).padStart(2, "0");
-  const day = String(d.getUTCDate()).padStart(2, "0");
-  const hours = String(d.getUTCHours() + 0).padStart(2, "0"); // UTCのまま
-  const minutes = String(d.getUTCMinutes()).padStart(2, "0");
-  return `${year}-${month}-${day} ${hours}:${minutes}`;
-}
-
 const styles = StyleSheet.create({
   scrollContainer: {
     flex: 1,
@@ -339,8 +339,10 @@ export default function SharedScheduleDetailScreen({ authenticated = false, sche
 
   const relatedIds = schedule.related_schedule_ids ?? [];
 
-  // UTC そのまま表示
+  // 開催地の現地時刻のまま表示（datetimeは現地のUTCオフセット付き）
   function formatDateTimeUTC(iso: string): string {
+    const local = iso.match(/^(\d{4}-\d{2}-\d{2})T(\d{2}:\d{2})/);
+    if (local) return `${local[1]} ${local[2]}`;
     const d = new Date(iso);
     if (isNaN(d.getTime())) return iso;
     const year = d.getUTCFullYear();
@@ -18,7 +18,8 @@ const brand = {
 
 type Notification = {
   id: number;
-  stay_id: number;
+  stay_id: number | null;
+  ticket_application_id: number | null;
   schedule_id: number;
   title: string;
   message: string;
@@ -27,6 +27,15 @@ export type PublicSchedule = {
   status: string;
   related_schedule_ids: string[];
   is_public: boolean;
+  setlist?: PublicSetlistEntry[] | null; // 共有ページのスケジュール詳細で、セットリスト公開時のみ
+};
+
+export type PublicSetlistEntry = {
+  position: number;
+  song_title: string;
+  artist?: string | null;
+  is_encore: boolean;
+  notes?: string | null;
 };
 
 export type PublicTraffic = {
@@ -1,6 +1,12 @@
-// RustのUTC時刻をそのまま表示するフォーマット
+// スケジュールのdatetime（開催地のUTCオフセット付き、例: "2025-07-04T19:30:00-04:00"）を
+// 端末のタイムゾーンに変換せず、開催地の現地時刻のまま表示するフォーマット
 
 export function formatDateTimeUTC(iso: string): string {
+  const local = iso.match(/^(\d{4}-\d{2}-\d{2})T(\d{2}:\d{2})/);
+  if (local) {
+    return `${local[1]} ${local[2]}`;
+  }
+
   const d = new Date(iso);
   if (isNaN(d.getTime())) {
     return iso;